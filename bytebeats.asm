@@ -54,7 +54,7 @@ l0:
 l5:
     addmimmw r8 r8 1
     addmw r0 r3
-    addm r1 r17
+    addm r1 r1
     addcimm r11 r11 1
     addmimm r0 r0 5
     rotlimmw r0 r0 0
@@ -0,0 +1,250 @@
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use lemurs::instruction::IsaFeatures;
+use lemurs::program::Program;
+use lemurs::synth::{MachineVoice, NUM_KNOBS};
+use nih_plug::prelude::*;
+use nih_plug_egui::{create_egui_editor, egui, EguiState};
+
+// Path of a .bin or .lprog file played by new instances which haven't had one
+// loaded in the editor. Once loaded, the program is saved with the host's
+// project, so the file is only needed once.
+const PROGRAM_PATH_VARIABLE: &str = "LEMURS_PROGRAM";
+
+struct LemursPlugin {
+    params: Arc<LemursParams>,
+    voice: Option<MachineVoice>,
+    sample_rate: f32,
+}
+
+#[derive(Params)]
+struct LemursParams {
+    #[persist = "program"]
+    program: RwLock<Vec<u8>>,
+    // the instruction set the program evolved with, kept apart from its bytes
+    // so that projects saved before it was persisted still load
+    #[persist = "features"]
+    features: RwLock<IsaFeatures>,
+    // set when the editor loads a program, for the audio thread to pick up
+    program_changed: AtomicBool,
+
+    #[persist = "editor-state"]
+    editor_state: Arc<EguiState>,
+
+    #[nested(array, group = "Knobs")]
+    knobs: [KnobParam; NUM_KNOBS],
+}
+
+#[derive(Params)]
+struct KnobParam {
+    #[id = "knob"]
+    value: IntParam,
+}
+
+impl Default for KnobParam {
+    fn default() -> KnobParam {
+        KnobParam {
+            value: IntParam::new("Knob", 0, IntRange::Linear { min: 0, max: 255 }),
+        }
+    }
+}
+
+impl Default for LemursParams {
+    fn default() -> LemursParams {
+        LemursParams {
+            program: RwLock::new(Vec::new()),
+            features: RwLock::new(IsaFeatures::default()),
+            program_changed: AtomicBool::new(false),
+            editor_state: EguiState::from_size(320, 120),
+            knobs: Default::default(),
+        }
+    }
+}
+
+impl Default for LemursPlugin {
+    fn default() -> LemursPlugin {
+        LemursPlugin {
+            params: Arc::new(LemursParams::default()),
+            voice: None,
+            sample_rate: 44_100.0,
+        }
+    }
+}
+
+/// The program in a .bin or .lprog file. Programs which evolved with physics
+/// other than the default are refused, since the voice plays every byte the
+/// machine outputs as a sample.
+fn read_program_file(path: &Path) -> Result<Program, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let program = if path.extension().is_some_and(|e| e == "lprog") {
+        Program::read_container(&data)?.0
+    } else {
+        Program::new(data)?
+    };
+    if !program.physics().is_default() {
+        return Err(format!(
+            "it evolved with physics the plugin can't play ({})",
+            program.physics()
+        ));
+    }
+    Ok(program)
+}
+
+/// Reads a program into the persisted state, to be played from the next block
+fn load_program(params: &LemursParams, path: &Path) -> Result<(), String> {
+    let program = read_program_file(path)?;
+    *params.features.write().unwrap() = program.features();
+    *params.program.write().unwrap() = program.into_bytes();
+    params.program_changed.store(true, Ordering::Release);
+    Ok(())
+}
+
+impl Plugin for LemursPlugin {
+    const NAME: &'static str = "Lemurs";
+    const VENDOR: &'static str = "timstr";
+    const URL: &'static str = "https://github.com/timstr/lemurs";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
+        main_input_channels: None,
+        main_output_channels: NonZeroU32::new(2),
+        ..AudioIOLayout::const_default()
+    }];
+
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
+
+    type SysExMessage = ();
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        let params = self.params.clone();
+        create_egui_editor(
+            self.params.editor_state.clone(),
+            // what happened to the last file loaded
+            String::from("Choose a .bin or .lprog file, or drop one here"),
+            |_, _| {},
+            move |ctx, _setter, status| {
+                let mut path =
+                    ctx.input(|i| i.raw.dropped_files.iter().find_map(|f| f.path.clone()));
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    if ui.button("Load program...").clicked() {
+                        path = rfd::FileDialog::new()
+                            .add_filter("Programs", &["bin", "lprog"])
+                            .pick_file();
+                    }
+                    ui.label(status.as_str());
+                });
+                if let Some(path) = path {
+                    *status = match load_program(&params, &path) {
+                        Ok(()) => format!("Playing {}", path.display()),
+                        Err(e) => format!("Failed to load {}: {}", path.display(), e),
+                    };
+                }
+            },
+        )
+    }
+
+    fn initialize(
+        &mut self,
+        _audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.sample_rate = buffer_config.sample_rate;
+        let mut program = self.params.program.write().unwrap();
+        let mut features = self.params.features.write().unwrap();
+        if program.is_empty() {
+            if let Ok(path) = std::env::var(PROGRAM_PATH_VARIABLE) {
+                match read_program_file(Path::new(&path)) {
+                    Ok(p) => {
+                        *features = p.features();
+                        *program = p.into_bytes();
+                    }
+                    Err(e) => nih_error!("Failed to load {}: {}", path, e),
+                }
+            }
+        }
+        // silent until a program is loaded in the editor
+        self.voice = (!program.is_empty())
+            .then(|| MachineVoice::new_with_features(program.clone(), *features));
+        self.params.program_changed.store(false, Ordering::Release);
+        true
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        if self.params.program_changed.swap(false, Ordering::AcqRel) {
+            let program = self.params.program.try_read();
+            let features = self.params.features.try_read();
+            match (program, features) {
+                (Ok(program), Ok(features)) => {
+                    self.voice = Some(MachineVoice::new_with_features(program.clone(), *features));
+                }
+                // the editor is still writing it, try again next block
+                _ => self.params.program_changed.store(true, Ordering::Release),
+            }
+        }
+        let Some(voice) = &mut self.voice else {
+            return ProcessStatus::Normal;
+        };
+        for (i, knob) in self.params.knobs.iter().enumerate() {
+            voice.set_knob(i, knob.value.value() as u8);
+        }
+
+        let mut next_event = context.next_event();
+        for (sample_index, mut channels) in buffer.iter_samples().enumerate() {
+            while let Some(event) = next_event {
+                if event.timing() as usize > sample_index {
+                    break;
+                }
+                match event {
+                    NoteEvent::NoteOn { note, velocity, .. } => {
+                        voice.note_on(note, (velocity * 127.0).round() as u8)
+                    }
+                    NoteEvent::NoteOff { note, .. } => voice.note_off(note),
+                    _ => (),
+                }
+                next_event = context.next_event();
+            }
+
+            // the machine's four channels are mixed down in pairs, like a stereo pair of stereo pairs
+            let [a, b, c, d] = voice.next_frame(self.sample_rate);
+            *channels.get_mut(0).unwrap() = 0.5 * (a + c);
+            *channels.get_mut(1).unwrap() = 0.5 * (b + d);
+        }
+        ProcessStatus::Normal
+    }
+}
+
+impl ClapPlugin for LemursPlugin {
+    const CLAP_ID: &'static str = "com.github.timstr.lemurs";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("Plays an evolved lemurs program");
+    const CLAP_MANUAL_URL: Option<&'static str> = None;
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::Instrument,
+        ClapFeature::Synthesizer,
+        ClapFeature::Stereo,
+    ];
+}
+
+impl Vst3Plugin for LemursPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"LemursEvolvedSyn";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Instrument, Vst3SubCategory::Synth];
+}
+
+nih_export_clap!(LemursPlugin);
+nih_export_vst3!(LemursPlugin);
@@ -0,0 +1,5622 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::audio::{AudioBackend, Playback};
+use crate::constraints::Constraints;
+use crate::corpus::{format_date, Corpus, CorpusEntry};
+use crate::diff::{diff, Change};
+use crate::embedding::classical_mds;
+use crate::evaluate::{
+    evaluate, evaluate_and_analyse, preview, register_timeline, EvalConfig, OutputMode, StopReason,
+    AUDIO_CHANNELS,
+};
+use crate::event_log::{ChildEvent, Event, EventLog, JudgementEvent};
+#[cfg(feature = "ogg")]
+use crate::export::write_ogg;
+use crate::export::{crossfade_concatenate, write_flac, write_midi_file, write_wav, ExportFormat};
+use crate::features::{novelty, Features, NUM_MFCC};
+use crate::filter::speaker_protection;
+use crate::fitness::FitnessScript;
+use crate::instruction::{
+    assemble, disassemble, disassemble_lines, Instruction, IsaFeatures, RegId, Value,
+};
+use crate::logging::with_recent_entries;
+use crate::machine::{Machine, TraceStep};
+use crate::memory_watch::MemoryWatch;
+#[cfg(feature = "midi")]
+use crate::midi::{note_name, MidiInput};
+use crate::mutation::{ChangePhysics, CrossoverOperator, MutationRegistry, SegmentRecombination};
+use crate::osc::{OscMessage, OscServer};
+use crate::parallel::ParallelMap;
+use crate::physics::{Physics, PHYSICS_MUTATION_WEIGHT};
+use crate::plugin::{Plugin, PluginMutation, Plugins, PLUGIN_MUTATION_WEIGHT};
+use crate::population::{is_population, read_population, write_population, Annotations, Member};
+use crate::profile::{load_profiles, Profile};
+use crate::program::{Program, ProgramHash, Provenance};
+use crate::rewind::Rewind;
+use crate::routing::Routing;
+use crate::sequence::decode_midi;
+use crate::share::{SharedProgram, Sharing, MAX_INBOX_LENGTH};
+use crate::shortcuts::{Action, KeyBindings, Shortcut};
+use crate::spectrogram::{gradient_colour, Spectrogram, SpectrogramRenderer, SPECTROGRAM_COLOURS};
+use crate::storage;
+use crate::tape::Tape;
+use crate::templates::TEMPLATES;
+use eframe::egui::PointerButton;
+use eframe::{
+    egui::{self, Context},
+    epaint::{Color32, ColorImage, TextureHandle},
+    App, Frame,
+};
+use log::{error, info, warn};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use threadpool::ThreadPool;
+use web_time::Instant;
+
+// Browsers have no threads to spare, so programs are rendered one at a time there
+#[cfg(not(target_arch = "wasm32"))]
+type Executor = ThreadPool;
+#[cfg(target_arch = "wasm32")]
+type Executor = crate::parallel::SerialMap;
+
+// Bytes which haven't changed for this many generations are drawn as cold
+const HEATMAP_MAX_AGE: u32 = 8;
+const HEATMAP_HEIGHT: f32 = 6.0;
+// Points along each register's trace in the detail view
+const TIMELINE_POINTS: usize = 512;
+// Colours of the register traces, picked by register number
+const TRACE_COLOURS: [Color32; 6] = [
+    Color32::LIGHT_RED,
+    Color32::LIGHT_GREEN,
+    Color32::LIGHT_BLUE,
+    Color32::YELLOW,
+    Color32::from_rgb(255, 128, 255),
+    Color32::from_rgb(128, 255, 255),
+];
+// Height of the onset strength track under each spectrogram
+const ONSET_TRACK_HEIGHT: f32 = 8.0;
+
+// Below this, cells are unusably small and the grid scrolls instead
+const MIN_CELL_HEIGHT: f32 = 48.0;
+
+// Number of rendered programs kept around for reuse. Each holds its full output.
+const RENDER_CACHE_SIZE: usize = 64;
+
+// Number of corpus programs listed by "Find similar in corpus"
+const NUM_SIMILAR: usize = 12;
+
+// Ranges of the toolbar sliders, which remote control knobs are scaled to as well
+const MUTATION_AMOUNT_RANGE: RangeInclusive<usize> = 1..=32;
+const POPULATION_SIZE_RANGE: RangeInclusive<usize> = 1..=128;
+
+const MAP_THUMBNAIL_SIZE: egui::Vec2 = egui::vec2(120.0, 60.0);
+
+// Grid cells and the map draw spectrograms downscaled to fit within this many
+// pixels, so that large populations don't fill GPU memory with full-size images
+const THUMBNAIL_MAX_SIZE: [usize; 2] = [256, 64];
+
+// Width and height in pixels of the XY scope image
+const SCOPE_SIZE: usize = 96;
+
+// Thumbnail textures created per frame. Uploading a whole large generation at
+// once freezes the UI, so cells beyond this show a placeholder until a later frame.
+const TEXTURE_UPLOADS_PER_FRAME: usize = 8;
+
+// How often to look for new or changed plugins
+const PLUGIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const DIFFERENCE_COLOURS: [(f32, f32, f32); 3] =
+    [(0.0, 0.0, 0.0), (0.7, 0.0, 0.2), (1.0, 0.9, 0.4)];
+
+fn colourize(spectrogram: &Spectrogram, colours: &[(f32, f32, f32)]) -> ColorImage {
+    ColorImage {
+        size: [spectrogram.width, spectrogram.height],
+        pixels: spectrogram
+            .values
+            .iter()
+            .map(|t| {
+                let [r, g, b] = gradient_colour(*t, colours);
+                Color32::from_rgb(r, g, b)
+            })
+            .collect(),
+    }
+}
+
+/// How often the first two channels were at each pair of values, with the
+/// first across and the second upwards, like an oscilloscope in XY mode
+fn scope_image(output: &[u8], channels: usize) -> ColorImage {
+    let mut counts = vec![0u32; SCOPE_SIZE * SCOPE_SIZE];
+    for frame in output.chunks_exact(channels.max(2)) {
+        let x = frame[0] as usize * SCOPE_SIZE / 256;
+        let y = SCOPE_SIZE - 1 - frame[1] as usize * SCOPE_SIZE / 256;
+        counts[y * SCOPE_SIZE + x] += 1;
+    }
+    // logarithmic, so that rarely visited points still show
+    let max = (*counts.iter().max().unwrap() as f32)
+        .ln_1p()
+        .max(f32::EPSILON);
+    ColorImage {
+        size: [SCOPE_SIZE, SCOPE_SIZE],
+        pixels: counts
+            .iter()
+            .map(|c| {
+                let [r, g, b] = gradient_colour((*c as f32).ln_1p() / max, &SPECTROGRAM_COLOURS);
+                Color32::from_rgb(r, g, b)
+            })
+            .collect(),
+    }
+}
+
+/// Halves the image with a box filter until it fits within `max_size`,
+/// like picking a mip level
+fn downscale(image: &ColorImage, max_size: [usize; 2]) -> ColorImage {
+    let mut image = image.clone();
+    while image.size[0] > max_size[0] || image.size[1] > max_size[1] {
+        let [width, height] = image.size;
+        let (step_x, step_y) = (
+            if width > max_size[0] { 2 } else { 1 },
+            if height > max_size[1] { 2 } else { 1 },
+        );
+        let size = [width.div_ceil(step_x), height.div_ceil(step_y)];
+        let mut pixels = Vec::with_capacity(size[0] * size[1]);
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                let mut sum = [0u32; 4];
+                let mut count = 0;
+                for sy in (y * step_y)..((y + 1) * step_y).min(height) {
+                    for sx in (x * step_x)..((x + 1) * step_x).min(width) {
+                        for (s, c) in sum.iter_mut().zip(image.pixels[sy * width + sx].to_array()) {
+                            *s += c as u32;
+                        }
+                        count += 1;
+                    }
+                }
+                let [r, g, b, a] = sum.map(|s| (s / count) as u8);
+                pixels.push(Color32::from_rgba_premultiplied(r, g, b, a));
+            }
+        }
+        image = ColorImage { size, pixels };
+    }
+    image
+}
+
+struct AudioQueue {
+    current_index: Option<usize>,
+    backend: Box<dyn AudioBackend>,
+    // play everything at a similar loudness, using each instance's playback gain
+    normalise_loudness: bool,
+    // mix the channels into stereo with `routing` when playing and exporting audio
+    route_to_stereo: bool,
+    routing: Routing,
+    // filter what's played, but not what's exported, to make it easier on the ears
+    speaker_protection: bool,
+    sample_rate: usize,
+    // play from the start again on reaching the end, rather than stopping
+    looping: bool,
+    // the instance being hovered, with the times in seconds when the hover
+    // started and when it was last seen
+    hover: Option<(usize, f64, f64)>,
+}
+
+// Hovering over an instance for this long in seconds plays it, so that
+// skimming across the grid doesn't start every cell on the way
+const HOVER_DWELL: f64 = 0.08;
+
+impl AudioQueue {
+    /// Plays the hovered instance once the pointer has rested on it. Returns
+    /// whether it's still waiting, in which case the caller should repaint.
+    fn queue_audio(&mut self, index: usize, data: &Arc<[u8]>, gain: f32, time: f64) -> bool {
+        if self.current_index == Some(index) {
+            return false;
+        }
+        let start = match self.hover {
+            // a gap means the pointer left and came back
+            Some((i, start, last_seen)) if i == index && time - last_seen < HOVER_DWELL => start,
+            _ => time,
+        };
+        self.hover = Some((index, start, time));
+        if time - start < HOVER_DWELL {
+            return true;
+        }
+        self.play(Some(index), data, gain);
+        false
+    }
+
+    fn play(&mut self, index: Option<usize>, data: &Arc<[u8]>, gain: f32) {
+        self.play_from(index, data, 0, gain);
+    }
+
+    /// Plays `data` starting `start` bytes in
+    fn play_from(&mut self, index: Option<usize>, data: &Arc<[u8]>, start: usize, gain: f32) {
+        self.current_index = index;
+        self.backend.play(Playback {
+            data: self.for_playback(data),
+            start,
+            looping: self.looping,
+            gain: if self.normalise_loudness { gain } else { 1.0 },
+        });
+    }
+
+    fn stop(&mut self) {
+        self.current_index = None;
+        self.backend.stop();
+    }
+
+    /// The output as it should be heard, keeping its channel layout
+    fn for_playback(&self, data: &Arc<[u8]>) -> Arc<[u8]> {
+        if !self.speaker_protection && !self.route_to_stereo {
+            return Arc::clone(data);
+        }
+        let mut data = data.to_vec();
+        if self.speaker_protection {
+            data = speaker_protection(&data, AUDIO_CHANNELS, self.sample_rate);
+        }
+        if self.route_to_stereo {
+            data = self.routing.apply(&data);
+        }
+        Arc::from(data)
+    }
+
+    /// Audio output as it should be exported, with `exported_channels` channels
+    fn exported<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.route_to_stereo {
+            Cow::Owned(self.routing.to_stereo(data))
+        } else {
+            Cow::Borrowed(data)
+        }
+    }
+
+    fn exported_channels(&self) -> usize {
+        if self.route_to_stereo {
+            2
+        } else {
+            AUDIO_CHANNELS
+        }
+    }
+
+    /// How far into the given instance's output playback has reached, if it's playing
+    fn playhead(&self, index: usize) -> Option<usize> {
+        if self.current_index != Some(index) {
+            return None;
+        }
+        self.backend.playhead()
+    }
+}
+
+struct Lineage {
+    generation: usize,
+    program: Program,
+    parent: Option<Arc<Lineage>>,
+}
+
+struct Instance {
+    program: Program,
+    // for each program byte, the number of generations since it last changed
+    byte_ages: Vec<u32>,
+    generation: usize,
+    lineage: Option<Arc<Lineage>>,
+    // shared with the render cache and the audio backend
+    output: Arc<[u8]>,
+    // whether the program ran out of time before producing all of its output
+    timed_out: bool,
+    features: Features,
+    // applied when playing with loudness normalisation on
+    playback_gain: f32,
+    // spectral flux, one value per spectrogram column
+    onset_strength: Vec<f32>,
+    // estimated pitch in Hz, one value per spectrogram column
+    pitch_track: Vec<Option<f32>>,
+    spectrogram_image: ColorImage,
+    // full size, only created for the detail view and child preview
+    spectrogram_texture: Option<TextureHandle>,
+    thumbnail_image: ColorImage,
+    // only created once the instance's cell is on screen
+    thumbnail_texture: Option<TextureHandle>,
+    chroma_image: ColorImage,
+    // only created once the instance's cell is on screen in chroma mode
+    chroma_texture: Option<TextureHandle>,
+    scope_image: ColorImage,
+    // only created once the instance's cell is on screen in scope mode
+    scope_texture: Option<TextureHandle>,
+    is_selected: bool,
+    is_minimized: bool,
+    // pinned instances keep their slot across generations
+    is_pinned: bool,
+    // mutations applied to this instance's children, instead of the global amount
+    mutation_amount: Option<usize>,
+    // from the fitness script, once it has been run on this instance
+    fitness: Option<f32>,
+    // names of the mutation operators which made this from its parent, in order
+    operators: Vec<String>,
+    rating: Option<u8>,
+    tags: Vec<String>,
+    note: String,
+}
+
+/// Register values plotted for a program, kept until the program or the
+/// registers shown change
+struct RegisterTimeline {
+    program: Program,
+    registers: Vec<u8>,
+    values: Vec<Vec<Value>>,
+}
+
+struct AsmEditor {
+    index: Option<usize>,
+    // of the instance being edited, which the assembled program runs with too
+    features: IsaFeatures,
+    physics: Physics,
+    text: String,
+    error: Option<String>,
+    preview: Option<Instance>,
+}
+
+/// A single mutated child rendered outside of the population, for hearing
+/// what the current mutation settings do before committing a generation
+struct ChildPreview {
+    parent: Arc<Lineage>,
+    parent_byte_ages: Vec<u32>,
+    mutation_amount: usize,
+    child: Instance,
+}
+
+/// Steps forwards and backwards through an instance's program, from the
+/// detail view
+struct Debugger {
+    // the instance being debugged
+    index: usize,
+    machine: Machine,
+    rewind: Rewind,
+    steps: u64,
+    output_len: u64,
+    // what the last instruction did, if the last move was a single step
+    last_step: Option<TraceStep>,
+    // instructions the far buttons move by
+    stride: u64,
+}
+
+impl Debugger {
+    fn new(index: usize, program: &[u8], features: IsaFeatures) -> Debugger {
+        let machine = Machine::new_with_features(program.to_vec(), features);
+        Debugger {
+            index,
+            rewind: Rewind::new(&machine),
+            machine,
+            steps: 0,
+            output_len: 0,
+            last_step: None,
+            stride: 100,
+        }
+    }
+
+    fn forward(&mut self, num_steps: u64) {
+        let mut output = Vec::new();
+        for _ in 0..num_steps {
+            output.clear();
+            let step = self.machine.step_traced(&mut output);
+            self.steps += 1;
+            self.output_len += output.len() as u64;
+            self.rewind
+                .record(&self.machine, self.steps, self.output_len);
+            self.last_step = (num_steps == 1).then_some(step);
+        }
+    }
+
+    fn back(&mut self, num_steps: u64) {
+        let target = self.steps.saturating_sub(num_steps);
+        (self.steps, self.output_len) = self.rewind.rewind(&mut self.machine, target);
+        self.last_step = None;
+    }
+
+    /// The instruction which will run next
+    fn next_instruction(&self) -> Instruction {
+        let memory = self.machine.memory();
+        let mut address = self.machine.program_counter();
+        Instruction::decode(self.machine.features(), || {
+            let b = memory[address];
+            address = (address + 1) % memory.len();
+            b
+        })
+    }
+}
+
+/// Playing instances from a MIDI controller
+#[cfg(feature = "midi")]
+struct Performance {
+    input: MidiInput,
+    // by note. Holding the outputs here means the audio thread never frees them.
+    bank: HashMap<u8, BankEntry>,
+    // the instance which the next note received will be assigned to
+    learning: Option<usize>,
+}
+
+#[cfg(feature = "midi")]
+struct BankEntry {
+    // None once the instance has left the population, though its sound still plays
+    index: Option<usize>,
+    output: Arc<[u8]>,
+}
+
+// Notes from this one up are assigned to each instance in turn, like a drum pad
+#[cfg(feature = "midi")]
+const FIRST_BANK_NOTE: u8 = 36;
+
+struct DiffView {
+    a: usize,
+    b: usize,
+    program_a: Vec<u8>,
+    program_b: Vec<u8>,
+    byte_changes: Vec<Change>,
+    lines_a: Vec<(usize, String)>,
+    lines_b: Vec<(usize, String)>,
+    line_changes: Vec<Change>,
+    difference_image: ColorImage,
+    difference_texture: Option<TextureHandle>,
+}
+
+impl DiffView {
+    fn new(
+        a: usize,
+        instance_a: &Instance,
+        b: usize,
+        instance_b: &Instance,
+        spectrogram_renderer: &SpectrogramRenderer,
+        config: &EvalConfig,
+    ) -> DiffView {
+        let program_a = instance_a.program.bytes().to_vec();
+        let program_b = instance_b.program.bytes().to_vec();
+        let byte_changes = diff(&program_a, &program_b);
+        let lines_a = disassemble_lines(&program_a, instance_a.program.features());
+        let lines_b = disassemble_lines(&program_b, instance_b.program.features());
+        let text_a: Vec<&str> = lines_a.iter().map(|(_, l)| l.as_str()).collect();
+        let text_b: Vec<&str> = lines_b.iter().map(|(_, l)| l.as_str()).collect();
+        let line_changes = diff(&text_a, &text_b);
+
+        let mut difference = preview(&instance_a.output, config, spectrogram_renderer);
+        let spectrogram_b = preview(&instance_b.output, config, spectrogram_renderer);
+        for (va, vb) in difference.values.iter_mut().zip(&spectrogram_b.values) {
+            *va = (*va - *vb).abs();
+        }
+
+        DiffView {
+            a,
+            b,
+            program_a,
+            program_b,
+            byte_changes,
+            lines_a,
+            lines_b,
+            line_changes,
+            difference_image: colourize(&difference, &DIFFERENCE_COLOURS),
+            difference_texture: None,
+        }
+    }
+}
+
+enum InstanceAction {
+    ToggleSelected,
+    Audition,
+    Rate(Option<u8>),
+    Inspect,
+    EditAssembly,
+    MarkForComparison,
+    CompareWithMarked,
+    Save,
+    Export(ExportFormat),
+    AppendToTape,
+    Disassemble,
+    Minimize,
+    Restore,
+    TogglePinned,
+    ResetMutationAmount,
+    SetAsSeed,
+    SortBySimilarity,
+    FindSimilarInCorpus,
+    PreviewChild,
+    SendToPeers,
+    #[cfg(feature = "midi")]
+    AssignNote,
+    Delete,
+    Paste(Program),
+}
+
+// Session files start with these, so that they can be told apart from other
+// TOML and from files written by a later version with a different layout
+const SESSION_FORMAT: &str = "lemurs session";
+const SESSION_VERSION: i64 = 2;
+
+/// A snapshot of the population and settings, saved as TOML.
+/// Lineages aren't saved, so resumed instances have no recorded ancestors.
+///
+/// The file has a `format` key of "lemurs session" and a `version`. Files
+/// from before versioning are version 0. Adding a field with a default
+/// doesn't need a new version, since missing fields take their defaults and
+/// unknown ones are ignored. A change which does, like renaming a field,
+/// bumps `SESSION_VERSION` and adds a step to `Session::migrate`.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    format: String,
+    version: i64,
+    generation: usize,
+    // the random number generator is reseeded with this when saving and resuming,
+    // so that a resumed session continues exactly like the original would have
+    rng_seed: u64,
+    pub settings: Settings,
+    instances: Vec<SessionInstance>,
+    #[serde(default)]
+    history: Vec<GenerationBest>,
+}
+
+/// The best parent of a past generation, as far as ratings tell
+#[derive(Clone, Serialize, Deserialize)]
+struct GenerationBest {
+    generation: usize,
+    program: Program,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SessionInstance {
+    program: Program,
+    #[serde(default)]
+    generation: usize,
+    #[serde(default)]
+    byte_ages: Vec<u32>,
+    #[serde(default)]
+    is_selected: bool,
+    #[serde(default)]
+    is_minimized: bool,
+    #[serde(default)]
+    is_pinned: bool,
+    #[serde(default)]
+    mutation_amount: Option<usize>,
+    #[serde(default)]
+    rating: Option<u8>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    note: String,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Session, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut value: toml::Value = toml::from_str(&text).map_err(|e| e.to_string())?;
+        let table = value.as_table_mut().ok_or("not a lemurs session")?;
+        let version = match table.get("format") {
+            Some(format) if format.as_str() != Some(SESSION_FORMAT) => {
+                return Err("not a lemurs session".to_string())
+            }
+            Some(_) => table
+                .get("version")
+                .and_then(|v| v.as_integer())
+                .ok_or("session has no version")?,
+            None => 0,
+        };
+        if version > SESSION_VERSION {
+            warn!(
+                "Session was saved by a newer version of lemurs (format version {}), \
+                 anything this version doesn't know about will be lost",
+                version
+            );
+        }
+        Session::migrate(table, version);
+        value.try_into().map_err(|e| e.to_string())
+    }
+
+    /// Rewrites a session saved with an older format version into the current one
+    fn migrate(table: &mut toml::value::Table, version: i64) {
+        if version < 1 {
+            // version 0 is the same layout, just without the header
+            table.insert("format".to_string(), SESSION_FORMAT.into());
+        }
+        if version < 2 {
+            // programs were hex strings with their physics alongside, and ran with
+            // the instruction set in the settings rather than their own
+            let isa = table
+                .get("settings")
+                .and_then(|s| s.get("isa"))
+                .cloned()
+                .unwrap_or_else(|| toml::Value::Array(Vec::new()));
+            for key in ["instances", "history"] {
+                let Some(entries) = table.get_mut(key).and_then(|v| v.as_array_mut()) else {
+                    continue;
+                };
+                for entry in entries.iter_mut().filter_map(|e| e.as_table_mut()) {
+                    let Some(bytes) = entry.remove("program") else {
+                        continue;
+                    };
+                    let mut program = toml::value::Table::new();
+                    program.insert("bytes".to_string(), bytes);
+                    program.insert("features".to_string(), isa.clone());
+                    if let Some(physics) = entry.remove("physics") {
+                        program.insert("physics".to_string(), physics);
+                    }
+                    entry.insert("program".to_string(), program.into());
+                }
+            }
+        }
+        table.insert("version".to_string(), SESSION_VERSION.into());
+    }
+}
+
+/// A population kept in memory before a destructive change
+struct Snapshot {
+    session: Session,
+    // what was thrown away, shown next to the restore button
+    reason: String,
+}
+
+/// What the first generation is made of
+pub enum InitialPopulation {
+    // mutated copies of a single program
+    Seed(Program),
+    // previously saved programs, used as they are, with the files they were loaded from
+    Programs(Vec<(PathBuf, Program)>),
+    Session(Box<Session>),
+    // one instance per built-in template and a few random programs, to give
+    // someone new a varied first generation to learn selecting from
+    Onboarding,
+}
+
+/// Everything that comes from running a program
+struct Rendering {
+    output: Arc<[u8]>,
+    // whether the program ran out of time before producing all of its output
+    timed_out: bool,
+    features: Features,
+    playback_gain: f32,
+    onset_strength: Vec<f32>,
+    pitch_track: Vec<Option<f32>>,
+    spectrogram_image: ColorImage,
+    thumbnail_image: ColorImage,
+    chroma_image: ColorImage,
+    scope_image: ColorImage,
+    // time spent running the program, and analysing and drawing its output
+    vm_time: Duration,
+    analysis_time: Duration,
+}
+
+impl Rendering {
+    fn new(
+        program: &Program,
+        spectrogram_renderer: &SpectrogramRenderer,
+        config: &EvalConfig,
+    ) -> Rendering {
+        let start = Instant::now();
+        let evaluation = evaluate_and_analyse(program, config, spectrogram_renderer);
+        let features = evaluation.features.unwrap();
+        // audio is shown as a spectrogram per channel, rather than of the interleaved output
+        let spectrogram = match config.output_mode {
+            OutputMode::Audio => {
+                spectrogram_renderer.compute_channels(&evaluation.output, AUDIO_CHANNELS)
+            }
+            OutputMode::Midi => evaluation.spectrogram.unwrap(),
+        };
+        let spectrogram_image = colourize(&spectrogram, &SPECTROGRAM_COLOURS);
+        let thumbnail_image = downscale(&spectrogram_image, THUMBNAIL_MAX_SIZE);
+        let chroma_image = downscale(
+            &colourize(&evaluation.chromagram.unwrap(), &SPECTROGRAM_COLOURS),
+            THUMBNAIL_MAX_SIZE,
+        );
+        let scope_image = match config.output_mode {
+            OutputMode::Audio => scope_image(&evaluation.output, AUDIO_CHANNELS),
+            OutputMode::Midi => scope_image(&evaluation.output, 2),
+        };
+
+        Rendering {
+            output: evaluation.output,
+            timed_out: evaluation.stop_reason == StopReason::TimedOut,
+            features,
+            // MIDI output is messages rather than samples, so it's never scaled
+            playback_gain: match config.output_mode {
+                OutputMode::Audio => features.normalising_gain(),
+                OutputMode::Midi => 1.0,
+            },
+            onset_strength: evaluation.onset_strength.unwrap(),
+            pitch_track: evaluation.pitch_track.unwrap(),
+            spectrogram_image,
+            thumbnail_image,
+            chroma_image,
+            scope_image,
+            vm_time: evaluation.elapsed,
+            analysis_time: start.elapsed().saturating_sub(evaluation.elapsed),
+        }
+    }
+}
+
+/// The shortest, mean and longest of some times
+#[derive(Clone, Copy, Default)]
+struct TimingSummary {
+    min: Duration,
+    mean: Duration,
+    max: Duration,
+}
+
+impl TimingSummary {
+    fn of(times: &[Duration]) -> TimingSummary {
+        if times.is_empty() {
+            return TimingSummary::default();
+        }
+        TimingSummary {
+            min: times.iter().copied().min().unwrap(),
+            mean: times.iter().sum::<Duration>() / times.len() as u32,
+            max: times.iter().copied().max().unwrap(),
+        }
+    }
+}
+
+impl std::fmt::Display for TimingSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1} / {:.1} / {:.1} ms",
+            self.min.as_secs_f64() * 1000.0,
+            self.mean.as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Where the time went in rendering the last generation
+struct RenderTimings {
+    // programs which were run, rather than found in the render cache
+    rendered: usize,
+    cached: usize,
+    total: Duration,
+    // per rendered program
+    vm: TimingSummary,
+    analysis: TimingSummary,
+    // thumbnail textures created since, as the cells came on screen
+    textures: usize,
+    texture_time: Duration,
+}
+
+/// Recently rendered programs, so that programs which come up again (pinned
+/// instances, duplicates, pasted programs) don't have to be run again
+struct RenderCache {
+    capacity: usize,
+    // each rendering with the time it was last used, by program, features and physics
+    entries: HashMap<Program, (Arc<Rendering>, u64)>,
+    clock: u64,
+}
+
+impl RenderCache {
+    fn new(capacity: usize) -> RenderCache {
+        RenderCache {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, program: &Program) -> Option<Arc<Rendering>> {
+        self.clock += 1;
+        let (rendering, last_used) = self.entries.get_mut(program)?;
+        *last_used = self.clock;
+        Some(Arc::clone(rendering))
+    }
+
+    fn insert(&mut self, program: Program, rendering: Arc<Rendering>) {
+        if self.capacity == 0 {
+            return;
+        }
+        // Evict the least recently used
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&program) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(p, _)| p.clone());
+            if let Some(p) = oldest {
+                self.entries.remove(&p);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(program, (rendering, self.clock));
+    }
+}
+
+impl Instance {
+    fn new(program: Program, rendering: &Rendering) -> Instance {
+        Instance {
+            byte_ages: vec![HEATMAP_MAX_AGE; program.len()],
+            program,
+            generation: 0,
+            lineage: None,
+            output: Arc::clone(&rendering.output),
+            timed_out: rendering.timed_out,
+            features: rendering.features,
+            playback_gain: rendering.playback_gain,
+            onset_strength: rendering.onset_strength.clone(),
+            pitch_track: rendering.pitch_track.clone(),
+            spectrogram_image: rendering.spectrogram_image.clone(),
+            spectrogram_texture: None,
+            thumbnail_image: rendering.thumbnail_image.clone(),
+            thumbnail_texture: None,
+            chroma_image: rendering.chroma_image.clone(),
+            chroma_texture: None,
+            scope_image: rendering.scope_image.clone(),
+            scope_texture: None,
+            is_selected: false,
+            is_minimized: false,
+            is_pinned: false,
+            mutation_amount: None,
+            fitness: None,
+            operators: Vec::new(),
+            rating: None,
+            tags: Vec::new(),
+            note: String::new(),
+        }
+    }
+
+    fn as_ancestor(&self) -> Arc<Lineage> {
+        Arc::new(Lineage {
+            generation: self.generation,
+            program: self.program.clone(),
+            parent: self.lineage.clone(),
+        })
+    }
+
+    fn inherit_byte_ages(&mut self, parent_program: &[u8], parent_byte_ages: &[u32]) {
+        for change in diff(parent_program, self.program.bytes()) {
+            match change {
+                Change::Same(i, j) => {
+                    self.byte_ages[j] = (parent_byte_ages[i] + 1).min(HEATMAP_MAX_AGE)
+                }
+                Change::Added(j) => self.byte_ages[j] = 0,
+                Change::Removed(_) => (),
+            }
+        }
+    }
+
+    /// Every word of the filter must be contained in one of the tags
+    fn matches_tag_filter(&self, filter: &str) -> bool {
+        filter
+            .split_whitespace()
+            .all(|word| self.tags.iter().any(|tag| tag.contains(word)))
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &Lineage> {
+        std::iter::successors(self.lineage.as_deref(), |l| l.parent.as_deref())
+    }
+
+    fn provenance(&self) -> Provenance {
+        Provenance {
+            seed: self.ancestors().last().map(|l| l.program.content_hash()),
+            generation: self.generation,
+            parents: self
+                .lineage
+                .iter()
+                .map(|l| l.program.content_hash())
+                .collect(),
+        }
+    }
+}
+
+struct MapCache {
+    // the mfcc vectors the positions were computed from
+    mfccs: Vec<[f32; NUM_MFCC]>,
+    positions: Vec<[f32; 2]>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum SortKey {
+    Unsorted,
+    Loudness,
+    SpectralCentroid,
+    // unpitched instances come first
+    Pitch,
+    ProgramLength,
+    // closest to the reference features first
+    Similarity,
+    // highest score from the fitness script first
+    Fitness,
+}
+
+impl SortKey {
+    const ALL: [SortKey; 7] = [
+        SortKey::Unsorted,
+        SortKey::Loudness,
+        SortKey::SpectralCentroid,
+        SortKey::Pitch,
+        SortKey::ProgramLength,
+        SortKey::Similarity,
+        SortKey::Fitness,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            SortKey::Unsorted => "Unsorted",
+            SortKey::Loudness => "Loudness",
+            SortKey::SpectralCentroid => "Spectral centroid",
+            SortKey::Pitch => "Pitch",
+            SortKey::ProgramLength => "Program length",
+            SortKey::Similarity => "Similarity",
+            SortKey::Fitness => "Fitness",
+        }
+    }
+}
+
+/// What grid cells show of each instance's output
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ThumbnailMode {
+    #[default]
+    Spectrogram,
+    // energy of each of the 12 pitch classes, for finding tonal material
+    Chroma,
+    // the first two channels plotted against each other, showing how their
+    // phases relate
+    Scope,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct GridLayout {
+    // 0 means pick a roughly square grid automatically
+    columns: usize,
+    // 0 means show all rows on one page
+    rows_per_page: usize,
+    // width / height of each cell, or None to stretch cells to fill the page
+    thumbnail_aspect: Option<f32>,
+    #[serde(default)]
+    thumbnail_mode: ThumbnailMode,
+}
+
+impl GridLayout {
+    fn num_columns(&self, num_instances: usize) -> usize {
+        if self.columns == 0 {
+            ((num_instances as f64).sqrt().ceil() as usize).max(1)
+        } else {
+            self.columns
+        }
+    }
+
+    fn rows_per_page(&self, num_instances: usize) -> usize {
+        let num_rows = num_instances.div_ceil(self.num_columns(num_instances));
+        if self.rows_per_page == 0 {
+            num_rows.max(1)
+        } else {
+            self.rows_per_page.min(num_rows).max(1)
+        }
+    }
+
+    fn instances_per_page(&self, num_instances: usize) -> usize {
+        self.num_columns(num_instances) * self.rows_per_page(num_instances)
+    }
+}
+
+impl Default for GridLayout {
+    fn default() -> GridLayout {
+        GridLayout {
+            columns: 1,
+            rows_per_page: 0,
+            thumbnail_aspect: None,
+            thumbnail_mode: ThumbnailMode::Spectrogram,
+        }
+    }
+}
+
+/// Whether the interface is drawn dark, light or as the system prefers
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum ThemeChoice {
+    // whichever the operating system uses
+    System,
+    Dark,
+    Light,
+}
+
+impl ThemeChoice {
+    const ALL: [ThemeChoice; 3] = [ThemeChoice::System, ThemeChoice::Dark, ThemeChoice::Light];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ThemeChoice::System => "System",
+            ThemeChoice::Dark => "Dark",
+            ThemeChoice::Light => "Light",
+        }
+    }
+}
+
+/// Everything that is remembered between launches, stored as TOML in the
+/// platform's config directory. Missing fields take their default values.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub mutation_amount: usize,
+    // also use the instruction-aware mutation operators
+    smart_mutations: bool,
+    // weights of mutation operators by name, where they differ from the defaults
+    mutation_weights: BTreeMap<String, u32>,
+    // build some children from segments of every selected parent
+    recombine_parents: bool,
+    // also mutate the physics programs run with
+    evolve_physics: bool,
+    // what mutated children must and mustn't contain
+    pub constraints: Constraints,
+    // source of the fitness script, empty for none
+    pub fitness_script: String,
+    // name of the plugin which scores instances instead of the fitness script
+    fitness_plugin: Option<String>,
+    pub population_size: usize,
+    layout: GridLayout,
+    sort_key: SortKey,
+    show_map: bool,
+    normalise_loudness: bool,
+    // mix the channels into stereo with `routing`, when the output is audio
+    route_to_stereo: bool,
+    routing: Routing,
+    // filter playback to remove DC offset and soften harsh highs, when the output is audio
+    speaker_protection: bool,
+    // part of the name of the audio output device, or None for the default device
+    audio_device: Option<String>,
+    shortcuts: KeyBindings,
+    theme: ThemeChoice,
+    // multiplies the display's own scale factor
+    ui_scale: f32,
+    show_settings_panel: bool,
+    // generations auto-evolve runs before stopping for a listen
+    auto_generations: usize,
+    // instances left showing when auto-evolve stops
+    auto_candidates: usize,
+    // auto-evolve selects the fittest instances rather than the most novel
+    auto_by_fitness: bool,
+    // length of each sample in an exported sample pack
+    sample_pack_secs: f32,
+    // how long each generation plays for in an exported piece, and how long
+    // it takes to fade into the next
+    piece_secs: f32,
+    piece_crossfade_secs: f32,
+    // write each session's events to a JSONL file
+    log_events: bool,
+    // instruction set extensions new programs run with
+    isa: IsaFeatures,
+    pub window_size: Option<[f32; 2]>,
+    pub window_position: Option<[f32; 2]>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            mutation_amount: 8,
+            smart_mutations: false,
+            mutation_weights: BTreeMap::new(),
+            recombine_parents: true,
+            evolve_physics: false,
+            constraints: Constraints::default(),
+            fitness_script: String::new(),
+            fitness_plugin: None,
+            population_size: 25,
+            layout: GridLayout::default(),
+            sort_key: SortKey::Unsorted,
+            show_map: false,
+            normalise_loudness: true,
+            route_to_stereo: false,
+            routing: Routing::default(),
+            speaker_protection: false,
+            audio_device: None,
+            shortcuts: KeyBindings::default(),
+            theme: ThemeChoice::System,
+            ui_scale: 1.0,
+            show_settings_panel: true,
+            auto_generations: 20,
+            auto_candidates: 6,
+            auto_by_fitness: false,
+            sample_pack_secs: 2.0,
+            piece_secs: 8.0,
+            piece_crossfade_secs: 2.0,
+            log_events: false,
+            isa: IsaFeatures::default(),
+            window_size: None,
+            window_position: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Settings {
+        let Some(text) = storage::read_settings() else {
+            return Settings::default();
+        };
+        match toml::from_str(&text) {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!("Ignoring invalid settings: {}", e);
+                Settings::default()
+            }
+        }
+    }
+
+    /// Takes the settings a profile has. Its sample rate, preview length and
+    /// time budget are part of the `EvalConfig` instead.
+    pub fn apply_profile(&mut self, profile: &Profile) {
+        if let Some(amount) = profile.mutation_amount {
+            self.mutation_amount = amount;
+        }
+        if let Some(smart_mutations) = profile.smart_mutations {
+            self.smart_mutations = smart_mutations;
+        }
+        if let Some(weights) = &profile.mutation_weights {
+            self.mutation_weights = weights.clone();
+        }
+        if let Some(evolve_physics) = profile.evolve_physics {
+            self.evolve_physics = evolve_physics;
+        }
+        if let Some(normalise_loudness) = profile.normalise_loudness {
+            self.normalise_loudness = normalise_loudness;
+        }
+        if let Some(mode) = profile.thumbnail_mode {
+            self.layout.thumbnail_mode = mode;
+        }
+        if let Some(isa) = profile.isa {
+            self.isa = isa;
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = storage::write_settings(&toml::to_string(self).unwrap()) {
+            error!("Failed to save settings: {}", e);
+        }
+    }
+}
+
+pub struct LemursApp {
+    population: Vec<Instance>,
+    spectrogram_renderer: SpectrogramRenderer,
+    mutation_amount: usize,
+    mutations: MutationRegistry,
+    smart_mutations: bool,
+    mutation_weights: BTreeMap<String, u32>,
+    recombine_parents: bool,
+    evolve_physics: bool,
+    // from lemurs.toml, and the one last chosen
+    profiles: BTreeMap<String, Profile>,
+    profile: Option<String>,
+    profiles_error: Option<String>,
+    constraints: Constraints,
+    // the constraints field as typed, which may not parse yet
+    constraints_text: String,
+    constraints_error: Option<String>,
+    fitness: Option<FitnessScript>,
+    // the fitness script as typed, which is only compiled when applied
+    fitness_text: String,
+    fitness_error: Option<String>,
+    fitness_plugin: Option<String>,
+    event_log: Option<EventLog>,
+    plugins: Plugins,
+    // when the plugins directory was last checked for changes
+    plugins_checked: Instant,
+    desired_population_size: usize,
+    audio_queue: AudioQueue,
+    audio_device: Option<String>,
+    // listed when the audio menu is first opened, since listing can be slow
+    audio_devices: Option<Vec<String>>,
+    shortcuts: KeyBindings,
+    show_shortcuts: bool,
+    theme: ThemeChoice,
+    ui_scale: f32,
+    // the scale slider's value, which only applies once it's let go, so the
+    // slider doesn't move out from under the pointer
+    ui_scale_input: f32,
+    show_settings_panel: bool,
+    // the action whose shortcut is set by the next key pressed
+    rebinding: Option<Action>,
+    eval_config: EvalConfig,
+    // instruction set extensions programs made here get, rather than loaded ones
+    isa: IsaFeatures,
+    render_cache: RenderCache,
+    // all mutations draw from this, so that runs with a fixed seed are reproducible
+    rng: StdRng,
+    threadpool: Executor,
+    generation: usize,
+    disassembly: Option<(String, String)>,
+    focus_index: Option<usize>,
+    layout: GridLayout,
+    page: usize,
+    show_map: bool,
+    map_cache: Option<MapCache>,
+    // thumbnail textures which can still be created this frame
+    texture_uploads_left: usize,
+    render_timings: Option<RenderTimings>,
+    sort_key: SortKey,
+    sort_reference: Option<Features>,
+    tag_filter: String,
+    new_tag_text: String,
+    // vertical scroll offset and viewport height of the grid
+    grid_scroll: (f32, f32),
+    detail_index: Option<usize>,
+    detail_start_seconds: f32,
+    asm_editor: Option<AsmEditor>,
+    comparison_mark: Option<usize>,
+    diff_view: Option<DiffView>,
+    child_preview: Option<ChildPreview>,
+    debugger: Option<Debugger>,
+    // the instance whose memory is shown while it plays
+    memory_watch: Option<(usize, MemoryWatch)>,
+    memory_texture: Option<TextureHandle>,
+    // registers plotted under the waveform in the detail view
+    timeline_registers: Vec<u8>,
+    // values of those registers for the program shown
+    register_timeline: Option<RegisterTimeline>,
+    // earlier selection states of the current population, most recent last
+    selection_history: Vec<Vec<bool>>,
+    // the population from before the last change which threw away rated instances
+    snapshot: Option<Snapshot>,
+    auto_generations: usize,
+    auto_candidates: usize,
+    auto_by_fitness: bool,
+    // generations left to run automatically, if running
+    auto_generations_left: Option<usize>,
+    // features of the most novel instances found by auto-evolve so far
+    novelty_archive: Vec<Features>,
+    sample_pack_secs: f32,
+    piece_secs: f32,
+    piece_crossfade_secs: f32,
+    // the best parent of each past generation, oldest first
+    history: Vec<GenerationBest>,
+    // last known window geometry, remembered for the next launch
+    window_size: Option<[f32; 2]>,
+    window_position: Option<[f32; 2]>,
+    // shown until dismissed, oldest first
+    errors: Vec<String>,
+    osc_server: Option<OscServer>,
+    sharing: Option<Sharing>,
+    // host:port of each instance that programs are sent to
+    peers: Vec<String>,
+    new_peer_text: String,
+    // programs received from peers, oldest first
+    inbox: Vec<SharedProgram>,
+    show_inbox: bool,
+    // where saved programs go, instead of the working directory
+    corpus: Option<Corpus>,
+    show_corpus: bool,
+    corpus_query: String,
+    // features of corpus programs, computed when first searched for similar ones
+    corpus_features: HashMap<ProgramHash, Features>,
+    // corpus entries closest to an instance, closest first, with their distances
+    similar: Option<(String, Vec<(CorpusEntry, f32)>)>,
+    tape: Tape,
+    show_tape: bool,
+    #[cfg(feature = "midi")]
+    performance: Option<Performance>,
+    // part of the name of the MIDI input port to perform with
+    #[cfg(feature = "midi")]
+    midi_port: Option<String>,
+}
+
+pub fn random_program(length: usize, rng: &mut StdRng) -> Vec<u8> {
+    (0..length).map(|_| rng.gen()).collect()
+}
+
+impl LemursApp {
+    pub fn new(
+        initial_population: InitialPopulation,
+        settings: Settings,
+        eval_config: EvalConfig,
+        audio: Box<dyn AudioBackend>,
+        rng: StdRng,
+    ) -> LemursApp {
+        #[cfg(not(target_arch = "wasm32"))]
+        let threadpool =
+            ThreadPool::new(std::thread::available_parallelism().map_or(1, |n| n.get()));
+        #[cfg(target_arch = "wasm32")]
+        let threadpool = crate::parallel::SerialMap;
+
+        let mut app = LemursApp {
+            population: Vec::new(),
+            spectrogram_renderer: SpectrogramRenderer::new(),
+            mutation_amount: settings.mutation_amount,
+            mutations: MutationRegistry::empty(),
+            smart_mutations: settings.smart_mutations,
+            mutation_weights: settings.mutation_weights.clone(),
+            recombine_parents: settings.recombine_parents,
+            evolve_physics: settings.evolve_physics,
+            profiles: BTreeMap::new(),
+            profile: None,
+            profiles_error: None,
+            constraints_text: settings.constraints.to_string(),
+            constraints: settings.constraints,
+            constraints_error: None,
+            fitness: None,
+            fitness_text: settings.fitness_script.clone(),
+            fitness_error: None,
+            fitness_plugin: settings.fitness_plugin.clone(),
+            event_log: None,
+            plugins: Plugins::default(),
+            plugins_checked: Instant::now(),
+            desired_population_size: settings.population_size,
+            audio_queue: AudioQueue {
+                current_index: None,
+                backend: audio,
+                normalise_loudness: settings.normalise_loudness,
+                route_to_stereo: settings.route_to_stereo
+                    && eval_config.output_mode == OutputMode::Audio,
+                routing: settings.routing,
+                speaker_protection: settings.speaker_protection
+                    && eval_config.output_mode == OutputMode::Audio,
+                sample_rate: eval_config.sample_rate,
+                looping: false,
+                hover: None,
+            },
+            audio_device: None,
+            audio_devices: None,
+            shortcuts: settings.shortcuts,
+            show_shortcuts: false,
+            theme: settings.theme,
+            ui_scale: settings.ui_scale.clamp(0.5, 3.0),
+            ui_scale_input: settings.ui_scale.clamp(0.5, 3.0),
+            show_settings_panel: settings.show_settings_panel,
+            rebinding: None,
+            eval_config,
+            isa: settings.isa,
+            render_cache: RenderCache::new(RENDER_CACHE_SIZE),
+            rng,
+            threadpool,
+            generation: 0,
+            disassembly: None,
+            focus_index: None,
+            layout: settings.layout,
+            page: 0,
+            show_map: settings.show_map,
+            map_cache: None,
+            texture_uploads_left: TEXTURE_UPLOADS_PER_FRAME,
+            render_timings: None,
+            sort_key: settings.sort_key,
+            sort_reference: None,
+            tag_filter: String::new(),
+            new_tag_text: String::new(),
+            grid_scroll: (0.0, 0.0),
+            detail_index: None,
+            detail_start_seconds: 0.0,
+            asm_editor: None,
+            comparison_mark: None,
+            diff_view: None,
+            child_preview: None,
+            debugger: None,
+            memory_watch: None,
+            memory_texture: None,
+            timeline_registers: Vec::new(),
+            register_timeline: None,
+            selection_history: Vec::new(),
+            snapshot: None,
+            auto_generations: settings.auto_generations.max(1),
+            auto_candidates: settings.auto_candidates.max(1),
+            auto_by_fitness: settings.auto_by_fitness,
+            auto_generations_left: None,
+            novelty_archive: Vec::new(),
+            sample_pack_secs: settings.sample_pack_secs,
+            piece_secs: settings.piece_secs,
+            piece_crossfade_secs: settings.piece_crossfade_secs,
+            history: Vec::new(),
+            window_size: settings.window_size,
+            window_position: settings.window_position,
+            errors: Vec::new(),
+            osc_server: None,
+            sharing: None,
+            peers: Vec::new(),
+            new_peer_text: String::new(),
+            inbox: Vec::new(),
+            show_inbox: false,
+            corpus: None,
+            show_corpus: false,
+            corpus_query: String::new(),
+            corpus_features: HashMap::new(),
+            similar: None,
+            tape: Tape::default(),
+            show_tape: false,
+            #[cfg(feature = "midi")]
+            performance: None,
+            #[cfg(feature = "midi")]
+            midi_port: None,
+        };
+        app.set_smart_mutations(app.smart_mutations);
+        app.reload_plugins_if_changed();
+        if settings.log_events {
+            app.start_event_log();
+        }
+        match initial_population {
+            InitialPopulation::Seed(program) => {
+                app.reseed(Arc::new(Lineage {
+                    generation: 0,
+                    program,
+                    parent: None,
+                }));
+            }
+            InitialPopulation::Programs(programs) => app.load_population(programs),
+            InitialPopulation::Session(session) => app.restore_session(*session),
+            InitialPopulation::Onboarding => app.onboard(),
+        }
+        app.apply_fitness_script();
+        app.reload_profiles();
+        if let Some(device) = settings.audio_device {
+            app.set_audio_device(Some(device));
+        }
+        app
+    }
+
+    fn set_audio_device(&mut self, device: Option<String>) {
+        match self
+            .audio_queue
+            .backend
+            .set_output_device(device.as_deref())
+        {
+            Ok(()) => self.audio_device = device,
+            Err(e) => self.report_error(format!("Failed to change the audio device: {}", e)),
+        }
+    }
+
+    fn show_audio_settings(&mut self, ui: &mut egui::Ui) {
+        let devices = self
+            .audio_devices
+            .get_or_insert_with(|| self.audio_queue.backend.output_devices());
+        if devices.is_empty() {
+            ui.label("This audio backend plays on a fixed device");
+            return;
+        }
+        let mut chosen = self.audio_device.clone();
+        ui.radio_value(&mut chosen, None, "Default device");
+        for device in devices.iter() {
+            ui.radio_value(&mut chosen, Some(device.clone()), device);
+        }
+        if ui.button("Refresh").clicked() {
+            self.audio_devices = None;
+        }
+        if chosen != self.audio_device {
+            self.set_audio_device(chosen);
+        }
+    }
+
+    fn load_population(&mut self, programs: Vec<(PathBuf, Program)>) {
+        let (paths, programs): (Vec<PathBuf>, Vec<Program>) = programs.into_iter().unzip();
+        self.population = self.render_all(programs);
+        for (instance, path) in self.population.iter_mut().zip(&paths) {
+            read_instance_metadata(path, instance);
+        }
+        self.forget_population_indices();
+    }
+
+    fn session(&mut self) -> Session {
+        // TOML integers are signed
+        let rng_seed = self.rng.gen_range(0..i64::MAX as u64);
+        self.rng = StdRng::seed_from_u64(rng_seed);
+        Session {
+            format: SESSION_FORMAT.to_string(),
+            version: SESSION_VERSION,
+            generation: self.generation,
+            rng_seed,
+            settings: self.settings(),
+            instances: self
+                .population
+                .iter()
+                .map(|i| SessionInstance {
+                    program: i.program.clone(),
+                    generation: i.generation,
+                    byte_ages: i.byte_ages.clone(),
+                    is_selected: i.is_selected,
+                    is_minimized: i.is_minimized,
+                    is_pinned: i.is_pinned,
+                    mutation_amount: i.mutation_amount,
+                    rating: i.rating,
+                    tags: i.tags.clone(),
+                    note: i.note.clone(),
+                })
+                .collect(),
+            history: self.history.clone(),
+        }
+    }
+
+    fn onboard(&mut self) {
+        const NUM_RANDOM_PROGRAMS: usize = 4;
+        let mut programs: Vec<Program> = TEMPLATES
+            .iter()
+            .map(|t| self.new_program(t.program()))
+            .collect();
+        for _ in 0..NUM_RANDOM_PROGRAMS {
+            let bytes = random_program(256, &mut self.rng);
+            programs.push(self.new_program(bytes));
+        }
+        self.population = self.render_all(programs);
+        for (instance, template) in self.population.iter_mut().zip(&TEMPLATES) {
+            instance.note = format!("The {} template: {}", template.name, template.description);
+        }
+        self.forget_population_indices();
+    }
+
+    fn save_session(&mut self) {
+        let session = self.session();
+        let stamp: u32 = thread_rng().gen();
+        let filename = format!("lemurs_session_{}.lemurs", stamp);
+        match storage::save_file(&filename, toml::to_string(&session).unwrap().as_bytes()) {
+            Ok(()) => info!("Saved session to {}", filename),
+            Err(e) => self.report_error(format!("Failed to save session {}: {}", filename, e)),
+        }
+    }
+
+    fn restore_session(&mut self, session: Session) {
+        self.rng = StdRng::seed_from_u64(session.rng_seed);
+        self.generation = session.generation;
+        self.history = session.history;
+        let programs: Vec<Program> = session
+            .instances
+            .iter()
+            .map(|i| i.program.clone())
+            .collect();
+        self.population = self.render_all(programs);
+        for (instance, saved) in self.population.iter_mut().zip(session.instances) {
+            instance.generation = saved.generation;
+            if saved.byte_ages.len() == instance.program.len() {
+                instance.byte_ages = saved.byte_ages;
+            }
+            instance.is_selected = saved.is_selected;
+            instance.is_minimized = saved.is_minimized;
+            instance.is_pinned = saved.is_pinned;
+            instance.mutation_amount = saved.mutation_amount;
+            instance.rating = saved.rating;
+            instance.tags = saved.tags;
+            instance.note = saved.note;
+        }
+        self.forget_population_indices();
+    }
+
+    /// Keeps the current population so that it can be brought back after
+    /// something throws part of it away
+    fn take_snapshot(&mut self, reason: String) {
+        info!("{}, kept a snapshot to restore", reason);
+        let session = self.session();
+        self.snapshot = Some(Snapshot { session, reason });
+    }
+
+    fn restore_snapshot(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            self.restore_session(snapshot.session);
+        }
+    }
+
+    /// Shows an error in the app until it's dismissed, instead of stopping
+    pub fn report_error(&mut self, message: String) {
+        error!("{}", message);
+        self.errors.push(message);
+    }
+
+    fn show_errors(&mut self, ctx: &Context) {
+        if let Some(e) = self.audio_queue.backend.take_error() {
+            self.report_error(e);
+        }
+        if self.errors.is_empty() {
+            return;
+        }
+        let mut dismissed = None;
+        egui::Window::new("Errors")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-8.0, -8.0))
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                for (i, error) in self.errors.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(Color32::RED, error);
+                        if ui.small_button("Dismiss").clicked() {
+                            dismissed = Some(i);
+                        }
+                    });
+                }
+            });
+        if let Some(i) = dismissed {
+            self.errors.remove(i);
+        }
+    }
+
+    fn show_log_panel(&mut self, ctx: &Context) {
+        egui::TopBottomPanel::bottom("log_panel").show(ctx, |ui| {
+            egui::CollapsingHeader::new("Log")
+                .id_source("log_panel_header")
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(150.0)
+                        .stick_to_bottom(true)
+                        .auto_shrink([false, true])
+                        .show(ui, |ui| {
+                            with_recent_entries(|entries| {
+                                for entry in entries {
+                                    let colour = match entry.level {
+                                        log::Level::Error => Color32::RED,
+                                        log::Level::Warn => Color32::YELLOW,
+                                        _ => Color32::GRAY,
+                                    };
+                                    ui.colored_label(
+                                        colour,
+                                        format!("[{}] {}", entry.level, entry.message),
+                                    );
+                                }
+                            });
+                        });
+                });
+        });
+    }
+
+    fn settings(&self) -> Settings {
+        Settings {
+            mutation_amount: self.mutation_amount,
+            smart_mutations: self.smart_mutations,
+            mutation_weights: self.mutation_weights.clone(),
+            recombine_parents: self.recombine_parents,
+            evolve_physics: self.evolve_physics,
+            constraints: self.constraints.clone(),
+            fitness_script: self.fitness_text.clone(),
+            fitness_plugin: self.fitness_plugin.clone(),
+            population_size: self.desired_population_size,
+            layout: self.layout.clone(),
+            sort_key: self.sort_key,
+            show_map: self.show_map,
+            normalise_loudness: self.audio_queue.normalise_loudness,
+            route_to_stereo: self.audio_queue.route_to_stereo,
+            routing: self.audio_queue.routing.clone(),
+            speaker_protection: self.audio_queue.speaker_protection,
+            audio_device: self.audio_device.clone(),
+            shortcuts: self.shortcuts.clone(),
+            theme: self.theme,
+            ui_scale: self.ui_scale,
+            show_settings_panel: self.show_settings_panel,
+            auto_generations: self.auto_generations,
+            auto_candidates: self.auto_candidates,
+            auto_by_fitness: self.auto_by_fitness,
+            sample_pack_secs: self.sample_pack_secs,
+            piece_secs: self.piece_secs,
+            piece_crossfade_secs: self.piece_crossfade_secs,
+            log_events: self.event_log.is_some(),
+            isa: self.isa,
+            window_size: self.window_size,
+            window_position: self.window_position,
+        }
+    }
+
+    fn reseed(&mut self, seed: Arc<Lineage>) {
+        self.generation += 1;
+
+        // Mutate up front so that the random sequence doesn't depend on thread scheduling
+        let mut programs: Vec<Program> = Vec::with_capacity(self.desired_population_size);
+        let mut operators: Vec<Vec<String>> = Vec::with_capacity(self.desired_population_size);
+        for _ in 0..self.desired_population_size {
+            let (p, o) = self.mutated(&seed.program, 1);
+            programs.push(p);
+            operators.push(o);
+        }
+
+        self.population = self.render_all(programs);
+        let seed_byte_ages = vec![HEATMAP_MAX_AGE; seed.program.len()];
+        for (instance, operators) in self.population.iter_mut().zip(operators) {
+            instance.generation = self.generation;
+            instance.lineage = Some(Arc::clone(&seed));
+            instance.inherit_byte_ages(seed.program.bytes(), &seed_byte_ages);
+            instance.operators = operators;
+        }
+        self.forget_population_indices();
+        self.log_generation(vec![seed.program.content_hash()]);
+    }
+
+    fn forget_population_indices(&mut self) {
+        self.audio_queue.current_index = None;
+        self.focus_index = self.focus_index.filter(|i| *i < self.population.len());
+        // full-size textures are only kept while the detail view is open
+        if let Some(instance) = self
+            .detail_index
+            .take()
+            .and_then(|i| self.population.get_mut(i))
+        {
+            instance.spectrogram_texture = None;
+        }
+        if let Some(editor) = &mut self.asm_editor {
+            editor.index = None;
+        }
+        self.comparison_mark = None;
+        self.selection_history.clear();
+        #[cfg(feature = "midi")]
+        if let Some(performance) = &mut self.performance {
+            performance.learning = None;
+            for entry in performance.bank.values_mut() {
+                entry.index = None;
+            }
+        }
+    }
+
+    fn remember_selection(&mut self) {
+        const MAX_SELECTION_HISTORY: usize = 256;
+        let selection = self.population.iter().map(|i| i.is_selected).collect();
+        if self.selection_history.len() == MAX_SELECTION_HISTORY {
+            self.selection_history.remove(0);
+        }
+        self.selection_history.push(selection);
+    }
+
+    fn undo_selection(&mut self) {
+        let Some(selection) = self.selection_history.pop() else {
+            return;
+        };
+        for (instance, is_selected) in self.population.iter_mut().zip(selection) {
+            instance.is_selected = is_selected;
+        }
+    }
+
+    /// How many spectrograms are stacked in each instance's image
+    fn spectrogram_channels(&self) -> usize {
+        match self.eval_config.output_mode {
+            OutputMode::Audio => AUDIO_CHANNELS,
+            OutputMode::Midi => 1,
+        }
+    }
+
+    fn show_instance(&mut self, ui: &mut egui::Ui, index: usize) -> Option<InstanceAction> {
+        let thumbnail_mode = self.layout.thumbnail_mode;
+        let global_mutation_amount = self.mutation_amount;
+        let channels = self.spectrogram_channels();
+        let uploads_left = &mut self.texture_uploads_left;
+        let timings = &mut self.render_timings;
+        let instance = &mut self.population[index];
+        let visuals = ui.visuals();
+        let (background, mut border) = match (instance.is_selected, visuals.dark_mode) {
+            (true, true) => (Color32::DARK_GREEN, Color32::GREEN),
+            (true, false) => (Color32::LIGHT_GREEN, Color32::DARK_GREEN),
+            (false, _) => (visuals.extreme_bg_color, Color32::GRAY),
+        };
+        let placeholder = visuals.faint_bg_color;
+        if self.focus_index == Some(index) {
+            border = Color32::YELLOW;
+        }
+        let ir = egui::Frame::default()
+            .stroke(egui::Stroke::new(2.0, border))
+            .fill(background)
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.set_height(ui.available_height());
+                ui.vertical(|ui| {
+                    let image_size =
+                        ui.available_size() - egui::vec2(0.0, ONSET_TRACK_HEIGHT + HEATMAP_HEIGHT);
+                    let image_rect = egui::Rect::from_min_size(ui.cursor().min, image_size);
+                    let (texture, image, name, options) = match thumbnail_mode {
+                        ThumbnailMode::Chroma => (
+                            &mut instance.chroma_texture,
+                            &instance.chroma_image,
+                            "chroma",
+                            egui::TextureOptions::NEAREST,
+                        ),
+                        ThumbnailMode::Spectrogram => (
+                            &mut instance.thumbnail_texture,
+                            &instance.thumbnail_image,
+                            "thumbnail",
+                            Default::default(),
+                        ),
+                        ThumbnailMode::Scope => (
+                            &mut instance.scope_texture,
+                            &instance.scope_image,
+                            "scope",
+                            Default::default(),
+                        ),
+                    };
+                    if !ui.is_rect_visible(image_rect) {
+                        ui.allocate_space(image_size);
+                    } else if texture.is_none() && *uploads_left == 0 {
+                        let (rect, _) = ui.allocate_exact_size(image_size, egui::Sense::hover());
+                        ui.painter()
+                            .rect_filled(rect, egui::Rounding::none(), placeholder);
+                        ui.ctx().request_repaint();
+                    } else {
+                        let texture: &TextureHandle = texture.get_or_insert_with(|| {
+                            *uploads_left -= 1;
+                            let start = Instant::now();
+                            let texture = ui.ctx().load_texture(name, image.clone(), options);
+                            if let Some(timings) = timings {
+                                timings.textures += 1;
+                                timings.texture_time += start.elapsed();
+                            }
+                            texture
+                        });
+                        if thumbnail_mode == ThumbnailMode::Scope {
+                            // kept square, so that circles look like circles
+                            let (rect, _) =
+                                ui.allocate_exact_size(image_size, egui::Sense::hover());
+                            let side = rect.width().min(rect.height());
+                            ui.painter().image(
+                                texture.id(),
+                                egui::Rect::from_center_size(rect.center(), egui::vec2(side, side)),
+                                egui::Rect::from_min_max(
+                                    egui::pos2(0.0, 0.0),
+                                    egui::pos2(1.0, 1.0),
+                                ),
+                                Color32::WHITE,
+                            );
+                        } else {
+                            let rect = ui.image(texture.id(), image_size).rect;
+                            if thumbnail_mode == ThumbnailMode::Spectrogram {
+                                paint_channel_dividers(ui.painter(), rect, channels);
+                                paint_pitch_contour(ui.painter(), rect, &instance.pitch_track);
+                            }
+                        }
+                    }
+                    let (rect, _) = ui.allocate_exact_size(
+                        egui::vec2(ui.available_width(), ONSET_TRACK_HEIGHT),
+                        egui::Sense::hover(),
+                    );
+                    paint_onset_track(ui.painter(), rect, &instance.onset_strength);
+                    let (rect, _) = ui.allocate_exact_size(
+                        egui::vec2(ui.available_width(), HEATMAP_HEIGHT),
+                        egui::Sense::hover(),
+                    );
+                    paint_byte_heatmap(ui.painter(), rect, &instance.byte_ages);
+                });
+            });
+        let mut action: Option<InstanceAction> = None;
+        let is_selected = instance.is_selected;
+        let is_pinned = instance.is_pinned;
+        let has_mutation_amount = instance.mutation_amount.is_some();
+        let comparison_mark = self.comparison_mark;
+        let can_send = self.sharing.is_some() && !self.peers.is_empty();
+        let has_corpus = self.corpus.is_some();
+        let export_items: &[(&str, ExportFormat)] = match self.eval_config.output_mode {
+            OutputMode::Audio => &[
+                ("Export WAV", ExportFormat::Wav),
+                ("Export FLAC", ExportFormat::Flac),
+                #[cfg(feature = "ogg")]
+                ("Export Ogg Vorbis", ExportFormat::Ogg),
+            ],
+            OutputMode::Midi => &[("Export MIDI file", ExportFormat::Midi)],
+        };
+        #[cfg(feature = "midi")]
+        let is_performing = self.performance.is_some();
+        let tags = &mut instance.tags;
+        let new_tag_text = &mut self.new_tag_text;
+        let r = ir
+            .response
+            .interact(egui::Sense::click())
+            .context_menu(|ui| {
+                let mut item = |ui: &mut egui::Ui, label: &str, a: InstanceAction| {
+                    if ui.button(label).clicked() {
+                        action = Some(a);
+                        ui.close_menu();
+                    }
+                };
+                let select_label = if is_selected { "Deselect" } else { "Select" };
+                item(ui, select_label, InstanceAction::ToggleSelected);
+                item(ui, "Inspect", InstanceAction::Inspect);
+                item(ui, "Edit assembly", InstanceAction::EditAssembly);
+                item(ui, "Mark for comparison", InstanceAction::MarkForComparison);
+                if let Some(mark) = comparison_mark.filter(|m| *m != index) {
+                    item(
+                        ui,
+                        &format!("Compare with #{}", mark),
+                        InstanceAction::CompareWithMarked,
+                    );
+                }
+                item(ui, "Save program", InstanceAction::Save);
+                for (label, format) in export_items {
+                    item(ui, label, InstanceAction::Export(*format));
+                }
+                item(ui, "Append to tape", InstanceAction::AppendToTape);
+                item(ui, "Disassemble", InstanceAction::Disassemble);
+                item(ui, "Minimize", InstanceAction::Minimize);
+                let pin_label = if is_pinned { "Unpin" } else { "Pin" };
+                item(ui, pin_label, InstanceAction::TogglePinned);
+                if has_mutation_amount {
+                    item(
+                        ui,
+                        "Use the global mutation amount",
+                        InstanceAction::ResetMutationAmount,
+                    );
+                }
+                item(ui, "Set as seed", InstanceAction::SetAsSeed);
+                item(ui, "Preview child", InstanceAction::PreviewChild);
+                if can_send {
+                    item(ui, "Send to peers", InstanceAction::SendToPeers);
+                }
+                item(ui, "Sort by similarity", InstanceAction::SortBySimilarity);
+                if has_corpus {
+                    item(
+                        ui,
+                        "Find similar in corpus",
+                        InstanceAction::FindSimilarInCorpus,
+                    );
+                }
+                #[cfg(feature = "midi")]
+                if is_performing {
+                    item(ui, "Assign MIDI note", InstanceAction::AssignNote);
+                }
+                ui.separator();
+                item(ui, "Delete", InstanceAction::Delete);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Add tags");
+                    let r = ui.text_edit_singleline(new_tag_text);
+                    if r.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        for tag in new_tag_text.split(|c: char| c == ',' || c.is_whitespace()) {
+                            if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                                tags.push(tag.to_string());
+                            }
+                        }
+                        new_tag_text.clear();
+                        r.request_focus();
+                    }
+                });
+                let mut removed_tag = None;
+                ui.horizontal_wrapped(|ui| {
+                    for (i, tag) in tags.iter().enumerate() {
+                        if ui
+                            .small_button(format!("{} ×", tag))
+                            .on_hover_text("Remove tag")
+                            .clicked()
+                        {
+                            removed_tag = Some(i);
+                        }
+                    }
+                });
+                if let Some(i) = removed_tag {
+                    tags.remove(i);
+                }
+            });
+        if instance.is_selected {
+            ui.painter().rect_filled(
+                ir.response.rect,
+                egui::Rounding::none(),
+                Color32::from_rgba_unmultiplied(0, 255, 0, 64),
+            );
+        }
+        if let Some(rating) = instance.rating {
+            ui.painter().text(
+                ir.response.rect.right_top() + egui::vec2(-6.0, 4.0),
+                egui::Align2::RIGHT_TOP,
+                "★".repeat(rating as usize),
+                egui::FontId::proportional(16.0),
+                Color32::GOLD,
+            );
+        }
+        if instance.timed_out {
+            ui.painter().text(
+                ir.response.rect.center_top() + egui::vec2(0.0, 4.0),
+                egui::Align2::CENTER_TOP,
+                "⏱ timed out",
+                egui::FontId::proportional(12.0),
+                Color32::LIGHT_RED,
+            );
+        }
+        if let Some(amount) = instance.mutation_amount {
+            ui.painter().text(
+                ir.response.rect.left_bottom() + egui::vec2(6.0, -(HEATMAP_HEIGHT + 4.0)),
+                egui::Align2::LEFT_BOTTOM,
+                format!("🌡 {}", amount),
+                egui::FontId::proportional(12.0),
+                Color32::LIGHT_BLUE,
+            );
+        }
+        if !instance.program.physics().is_default() {
+            ui.painter().text(
+                ir.response.rect.right_bottom() + egui::vec2(-6.0, -(HEATMAP_HEIGHT + 4.0)),
+                egui::Align2::RIGHT_BOTTOM,
+                format!("⚙ {}", instance.program.physics()),
+                egui::FontId::proportional(12.0),
+                Color32::LIGHT_GREEN,
+            );
+        }
+        if instance.is_pinned {
+            ui.painter().text(
+                ir.response.rect.left_top() + egui::vec2(6.0, 4.0),
+                egui::Align2::LEFT_TOP,
+                "📌",
+                egui::FontId::proportional(16.0),
+                Color32::WHITE,
+            );
+        }
+        #[cfg(feature = "midi")]
+        if let Some(performance) = &self.performance {
+            let mut notes: Vec<u8> = performance
+                .bank
+                .iter()
+                .filter(|(_, e)| e.index == Some(index))
+                .map(|(n, _)| *n)
+                .collect();
+            notes.sort();
+            let mut label: Vec<String> = notes.into_iter().map(note_name).collect();
+            if performance.learning == Some(index) {
+                label.push("press a key…".to_string());
+            }
+            if !label.is_empty() {
+                ui.painter().text(
+                    ir.response.rect.right_bottom()
+                        + egui::vec2(-6.0, -ONSET_TRACK_HEIGHT - HEATMAP_HEIGHT - 6.0),
+                    egui::Align2::RIGHT_BOTTOM,
+                    label.join(" "),
+                    egui::FontId::monospace(12.0),
+                    Color32::LIGHT_BLUE,
+                );
+            }
+        }
+        if !instance.tags.is_empty() {
+            ui.painter().text(
+                ir.response.rect.left_bottom()
+                    + egui::vec2(6.0, -ONSET_TRACK_HEIGHT - HEATMAP_HEIGHT - 6.0),
+                egui::Align2::LEFT_BOTTOM,
+                instance.tags.join(" · "),
+                egui::FontId::proportional(12.0),
+                Color32::WHITE,
+            );
+        }
+        if !instance.matches_tag_filter(&self.tag_filter) {
+            ui.painter().rect_filled(
+                ir.response.rect,
+                egui::Rounding::none(),
+                Color32::from_black_alpha(170),
+            );
+        }
+        let mut summary = instance.features.summary();
+        if let Some(fitness) = instance.fitness {
+            summary += &format!("\nfitness {:.3}", fitness);
+        }
+        let r = r.on_hover_text(summary);
+        if r.clicked_by(PointerButton::Primary) {
+            action = Some(InstanceAction::ToggleSelected);
+            self.focus_index = Some(index);
+        }
+        if r.double_clicked_by(PointerButton::Primary) {
+            // undo the selection toggle from the first click
+            instance.is_selected = !instance.is_selected;
+            action = Some(InstanceAction::Inspect);
+        }
+        if r.hovered() {
+            // while performing, the controller decides what plays
+            #[cfg(feature = "midi")]
+            let audition = self.performance.is_none();
+            #[cfg(not(feature = "midi"))]
+            let audition = true;
+            let time = ui.input(|i| i.time);
+            if audition
+                && self.audio_queue.queue_audio(
+                    index,
+                    &instance.output,
+                    instance.playback_gain,
+                    time,
+                )
+            {
+                ui.ctx().request_repaint();
+            }
+            ui.painter().rect_filled(
+                ir.response.rect,
+                egui::Rounding::none(),
+                Color32::from_white_alpha(16),
+            );
+            // Alt and the scroll wheel change how much this instance's children mutate
+            let (alt, scroll) = ui.input(|i| (i.modifiers.alt, i.scroll_delta.y));
+            if alt && scroll != 0.0 {
+                let amount = instance.mutation_amount.unwrap_or(global_mutation_amount);
+                let amount = if scroll > 0.0 {
+                    amount + 1
+                } else {
+                    amount.saturating_sub(1)
+                };
+                instance.mutation_amount = Some(
+                    amount.clamp(*MUTATION_AMOUNT_RANGE.start(), *MUTATION_AMOUNT_RANGE.end()),
+                );
+                // so the grid doesn't scroll too
+                ui.input_mut(|i| i.scroll_delta = egui::Vec2::ZERO);
+            }
+            for event in ui.input(|i| i.events.clone()) {
+                match event {
+                    egui::Event::Copy => {
+                        let hex = instance.program.to_hex();
+                        ui.output_mut(|o| o.copied_text = hex);
+                        info!("Copied program to clipboard");
+                    }
+                    egui::Event::Paste(text) => match Program::from_text(&text) {
+                        Some(p) => action = Some(InstanceAction::Paste(p)),
+                        None => warn!("Clipboard does not contain a hex or base64 program"),
+                    },
+                    _ => (),
+                }
+            }
+        }
+        action
+    }
+
+    fn show_minimized_instances(&mut self, ui: &mut egui::Ui) -> Option<(usize, InstanceAction)> {
+        let mut action = None;
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Minimized:");
+            for index in 0..self.population.len() {
+                if !self.population[index].is_minimized {
+                    continue;
+                }
+                let r = ui.button(format!("#{}", index));
+                if r.hovered() {
+                    let instance = &self.population[index];
+                    let time = ui.input(|i| i.time);
+                    if self.audio_queue.queue_audio(
+                        index,
+                        &instance.output,
+                        instance.playback_gain,
+                        time,
+                    ) {
+                        ui.ctx().request_repaint();
+                    }
+                }
+                if r.clicked() {
+                    action = Some((index, InstanceAction::Restore));
+                }
+            }
+        });
+        action
+    }
+
+    /// Saves output to a new file named after `prefix`, noting the `programs`
+    /// that made it in the event log
+    fn export_output(
+        &mut self,
+        prefix: &str,
+        output: &[u8],
+        format: ExportFormat,
+        programs: Vec<ProgramHash>,
+    ) {
+        let stamp: u32 = thread_rng().gen();
+        let filename = format!("{}_{}.{}", prefix, stamp, format.extension());
+        let mut data = Vec::new();
+        let channels = self.audio_queue.exported_channels() as u16;
+        let sample_rate = self.eval_config.sample_rate as u32;
+        let result = match format {
+            ExportFormat::Wav => {
+                let output = self.audio_queue.exported(output);
+                write_wav(&mut data, &output, channels, sample_rate)
+            }
+            ExportFormat::Flac => {
+                let output = self.audio_queue.exported(output);
+                write_flac(&mut data, &output, channels, sample_rate)
+            }
+            #[cfg(feature = "ogg")]
+            ExportFormat::Ogg => {
+                let output = self.audio_queue.exported(output);
+                write_ogg(&mut data, &output, channels, sample_rate)
+            }
+            ExportFormat::Midi => write_midi_file(&mut data, &decode_midi(output)),
+        };
+        let result = result.and_then(|_| storage::save_file(&filename, &data));
+        match result {
+            Ok(()) => {
+                info!("Exported output to {}", filename);
+                self.log_event(&Event::Exported {
+                    file: &filename,
+                    programs,
+                });
+            }
+            Err(e) => self.report_error(format!("Failed to export {}: {}", filename, e)),
+        }
+    }
+
+    /// Saves every instance to one file, lemurs_population_<n>.lpop, which can
+    /// be dropped onto another window to add them to its population
+    fn export_population(&mut self) {
+        let members: Vec<Member> = self
+            .population
+            .iter()
+            .map(|i| Member {
+                program: i.program.clone(),
+                provenance: i.provenance(),
+                annotations: Annotations {
+                    rating: i.rating,
+                    tags: i.tags.clone(),
+                    note: i.note.clone(),
+                },
+            })
+            .collect();
+        let stamp: u32 = thread_rng().gen();
+        let filename = format!("lemurs_population_{}.lpop", stamp);
+        let mut data = Vec::new();
+        let result = write_population(&mut data, &members)
+            .and_then(|_| storage::save_file(&filename, &data));
+        match result {
+            Ok(()) => {
+                info!("Exported {} programs to {}", members.len(), filename);
+                let programs = members.iter().map(|m| m.program.content_hash()).collect();
+                self.log_event(&Event::Exported {
+                    file: &filename,
+                    programs,
+                });
+            }
+            Err(e) => self.report_error(format!("Failed to export {}: {}", filename, e)),
+        }
+    }
+
+    /// Adds every program of a population file to the population, with the
+    /// ratings, tags and notes they were exported with
+    fn import_population(&mut self, name: &str, data: &[u8]) {
+        let members = match read_population(data) {
+            Ok(m) => m,
+            Err(e) => {
+                self.report_error(format!("Failed to import {}: {}", name, e));
+                return;
+            }
+        };
+        let programs = members.iter().map(|m| m.program.clone()).collect();
+        let mut instances = self.render_all(programs);
+        for (instance, member) in instances.iter_mut().zip(members) {
+            instance.generation = member.provenance.generation;
+            instance.rating = member.annotations.rating;
+            instance.tags = member.annotations.tags;
+            instance.note = member.annotations.note;
+        }
+        info!("Imported {} programs from {}", instances.len(), name);
+        self.population.extend(instances);
+    }
+
+    /// Imports population files dropped onto the window. Natively they come as
+    /// paths, in the browser with their contents.
+    fn handle_dropped_files(&mut self, ctx: &Context) {
+        for file in ctx.input(|i| i.raw.dropped_files.clone()) {
+            let (name, data) = match (&file.path, &file.bytes) {
+                (_, Some(bytes)) => (file.name.clone(), bytes.to_vec()),
+                (Some(path), None) => match fs::read(path) {
+                    Ok(data) => (path.display().to_string(), data),
+                    Err(e) => {
+                        self.report_error(format!("Failed to read {}: {}", path.display(), e));
+                        continue;
+                    }
+                },
+                (None, None) => continue,
+            };
+            if !is_population(&data) {
+                warn!("Ignoring {}, which isn't a population file", name);
+                continue;
+            }
+            self.import_population(&name, &data);
+        }
+    }
+
+    fn apply_instance_action(&mut self, index: usize, action: InstanceAction) {
+        if let InstanceAction::ToggleSelected = action {
+            self.remember_selection();
+        }
+        let instance = &mut self.population[index];
+        match action {
+            InstanceAction::ToggleSelected => instance.is_selected = !instance.is_selected,
+            InstanceAction::Audition => {
+                self.audio_queue
+                    .play(Some(index), &instance.output, instance.playback_gain)
+            }
+            InstanceAction::Rate(rating) => instance.rating = rating,
+            InstanceAction::Inspect => {
+                self.detail_index = Some(index);
+                self.audio_queue.stop();
+            }
+            InstanceAction::Save if self.corpus.is_some() => {
+                let program = instance.program.clone();
+                let provenance = instance.provenance();
+                let tags = instance.tags.clone();
+                let corpus = self.corpus.as_mut().unwrap();
+                if let Err(e) = corpus.add(&program, &provenance, tags) {
+                    self.report_error(format!("Failed to save to the corpus: {}", e));
+                }
+            }
+            InstanceAction::Save => {
+                let stamp: u32 = thread_rng().gen();
+                let filename = format!("lemurs_instance_{}.lprog", stamp);
+                let program = instance.program.clone();
+                let mut data = Vec::new();
+                let result = program
+                    .write_container(&mut data, &instance.provenance())
+                    .and_then(|_| storage::save_file(&filename, &data))
+                    .and_then(|_| write_instance_metadata(&filename, instance));
+                match result {
+                    Ok(()) => {
+                        info!("Saved program to {}", filename);
+                        self.log_event(&Event::Exported {
+                            file: &filename,
+                            programs: vec![program.content_hash()],
+                        });
+                    }
+                    Err(e) => self.report_error(format!("Failed to save {}: {}", filename, e)),
+                }
+            }
+            InstanceAction::Export(format) => {
+                let output = Arc::clone(&instance.output);
+                let hash = instance.program.content_hash();
+                self.export_output("lemurs_instance", &output, format, vec![hash]);
+            }
+            InstanceAction::AppendToTape => {
+                self.tape
+                    .append(format!("#{}", index), Arc::clone(&instance.output));
+                self.show_tape = true;
+            }
+            InstanceAction::Disassemble => {
+                self.disassembly = Some((
+                    format!("Disassembly of #{}", index),
+                    disassemble(instance.program.bytes(), instance.program.features()),
+                ));
+            }
+            InstanceAction::Minimize => instance.is_minimized = true,
+            InstanceAction::Restore => instance.is_minimized = false,
+            InstanceAction::TogglePinned => instance.is_pinned = !instance.is_pinned,
+            InstanceAction::ResetMutationAmount => instance.mutation_amount = None,
+            InstanceAction::SetAsSeed => {
+                let seed = instance.as_ancestor();
+                self.reseed(seed);
+            }
+            InstanceAction::EditAssembly => {
+                self.asm_editor = Some(AsmEditor {
+                    index: Some(index),
+                    features: instance.program.features(),
+                    physics: instance.program.physics(),
+                    text: disassemble(instance.program.bytes(), instance.program.features()),
+                    error: None,
+                    preview: None,
+                });
+            }
+            InstanceAction::SortBySimilarity => {
+                self.sort_reference = Some(instance.features);
+                self.sort_key = SortKey::Similarity;
+            }
+            InstanceAction::FindSimilarInCorpus => {
+                let features = instance.features;
+                let hash = instance.program.content_hash();
+                let matches = self.find_similar_in_corpus(&features, hash);
+                self.similar = Some((format!("#{}", index), matches));
+            }
+            InstanceAction::PreviewChild => {
+                let parent = instance.as_ancestor();
+                let parent_byte_ages = instance.byte_ages.clone();
+                let mutation_amount = instance.mutation_amount.unwrap_or(self.mutation_amount);
+                let child = self.make_child(&parent, &parent_byte_ages, mutation_amount);
+                self.audio_queue
+                    .play(None, &child.output, child.playback_gain);
+                self.child_preview = Some(ChildPreview {
+                    parent,
+                    parent_byte_ages,
+                    mutation_amount,
+                    child,
+                });
+            }
+            InstanceAction::MarkForComparison => self.comparison_mark = Some(index),
+            InstanceAction::CompareWithMarked => {
+                let Some(mark) = self.comparison_mark else {
+                    return;
+                };
+                self.diff_view = Some(DiffView::new(
+                    mark,
+                    &self.population[mark],
+                    index,
+                    &self.population[index],
+                    &self.spectrogram_renderer,
+                    &self.eval_config,
+                ));
+            }
+            InstanceAction::SendToPeers => {
+                if let Some(sharing) = &self.sharing {
+                    let program = &instance.program;
+                    for peer in &self.peers {
+                        sharing.send(peer, program, &instance.provenance());
+                    }
+                }
+            }
+            InstanceAction::Delete => {
+                self.population.remove(index);
+                self.forget_population_indices();
+            }
+            InstanceAction::Paste(p) => {
+                let program = p.with_features(self.isa);
+                self.population[index] = self.render(program);
+                self.audio_queue.current_index = None;
+                #[cfg(feature = "midi")]
+                if let Some(performance) = &mut self.performance {
+                    for entry in performance.bank.values_mut() {
+                        entry.index = entry.index.filter(|i| *i != index);
+                    }
+                }
+            }
+            #[cfg(feature = "midi")]
+            InstanceAction::AssignNote => {
+                if let Some(performance) = &mut self.performance {
+                    performance.learning = Some(index);
+                }
+            }
+        }
+    }
+
+    fn show_disassembly(&mut self, ctx: &Context) {
+        let Some((title, text)) = &self.disassembly else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new(title.as_str())
+            .open(&mut open)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                if ui.button("Copy").clicked() {
+                    ui.output_mut(|o| o.copied_text = text.clone());
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.monospace(text);
+                });
+            });
+        if !open {
+            self.disassembly = None;
+        }
+    }
+
+    fn show_debugger(&mut self, ctx: &Context) {
+        let Some(debugger) = &mut self.debugger else {
+            return;
+        };
+        if self.detail_index != Some(debugger.index) {
+            self.debugger = None;
+            return;
+        }
+        let mut open = true;
+        egui::Window::new(format!("Debugging #{}", debugger.index))
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let stride = debugger.stride;
+                    if ui
+                        .button("⏮")
+                        .on_hover_text(format!("Back {} instructions", stride))
+                        .clicked()
+                    {
+                        debugger.back(stride);
+                    }
+                    if ui
+                        .button("◀")
+                        .on_hover_text("Back one instruction")
+                        .clicked()
+                    {
+                        debugger.back(1);
+                    }
+                    if ui
+                        .button("▶")
+                        .on_hover_text("Run one instruction")
+                        .clicked()
+                    {
+                        debugger.forward(1);
+                    }
+                    if ui
+                        .button("⏭")
+                        .on_hover_text(format!("Run {} instructions", stride))
+                        .clicked()
+                    {
+                        debugger.forward(stride);
+                    }
+                    ui.add(
+                        egui::DragValue::new(&mut debugger.stride)
+                            .clamp_range(1..=1_000_000)
+                            .prefix("by "),
+                    );
+                });
+                ui.label(format!(
+                    "{} instructions run, {} bytes of output",
+                    debugger.steps, debugger.output_len
+                ));
+                if let Some(step) = &debugger.last_step {
+                    let changes: Vec<String> = step
+                        .register_changes
+                        .iter()
+                        .map(|(r, old, new)| format!("r{}: {} -> {}", r.0, old, new))
+                        .collect();
+                    ui.monospace(format!(
+                        "ran   {:04x}  {}  {}",
+                        step.program_counter,
+                        step.instruction,
+                        changes.join(", ")
+                    ));
+                }
+                ui.monospace(format!(
+                    "next  {:04x}  {}",
+                    debugger.machine.program_counter(),
+                    debugger.next_instruction()
+                ));
+                egui::Grid::new("debugger_registers").show(ui, |ui| {
+                    for register in 0..16 {
+                        let value = debugger.machine.read_register(RegId(register));
+                        ui.monospace(format!("r{:<2} {:>10}", register, value));
+                        if register % 4 == 3 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+        if !open {
+            self.debugger = None;
+        }
+    }
+
+    fn show_memory_watch(&mut self, ctx: &Context) {
+        let Some((index, watch)) = &mut self.memory_watch else {
+            return;
+        };
+        if self.detail_index != Some(*index) {
+            self.memory_watch = None;
+            self.memory_texture = None;
+            return;
+        }
+        if let Some(position) = self.audio_queue.playhead(*index) {
+            watch.advance_to(position);
+            ctx.request_repaint();
+        }
+        let image = memory_image(watch);
+        let texture = match &mut self.memory_texture {
+            Some(texture) => {
+                texture.set(image, egui::TextureOptions::NEAREST);
+                texture
+            }
+            None => self.memory_texture.insert(ctx.load_texture(
+                "memory",
+                image,
+                egui::TextureOptions::NEAREST,
+            )),
+        };
+        let mut open = true;
+        egui::Window::new(format!("Memory of #{}", index))
+            .open(&mut open)
+            .default_width(384.0)
+            .show(ctx, |ui| {
+                ui.label("Blue for instructions run, green for reads, red for writes")
+                    .on_hover_text(
+                        "The program runs again in step with playback. \
+                         Each row is one line of memory, starting at address 0.",
+                    );
+                let size = texture.size_vec2();
+                let width = ui.available_width();
+                ui.image(texture.id(), size * (width / size.x));
+            });
+        if !open {
+            self.memory_watch = None;
+            self.memory_texture = None;
+        }
+    }
+
+    fn show_layout_settings(&mut self, ui: &mut egui::Ui) {
+        let layout = &mut self.layout;
+        let mut auto_columns = layout.columns == 0;
+        ui.checkbox(&mut auto_columns, "Square grid");
+        if auto_columns {
+            layout.columns = 0;
+        } else {
+            layout.columns = layout.columns.max(1);
+            ui.add(egui::Slider::new(&mut layout.columns, 1..=16).text("Columns"));
+        }
+
+        let mut paginate = layout.rows_per_page > 0;
+        ui.checkbox(&mut paginate, "Paginate");
+        if paginate {
+            layout.rows_per_page = layout.rows_per_page.max(1);
+            ui.add(egui::Slider::new(&mut layout.rows_per_page, 1..=32).text("Rows per page"));
+        } else {
+            layout.rows_per_page = 0;
+        }
+
+        let mut fixed_aspect = layout.thumbnail_aspect.is_some();
+        ui.checkbox(&mut fixed_aspect, "Fixed thumbnail aspect");
+        if fixed_aspect {
+            let aspect = layout.thumbnail_aspect.get_or_insert(4.0);
+            ui.add(
+                egui::Slider::new(aspect, 0.5..=16.0)
+                    .logarithmic(true)
+                    .text("Width / height"),
+            );
+        } else {
+            layout.thumbnail_aspect = None;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Thumbnails");
+            ui.radio_value(
+                &mut layout.thumbnail_mode,
+                ThumbnailMode::Spectrogram,
+                "Spectrogram",
+            );
+            ui.radio_value(&mut layout.thumbnail_mode, ThumbnailMode::Chroma, "Chroma")
+                .on_hover_text("Energy of each pitch class, C at the bottom");
+            ui.radio_value(&mut layout.thumbnail_mode, ThumbnailMode::Scope, "Scope")
+                .on_hover_text(
+                    "The first channel across and the second upwards, like an \
+                     oscilloscope in XY mode",
+                );
+        });
+    }
+
+    /// Orders the given population indices for display according to the sort key
+    fn sort_indices(&self, indices: &mut [usize]) {
+        let key = |i: &usize| -> f32 {
+            let features = &self.population[*i].features;
+            match self.sort_key {
+                SortKey::Unsorted => 0.0,
+                SortKey::Loudness => features.rms,
+                SortKey::SpectralCentroid => features.spectral_centroid,
+                SortKey::Pitch => features.fundamental.unwrap_or(0.0),
+                SortKey::ProgramLength => features.program_length as f32,
+                SortKey::Similarity => self.sort_reference.map_or(0.0, |r| features.distance(&r)),
+                SortKey::Fitness => -self.population[*i].fitness.unwrap_or(f32::NEG_INFINITY),
+            }
+        };
+        // stable, so ties stay in population order
+        indices.sort_by(|a, b| key(a).total_cmp(&key(b)));
+    }
+
+    /// Places thumbnails of the given instances so that similar sounding ones
+    /// are close together
+    fn show_map(
+        &mut self,
+        ui: &mut egui::Ui,
+        indices: &[usize],
+    ) -> Option<(usize, InstanceAction)> {
+        let mfccs: Vec<[f32; NUM_MFCC]> = indices
+            .iter()
+            .map(|i| self.population[*i].features.mfcc)
+            .collect();
+        if self.map_cache.as_ref().map(|c| &c.mfccs) != Some(&mfccs) {
+            let distances: Vec<Vec<f32>> = indices
+                .iter()
+                .map(|i| {
+                    let f = &self.population[*i].features;
+                    indices
+                        .iter()
+                        .map(|j| f.timbre_distance(&self.population[*j].features))
+                        .collect()
+                })
+                .collect();
+            self.map_cache = Some(MapCache {
+                positions: classical_mds(&distances),
+                mfccs,
+            });
+        }
+        let positions = self.map_cache.as_ref().unwrap().positions.clone();
+
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        let area = rect.shrink2(MAP_THUMBNAIL_SIZE * 0.5);
+        let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+        for p in &positions {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        let normalize = |p: [f32; 2], axis: usize| {
+            let range = max[axis] - min[axis];
+            if range > 0.0 {
+                (p[axis] - min[axis]) / range
+            } else {
+                0.5
+            }
+        };
+
+        let mut action = None;
+        for (i, p) in indices.iter().zip(&positions) {
+            let centre = egui::pos2(
+                area.left() + normalize(*p, 0) * area.width(),
+                area.top() + normalize(*p, 1) * area.height(),
+            );
+            let cell = egui::Rect::from_center_size(centre, MAP_THUMBNAIL_SIZE);
+            ui.allocate_ui_at_rect(cell, |ui| {
+                if let Some(a) = self.show_instance(ui, *i) {
+                    action = Some((*i, a));
+                }
+            });
+        }
+        action
+    }
+
+    fn handle_grid_keys(
+        &mut self,
+        ctx: &Context,
+        visible_indices: &[usize],
+        num_columns: usize,
+    ) -> Option<(usize, InstanceAction)> {
+        if ctx.wants_keyboard_input() || visible_indices.is_empty() {
+            return None;
+        }
+        let pressed = |action: Action| self.shortcuts.pressed(ctx, action);
+
+        let position = self
+            .focus_index
+            .and_then(|f| visible_indices.iter().position(|i| *i == f));
+        let last = visible_indices.len() - 1;
+        let moved = if pressed(Action::FocusLeft) {
+            Some(position.map_or(0, |p| p.saturating_sub(1)))
+        } else if pressed(Action::FocusRight) {
+            Some(position.map_or(0, |p| (p + 1).min(last)))
+        } else if pressed(Action::FocusUp) {
+            Some(position.map_or(0, |p| p.saturating_sub(num_columns)))
+        } else if pressed(Action::FocusDown) {
+            Some(position.map_or(0, |p| (p + num_columns).min(last)))
+        } else {
+            None
+        };
+        if let Some(p) = moved {
+            self.focus_index = Some(visible_indices[p]);
+        }
+
+        let focus = self.focus_index?;
+        if pressed(Action::Audition) {
+            return Some((focus, InstanceAction::Audition));
+        }
+        if pressed(Action::ToggleSelected) {
+            return Some((focus, InstanceAction::ToggleSelected));
+        }
+        if pressed(Action::Save) {
+            return Some((focus, InstanceAction::Save));
+        }
+        if pressed(Action::AppendToTape) {
+            return Some((focus, InstanceAction::AppendToTape));
+        }
+        for action in Action::ALL {
+            if let Some(rating) = action.rating() {
+                if pressed(action) {
+                    return Some((focus, InstanceAction::Rate(rating)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Indices of the instances which the next children come from
+    fn parents(&self) -> Vec<usize> {
+        // Pinned instances only reproduce when they are also selected
+        let all_indices = 0..self.population.len();
+        let selected_parents: Vec<usize> = all_indices
+            .clone()
+            .filter(|i| self.population[*i].is_selected)
+            .collect();
+        let unpinned: Vec<usize> = all_indices
+            .clone()
+            .filter(|i| !self.population[*i].is_pinned)
+            .collect();
+        if !selected_parents.is_empty() {
+            selected_parents
+        } else if !unpinned.is_empty() {
+            unpinned
+        } else {
+            all_indices.collect()
+        }
+    }
+
+    /// Renders `count` children of randomly chosen parents. With recombination
+    /// and more than one parent, half of the children are instead mutations of
+    /// a recombination of every parent.
+    fn breed(&mut self, parents: &[usize], count: usize) -> Vec<Instance> {
+        let ancestors: Vec<Arc<Lineage>> = parents
+            .iter()
+            .map(|i| self.population[*i].as_ancestor())
+            .collect();
+
+        let mut new_programs: Vec<Program> = Vec::with_capacity(count);
+        let mut child_operators: Vec<Vec<String>> = Vec::with_capacity(count);
+        // index into parents of each child
+        let mut child_parents: Vec<usize> = Vec::with_capacity(count);
+        let recombine = self.recombine_parents && parents.len() > 1;
+        for _ in 0..count {
+            if recombine && self.rng.gen() {
+                let (p, i) = self.recombined(parents);
+                let (p, operators) = self.mutated(&p, self.mutation_amount);
+                new_programs.push(p);
+                child_operators.push(
+                    std::iter::once(SegmentRecombination.name().to_string())
+                        .chain(operators)
+                        .collect(),
+                );
+                child_parents.push(i);
+                continue;
+            }
+            let i = self.rng.gen_range(0..parents.len());
+            let parent = &self.population[parents[i]];
+            let amount = parent.mutation_amount.unwrap_or(self.mutation_amount);
+            let program = parent.program.clone();
+            let (p, operators) = self.mutated(&program, amount);
+            new_programs.push(p);
+            child_operators.push(operators);
+            child_parents.push(i);
+        }
+
+        let mut children = self.render_all(new_programs);
+        for ((child, i), operators) in children.iter_mut().zip(child_parents).zip(child_operators) {
+            let parent = &self.population[parents[i]];
+            child.generation = self.generation + 1;
+            child.lineage = Some(Arc::clone(&ancestors[i]));
+            child.inherit_byte_ages(parent.program.bytes(), &parent.byte_ages);
+            child.operators = operators;
+        }
+        children
+    }
+
+    /// Grows the population with children of the current selection, or shrinks
+    /// it by removing unselected and low rated instances first. Pinned instances
+    /// are never removed.
+    fn resize_population(&mut self) {
+        let size = self.desired_population_size;
+        if self.population.is_empty() || size == self.population.len() {
+            return;
+        }
+        if size > self.population.len() {
+            let parents = self.parents();
+            let children = self.breed(&parents, size - self.population.len());
+            self.population.extend(children);
+            return;
+        }
+        let mut removable: Vec<usize> = (0..self.population.len())
+            .filter(|i| !self.population[*i].is_pinned)
+            .collect();
+        // least wanted first, and later cells before earlier ones
+        removable.sort_by_key(|i| {
+            let instance = &self.population[*i];
+            (instance.is_selected, instance.rating, std::cmp::Reverse(*i))
+        });
+        removable.truncate(self.population.len() - size);
+        if removable.is_empty() {
+            return;
+        }
+        self.take_snapshot(format!(
+            "Shrinking the population removed {} instances",
+            removable.len()
+        ));
+        let mut index = 0;
+        self.population.retain(|_| {
+            index += 1;
+            !removable.contains(&(index - 1))
+        });
+        self.forget_population_indices();
+    }
+
+    fn mutate(&mut self) {
+        if self.population.is_empty() {
+            return;
+        }
+        let parents = self.parents();
+        let num_rated_discarded = self
+            .population
+            .iter()
+            .filter(|i| i.rating.is_some() && !i.is_selected && !i.is_pinned)
+            .count();
+        if num_rated_discarded > 0 {
+            self.take_snapshot(format!(
+                "Generation {} had {} rated instances which weren't selected",
+                self.generation, num_rated_discarded
+            ));
+        }
+        // Pinned instances keep their slot, children fill the remaining ones
+        let is_pinned_slot = |slot: usize| self.population.get(slot).is_some_and(|i| i.is_pinned);
+        let num_slots = self
+            .population
+            .iter()
+            .rposition(|i| i.is_pinned)
+            .map_or(0, |i| i + 1)
+            .max(self.desired_population_size);
+        let num_children = (0..self.desired_population_size)
+            .filter(|slot| !is_pinned_slot(*slot))
+            .count();
+
+        self.log_judgements();
+        let parent_hashes = parents
+            .iter()
+            .map(|i| self.population[*i].program.content_hash())
+            .collect();
+        let children = self.breed(&parents, num_children);
+        let best = self.best_of(&parents);
+        self.history.push(best);
+
+        let mut old_population = std::mem::take(&mut self.population).into_iter();
+        let mut children = children.into_iter();
+        for slot in 0..num_slots {
+            match old_population.next() {
+                Some(instance) if instance.is_pinned => self.population.push(instance),
+                _ if slot < self.desired_population_size => self.population.extend(children.next()),
+                _ => {}
+            }
+        }
+        self.generation += 1;
+        self.forget_population_indices();
+        self.log_generation(parent_hashes);
+    }
+
+    /// Starts writing events to a new file, lemurs_events_<n>.jsonl
+    fn start_event_log(&mut self) {
+        let stamp: u32 = thread_rng().gen();
+        let filename = format!("lemurs_events_{}.jsonl", stamp);
+        match storage::create_file(&filename) {
+            Ok(out) => {
+                info!("Logging events to {}", filename);
+                self.event_log = Some(EventLog::new(out));
+            }
+            Err(e) => self.report_error(format!("Failed to create {}: {}", filename, e)),
+        }
+    }
+
+    fn log_event(&mut self, event: &Event) {
+        let Some(log) = &mut self.event_log else {
+            return;
+        };
+        let result = log.record(event);
+        self.check_logged(result);
+    }
+
+    /// Stops logging if an event couldn't be written
+    fn check_logged(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            self.event_log = None;
+            self.report_error(format!("Failed to log an event, stopped logging: {}", e));
+        }
+    }
+
+    /// Logs the instances of the current generation, which were just bred
+    fn log_generation(&mut self, parents: Vec<ProgramHash>) {
+        let Some(log) = &mut self.event_log else {
+            return;
+        };
+        let children = self
+            .population
+            .iter()
+            .filter(|i| i.generation == self.generation)
+            .map(|i| ChildEvent {
+                program: i.program.content_hash(),
+                parent: i.lineage.as_ref().map(|l| l.program.content_hash()),
+                operators: &i.operators,
+                features: &i.features,
+            })
+            .collect();
+        let result = log.record(&Event::Generation {
+            generation: self.generation,
+            parents,
+            children,
+        });
+        self.check_logged(result);
+    }
+
+    /// Logs which instances of the current generation were selected, pinned and
+    /// rated, before breeding from them
+    fn log_judgements(&mut self) {
+        if self.event_log.is_none() {
+            return;
+        }
+        let instances = self
+            .population
+            .iter()
+            .map(|i| JudgementEvent {
+                program: i.program.content_hash(),
+                selected: i.is_selected,
+                pinned: i.is_pinned,
+                rating: i.rating,
+            })
+            .collect();
+        self.log_event(&Event::Judged {
+            generation: self.generation,
+            instances,
+        });
+    }
+
+    /// The highest rated of the given instances, or the first if none are rated
+    fn best_of(&self, indices: &[usize]) -> GenerationBest {
+        let best = indices
+            .iter()
+            .max_by_key(|i| (self.population[**i].rating, std::cmp::Reverse(**i)))
+            .unwrap();
+        GenerationBest {
+            generation: self.generation,
+            program: self.population[*best].program.clone(),
+        }
+    }
+
+    /// Renders a generation, keeping and logging how long it took
+    fn render_all(&mut self, programs: Vec<Program>) -> Vec<Instance> {
+        let (instances, timings) = self.render_programs(programs);
+        info!(
+            "Rendered {} programs ({} cached) in {:.0} ms. Per program, min / mean / max \
+             running {}, analysis {}",
+            timings.rendered,
+            timings.cached,
+            timings.total.as_secs_f64() * 1000.0,
+            timings.vm,
+            timings.analysis
+        );
+        self.render_timings = Some(timings);
+        instances
+    }
+
+    /// Runs the programs in parallel, or reuses their output if they were run
+    /// recently with the same features and physics
+    fn render_programs(&mut self, programs: Vec<Program>) -> (Vec<Instance>, RenderTimings) {
+        let start = Instant::now();
+        let mut renderings: Vec<Option<Arc<Rendering>>> =
+            programs.iter().map(|p| self.render_cache.get(p)).collect();
+
+        // Render each distinct missing program once
+        let mut missing: Vec<Program> = Vec::new();
+        for (p, r) in programs.iter().zip(&renderings) {
+            if r.is_none() && !missing.contains(p) {
+                missing.push(p.clone());
+            }
+        }
+        let num_missing = missing.len();
+        let num_cached = programs.len() - renderings.iter().filter(|r| r.is_none()).count();
+        let rendered = self.threadpool.map_balanced(missing.clone(), |p| {
+            Arc::new(Rendering::new(
+                &p,
+                &self.spectrogram_renderer,
+                &self.eval_config,
+            ))
+        });
+        let vm_times: Vec<Duration> = rendered.iter().map(|r| r.vm_time).collect();
+        let analysis_times: Vec<Duration> = rendered.iter().map(|r| r.analysis_time).collect();
+        for (p, r) in missing.into_iter().zip(rendered) {
+            for (program, rendering) in programs.iter().zip(renderings.iter_mut()) {
+                if rendering.is_none() && *program == p {
+                    *rendering = Some(Arc::clone(&r));
+                }
+            }
+            self.render_cache.insert(p, r);
+        }
+
+        let instances = programs
+            .into_iter()
+            .zip(renderings)
+            .map(|(p, r)| Instance::new(p, &r.unwrap()))
+            .collect();
+        let timings = RenderTimings {
+            rendered: num_missing,
+            cached: num_cached,
+            total: start.elapsed(),
+            vm: TimingSummary::of(&vm_times),
+            analysis: TimingSummary::of(&analysis_times),
+            textures: 0,
+            texture_time: Duration::ZERO,
+        };
+        (instances, timings)
+    }
+
+    /// Starts running generations automatically, selecting the most novel
+    /// instances of each as parents. The first uses the current selection if
+    /// there is one.
+    fn start_auto_evolve(&mut self) {
+        self.auto_generations_left = Some(self.auto_generations);
+        if self.population.iter().any(|i| i.is_selected) {
+            self.auto_generations_left = Some(self.auto_generations - 1);
+            self.mutate();
+        }
+    }
+
+    /// Runs one automatic generation, or stops after the last one
+    fn auto_evolve_step(&mut self) {
+        const NEAREST_NEIGHBOURS: usize = 5;
+        const MAX_ARCHIVE_SIZE: usize = 512;
+        let Some(left) = self.auto_generations_left else {
+            return;
+        };
+        if left == 0 || self.population.is_empty() {
+            self.stop_auto_evolve();
+            return;
+        }
+        self.auto_generations_left = Some(left - 1);
+
+        let by_fitness = self.auto_by_fitness && self.has_fitness();
+        self.score_population();
+        let scores: Vec<f32> = (0..self.population.len())
+            .map(|i| {
+                if by_fitness {
+                    return self.population[i].fitness.unwrap();
+                }
+                let others: Vec<Features> = self
+                    .population
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, o)| o.features)
+                    .chain(self.novelty_archive.iter().copied())
+                    .collect();
+                novelty(&self.population[i].features, &others, NEAREST_NEIGHBOURS)
+            })
+            .collect();
+        let mut ranked: Vec<usize> = (0..self.population.len()).collect();
+        ranked.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+        let num_parents = (self.population.len() / 4).max(1);
+        for instance in &mut self.population {
+            instance.is_selected = false;
+        }
+        for i in &ranked[..num_parents] {
+            self.population[*i].is_selected = true;
+        }
+        if self.novelty_archive.len() < MAX_ARCHIVE_SIZE {
+            self.novelty_archive
+                .push(self.population[ranked[0]].features);
+        }
+        self.mutate();
+    }
+
+    /// Stops auto-evolve and leaves the most varied instances showing, with
+    /// the rest minimized, for choosing parents by ear
+    fn stop_auto_evolve(&mut self) {
+        if self.auto_generations_left.take().is_none() {
+            return;
+        }
+        // Start from the instance furthest from the rest on average, then each
+        // pick is the one furthest from everything picked so far
+        let population = &self.population;
+        let mut candidates: Vec<usize> = Vec::new();
+        while candidates.len() < self.auto_candidates.min(population.len()) {
+            let spread = |i: usize| -> f32 {
+                let features = &population[i].features;
+                if candidates.is_empty() {
+                    population
+                        .iter()
+                        .map(|o| features.distance(&o.features))
+                        .sum()
+                } else {
+                    candidates
+                        .iter()
+                        .map(|j| features.distance(&population[*j].features))
+                        .fold(f32::MAX, f32::min)
+                }
+            };
+            let next = (0..population.len())
+                .filter(|i| !candidates.contains(i))
+                .max_by(|a, b| spread(*a).total_cmp(&spread(*b)))
+                .unwrap();
+            candidates.push(next);
+        }
+        for (i, instance) in self.population.iter_mut().enumerate() {
+            instance.is_selected = false;
+            instance.is_minimized = !candidates.contains(&i);
+        }
+        info!(
+            "Auto-evolve stopped at generation {}, showing the {} most varied instances",
+            self.generation,
+            candidates.len()
+        );
+    }
+
+    /// The program's rendering from the cache, or rendered now and cached.
+    /// Lineages only keep programs, so this is how ancestors are heard again.
+    fn rendering(&mut self, program: &Program) -> Arc<Rendering> {
+        if let Some(rendering) = self.render_cache.get(program) {
+            return rendering;
+        }
+        let rendering = Arc::new(Rendering::new(
+            program,
+            &self.spectrogram_renderer,
+            &self.eval_config,
+        ));
+        self.render_cache
+            .insert(program.clone(), Arc::clone(&rendering));
+        rendering
+    }
+
+    /// Joins segments of every parent into one program. Also returns the index
+    /// into `parents` of the parent which the program has the most bytes in
+    /// common with, for its lineage.
+    fn recombined(&mut self, parents: &[usize]) -> (Program, usize) {
+        let programs: Vec<Program> = parents
+            .iter()
+            .map(|i| self.population[*i].program.clone())
+            .collect();
+        let program_refs: Vec<&Program> = programs.iter().collect();
+        let child = SegmentRecombination.recombine(&program_refs, &mut self.rng);
+        let closest = (0..programs.len())
+            .max_by_key(|i| {
+                diff(programs[*i].bytes(), child.bytes())
+                    .iter()
+                    .filter(|c| matches!(c, Change::Same(..)))
+                    .count()
+            })
+            .unwrap();
+        (child, closest)
+    }
+
+    /// A program made here rather than loaded, which gets the instruction set
+    /// chosen in the settings
+    fn new_program(&self, bytes: Vec<u8>) -> Program {
+        Program::new(bytes).unwrap().with_features(self.isa)
+    }
+
+    /// Applies `count` mutations chosen from the registry, returning the names
+    /// of the operators too. If the result doesn't meet the constraints, starts
+    /// again a few times before giving up and keeping the last attempt.
+    fn mutated(&mut self, program: &Program, count: usize) -> (Program, Vec<String>) {
+        const MAX_ATTEMPTS: usize = 32;
+        let mut attempts = 0;
+        loop {
+            let mut p = program.clone();
+            let mut operators = Vec::with_capacity(count);
+            for _ in 0..count {
+                if let Some(name) = self.mutations.mutate(&mut p, &mut self.rng) {
+                    operators.push(name.to_string());
+                }
+            }
+            attempts += 1;
+            if attempts == MAX_ATTEMPTS || self.constraints.allow(p.bytes(), p.features()) {
+                return (p, operators);
+            }
+        }
+    }
+
+    /// Chooses the MIDI input port to perform with, by part of its name.
+    /// The first port is used otherwise.
+    #[cfg(feature = "midi")]
+    pub fn set_midi_port(&mut self, port: Option<String>) {
+        self.midi_port = port;
+    }
+
+    #[cfg(feature = "midi")]
+    fn start_performance(&mut self, ctx: &Context) {
+        let ctx = ctx.clone();
+        match MidiInput::connect(self.midi_port.as_deref(), move || ctx.request_repaint()) {
+            Ok(input) => {
+                info!("Performing from MIDI input {}", input.port_name());
+                self.audio_queue.stop();
+                self.performance = Some(Performance {
+                    input,
+                    bank: HashMap::new(),
+                    learning: None,
+                });
+                self.map_notes();
+            }
+            Err(e) => self.report_error(format!("Failed to open MIDI input: {}", e)),
+        }
+    }
+
+    /// Assigns notes upwards from `FIRST_BANK_NOTE` to each instance that isn't minimized
+    #[cfg(feature = "midi")]
+    fn map_notes(&mut self) {
+        let Some(performance) = &mut self.performance else {
+            return;
+        };
+        performance.bank.clear();
+        let indices = (0..self.population.len()).filter(|i| !self.population[*i].is_minimized);
+        for (note, index) in (FIRST_BANK_NOTE..=127).zip(indices) {
+            performance.bank.insert(
+                note,
+                BankEntry {
+                    index: Some(index),
+                    output: Arc::clone(&self.population[index].output),
+                },
+            );
+        }
+    }
+
+    /// Plays or assigns every note received since the last frame
+    #[cfg(feature = "midi")]
+    fn handle_midi(&mut self) {
+        let Some(performance) = &mut self.performance else {
+            return;
+        };
+        while let Some(event) = performance.input.try_recv() {
+            if let Some(index) = performance.learning.take() {
+                performance.bank.insert(
+                    event.note,
+                    BankEntry {
+                        index: Some(index),
+                        output: Arc::clone(&self.population[index].output),
+                    },
+                );
+                continue;
+            }
+            if let Some(entry) = performance.bank.get(&event.note) {
+                // squared, since players expect soft notes to be quieter than linear gain gives
+                let gain = (event.velocity as f32 / 127.0).powi(2);
+                let output = self.audio_queue.for_playback(&entry.output);
+                self.audio_queue.backend.trigger(output, gain);
+            }
+        }
+    }
+
+    #[cfg(feature = "midi")]
+    fn show_performance_controls(&mut self, ui: &mut egui::Ui) {
+        let mut performing = self.performance.is_some();
+        if ui
+            .checkbox(&mut performing, "Perform")
+            .on_hover_text(format!(
+                "Play instances from a MIDI controller. Notes from {} up play each instance in turn.",
+                note_name(FIRST_BANK_NOTE)
+            ))
+            .changed()
+        {
+            if performing {
+                self.start_performance(ui.ctx());
+            } else {
+                self.performance = None;
+            }
+        }
+        if self.performance.is_some()
+            && ui
+                .button("Map notes")
+                .on_hover_text("Assign the notes to the current population again")
+                .clicked()
+        {
+            self.map_notes();
+        }
+    }
+
+    fn set_smart_mutations(&mut self, smart_mutations: bool) {
+        self.smart_mutations = smart_mutations;
+        self.mutations = if smart_mutations {
+            MutationRegistry::instruction_aware()
+        } else {
+            MutationRegistry::default()
+        };
+        for plugin in self.plugins.plugins().iter().filter(|p| p.can_mutate()) {
+            self.mutations
+                .register(PluginMutation(Rc::clone(plugin)), PLUGIN_MUTATION_WEIGHT);
+        }
+        if self.evolve_physics {
+            self.mutations
+                .register(ChangePhysics, PHYSICS_MUTATION_WEIGHT);
+        }
+        for (name, weight) in &self.mutation_weights {
+            self.mutations.set_weight(name, *weight);
+        }
+    }
+
+    /// Reads the profiles from lemurs.toml again
+    fn reload_profiles(&mut self) {
+        match load_profiles() {
+            Ok(profiles) => {
+                self.profiles = profiles;
+                self.profiles_error = None;
+            }
+            Err(e) => self.profiles_error = Some(e),
+        }
+    }
+
+    /// Marks the profile which was applied at startup as the current one
+    pub fn set_profile_name(&mut self, name: String) {
+        self.profile = Some(name);
+    }
+
+    /// Switches to a profile's settings. New instances are rendered with its
+    /// preview length and time budget, but the sample rate stays as it is.
+    fn apply_profile(&mut self, name: &str) {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            return;
+        };
+        self.profile = Some(name.to_string());
+        if let Some(amount) = profile.mutation_amount {
+            self.mutation_amount = amount;
+        }
+        if let Some(weights) = &profile.mutation_weights {
+            self.mutation_weights = weights.clone();
+        }
+        if let Some(evolve_physics) = profile.evolve_physics {
+            self.evolve_physics = evolve_physics;
+        }
+        self.set_smart_mutations(profile.smart_mutations.unwrap_or(self.smart_mutations));
+        if let Some(normalise_loudness) = profile.normalise_loudness {
+            self.audio_queue.normalise_loudness = normalise_loudness;
+        }
+        if let Some(mode) = profile.thumbnail_mode {
+            self.layout.thumbnail_mode = mode;
+        }
+        if let Some(isa) = profile.isa {
+            self.isa = isa;
+        }
+        let profile = Profile {
+            sample_rate: None,
+            ..profile
+        };
+        self.eval_config = profile.eval_config(&self.eval_config);
+        // renderings at the old length would be reused otherwise
+        self.render_cache = RenderCache::new(RENDER_CACHE_SIZE);
+    }
+
+    fn show_profile_settings(&mut self, ui: &mut egui::Ui) {
+        let mut chosen = None;
+        egui::ComboBox::from_label("Profile")
+            .selected_text(self.profile.as_deref().unwrap_or("none"))
+            .show_ui(ui, |ui| {
+                for name in self.profiles.keys() {
+                    let selected = self.profile.as_deref() == Some(name.as_str());
+                    if ui.selectable_label(selected, name).clicked() {
+                        chosen = Some(name.clone());
+                    }
+                }
+            })
+            .response
+            .on_hover_text(
+                "Profiles from lemurs.toml in the working directory or the config \
+                 directory. Choosing one changes the mutation, preview and display \
+                 settings it has. Its sample rate is only used with --profile.",
+            );
+        if let Some(name) = chosen {
+            self.apply_profile(&name);
+        }
+        if ui.button("Reload").clicked() {
+            self.reload_profiles();
+        }
+        if let Some(e) = &self.profiles_error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+
+    /// Takes remote control commands from an OSC server. Instances are given by
+    /// their index, and settings take integers, or floats from 0 to 1 for knobs.
+    ///
+    /// - /lemurs/mutate, /lemurs/save_session, /lemurs/stop
+    /// - /lemurs/select index [selected], toggling without the second argument
+    /// - /lemurs/audition index, /lemurs/save index, /lemurs/rate index stars
+    /// - /lemurs/mutation_amount, /lemurs/population_size, /lemurs/smart_mutations
+    pub fn set_osc_server(&mut self, server: OscServer) {
+        info!("Listening for OSC messages on port {}", server.port());
+        self.osc_server = Some(server);
+    }
+
+    /// Sends and receives programs with other running instances.
+    /// `peers` are host:port addresses to send to.
+    pub fn set_sharing(&mut self, sharing: Sharing, peers: Vec<String>) {
+        info!(
+            "Listening for programs from peers on port {}",
+            sharing.port()
+        );
+        self.sharing = Some(sharing);
+        self.peers = peers;
+    }
+
+    /// Saves programs into `corpus` and lets them be browsed from the toolbar
+    pub fn set_corpus(&mut self, corpus: Corpus) {
+        info!(
+            "Using the corpus in {}, with {} programs",
+            corpus.dir().display(),
+            corpus.entries().len()
+        );
+        self.corpus = Some(corpus);
+        self.corpus_features.clear();
+    }
+
+    /// Switches visuals and scale when the chosen theme, the system theme or the scale changes
+    fn apply_appearance(
+        &self,
+        ctx: &Context,
+        system_theme: Option<eframe::Theme>,
+        native_pixels_per_point: f32,
+    ) {
+        let theme = match self.theme {
+            ThemeChoice::System => system_theme.unwrap_or(eframe::Theme::Dark),
+            ThemeChoice::Dark => eframe::Theme::Dark,
+            ThemeChoice::Light => eframe::Theme::Light,
+        };
+        if ctx.style().visuals.dark_mode != (theme == eframe::Theme::Dark) {
+            ctx.set_visuals(theme.egui_visuals());
+        }
+        let pixels_per_point = native_pixels_per_point * self.ui_scale;
+        if (ctx.pixels_per_point() - pixels_per_point).abs() > 1e-3 {
+            ctx.set_pixels_per_point(pixels_per_point);
+        }
+    }
+
+    fn show_appearance_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Theme");
+            for theme in ThemeChoice::ALL {
+                ui.radio_value(&mut self.theme, theme, theme.name());
+            }
+        });
+        let r = ui.add(
+            egui::Slider::new(&mut self.ui_scale_input, 0.5..=3.0)
+                .text("UI scale")
+                .fixed_decimals(2),
+        );
+        if r.drag_released() || (r.changed() && !r.dragged()) {
+            self.ui_scale = self.ui_scale_input;
+        }
+        if ui.button("Reset scale").clicked() {
+            self.ui_scale = 1.0;
+            self.ui_scale_input = 1.0;
+        }
+    }
+
+    /// Compiles the fitness script as typed, or removes it if nothing is
+    /// typed, so that every instance is scored again
+    fn apply_fitness_script(&mut self) {
+        self.fitness = None;
+        self.fitness_error = None;
+        if !self.fitness_text.trim().is_empty() {
+            match FitnessScript::compile(&self.fitness_text) {
+                Ok(script) => self.fitness = Some(script),
+                Err(e) => self.fitness_error = Some(e),
+            }
+        }
+        for instance in &mut self.population {
+            instance.fitness = None;
+        }
+        if !self.has_fitness() && self.sort_key == SortKey::Fitness {
+            self.sort_key = SortKey::Unsorted;
+        }
+    }
+
+    /// The plugin chosen to score instances, if it's loaded
+    fn fitness_plugin(&self) -> Option<Rc<Plugin>> {
+        let plugin = self.plugins.find(self.fitness_plugin.as_deref()?)?;
+        plugin.can_score().then(|| Rc::clone(plugin))
+    }
+
+    fn has_fitness(&self) -> bool {
+        self.fitness.is_some() || self.fitness_plugin().is_some()
+    }
+
+    /// Runs the fitness plugin, or else the fitness script, on instances which
+    /// haven't been scored yet. Instances it fails on score lowest.
+    fn score_population(&mut self) {
+        let plugin = self.fitness_plugin();
+        if plugin.is_none() && self.fitness.is_none() {
+            return;
+        }
+        for instance in self.population.iter_mut().filter(|i| i.fitness.is_none()) {
+            let score = match (&plugin, &self.fitness) {
+                (Some(plugin), _) => plugin.score(&instance.features),
+                (None, Some(script)) => script.score(&instance.features, &instance.output),
+                (None, None) => unreachable!(),
+            };
+            let score = match score {
+                Ok(score) if !score.is_nan() => score,
+                Ok(_) => f32::NEG_INFINITY,
+                Err(e) => {
+                    self.fitness_error = Some(e);
+                    f32::NEG_INFINITY
+                }
+            };
+            instance.fitness = Some(score);
+        }
+    }
+
+    fn show_fitness_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label("Script").on_hover_text(
+            "A Rhai expression scoring each instance, higher is better, \
+             e.g. centroid - abs(rms - 0.2). It can use rms, peak, centroid, \
+             rolloff, flatness, zero_crossings, pitched, fundamental, length, \
+             the mfcc array and the output blob.",
+        );
+        ui.add(
+            egui::TextEdit::multiline(&mut self.fitness_text)
+                .code_editor()
+                .desired_rows(4),
+        );
+        ui.horizontal(|ui| {
+            if ui.button("Apply").clicked() {
+                self.apply_fitness_script();
+            }
+            if self.has_fitness() && ui.button("Sort by fitness").clicked() {
+                self.sort_key = SortKey::Fitness;
+            }
+        });
+        if let Some(e) = &self.fitness_error {
+            ui.colored_label(Color32::RED, e);
+        }
+        let scorers: Vec<String> = self
+            .plugins
+            .plugins()
+            .iter()
+            .filter(|p| p.can_score())
+            .map(|p| p.name.clone())
+            .collect();
+        if scorers.is_empty() && self.fitness_plugin.is_none() {
+            return;
+        }
+        let previous = self.fitness_plugin.clone();
+        egui::ComboBox::from_label("Scored by")
+            .selected_text(self.fitness_plugin.as_deref().unwrap_or("the script"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.fitness_plugin, None, "the script");
+                for name in scorers {
+                    let label = name.clone();
+                    ui.selectable_value(&mut self.fitness_plugin, Some(name), label);
+                }
+            });
+        if self.fitness_plugin != previous {
+            for instance in &mut self.population {
+                instance.fitness = None;
+            }
+        }
+    }
+
+    /// Loads the plugins again if their files changed, and uses the mutations
+    /// of the ones which have them
+    fn reload_plugins_if_changed(&mut self) {
+        self.plugins_checked = Instant::now();
+        let Some(dir) = storage::plugins_dir() else {
+            return;
+        };
+        if !self.plugins.reload_if_changed(&dir) {
+            return;
+        }
+        self.set_smart_mutations(self.smart_mutations);
+        if self.fitness_plugin.is_some() {
+            for instance in &mut self.population {
+                instance.fitness = None;
+            }
+        }
+    }
+
+    fn show_plugin_settings(&mut self, ui: &mut egui::Ui) {
+        let dir = storage::plugins_dir();
+        ui.label(match &dir {
+            Some(dir) => format!("Put .wasm plugins in {}", dir.display()),
+            None => "Plugins aren't supported here".to_string(),
+        })
+        .on_hover_text("Plugins are loaded again whenever their files change, see lemurs::plugin");
+        for plugin in self.plugins.plugins() {
+            let uses: Vec<&str> = [
+                plugin.can_mutate().then_some("mutation"),
+                plugin.can_score().then_some("fitness"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            ui.label(format!("{} ({})", plugin.name, uses.join(", ")));
+        }
+        for (name, e) in self.plugins.errors() {
+            ui.colored_label(Color32::RED, format!("{}: {}", name, e));
+        }
+    }
+
+    fn show_auto_evolve_controls(&mut self, ui: &mut egui::Ui) {
+        ui.label("Run").on_hover_text(
+            "Runs generations on its own, choosing the most novel sounds as parents, \
+                 then stops and shows the most varied for you to choose from. \
+                 It starts from the current selection, if there is one.",
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.auto_generations)
+                .clamp_range(1..=1000)
+                .suffix(" generations"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.auto_candidates)
+                .clamp_range(1..=*POPULATION_SIZE_RANGE.end())
+                .prefix("then show ")
+                .suffix(" instances"),
+        );
+        ui.add_enabled(
+            self.has_fitness(),
+            egui::Checkbox::new(&mut self.auto_by_fitness, "Select by fitness"),
+        )
+        .on_hover_text("Choose parents with the fitness script or plugin instead of by novelty")
+        .on_disabled_hover_text("Write a fitness script first");
+        match self.auto_generations_left {
+            Some(left) => {
+                ui.label(format!("{} generations left", left));
+                if ui.button("Stop").clicked() {
+                    self.stop_auto_evolve();
+                }
+            }
+            None => {
+                if ui.button("Run").clicked() {
+                    self.start_auto_evolve();
+                }
+            }
+        }
+    }
+
+    fn show_routing_settings(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.audio_queue.route_to_stereo, "Mix to stereo")
+            .on_hover_text(
+                "Mix the channels into left and right with these gains, \
+                 when playing and exporting",
+            );
+        ui.add_enabled_ui(self.audio_queue.route_to_stereo, |ui| {
+            egui::Grid::new("routing").show(ui, |ui| {
+                ui.label("");
+                ui.label("Left");
+                ui.label("Right");
+                ui.end_row();
+                for (channel, gains) in self.audio_queue.routing.gains.iter_mut().enumerate() {
+                    ui.label(format!("Channel {}", channel + 1));
+                    for gain in gains {
+                        ui.add(
+                            egui::DragValue::new(gain)
+                                .clamp_range(0.0..=1.0)
+                                .speed(0.01),
+                        );
+                    }
+                    ui.end_row();
+                }
+            });
+            if ui.button("Reset").clicked() {
+                self.audio_queue.routing = Routing::default();
+            }
+        });
+    }
+
+    fn show_sample_pack_controls(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::DragValue::new(&mut self.sample_pack_secs)
+                .clamp_range(0.1..=60.0)
+                .speed(0.1)
+                .prefix("Length ")
+                .suffix(" s"),
+        );
+        let num_selected = self.population.iter().filter(|i| i.is_selected).count();
+        let enabled = num_selected > 0 && self.eval_config.output_mode == OutputMode::Audio;
+        if ui
+            .add_enabled(
+                enabled,
+                egui::Button::new(format!("Export {} selected", num_selected)),
+            )
+            .on_hover_text(
+                "Renders each selected instance and saves it as a WAV in a new folder, \
+                 with a metadata.json describing them",
+            )
+            .on_disabled_hover_text("Select some instances, in audio mode")
+            .clicked()
+        {
+            self.export_sample_pack();
+        }
+    }
+
+    /// Like the preview's configuration, but producing `secs` of output. Longer
+    /// output gets proportionally more time and steps to run in.
+    fn long_eval_config(&self, secs: f32) -> EvalConfig {
+        let base = &self.eval_config;
+        let mut config = EvalConfig::new(base.sample_rate, Some(secs), base.output_mode);
+        let scale = (config.preview_length as f64 / base.preview_length as f64).max(1.0);
+        config.time_budget = base.time_budget.mul_f64(scale);
+        config.max_steps = (base.max_steps as f64 * scale) as usize;
+        config
+    }
+
+    fn show_piece_controls(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::DragValue::new(&mut self.piece_secs)
+                .clamp_range(1.0..=120.0)
+                .speed(0.1)
+                .suffix(" s per generation"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.piece_crossfade_secs)
+                .clamp_range(0.0..=self.piece_secs)
+                .speed(0.1)
+                .suffix(" s crossfade"),
+        );
+        let num_generations = self.history.len() + 1;
+        let enabled =
+            !self.population.is_empty() && self.eval_config.output_mode == OutputMode::Audio;
+        ui.add_enabled_ui(enabled, |ui| {
+            ui.horizontal(|ui| {
+                let hover = format!(
+                    "Plays the best of each of the {} generations so far in turn, \
+                     fading from each into the next",
+                    num_generations
+                );
+                if ui.button("Export WAV").on_hover_text(&hover).clicked() {
+                    let (piece, programs) = self.render_piece();
+                    self.export_output("lemurs_piece", &piece, ExportFormat::Wav, programs);
+                }
+                if ui.button("Append to tape").on_hover_text(&hover).clicked() {
+                    let (piece, _) = self.render_piece();
+                    self.tape.append("piece".to_string(), Arc::from(piece));
+                    self.show_tape = true;
+                }
+            });
+        });
+    }
+
+    /// The best of every generation so far, including the current one, each
+    /// rendered for `piece_secs` and crossfaded into the next
+    /// The piece and the programs in it
+    fn render_piece(&mut self) -> (Vec<u8>, Vec<ProgramHash>) {
+        let mut programs: Vec<Program> = self.history.iter().map(|b| b.program.clone()).collect();
+        programs.push(self.best_of(&self.parents()).program);
+        info!("Rendering a piece of {} generations", programs.len());
+        let hashes = programs.iter().map(|p| p.content_hash()).collect();
+
+        let config = self.long_eval_config(self.piece_secs);
+        let evaluations = self
+            .threadpool
+            .map_balanced(programs, |p| evaluate(&p, &config));
+        let parts: Vec<&[u8]> = evaluations.iter().map(|e| &e.output[..]).collect();
+        let crossfade = (self.piece_crossfade_secs * config.bytes_per_second() as f32) as usize
+            / AUDIO_CHANNELS
+            * AUDIO_CHANNELS;
+        (
+            crossfade_concatenate(&parts, crossfade, AUDIO_CHANNELS),
+            hashes,
+        )
+    }
+
+    /// Renders every selected instance for `sample_pack_secs` and saves them as
+    /// WAVs in a new folder, along with metadata describing each of them
+    fn export_sample_pack(&mut self) {
+        let selected: Vec<usize> = (0..self.population.len())
+            .filter(|i| self.population[*i].is_selected)
+            .collect();
+        if selected.is_empty() {
+            return;
+        }
+        let config = self.long_eval_config(self.sample_pack_secs);
+        let programs: Vec<Program> = selected
+            .iter()
+            .map(|i| self.population[*i].program.clone())
+            .collect();
+        let evaluations = self
+            .threadpool
+            .map_balanced(programs, |p| evaluate(&p, &config));
+
+        let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut samples = Vec::new();
+        for (n, (i, evaluation)) in selected.iter().zip(evaluations).enumerate() {
+            let instance = &self.population[*i];
+            let program = &instance.program;
+            let hash = program.content_hash();
+            let file = sample_file_name(n + 1, &instance.tags, hash);
+            let mut data = Vec::new();
+            write_wav(
+                &mut data,
+                &self.audio_queue.exported(&evaluation.output),
+                self.audio_queue.exported_channels() as u16,
+                config.sample_rate as u32,
+            )
+            .unwrap();
+            files.push((file.clone(), data));
+            samples.push(SamplePackSample {
+                file,
+                hash: hash.to_string(),
+                program: program.to_base64(),
+                features: program.features(),
+                physics: program.physics(),
+                generation: instance.generation,
+                rating: instance.rating,
+                tags: instance.tags.clone(),
+                note: instance.note.clone(),
+                timed_out: evaluation.stop_reason == StopReason::TimedOut,
+            });
+        }
+        let metadata = SamplePackMetadata {
+            sample_rate: config.sample_rate,
+            channels: self.audio_queue.exported_channels(),
+            seconds: self.sample_pack_secs,
+            samples,
+        };
+        files.push((
+            "metadata.json".to_string(),
+            serde_json::to_vec_pretty(&metadata).unwrap(),
+        ));
+
+        let stamp: u32 = thread_rng().gen();
+        let folder = format!("lemurs_samples_{}", stamp);
+        match storage::save_files(&folder, &files) {
+            Ok(()) => {
+                info!("Exported {} samples to {}", selected.len(), folder);
+                let programs = selected
+                    .iter()
+                    .map(|i| self.population[*i].program.content_hash())
+                    .collect();
+                self.log_event(&Event::Exported {
+                    file: &folder,
+                    programs,
+                });
+            }
+            Err(e) => self.report_error(format!("Failed to export {}: {}", folder, e)),
+        }
+    }
+
+    fn show_render_timings(&self, ui: &mut egui::Ui) {
+        let Some(timings) = &self.render_timings else {
+            ui.label("Nothing rendered yet");
+            return;
+        };
+        let ms = |d: Duration| format!("{:.1} ms", d.as_secs_f64() * 1000.0);
+        egui::Grid::new("render_timings").show(ui, |ui| {
+            ui.label("Programs run");
+            ui.label(format!("{} ({} cached)", timings.rendered, timings.cached));
+            ui.end_row();
+            ui.label("Total");
+            ui.label(ms(timings.total));
+            ui.end_row();
+            ui.label("Running");
+            ui.label(timings.vm.to_string());
+            ui.end_row();
+            ui.label("Analysis");
+            ui.label(timings.analysis.to_string());
+            ui.end_row();
+            ui.label("Textures");
+            ui.label(format!(
+                "{} in {}",
+                timings.textures,
+                ms(timings.texture_time)
+            ));
+            ui.end_row();
+        });
+        ui.weak("Per program times are min / mean / max");
+    }
+
+    /// The less used controls, in collapsible sections down the right
+    fn show_settings_panel(&mut self, ctx: &Context) {
+        if !self.show_settings_panel {
+            return;
+        }
+        egui::SidePanel::right("settings_panel").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if !self.profiles.is_empty() || self.profiles_error.is_some() {
+                    egui::CollapsingHeader::new("Profile")
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            self.show_profile_settings(ui);
+                        });
+                }
+                egui::CollapsingHeader::new("Mutation")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let mut smart_mutations = self.smart_mutations;
+                        if ui
+                            .checkbox(&mut smart_mutations, "Smart mutations")
+                            .on_hover_text(
+                                "Also mutate whole instructions: registers, operations, \
+                                 jump targets and immediate values",
+                            )
+                            .changed()
+                        {
+                            self.set_smart_mutations(smart_mutations);
+                        }
+                        ui.checkbox(&mut self.recombine_parents, "Recombine parents")
+                            .on_hover_text(
+                                "When several instances are selected, build half of the \
+                                 children from pieces of all of them",
+                            );
+                        if ui
+                            .checkbox(&mut self.evolve_physics, "Evolve physics")
+                            .on_hover_text(
+                                "Also mutate how each program's output becomes sound: how \
+                                 much slower it plays, how many channels it has, and how \
+                                 many instructions it runs per sample",
+                            )
+                            .changed()
+                        {
+                            self.set_smart_mutations(self.smart_mutations);
+                        }
+                        ui.checkbox(&mut self.isa.dsp, "DSP instructions")
+                            .on_hover_text(
+                                "Give new programs, whether random, from a template, pasted \
+                                 or assembled, instructions for sines, exponential decays, \
+                                 fixed point multiplication and one-pole filters. Other \
+                                 programs keep the instructions they were made with.",
+                            );
+                        ui.label("Constraints").on_hover_text(
+                            "Instructions or operations which children must contain, \
+                             separated by spaces. Prefix one with ! to forbid it, \
+                             like \"jmp !outputw\".",
+                        );
+                        if ui
+                            .text_edit_singleline(&mut self.constraints_text)
+                            .changed()
+                        {
+                            match self.constraints_text.parse() {
+                                Ok(constraints) => {
+                                    self.constraints = constraints;
+                                    self.constraints_error = None;
+                                }
+                                Err(e) => self.constraints_error = Some(e),
+                            }
+                        }
+                        if let Some(e) = &self.constraints_error {
+                            ui.colored_label(Color32::RED, e);
+                        }
+                    });
+                egui::CollapsingHeader::new("Fitness").show(ui, |ui| {
+                    self.show_fitness_settings(ui);
+                });
+                egui::CollapsingHeader::new("Plugins").show(ui, |ui| {
+                    self.show_plugin_settings(ui);
+                });
+                egui::CollapsingHeader::new("Auto-evolve").show(ui, |ui| {
+                    self.show_auto_evolve_controls(ui);
+                });
+                egui::CollapsingHeader::new("Layout")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.show_layout_settings(ui);
+                        ui.checkbox(&mut self.show_map, "Map");
+                    });
+                egui::CollapsingHeader::new("Audio").show(ui, |ui| {
+                    ui.checkbox(
+                        &mut self.audio_queue.normalise_loudness,
+                        "Normalise loudness",
+                    )
+                    .on_hover_text("Play every instance at a similar loudness");
+                    if self.eval_config.output_mode == OutputMode::Audio {
+                        ui.checkbox(
+                            &mut self.audio_queue.speaker_protection,
+                            "Speaker protection",
+                        )
+                        .on_hover_text(
+                            "Remove DC offset and soften harsh high frequencies when \
+                             playing. Exports are left as they are.",
+                        );
+                        ui.separator();
+                        self.show_routing_settings(ui);
+                    }
+                    ui.separator();
+                    self.show_audio_settings(ui);
+                    #[cfg(feature = "midi")]
+                    {
+                        ui.separator();
+                        self.show_performance_controls(ui);
+                    }
+                });
+                egui::CollapsingHeader::new("Appearance").show(ui, |ui| {
+                    self.show_appearance_settings(ui);
+                });
+                egui::CollapsingHeader::new("Sample pack").show(ui, |ui| {
+                    self.show_sample_pack_controls(ui);
+                });
+                egui::CollapsingHeader::new("Piece").show(ui, |ui| {
+                    self.show_piece_controls(ui);
+                });
+                egui::CollapsingHeader::new("Statistics").show(ui, |ui| {
+                    self.show_render_timings(ui);
+                    let mut log_events = self.event_log.is_some();
+                    if ui
+                        .checkbox(&mut log_events, "Log events")
+                        .on_hover_text(
+                            "Write each generation, its parents and how they were \
+                             judged, and every export to lemurs_events_<n>.jsonl, \
+                             for analysing afterwards",
+                        )
+                        .changed()
+                    {
+                        if log_events {
+                            self.start_event_log();
+                        } else {
+                            self.event_log = None;
+                        }
+                    }
+                });
+                egui::CollapsingHeader::new("Windows")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        if ui.button("Shortcuts").clicked() {
+                            self.show_shortcuts = !self.show_shortcuts;
+                        }
+                        let label = format!("Tape ({})", self.tape.segments().len());
+                        if ui.button(label).clicked() {
+                            self.show_tape = !self.show_tape;
+                        }
+                        if self.corpus.is_some() && ui.button("Corpus").clicked() {
+                            self.show_corpus = !self.show_corpus;
+                        }
+                        if self.sharing.is_some() {
+                            let label = format!("Inbox ({})", self.inbox.len());
+                            if ui.button(label).clicked() {
+                                self.show_inbox = !self.show_inbox;
+                            }
+                        }
+                    });
+            });
+        });
+    }
+
+    /// Binds the next key pressed while rebinding, and keeps it from doing anything else
+    fn capture_rebinding(&mut self, ctx: &Context) {
+        let Some(action) = self.rebinding else {
+            return;
+        };
+        let pressed = ctx.input(|i| {
+            i.events.iter().find_map(|e| match e {
+                egui::Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } if Shortcut::is_bindable(*key) => Some(Shortcut::new(*modifiers, *key)),
+                _ => None,
+            })
+        });
+        if let Some(shortcut) = pressed {
+            ctx.input_mut(|i| i.consume_key(shortcut.modifiers, shortcut.key));
+            self.shortcuts.bind(action, Some(shortcut));
+            self.rebinding = None;
+        }
+    }
+
+    fn show_shortcuts(&mut self, ctx: &Context) {
+        if !self.show_shortcuts {
+            return;
+        }
+        let mut open = true;
+        egui::Window::new("Shortcuts")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("shortcuts").striped(true).show(ui, |ui| {
+                    for action in Action::ALL {
+                        ui.label(action.name());
+                        let text = if self.rebinding == Some(action) {
+                            "Press a key...".to_string()
+                        } else {
+                            self.shortcuts
+                                .shortcut(action)
+                                .map_or("-".to_string(), |s| s.to_string())
+                        };
+                        if ui.button(text).clicked() {
+                            self.rebinding = Some(action);
+                        }
+                        if ui.small_button("Clear").clicked() {
+                            self.shortcuts.bind(action, None);
+                            self.rebinding = None;
+                        }
+                        ui.end_row();
+                    }
+                });
+                if ui.button("Reset to defaults").clicked() {
+                    self.shortcuts = KeyBindings::default();
+                    self.rebinding = None;
+                }
+            });
+        if !open {
+            self.show_shortcuts = false;
+            self.rebinding = None;
+        }
+    }
+
+    fn show_corpus(&mut self, ctx: &Context) {
+        let Some(corpus) = &self.corpus else {
+            return;
+        };
+        if !self.show_corpus {
+            return;
+        }
+        let mut open = true;
+        let mut added = None;
+        egui::Window::new("Corpus")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.corpus_query)
+                        .hint_text("Search tags, file names and hashes"),
+                );
+                ui.separator();
+                let entries = corpus.search(&self.corpus_query);
+                if entries.is_empty() {
+                    ui.label("No programs found");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for entry in entries {
+                        ui.horizontal(|ui| {
+                            ui.monospace(entry.hash.to_string());
+                            ui.label(format!(
+                                "{}, {} bytes, generation {}",
+                                format_date(entry.added),
+                                entry.length,
+                                entry.provenance.generation
+                            ));
+                            if !entry.tags.is_empty() {
+                                ui.label(entry.tags.join(" "));
+                            }
+                            if ui.button("Add to population").clicked() {
+                                added = Some(entry.clone());
+                            }
+                        })
+                        .response
+                        .on_hover_text(&entry.file);
+                    }
+                });
+            });
+        self.show_corpus = open;
+        if let Some(entry) = added {
+            self.add_from_corpus(entry);
+        }
+    }
+
+    fn add_from_corpus(&mut self, entry: CorpusEntry) {
+        let Some(corpus) = &self.corpus else {
+            return;
+        };
+        match corpus.load(&entry) {
+            Ok(program) => {
+                let mut instance = self.render(program);
+                instance.generation = entry.provenance.generation;
+                instance.tags = entry.tags;
+                self.population.push(instance);
+            }
+            Err(e) => self.report_error(format!("Failed to load {}: {}", entry.file, e)),
+        }
+    }
+
+    /// The corpus entries whose timbre is closest to `features`, leaving out
+    /// the program with `hash` itself. Features of programs not seen before are
+    /// computed first, which can take a while for a large corpus.
+    fn find_similar_in_corpus(
+        &mut self,
+        features: &Features,
+        hash: ProgramHash,
+    ) -> Vec<(CorpusEntry, f32)> {
+        let Some(corpus) = &self.corpus else {
+            return Vec::new();
+        };
+        let mut missing: Vec<(ProgramHash, Program)> = Vec::new();
+        for entry in corpus.entries() {
+            if self.corpus_features.contains_key(&entry.hash)
+                || missing.iter().any(|(h, _)| *h == entry.hash)
+            {
+                continue;
+            }
+            match corpus.load(entry) {
+                Ok(program) => missing.push((entry.hash, program)),
+                Err(e) => warn!("Skipping {}: {}", entry.file, e),
+            }
+        }
+        if !missing.is_empty() {
+            info!("Analysing {} corpus programs", missing.len());
+            let computed = self.threadpool.map_balanced(missing, |(hash, program)| {
+                let evaluation =
+                    evaluate_and_analyse(&program, &self.eval_config, &self.spectrogram_renderer);
+                (hash, evaluation.features.unwrap())
+            });
+            self.corpus_features.extend(computed);
+        }
+
+        let mut matches: Vec<(CorpusEntry, f32)> = corpus
+            .entries()
+            .iter()
+            .filter(|e| e.hash != hash)
+            .filter_map(|e| {
+                let f = self.corpus_features.get(&e.hash)?;
+                Some((e.clone(), features.timbre_distance(f)))
+            })
+            .collect();
+        matches.sort_by(|a, b| a.1.total_cmp(&b.1));
+        matches.dedup_by(|a, b| a.0.hash == b.0.hash);
+        matches.truncate(NUM_SIMILAR);
+        matches
+    }
+
+    fn show_similar(&mut self, ctx: &Context) {
+        let Some((reference, matches)) = &self.similar else {
+            return;
+        };
+        let mut open = true;
+        let mut added = None;
+        egui::Window::new(format!("Similar to {} in the corpus", reference))
+            .id(egui::Id::new("similar_in_corpus"))
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                if matches.is_empty() {
+                    ui.label("The corpus has no other programs");
+                }
+                for (entry, distance) in matches {
+                    ui.horizontal(|ui| {
+                        ui.monospace(entry.hash.to_string());
+                        ui.label(format!("distance {:.2}", distance));
+                        if !entry.tags.is_empty() {
+                            ui.label(entry.tags.join(" "));
+                        }
+                        if ui.button("Add to population").clicked() {
+                            added = Some(entry.clone());
+                        }
+                    })
+                    .response
+                    .on_hover_text(&entry.file);
+                }
+            });
+        if !open {
+            self.similar = None;
+        }
+        if let Some(entry) = added {
+            self.add_from_corpus(entry);
+        }
+    }
+
+    fn receive_shared_programs(&mut self) {
+        let Some(sharing) = &self.sharing else {
+            return;
+        };
+        while let Some(shared) = sharing.try_recv() {
+            if self.inbox.len() >= MAX_INBOX_LENGTH {
+                warn!("Inbox is full, dropping a program from {}", shared.from);
+                continue;
+            }
+            self.inbox.push(shared);
+            self.show_inbox = true;
+        }
+        if let Some(e) = sharing.take_error() {
+            self.report_error(e);
+        }
+    }
+
+    fn show_tape(&mut self, ctx: &Context) {
+        if !self.show_tape {
+            return;
+        }
+        let bytes_per_second = self.eval_config.bytes_per_second() as f32;
+        // segments are cut at whole frames, so channels stay in place
+        let frame_len = match self.eval_config.output_mode {
+            OutputMode::Audio => AUDIO_CHANNELS,
+            OutputMode::Midi => 1,
+        };
+        let export_formats: &[(&str, ExportFormat)] = match self.eval_config.output_mode {
+            OutputMode::Audio => &[
+                ("Export WAV", ExportFormat::Wav),
+                ("Export FLAC", ExportFormat::Flac),
+                #[cfg(feature = "ogg")]
+                ("Export Ogg Vorbis", ExportFormat::Ogg),
+            ],
+            OutputMode::Midi => &[("Export MIDI file", ExportFormat::Midi)],
+        };
+        let mut open = true;
+        let mut play = None;
+        let mut export = None;
+        let mut removed = None;
+        let mut moved_down = None;
+        egui::Window::new("Tape")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{:.1} s",
+                        self.tape.len() as f32 / bytes_per_second
+                    ));
+                    ui.add_enabled_ui(!self.tape.is_empty(), |ui| {
+                        if ui.button("Play").clicked() {
+                            play = Some(self.tape.render());
+                        }
+                        if ui.button("Stop").clicked() {
+                            self.audio_queue.stop();
+                        }
+                        for (label, format) in export_formats {
+                            if ui.button(*label).clicked() {
+                                export = Some(*format);
+                            }
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.tape.clear();
+                        }
+                    });
+                });
+                ui.separator();
+                if self.tape.is_empty() {
+                    ui.label("Append instances from their menus, or with the shortcut");
+                }
+                let num_segments = self.tape.segments().len();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (i, segment) in self.tape.segments_mut().iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(&segment.label);
+                            let duration = segment.output.len() as f32 / bytes_per_second;
+                            let mut start = segment.start as f32 / bytes_per_second;
+                            let mut end = segment.end as f32 / bytes_per_second;
+                            ui.add(
+                                egui::DragValue::new(&mut start)
+                                    .clamp_range(0.0..=end)
+                                    .speed(0.01)
+                                    .prefix("from ")
+                                    .suffix(" s"),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut end)
+                                    .clamp_range(start..=duration)
+                                    .speed(0.01)
+                                    .prefix("to ")
+                                    .suffix(" s"),
+                            );
+                            let to_bytes = |secs: f32| {
+                                ((secs * bytes_per_second) as usize / frame_len * frame_len)
+                                    .min(segment.output.len())
+                            };
+                            segment.start = to_bytes(start);
+                            segment.end = to_bytes(end).max(segment.start);
+                            if ui
+                                .small_button("▶")
+                                .on_hover_text("Play this segment")
+                                .clicked()
+                            {
+                                play = Some(segment.bytes().to_vec());
+                            }
+                            if i > 0 && ui.small_button("⏶").on_hover_text("Move up").clicked() {
+                                moved_down = Some(i - 1);
+                            }
+                            if i + 1 < num_segments
+                                && ui.small_button("⏷").on_hover_text("Move down").clicked()
+                            {
+                                moved_down = Some(i);
+                            }
+                            if ui.small_button("×").on_hover_text("Remove").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                });
+            });
+        self.show_tape = open;
+        if let Some(i) = moved_down {
+            self.tape.swap_with_next(i);
+        }
+        if let Some(i) = removed {
+            self.tape.remove(i);
+        }
+        if let Some(bytes) = play {
+            if !bytes.is_empty() {
+                self.audio_queue.play(None, &Arc::from(bytes), 1.0);
+            }
+        }
+        if let Some(format) = export {
+            let bytes = self.tape.render();
+            self.export_output("lemurs_tape", &bytes, format, Vec::new());
+        }
+    }
+
+    fn show_inbox(&mut self, ctx: &Context) {
+        if self.sharing.is_none() || !self.show_inbox {
+            return;
+        }
+        let mut open = true;
+        let mut added = None;
+        let mut discarded = None;
+        let mut removed_peer = None;
+        egui::Window::new("Inbox")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.label("Send to");
+                for (i, peer) in self.peers.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.monospace(peer);
+                        if ui.small_button("×").on_hover_text("Remove peer").clicked() {
+                            removed_peer = Some(i);
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    let r = ui.add(
+                        egui::TextEdit::singleline(&mut self.new_peer_text).hint_text("host:port"),
+                    );
+                    let entered = r.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if (ui.button("Add peer").clicked() || entered)
+                        && !self.new_peer_text.trim().is_empty()
+                    {
+                        self.peers.push(self.new_peer_text.trim().to_string());
+                        self.new_peer_text.clear();
+                    }
+                });
+                ui.separator();
+                if self.inbox.is_empty() {
+                    ui.label("Nothing received yet");
+                }
+                for (i, shared) in self.inbox.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "{} bytes from {}, generation {}",
+                            shared.program.len(),
+                            shared.from,
+                            shared.provenance.generation
+                        ));
+                        if ui.button("Add to population").clicked() {
+                            added = Some(i);
+                        }
+                        if ui.button("Discard").clicked() {
+                            discarded = Some(i);
+                        }
+                    });
+                }
+            });
+        self.show_inbox = open;
+        if let Some(i) = removed_peer {
+            self.peers.remove(i);
+        }
+        if let Some(i) = discarded {
+            self.inbox.remove(i);
+        }
+        if let Some(i) = added {
+            let shared = self.inbox.remove(i);
+            let instance = self.render(shared.program);
+            self.population.push(instance);
+        }
+    }
+
+    /// Carries out every OSC message received since the last frame
+    fn handle_osc(&mut self) {
+        while let Some(message) = self.osc_server.as_ref().and_then(|s| s.try_recv()) {
+            self.handle_osc_message(message);
+        }
+    }
+
+    fn handle_osc_message(&mut self, message: OscMessage) {
+        let num_instances = self.population.len();
+        let index = message
+            .int(0)
+            .filter(|i| (0..num_instances as i64).contains(i))
+            .map(|i| i as usize);
+        let instance_action = match message.address.as_str() {
+            "/lemurs/mutate" => {
+                self.mutate();
+                return;
+            }
+            "/lemurs/save_session" => {
+                self.save_session();
+                return;
+            }
+            "/lemurs/stop" => {
+                self.audio_queue.stop();
+                return;
+            }
+            "/lemurs/mutation_amount" => {
+                if let Some(amount) = message.scaled(0, MUTATION_AMOUNT_RANGE) {
+                    self.mutation_amount = amount;
+                }
+                return;
+            }
+            "/lemurs/population_size" => {
+                if let Some(size) = message.scaled(0, POPULATION_SIZE_RANGE) {
+                    self.desired_population_size = size;
+                    self.resize_population();
+                }
+                return;
+            }
+            "/lemurs/smart_mutations" => {
+                if let Some(on) = message.int(0) {
+                    self.set_smart_mutations(on != 0);
+                }
+                return;
+            }
+            "/lemurs/select" => {
+                // an optional second argument selects or deselects instead of toggling
+                let wanted = message.int(1).map(|s| s != 0);
+                if index.is_some_and(|i| wanted == Some(self.population[i].is_selected)) {
+                    return;
+                }
+                InstanceAction::ToggleSelected
+            }
+            "/lemurs/audition" => InstanceAction::Audition,
+            "/lemurs/save" => InstanceAction::Save,
+            "/lemurs/rate" => {
+                let rating = message.scaled(1, 0..=5).map(|r| r as u8);
+                InstanceAction::Rate(rating.filter(|r| *r > 0))
+            }
+            address => {
+                warn!("Ignoring unknown OSC address {}", address);
+                return;
+            }
+        };
+        match index {
+            Some(i) => self.apply_instance_action(i, instance_action),
+            None => warn!(
+                "Ignoring {}: the first argument must be an instance index below {}",
+                message.address, num_instances
+            ),
+        }
+    }
+
+    /// Replaces the mutation operators used for new children
+    pub fn set_mutations(&mut self, mutations: MutationRegistry) {
+        self.mutations = mutations;
+    }
+
+    fn render(&mut self, program: Program) -> Instance {
+        self.render_programs(vec![program]).0.pop().unwrap()
+    }
+
+    fn make_child(
+        &mut self,
+        parent: &Arc<Lineage>,
+        parent_byte_ages: &[u32],
+        mutation_amount: usize,
+    ) -> Instance {
+        let (p, operators) = self.mutated(&parent.program, mutation_amount);
+        let mut child = self.render(p);
+        child.operators = operators;
+        child.generation = parent.generation + 1;
+        child.lineage = Some(Arc::clone(parent));
+        child.inherit_byte_ages(parent.program.bytes(), parent_byte_ages);
+        child
+    }
+
+    fn show_child_preview(&mut self, ctx: &Context) {
+        let channels = self.spectrogram_channels();
+        let Some(preview) = &mut self.child_preview else {
+            return;
+        };
+        let mut open = true;
+        let mut play_clicked = false;
+        let mut another_clicked = false;
+        let mut keep_clicked = false;
+        egui::Window::new("Child preview")
+            .open(&mut open)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Generation {}, {} mutation(s) from its parent",
+                    preview.child.generation, preview.mutation_amount
+                ));
+                ui.horizontal(|ui| {
+                    play_clicked = ui.button("Play").clicked();
+                    another_clicked = ui.button("Another").clicked();
+                    keep_clicked = ui
+                        .button("Keep")
+                        .on_hover_text("Add the child to the population")
+                        .clicked();
+                });
+                let child = &mut preview.child;
+                let texture: &TextureHandle = child.spectrogram_texture.get_or_insert_with(|| {
+                    ui.ctx().load_texture(
+                        "texture",
+                        child.spectrogram_image.clone(),
+                        Default::default(),
+                    )
+                });
+                let rect = ui
+                    .image(texture.id(), egui::vec2(ui.available_width(), 128.0))
+                    .rect;
+                paint_channel_dividers(ui.painter(), rect, channels);
+                paint_pitch_contour(ui.painter(), rect, &child.pitch_track);
+                let (rect, _) = ui.allocate_exact_size(
+                    egui::vec2(ui.available_width(), ONSET_TRACK_HEIGHT),
+                    egui::Sense::hover(),
+                );
+                paint_onset_track(ui.painter(), rect, &child.onset_strength);
+                let (rect, _) = ui.allocate_exact_size(
+                    egui::vec2(ui.available_width(), HEATMAP_HEIGHT),
+                    egui::Sense::hover(),
+                );
+                paint_byte_heatmap(ui.painter(), rect, &child.byte_ages);
+            });
+
+        if play_clicked {
+            self.audio_queue
+                .play(None, &preview.child.output, preview.child.playback_gain);
+        }
+        if another_clicked {
+            let mut preview = self.child_preview.take().unwrap();
+            preview.child = self.make_child(
+                &preview.parent,
+                &preview.parent_byte_ages,
+                preview.mutation_amount,
+            );
+            self.audio_queue
+                .play(None, &preview.child.output, preview.child.playback_gain);
+            self.child_preview = Some(preview);
+        } else if keep_clicked {
+            let mut child = self.child_preview.take().unwrap().child;
+            child.spectrogram_texture = None;
+            self.population.push(child);
+        } else if !open {
+            self.child_preview = None;
+            self.audio_queue.stop();
+        }
+    }
+
+    fn show_detail(&mut self, ui: &mut egui::Ui, index: usize) {
+        let channels = self.spectrogram_channels();
+        let instance = &mut self.population[index];
+
+        let mut back = self.shortcuts.pressed(ui.ctx(), Action::Back);
+        let mut edit = false;
+        let mut debug = false;
+        let mut watch_memory = false;
+        ui.horizontal(|ui| {
+            if ui.button("Back").clicked() {
+                back = true;
+            }
+            ui.separator();
+            ui.heading(format!(
+                "Instance #{} (generation {})",
+                index, instance.generation
+            ));
+            if instance.timed_out {
+                ui.colored_label(Color32::LIGHT_RED, "⏱ timed out")
+                    .on_hover_text(format!(
+                        "Ran past the {:.1} s time budget, so the rest of its output is silent",
+                        self.eval_config.time_budget.as_secs_f32()
+                    ));
+            }
+            ui.label(format!("⚙ {}", instance.program.physics()))
+                .on_hover_text(
+                    "How the program's output becomes sound: its clock divider, channels, and \
+                 instructions run per sample",
+                );
+            ui.separator();
+            if ui.button("Play").clicked() {
+                let frame_len = AUDIO_CHANNELS;
+                let offset = (self.detail_start_seconds as f64
+                    * self.eval_config.bytes_per_second() as f64)
+                    as usize
+                    / frame_len
+                    * frame_len;
+                let offset = offset.min(instance.output.len() - 1);
+                self.audio_queue.play_from(
+                    Some(index),
+                    &instance.output,
+                    offset,
+                    instance.playback_gain,
+                );
+            }
+            if ui.button("Stop").clicked() {
+                self.audio_queue.stop();
+            }
+            ui.checkbox(&mut self.audio_queue.looping, "Loop");
+            if ui.button("Edit assembly").clicked() {
+                edit = true;
+            }
+            if ui
+                .button("Debug")
+                .on_hover_text("Step through the program, forwards and backwards")
+                .clicked()
+            {
+                debug = true;
+            }
+            if ui
+                .button("Memory")
+                .on_hover_text("Watch the program use its memory while it plays")
+                .clicked()
+            {
+                watch_memory = true;
+            }
+            let duration =
+                instance.output.len() as f32 / self.eval_config.bytes_per_second() as f32;
+            ui.label("Start");
+            ui.add(egui::Slider::new(&mut self.detail_start_seconds, 0.0..=duration).suffix(" s"));
+        });
+
+        let width = ui.available_width();
+        let height = ui.available_height();
+
+        let texture: &TextureHandle = instance.spectrogram_texture.get_or_insert_with(|| {
+            ui.ctx().load_texture(
+                "texture",
+                instance.spectrogram_image.clone(),
+                Default::default(),
+            )
+        });
+        let rect = ui.image(texture.id(), egui::vec2(width, height * 0.4)).rect;
+        paint_channel_dividers(ui.painter(), rect, channels);
+        paint_pitch_contour(ui.painter(), rect, &instance.pitch_track);
+        if let Some(playhead) = self.audio_queue.playhead(index) {
+            let x = rect.left() + rect.width() * playhead as f32 / instance.output.len() as f32;
+            ui.painter()
+                .vline(x, rect.y_range(), egui::Stroke::new(1.0, Color32::WHITE));
+            ui.ctx().request_repaint();
+        }
+
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(width, ONSET_TRACK_HEIGHT * 3.0),
+            egui::Sense::hover(),
+        );
+        paint_onset_track(ui.painter(), rect, &instance.onset_strength);
+
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(width, HEATMAP_HEIGHT * 2.0),
+            egui::Sense::hover(),
+        );
+        paint_byte_heatmap(ui.painter(), rect, &instance.byte_ages);
+
+        let (rect, _) =
+            ui.allocate_exact_size(egui::vec2(width, height * 0.15), egui::Sense::hover());
+        paint_waveform(ui.painter(), rect, &instance.output);
+
+        ui.horizontal(|ui| {
+            ui.label("Registers").on_hover_text(
+                "Plot registers over the course of the output, each scaled to its own range",
+            );
+            for register in 0..16 {
+                let mut shown = self.timeline_registers.contains(&register);
+                let mut text = egui::RichText::new(format!("r{}", register));
+                if shown {
+                    text = text.color(trace_colour(register));
+                }
+                if ui.toggle_value(&mut shown, text).changed() {
+                    self.timeline_registers.retain(|r| *r != register);
+                    if shown {
+                        self.timeline_registers.push(register);
+                        self.timeline_registers.sort();
+                    }
+                }
+            }
+        });
+        if !self.timeline_registers.is_empty() {
+            let stale = !matches!(
+                &self.register_timeline,
+                Some(t) if t.program == instance.program && t.registers == self.timeline_registers
+            );
+            if stale {
+                let registers: Vec<RegId> =
+                    self.timeline_registers.iter().map(|r| RegId(*r)).collect();
+                let values = register_timeline(
+                    &instance.program,
+                    &self.eval_config,
+                    &registers,
+                    TIMELINE_POINTS,
+                );
+                self.register_timeline = Some(RegisterTimeline {
+                    program: instance.program.clone(),
+                    registers: self.timeline_registers.clone(),
+                    values,
+                });
+            }
+            let timeline = self.register_timeline.as_ref().unwrap();
+            let (rect, _) =
+                ui.allocate_exact_size(egui::vec2(width, height * 0.12), egui::Sense::hover());
+            paint_register_timeline(ui.painter(), rect, &timeline.registers, &timeline.values);
+        }
+
+        let mut summary = instance.features.summary().replace('\n', ", ");
+        if let Some(fitness) = instance.fitness {
+            summary += &format!(", fitness {:.3}", fitness);
+        }
+        ui.label(summary);
+
+        ui.label("Notes");
+        ui.add(
+            egui::TextEdit::multiline(&mut instance.note)
+                .hint_text("What's good about this one, what to try next...")
+                .desired_rows(3)
+                .desired_width(width),
+        );
+
+        let mut play_ancestor: Option<Program> = None;
+        ui.columns(3, |columns| {
+            columns[0].label("Disassembly");
+            egui::ScrollArea::vertical()
+                .id_source("detail_disassembly")
+                .show(&mut columns[0], |ui| {
+                    ui.monospace(disassemble(
+                        instance.program.bytes(),
+                        instance.program.features(),
+                    ));
+                });
+
+            columns[1].label(format!("Program ({} bytes)", instance.program.len()));
+            egui::ScrollArea::vertical()
+                .id_source("detail_hex")
+                .show(&mut columns[1], |ui| {
+                    let hex: Vec<String> = instance
+                        .program
+                        .bytes()
+                        .chunks(16)
+                        .enumerate()
+                        .map(|(i, c)| {
+                            let hex: String = c.iter().map(|b| format!("{:02x}", b)).collect();
+                            format!("{:04x}  {}", i * 16, hex)
+                        })
+                        .collect();
+                    ui.monospace(hex.join("\n"));
+                });
+
+            columns[2].label("Lineage");
+            egui::ScrollArea::vertical()
+                .id_source("detail_lineage")
+                .show(&mut columns[2], |ui| {
+                    let mut any = false;
+                    for ancestor in instance.ancestors() {
+                        any = true;
+                        ui.horizontal(|ui| {
+                            if ui
+                                .small_button("▶")
+                                .on_hover_text("Play this ancestor")
+                                .clicked()
+                            {
+                                play_ancestor = Some(ancestor.program.clone());
+                            }
+                            let mut label = format!(
+                                "generation {}: {} bytes",
+                                ancestor.generation,
+                                ancestor.program.len()
+                            );
+                            if ancestor.program.physics() != instance.program.physics() {
+                                label += &format!(", {}", ancestor.program.physics());
+                            }
+                            ui.label(label);
+                        });
+                    }
+                    if !any {
+                        ui.label("No recorded ancestors");
+                    }
+                });
+        });
+
+        if let Some(program) = play_ancestor {
+            let rendering = self.rendering(&program);
+            self.audio_queue
+                .play(None, &rendering.output, rendering.playback_gain);
+        }
+        if edit {
+            self.apply_instance_action(index, InstanceAction::EditAssembly);
+        }
+        if debug {
+            self.debugger = Some(Debugger::new(
+                index,
+                self.population[index].program.bytes(),
+                self.population[index].program.features(),
+            ));
+        }
+        if watch_memory {
+            let instance = &self.population[index];
+            let watch = MemoryWatch::new(
+                instance.program.bytes().to_vec(),
+                self.eval_config.isa(&instance.program),
+                self.eval_config.physics(&instance.program),
+            );
+            self.memory_watch = Some((index, watch));
+        }
+        if back {
+            self.detail_index = None;
+            self.population[index].spectrogram_texture = None;
+            self.audio_queue.stop();
+        }
+    }
+
+    fn show_diff_view(&mut self, ctx: &Context) {
+        let Some(view) = &mut self.diff_view else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new(format!("Diff #{} to #{}", view.a, view.b))
+            .id(egui::Id::new("diff_view"))
+            .open(&mut open)
+            .default_size(egui::vec2(700.0, 600.0))
+            .show(ctx, |ui| {
+                let texture: &TextureHandle = view.difference_texture.get_or_insert_with(|| {
+                    ui.ctx().load_texture(
+                        "texture",
+                        view.difference_image.clone(),
+                        Default::default(),
+                    )
+                });
+                ui.label("Spectrogram difference");
+                ui.image(texture.id(), egui::vec2(ui.available_width(), 128.0));
+
+                let count =
+                    |f: fn(&Change) -> bool| view.byte_changes.iter().filter(|c| f(c)).count();
+                ui.label(format!(
+                    "{} bytes unchanged, {} removed, {} added",
+                    count(|c| matches!(c, Change::Same(..))),
+                    count(|c| matches!(c, Change::Removed(..))),
+                    count(|c| matches!(c, Change::Added(..))),
+                ));
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::CollapsingHeader::new("Instructions")
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            for change in &view.line_changes {
+                                let (text, colour) = match *change {
+                                    Change::Same(i, _) => {
+                                        let (offset, line) = &view.lines_a[i];
+                                        (format!("  {:>5}  {}", offset, line), Color32::GRAY)
+                                    }
+                                    Change::Removed(i) => {
+                                        let (offset, line) = &view.lines_a[i];
+                                        (format!("- {:>5}  {}", offset, line), Color32::LIGHT_RED)
+                                    }
+                                    Change::Added(i) => {
+                                        let (offset, line) = &view.lines_b[i];
+                                        (format!("+ {:>5}  {}", offset, line), Color32::LIGHT_GREEN)
+                                    }
+                                };
+                                ui.label(egui::RichText::new(text).monospace().color(colour));
+                            }
+                        });
+                    egui::CollapsingHeader::new("Bytes").show(ui, |ui| {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.spacing_mut().item_spacing.x = 4.0;
+                            for change in &view.byte_changes {
+                                let text = match *change {
+                                    Change::Same(i, _) => {
+                                        egui::RichText::new(format!("{:02x}", view.program_a[i]))
+                                            .color(Color32::GRAY)
+                                    }
+                                    Change::Removed(i) => {
+                                        egui::RichText::new(format!("{:02x}", view.program_a[i]))
+                                            .color(Color32::LIGHT_RED)
+                                            .strikethrough()
+                                    }
+                                    Change::Added(i) => {
+                                        egui::RichText::new(format!("{:02x}", view.program_b[i]))
+                                            .color(Color32::LIGHT_GREEN)
+                                    }
+                                };
+                                ui.label(text.monospace());
+                            }
+                        });
+                    });
+                });
+            });
+        if !open {
+            self.diff_view = None;
+        }
+    }
+
+    fn show_asm_editor(&mut self, ctx: &Context) {
+        let Some(editor) = &mut self.asm_editor else {
+            return;
+        };
+        let mut open = true;
+        let mut assemble_clicked = false;
+        let mut write_back_clicked = false;
+        let title = match editor.index {
+            Some(index) => format!("Assembly editor: #{}", index),
+            None => "Assembly editor".to_string(),
+        };
+        egui::Window::new(title)
+            .id(egui::Id::new("asm_editor"))
+            .open(&mut open)
+            .default_size(egui::vec2(500.0, 600.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    assemble_clicked = ui.button("Assemble and play").clicked();
+                    let can_write_back = editor.preview.is_some() && editor.index.is_some();
+                    write_back_clicked = ui
+                        .add_enabled(can_write_back, egui::Button::new("Write back"))
+                        .on_disabled_hover_text(
+                            "Assemble first. Only possible while the original instance is still in the population.",
+                        )
+                        .clicked();
+                });
+                if let Some(error) = &editor.error {
+                    ui.colored_label(Color32::RED, error);
+                }
+                if let Some(preview) = &mut editor.preview {
+                    let texture: &TextureHandle =
+                        preview.spectrogram_texture.get_or_insert_with(|| {
+                            ui.ctx().load_texture(
+                                "texture",
+                                preview.spectrogram_image.clone(),
+                                Default::default(),
+                            )
+                        });
+                    ui.image(texture.id(), egui::vec2(ui.available_width(), 96.0));
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut editor.text)
+                            .code_editor()
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+            });
+
+        if assemble_clicked {
+            match assemble(&editor.text) {
+                Ok(program) if program.is_empty() => {
+                    editor.error = Some("The program is empty".to_string());
+                }
+                Ok(program) => {
+                    // new instructions may be written in, so it gets the ones new programs do too
+                    let program = Program::new(program)
+                        .unwrap()
+                        .with_features(editor.features.union(self.isa))
+                        .with_physics(editor.physics);
+                    let preview = self.render(program);
+                    self.audio_queue
+                        .play(None, &preview.output, preview.playback_gain);
+                    let editor = self.asm_editor.as_mut().unwrap();
+                    editor.preview = Some(preview);
+                    editor.error = None;
+                }
+                Err(e) => editor.error = Some(e.to_string()),
+            }
+        }
+        let editor = self.asm_editor.as_mut().unwrap();
+        if write_back_clicked {
+            if let (Some(index), Some(mut preview)) = (editor.index, editor.preview.take()) {
+                let original = &self.population[index];
+                preview.generation = original.generation;
+                preview.lineage = Some(original.as_ancestor());
+                preview.inherit_byte_ages(original.program.bytes(), &original.byte_ages);
+                preview.is_selected = original.is_selected;
+                self.population[index] = preview;
+                self.audio_queue.current_index = None;
+            }
+        }
+        if !open {
+            self.asm_editor = None;
+        }
+    }
+}
+
+/// Writes tags and other annotations to a text file next to a saved program,
+/// e.g. lemurs_instance_123.txt next to lemurs_instance_123.lprog.
+/// The note, if any, follows the header lines after a blank line.
+/// Written as metadata.json alongside the samples of an exported sample pack
+#[derive(Serialize)]
+struct SamplePackMetadata {
+    sample_rate: usize,
+    channels: usize,
+    seconds: f32,
+    samples: Vec<SamplePackSample>,
+}
+
+#[derive(Serialize)]
+struct SamplePackSample {
+    file: String,
+    hash: String,
+    // base64, as shared programs are
+    program: String,
+    // the instructions it ran with and how its output became the sample
+    features: IsaFeatures,
+    physics: Physics,
+    generation: usize,
+    rating: Option<u8>,
+    tags: Vec<String>,
+    note: String,
+    // whether the program ran out of time before filling the sample
+    timed_out: bool,
+}
+
+/// Numbers samples so that they sort in the order they were exported, and
+/// names them after their first tag, if they have one
+fn sample_file_name(number: usize, tags: &[String], hash: ProgramHash) -> String {
+    let tag: String = tags
+        .first()
+        .map(|t| {
+            t.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+                .collect()
+        })
+        .unwrap_or_default();
+    if tag.is_empty() {
+        format!("{:02}_{}.wav", number, hash)
+    } else {
+        format!("{:02}_{}_{}.wav", number, tag, hash)
+    }
+}
+
+fn write_instance_metadata(program_filename: &str, instance: &Instance) -> io::Result<()> {
+    if instance.tags.is_empty() && instance.rating.is_none() && instance.note.is_empty() {
+        return Ok(());
+    }
+    let path = Path::new(program_filename).with_extension("txt");
+    let mut text = String::new();
+    if !instance.tags.is_empty() {
+        text += &format!("tags: {}\n", instance.tags.join(" "));
+    }
+    if let Some(rating) = instance.rating {
+        text += &format!("rating: {}\n", rating);
+    }
+    if !instance.note.is_empty() {
+        text += "\n";
+        text += &instance.note;
+        if !instance.note.ends_with('\n') {
+            text += "\n";
+        }
+    }
+    storage::save_file(&path.to_string_lossy(), text.as_bytes())?;
+    info!("Saved metadata to {}", path.display());
+    Ok(())
+}
+
+/// Restores annotations written by `write_instance_metadata`, if there are any
+fn read_instance_metadata(program_path: &Path, instance: &mut Instance) {
+    let Ok(text) = fs::read_to_string(program_path.with_extension("txt")) else {
+        return;
+    };
+    let mut lines = text.lines();
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        if let Some(tags) = line.strip_prefix("tags: ") {
+            instance.tags = tags.split_whitespace().map(|t| t.to_string()).collect();
+        } else if let Some(rating) = line.strip_prefix("rating: ") {
+            instance.rating = rating.trim().parse().ok();
+        }
+    }
+    instance.note = lines.collect::<Vec<_>>().join("\n");
+}
+
+/// The memory as a grid of bytes, dimly showing their values, lit up where
+/// they were recently used
+fn memory_image(watch: &MemoryWatch) -> ColorImage {
+    let memory = watch.memory();
+    let width = ((memory.len() as f64).sqrt().ceil() as usize).clamp(16, 256);
+    let height = memory.len().div_ceil(width);
+    let mut image = ColorImage::new([width, height], Color32::BLACK);
+    for (i, b) in memory.iter().enumerate() {
+        let base = (*b / 4) as f32;
+        let lit = |heat: f32| (base + heat * (255.0 - base)) as u8;
+        image.pixels[i] = Color32::from_rgb(
+            lit(watch.written[i]),
+            lit(watch.read[i]),
+            lit(watch.fetched[i]),
+        );
+    }
+    image
+}
+
+fn paint_byte_heatmap(painter: &egui::Painter, rect: egui::Rect, byte_ages: &[u32]) {
+    painter.rect_filled(rect, egui::Rounding::none(), Color32::BLACK);
+    let byte_width = rect.width() / byte_ages.len().max(1) as f32;
+    for (i, age) in byte_ages.iter().enumerate() {
+        let heat = 1.0 - (*age as f32 / HEATMAP_MAX_AGE as f32);
+        if heat <= 0.0 {
+            continue;
+        }
+        let left = rect.left() + i as f32 * byte_width;
+        painter.rect_filled(
+            egui::Rect::from_min_max(
+                egui::pos2(left, rect.top()),
+                egui::pos2(left + byte_width.max(1.0), rect.bottom()),
+            ),
+            egui::Rounding::none(),
+            Color32::from_rgb((255.0 * heat) as u8, (160.0 * heat * heat) as u8, 0),
+        );
+    }
+}
+
+/// Draws onset strength as a bar per spectrogram column, lined up with the spectrogram above
+fn paint_onset_track(painter: &egui::Painter, rect: egui::Rect, onset_strength: &[f32]) {
+    painter.rect_filled(rect, egui::Rounding::none(), Color32::BLACK);
+    let column_width = rect.width() / onset_strength.len().max(1) as f32;
+    for (i, strength) in onset_strength.iter().enumerate() {
+        if *strength <= 0.0 {
+            continue;
+        }
+        let left = rect.left() + i as f32 * column_width;
+        painter.rect_filled(
+            egui::Rect::from_min_max(
+                egui::pos2(left, rect.bottom() - strength * rect.height()),
+                egui::pos2(left + column_width.max(1.0), rect.bottom()),
+            ),
+            egui::Rounding::none(),
+            Color32::from_rgb(120, 220, 120),
+        );
+    }
+}
+
+/// Separates the stacked spectrograms of each channel in `rect`
+fn paint_channel_dividers(painter: &egui::Painter, rect: egui::Rect, channels: usize) {
+    let stroke = egui::Stroke::new(1.0, Color32::from_gray(96));
+    for c in 1..channels {
+        let y = rect.top() + rect.height() * c as f32 / channels as f32;
+        painter.hline(rect.x_range(), y, stroke);
+    }
+}
+
+/// Draws the pitch track as a line over the spectrogram in `rect`. The spectrogram's
+/// linear frequency axis would squash every audible pitch into its bottom rows, so
+/// the contour gets its own logarithmic axis from 50 Hz at the bottom to 5 kHz at the top.
+fn paint_pitch_contour(painter: &egui::Painter, rect: egui::Rect, pitch_track: &[Option<f32>]) {
+    const LOWEST: f32 = 50.0;
+    const HIGHEST: f32 = 5000.0;
+    let column_width = rect.width() / pitch_track.len().max(1) as f32;
+    let stroke = egui::Stroke::new(1.5, Color32::from_rgb(80, 255, 255));
+    let mut previous: Option<egui::Pos2> = None;
+    for (i, pitch) in pitch_track.iter().enumerate() {
+        let Some(pitch) = pitch else {
+            previous = None;
+            continue;
+        };
+        let t = ((pitch / LOWEST).ln() / (HIGHEST / LOWEST).ln()).clamp(0.0, 1.0);
+        let point = egui::pos2(
+            rect.left() + (i as f32 + 0.5) * column_width,
+            rect.bottom() - t * rect.height(),
+        );
+        match previous {
+            Some(p) => painter.line_segment([p, point], stroke),
+            None => painter.circle_filled(point, 1.0, stroke.color),
+        }
+        previous = Some(point);
+    }
+}
+
+fn paint_waveform(painter: &egui::Painter, rect: egui::Rect, data: &[u8]) {
+    painter.rect_filled(rect, egui::Rounding::none(), Color32::BLACK);
+    let num_columns = rect.width().max(1.0) as usize;
+    let samples_per_column = (data.len() / num_columns).max(1);
+    let stroke = egui::Stroke::new(1.0, Color32::LIGHT_BLUE);
+    for (x, chunk) in data
+        .chunks(samples_per_column)
+        .take(num_columns)
+        .enumerate()
+    {
+        let lo = *chunk.iter().min().unwrap() as f32 / 255.0;
+        let hi = *chunk.iter().max().unwrap() as f32 / 255.0;
+        let px = rect.left() + x as f32;
+        painter.line_segment(
+            [
+                egui::pos2(px, rect.bottom() - lo * rect.height()),
+                egui::pos2(px, rect.bottom() - hi * rect.height()),
+            ],
+            stroke,
+        );
+    }
+}
+
+fn trace_colour(register: u8) -> Color32 {
+    TRACE_COLOURS[register as usize % TRACE_COLOURS.len()]
+}
+
+/// Draws each register's values across `rect`, scaled so that its smallest
+/// value is at the bottom and its largest at the top
+fn paint_register_timeline(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    registers: &[u8],
+    timeline: &[Vec<Value>],
+) {
+    painter.rect_filled(rect, egui::Rounding::none(), Color32::BLACK);
+    for (register, values) in registers.iter().zip(timeline) {
+        let (Some(lo), Some(hi)) = (values.iter().min(), values.iter().max()) else {
+            continue;
+        };
+        let range = (hi - lo).max(1) as f32;
+        let last = (values.len() - 1).max(1) as f32;
+        let points = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                egui::pos2(
+                    rect.left() + rect.width() * i as f32 / last,
+                    rect.bottom() - (v - lo) as f32 / range * rect.height(),
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.0, trace_colour(*register)),
+        ));
+    }
+}
+
+impl App for LemursApp {
+    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
+        let info = frame.info();
+        let window_info = info.window_info;
+        self.window_size = Some(window_info.size.into());
+        self.window_position = window_info.position.map(|p| p.into());
+        self.apply_appearance(
+            ctx,
+            info.system_theme,
+            info.native_pixels_per_point.unwrap_or(1.0),
+        );
+
+        self.texture_uploads_left = TEXTURE_UPLOADS_PER_FRAME;
+        self.capture_rebinding(ctx);
+        self.show_log_panel(ctx);
+        self.show_settings_panel(ctx);
+        self.handle_osc();
+        self.handle_dropped_files(ctx);
+        self.receive_shared_programs();
+        if self.plugins_checked.elapsed() > PLUGIN_CHECK_INTERVAL {
+            self.reload_plugins_if_changed();
+        }
+        self.score_population();
+        if self.auto_generations_left.is_some() {
+            self.auto_evolve_step();
+            ctx.request_repaint();
+        }
+        #[cfg(feature = "midi")]
+        self.handle_midi();
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical(|ui| {
+                let toolbar_colour = if ui.visuals().dark_mode {
+                    Color32::DARK_BLUE
+                } else {
+                    Color32::from_rgb(190, 205, 235)
+                };
+                egui::Frame::default().fill(toolbar_colour).show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.horizontal(|ui| {
+                        let mutate_key = self.shortcuts.pressed(ui.ctx(), Action::Mutate);
+                        if ui.button("MUTATE").clicked() || mutate_key {
+                            self.mutate();
+                        }
+                        let save_key = self.shortcuts.pressed(ui.ctx(), Action::SaveSession);
+                        if ui.button("Save session").clicked() || save_key {
+                            self.save_session();
+                        }
+                        if ui
+                            .add_enabled(
+                                !self.population.is_empty(),
+                                egui::Button::new("Export population"),
+                            )
+                            .on_hover_text(
+                                "Save every program with its ratings, tags and notes as one \
+                                 file. Drop the file onto a lemurs window to add them to its \
+                                 population.",
+                            )
+                            .clicked()
+                        {
+                            self.export_population();
+                        }
+                        ui.menu_button("New from template", |ui| {
+                            for template in &TEMPLATES {
+                                let r =
+                                    ui.button(template.name).on_hover_text(template.description);
+                                if r.clicked() {
+                                    self.take_snapshot(format!(
+                                        "Started again from the {} template",
+                                        template.name
+                                    ));
+                                    self.reseed(Arc::new(Lineage {
+                                        generation: self.generation,
+                                        program: self.new_program(template.program()),
+                                        parent: None,
+                                    }));
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                        if let Some(snapshot) = &self.snapshot {
+                            let reason = snapshot.reason.clone();
+                            if ui
+                                .button("Restore previous")
+                                .on_hover_text(reason)
+                                .clicked()
+                            {
+                                self.restore_snapshot();
+                            }
+                        }
+                        ui.separator();
+                        ui.label("Mutation Amount");
+                        ui.add(egui::Slider::new(
+                            &mut self.mutation_amount,
+                            MUTATION_AMOUNT_RANGE,
+                        ));
+                        ui.separator();
+                        ui.label("Population Size");
+                        let r = ui.add(egui::Slider::new(
+                            &mut self.desired_population_size,
+                            POPULATION_SIZE_RANGE,
+                        ));
+                        // resizing re-renders, so wait until the slider is let go
+                        if r.drag_released() || (r.changed() && !r.dragged()) {
+                            self.resize_population();
+                        }
+                        ui.separator();
+                        egui::ComboBox::from_label("Sort by")
+                            .selected_text(self.sort_key.name())
+                            .show_ui(ui, |ui| {
+                                for key in SortKey::ALL {
+                                    let (enabled, hint) = match key {
+                                        SortKey::Similarity => (
+                                            self.sort_reference.is_some(),
+                                            "Choose \"Sort by similarity\" on an instance first",
+                                        ),
+                                        SortKey::Fitness => (
+                                            self.has_fitness(),
+                                            "Write a fitness script in the settings first",
+                                        ),
+                                        _ => (true, ""),
+                                    };
+                                    ui.add_enabled_ui(enabled, |ui| {
+                                        ui.selectable_value(&mut self.sort_key, key, key.name())
+                                            .on_disabled_hover_text(hint);
+                                    });
+                                }
+                            });
+                        ui.separator();
+                        ui.label("Filter tags");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.tag_filter).desired_width(120.0),
+                        );
+                        ui.separator();
+                        ui.toggle_value(&mut self.show_settings_panel, "Settings");
+                    });
+                });
+
+                if let Some(index) = self.detail_index {
+                    self.show_detail(ui, index);
+                    return;
+                }
+
+                if self.shortcuts.pressed(ui.ctx(), Action::Undo) {
+                    self.undo_selection();
+                }
+
+                let mut action: Option<(usize, InstanceAction)> = None;
+
+                if self.population.iter().any(|i| i.is_minimized) {
+                    action = self.show_minimized_instances(ui);
+                }
+
+                let mut visible_indices: Vec<usize> = (0..self.population.len())
+                    .filter(|i| !self.population[*i].is_minimized)
+                    .collect();
+                self.sort_indices(&mut visible_indices);
+
+                let num_instances = visible_indices.len();
+                let num_columns = self.layout.num_columns(num_instances);
+                let rows_per_page = self.layout.rows_per_page(num_instances);
+                let per_page = self.layout.instances_per_page(num_instances);
+                let num_pages = num_instances.div_ceil(per_page).max(1);
+
+                let previous_focus = self.focus_index;
+                action = self
+                    .handle_grid_keys(ui.ctx(), &visible_indices, num_columns)
+                    .or(action);
+                if self.focus_index != previous_focus {
+                    // follow the keyboard focus onto its page
+                    if let Some(p) = self
+                        .focus_index
+                        .and_then(|f| visible_indices.iter().position(|i| *i == f))
+                    {
+                        self.page = p / per_page;
+                    }
+                }
+                self.page = self.page.min(num_pages - 1);
+
+                if self.show_map {
+                    action = self.show_map(ui, &visible_indices).or(action);
+                } else {
+                    if num_pages > 1 {
+                        ui.horizontal(|ui| {
+                            if ui.button("◀").clicked() {
+                                self.page = self.page.saturating_sub(1);
+                            }
+                            ui.label(format!("Page {} / {}", self.page + 1, num_pages));
+                            if ui.button("▶").clicked() {
+                                self.page = (self.page + 1).min(num_pages - 1);
+                            }
+                        });
+                    }
+
+                    if num_instances == 0 {
+                        ui.label("No instances");
+                    } else {
+                        let page_indices = &visible_indices[(self.page * per_page)
+                            ..((self.page + 1) * per_page).min(num_instances)];
+                        let col_width = ui.available_width() / num_columns as f32;
+                        let row_height = match self.layout.thumbnail_aspect {
+                            Some(aspect) => col_width / aspect,
+                            None => ui.available_height() / rows_per_page as f32,
+                        }
+                        .max(MIN_CELL_HEIGHT);
+                        let cell_size = egui::vec2(col_width, row_height);
+                        let rows: Vec<&[usize]> = page_indices.chunks(num_columns).collect();
+
+                        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+                        if self.focus_index != previous_focus {
+                            // keep the keyboard focus in view
+                            if let Some(row) = self
+                                .focus_index
+                                .and_then(|f| rows.iter().position(|r| r.contains(&f)))
+                            {
+                                let (offset, viewport_height) = self.grid_scroll;
+                                let top = row as f32 * row_height;
+                                let bottom = top + row_height;
+                                if top < offset {
+                                    scroll_area = scroll_area.vertical_scroll_offset(top);
+                                } else if bottom > offset + viewport_height {
+                                    scroll_area = scroll_area
+                                        .vertical_scroll_offset(bottom - viewport_height);
+                                }
+                            }
+                        }
+
+                        // Only the visible rows are laid out, so offscreen cells never
+                        // create textures until they're scrolled to
+                        ui.spacing_mut().item_spacing = egui::Vec2::ZERO;
+                        let output =
+                            scroll_area.show_rows(ui, row_height, rows.len(), |ui, range| {
+                                for row in &rows[range] {
+                                    ui.horizontal(|ui| {
+                                        for i in row.iter() {
+                                            ui.allocate_ui(cell_size, |ui| {
+                                                if let Some(a) = self.show_instance(ui, *i) {
+                                                    action = Some((*i, a));
+                                                }
+                                            });
+                                        }
+                                    });
+                                }
+                            });
+                        self.grid_scroll = (output.state.offset.y, output.inner_rect.height());
+                    }
+                }
+
+                if let Some((index, a)) = action {
+                    self.apply_instance_action(index, a);
+                }
+            });
+        });
+
+        self.show_disassembly(ctx);
+        self.show_debugger(ctx);
+        self.show_memory_watch(ctx);
+        self.show_asm_editor(ctx);
+        self.show_diff_view(ctx);
+        self.show_child_preview(ctx);
+        self.show_inbox(ctx);
+        self.show_tape(ctx);
+        self.show_corpus(ctx);
+        self.show_similar(ctx);
+        self.show_shortcuts(ctx);
+        self.show_errors(ctx);
+    }
+
+    fn on_close_event(&mut self) -> bool {
+        self.settings().save();
+        true
+    }
+}
@@ -0,0 +1,950 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(target_arch = "wasm32"))]
+use log::{info, warn};
+
+// Most sounds the mixer plays at once. Starting another cuts off the one furthest along.
+pub const MAX_VOICES: usize = 16;
+
+// Length in bytes of the fade between sounds when the sound playing changes, and
+// of the fade out when it stops. About 8 ms of 4-channel audio at 64 kHz.
+const CROSSFADE_LENGTH: usize = 2048;
+
+/// A sound for `AudioBackend::play`, and how to play it
+#[derive(Clone)]
+pub struct Playback {
+    pub data: Arc<[u8]>,
+    // byte offset to start from
+    pub start: usize,
+    // start again from the beginning on reaching the end
+    pub looping: bool,
+    pub gain: f32,
+}
+
+impl Playback {
+    /// Plays all of `data` once at full gain
+    pub fn new(data: Arc<[u8]>) -> Playback {
+        Playback {
+            data,
+            start: 0,
+            looping: false,
+            gain: 1.0,
+        }
+    }
+}
+
+/// Somewhere to play interleaved, unsigned 8-bit audio
+pub trait AudioBackend {
+    /// Replaces whatever is currently playing with `playback`
+    fn play(&mut self, playback: Playback);
+
+    /// Stops everything playing
+    fn stop(&mut self);
+
+    /// Starts `data` playing at `gain` on top of whatever is already playing.
+    /// Backends which can only play one sound at a time replace it instead.
+    fn trigger(&mut self, data: Arc<[u8]>, gain: f32) {
+        self.play(Playback {
+            gain,
+            ..Playback::new(data)
+        });
+    }
+
+    /// How many bytes into the sound passed to `play` playback has reached,
+    /// or None if it has finished or this backend can't tell
+    fn playhead(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns a description of the most recent failure, if playback stopped working
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Names of the devices `set_output_device` can choose between, if this
+    /// backend can choose
+    fn output_devices(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Part of the name of the chosen device, or None for the default device
+    fn output_device(&self) -> Option<&str> {
+        None
+    }
+
+    /// Plays on the first device whose name contains `device_name`, or the default device
+    fn set_output_device(&mut self, _device_name: Option<&str>) -> Result<(), String> {
+        Err("this audio backend can't choose an output device".to_string())
+    }
+}
+
+/// Something for a `VoiceMixer` to do
+pub enum MixerCommand {
+    // fades out every voice, while fading in this one
+    Play(Playback),
+    // starts another voice at the given gain
+    Trigger(Arc<[u8]>, f32),
+    StopAll,
+}
+
+/// Where a `VoiceMixer` has got to in the sound it was last told to play,
+/// readable from other threads
+#[derive(Clone)]
+pub struct Playhead(Arc<AtomicUsize>);
+
+impl Playhead {
+    // stored when nothing is playing
+    const IDLE: usize = usize::MAX;
+
+    fn new() -> Playhead {
+        Playhead(Arc::new(AtomicUsize::new(Playhead::IDLE)))
+    }
+
+    pub fn get(&self) -> Option<usize> {
+        let position = self.0.load(Ordering::Relaxed);
+        (position != Playhead::IDLE).then_some(position)
+    }
+
+    fn set(&self, position: Option<usize>) {
+        self.0
+            .store(position.unwrap_or(Playhead::IDLE), Ordering::Relaxed);
+    }
+}
+
+struct Voice {
+    data: Arc<[u8]>,
+    position: usize,
+    gain: f32,
+    looping: bool,
+    // bytes it has faded in over so far, up to CROSSFADE_LENGTH
+    faded_in: usize,
+    // bytes left until it's silent, once it's fading out
+    fade_out: Option<usize>,
+}
+
+/// Sums several sounds of interleaved, unsigned 8-bit audio into one.
+/// Mixing never allocates or locks, so it can run on an audio thread. Finished
+/// sounds are dropped while mixing though, so callers which keep their own
+/// reference to each sound also keep it from being freed there.
+pub struct VoiceMixer {
+    voices: [Option<Voice>; MAX_VOICES],
+    // slot of the voice started by the last Play command, while it's playing
+    current: Option<usize>,
+    playhead: Playhead,
+}
+
+impl VoiceMixer {
+    pub fn new() -> VoiceMixer {
+        VoiceMixer {
+            voices: Default::default(),
+            current: None,
+            playhead: Playhead::new(),
+        }
+    }
+
+    /// Follows the position of the voice started by the last Play command
+    pub fn playhead(&self) -> Playhead {
+        self.playhead.clone()
+    }
+
+    pub fn apply(&mut self, command: MixerCommand) {
+        match command {
+            MixerCommand::Play(playback) => {
+                self.fade_out_all();
+                let start = playback.start.min(playback.data.len());
+                self.current = self.start(Voice {
+                    data: playback.data,
+                    position: start,
+                    gain: playback.gain,
+                    looping: playback.looping,
+                    faded_in: 0,
+                    fade_out: None,
+                });
+            }
+            // triggered sounds start at full volume, so drum hits keep their attack
+            MixerCommand::Trigger(data, gain) => {
+                self.start(Voice {
+                    data,
+                    position: 0,
+                    gain,
+                    looping: false,
+                    faded_in: CROSSFADE_LENGTH,
+                    fade_out: None,
+                });
+            }
+            MixerCommand::StopAll => self.fade_out_all(),
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.voices.iter().all(|v| v.is_none())
+    }
+
+    /// Returns the slot the voice went in, unless it has nothing to play
+    fn start(&mut self, voice: Voice) -> Option<usize> {
+        if voice.data.is_empty() {
+            return None;
+        }
+        let slot = match self.voices.iter().position(|v| v.is_none()) {
+            Some(i) => i,
+            None => (0..MAX_VOICES)
+                .max_by_key(|i| self.voices[*i].as_ref().map_or(0, |v| v.position))
+                .unwrap(),
+        };
+        if self.current == Some(slot) {
+            self.current = None;
+        }
+        self.voices[slot] = Some(voice);
+        Some(slot)
+    }
+
+    fn fade_out_all(&mut self) {
+        for voice in self.voices.iter_mut().flatten() {
+            voice.fade_out.get_or_insert(CROSSFADE_LENGTH);
+        }
+        self.current = None;
+    }
+
+    /// Fills `output` with the next bytes of every voice added together,
+    /// or silence if nothing is playing
+    pub fn mix(&mut self, output: &mut [u8]) {
+        let fade_length = CROSSFADE_LENGTH as f32;
+        for (i, byte) in output.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for voice in self.voices.iter().flatten() {
+                let mut index = voice.position + i;
+                if voice.looping {
+                    index %= voice.data.len();
+                }
+                if let Some(b) = voice.data.get(index) {
+                    let mut envelope = ((voice.faded_in + i) as f32 / fade_length).min(1.0);
+                    if let Some(remaining) = voice.fade_out {
+                        envelope = envelope.min(remaining.saturating_sub(i) as f32 / fade_length);
+                    }
+                    sum += (*b as f32 - 128.0) * voice.gain * envelope;
+                }
+            }
+            *byte = (sum + 128.0).clamp(0.0, 255.0) as u8;
+        }
+        for (i, slot) in self.voices.iter_mut().enumerate() {
+            if let Some(voice) = slot {
+                voice.position += output.len();
+                voice.faded_in = (voice.faded_in + output.len()).min(CROSSFADE_LENGTH);
+                if let Some(remaining) = &mut voice.fade_out {
+                    *remaining = remaining.saturating_sub(output.len());
+                }
+                if voice.looping {
+                    voice.position %= voice.data.len();
+                }
+                let finished = voice.fade_out == Some(0) || voice.position >= voice.data.len();
+                if finished {
+                    *slot = None;
+                    if self.current == Some(i) {
+                        self.current = None;
+                    }
+                }
+            }
+        }
+        let current = self.current.and_then(|i| self.voices[i].as_ref());
+        self.playhead.set(current.map(|v| v.position));
+    }
+}
+
+impl Default for VoiceMixer {
+    fn default() -> VoiceMixer {
+        VoiceMixer::new()
+    }
+}
+
+/// Discards everything, for when no audio output is available
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _playback: Playback) {}
+
+    fn stop(&mut self) {}
+}
+
+/// Plays audio by piping it to ALSA's `aplay`, writing silence while idle
+pub struct AplayBackend {
+    sender: Sender<MixerCommand>,
+    playhead: Playhead,
+    error: Arc<Mutex<Option<String>>>,
+    _aplay_process: std::process::Child,
+    _aplay_writer_thread: std::thread::JoinHandle<()>,
+}
+
+impl AplayBackend {
+    pub fn new(channels: usize, sample_rate: usize) -> io::Result<AplayBackend> {
+        let (sender, receiver) = channel::<MixerCommand>();
+        let mut mixer = VoiceMixer::new();
+        let playhead = mixer.playhead();
+
+        let chunk_size = 4096;
+
+        let mut aplay_process = Command::new("aplay")
+            .args([
+                format!("-c{}", channels),
+                format!("-r{}", sample_rate),
+                format!("--buffer-size={}", chunk_size * channels),
+            ])
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        let mut aplay_stdin = aplay_process.stdin.take().unwrap();
+
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = Arc::clone(&error);
+
+        let chunk_interval =
+            std::time::Duration::from_secs_f64(channels as f64 / sample_rate as f64);
+
+        let mut timestamp = std::time::Instant::now();
+        let mut chunk: Vec<u8> = vec![0; chunk_size];
+        let aplay_writer_thread = std::thread::spawn(move || loop {
+            while let Ok(command) = receiver.try_recv() {
+                mixer.apply(command);
+            }
+
+            let was_idle = mixer.is_idle();
+            mixer.mix(&mut chunk);
+            let result = aplay_stdin.write_all(&chunk);
+            if !was_idle {
+                let next_timestamp = timestamp + chunk_interval;
+                std::thread::sleep(
+                    next_timestamp.saturating_duration_since(std::time::Instant::now()),
+                );
+                timestamp = next_timestamp;
+            }
+            if let Err(e) = result {
+                *thread_error.lock().unwrap() = Some(format!("Audio output stopped: {}", e));
+                return;
+            }
+        });
+
+        Ok(AplayBackend {
+            sender,
+            playhead,
+            error,
+            _aplay_process: aplay_process,
+            _aplay_writer_thread: aplay_writer_thread,
+        })
+    }
+}
+
+impl AudioBackend for AplayBackend {
+    fn play(&mut self, playback: Playback) {
+        // fails only if the writer thread has stopped, which take_error reports
+        let _ = self.sender.send(MixerCommand::Play(playback));
+    }
+
+    fn stop(&mut self) {
+        let _ = self.sender.send(MixerCommand::StopAll);
+    }
+
+    fn trigger(&mut self, data: Arc<[u8]>, gain: f32) {
+        let _ = self.sender.send(MixerCommand::Trigger(data, gain));
+    }
+
+    fn playhead(&self) -> Option<usize> {
+        self.playhead.get()
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn build_cpal_stream<T, F>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut source: F,
+    error: Arc<Mutex<Option<String>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+    F: FnMut(&mut [u8]) + Send + 'static,
+{
+    use cpal::traits::DeviceTrait;
+
+    let mut bytes = Vec::new();
+    device.build_output_stream(
+        config,
+        move |samples: &mut [T], _| {
+            // only allocates when the host asks for more than ever before
+            bytes.resize(samples.len(), 128);
+            source(&mut bytes);
+            for (sample, byte) in samples.iter_mut().zip(&bytes) {
+                *sample = T::from_sample((*byte as f32 - 128.0) / 128.0);
+            }
+        },
+        move |e| *error.lock().unwrap() = Some(format!("Audio output failed: {}", e)),
+        None,
+    )
+}
+
+/// Wraps `source`, which provides `channels` interleaved channels, to provide
+/// `device_channels` instead
+#[cfg(not(target_arch = "wasm32"))]
+fn remix<F: FnMut(&mut [u8]) + Send + 'static>(
+    mut source: F,
+    channels: usize,
+    device_channels: usize,
+) -> impl FnMut(&mut [u8]) + Send + 'static {
+    let mut frames = Vec::new();
+    move |output: &mut [u8]| {
+        if channels == device_channels {
+            source(output);
+            return;
+        }
+        frames.resize(output.len() / device_channels * channels, 128);
+        source(&mut frames);
+        for (out_frame, in_frame) in output
+            .chunks_mut(device_channels)
+            .zip(frames.chunks(channels))
+        {
+            for (d, sample) in out_frame.iter_mut().enumerate() {
+                let mut sum = 0;
+                let mut count = 0;
+                for b in in_frame.iter().skip(d).step_by(device_channels) {
+                    sum += *b as i32 - 128;
+                    count += 1;
+                }
+                *sample = (128 + sum / count.max(1)) as u8;
+            }
+        }
+    }
+}
+
+/// Wraps `source`, which provides interleaved frames of `channels` channels at
+/// `step` times the rate they're asked for, holding or skipping frames to match
+#[cfg(not(target_arch = "wasm32"))]
+fn resample<F: FnMut(&mut [u8]) + Send + 'static>(
+    mut source: F,
+    channels: usize,
+    step: f64,
+) -> impl FnMut(&mut [u8]) + Send + 'static {
+    let mut frames = Vec::new();
+    let mut frame = vec![128; channels];
+    let mut phase = 1.0;
+    move |output: &mut [u8]| {
+        if step == 1.0 {
+            source(output);
+            return;
+        }
+        let num_frames = output.len() / channels;
+        // count the source frames this block moves through, then ask for them all at once
+        let mut needed = 0;
+        let mut p = phase;
+        for _ in 0..num_frames {
+            while p >= 1.0 {
+                needed += 1;
+                p -= 1.0;
+            }
+            p += step;
+        }
+        frames.resize(needed * channels, 128);
+        source(&mut frames);
+        let mut next = frames.chunks(channels);
+        for out_frame in output.chunks_mut(channels) {
+            while phase >= 1.0 {
+                frame.copy_from_slice(next.next().unwrap());
+                phase -= 1.0;
+            }
+            out_frame.copy_from_slice(&frame);
+            phase += step;
+        }
+    }
+}
+
+/// Opens a stream on the first output device whose name contains `device_name`,
+/// or the default device, which plays interleaved, unsigned 8-bit audio that
+/// `source` is called on the audio thread to provide. If the device can't play
+/// `channels` channels, they're mixed down (or up) to the channels it has, with
+/// channel `i` going to device channel `i % device_channels`, and if it can't play
+/// at `sample_rate`, it's opened at its default rate with frames held or skipped
+/// to match. Stream failures are stored in `error`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_cpal_stream<F: FnMut(&mut [u8]) + Send + 'static>(
+    device_name: Option<&str>,
+    channels: usize,
+    sample_rate: usize,
+    source: F,
+    error: Arc<Mutex<Option<String>>>,
+) -> Result<cpal::Stream, String> {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let host = cpal::default_host();
+    let device = match device_name {
+        None => host
+            .default_output_device()
+            .ok_or("no audio output device found")?,
+        Some(name) => {
+            let devices: Vec<cpal::Device> =
+                host.output_devices().map_err(|e| e.to_string())?.collect();
+            let names: Vec<String> = devices
+                .iter()
+                .map(|d| d.name().unwrap_or_default())
+                .collect();
+            match names.iter().position(|n| n.contains(name)) {
+                Some(i) => devices.into_iter().nth(i).unwrap(),
+                None => {
+                    return Err(format!(
+                        "no output device matches \"{}\", the devices are: {}",
+                        name,
+                        names.join(", ")
+                    ))
+                }
+            }
+        }
+    };
+    let default_config = device.default_output_config().map_err(|e| e.to_string())?;
+    let supported: Vec<cpal::SupportedStreamConfigRange> = device
+        .supported_output_configs()
+        .map_err(|e| e.to_string())?
+        .collect();
+    let supports_channels = supported.iter().any(|c| c.channels() as usize == channels);
+    let device_channels = if supports_channels {
+        channels
+    } else {
+        let device_channels = default_config.channels() as usize;
+        info!(
+            "The output device can't play {} channels, mixing them into {}",
+            channels, device_channels
+        );
+        device_channels
+    };
+    let source = remix(source, channels, device_channels);
+    let rate = cpal::SampleRate(sample_rate as u32);
+    // prefer the default sample format among the configs that can play at `rate`
+    let mut at_rate: Vec<&cpal::SupportedStreamConfigRange> = supported
+        .iter()
+        .filter(|c| {
+            c.channels() as usize == device_channels
+                && c.min_sample_rate() <= rate
+                && rate <= c.max_sample_rate()
+        })
+        .collect();
+    at_rate.sort_by_key(|c| c.sample_format() != default_config.sample_format());
+    let (sample_format, device_rate) = match at_rate.first() {
+        Some(c) => (c.sample_format(), rate),
+        None => {
+            info!(
+                "The output device can't play at {} Hz, resampling to {} Hz",
+                sample_rate,
+                default_config.sample_rate().0
+            );
+            (default_config.sample_format(), default_config.sample_rate())
+        }
+    };
+    let step = sample_rate as f64 / device_rate.0 as f64;
+    let source = resample(source, device_channels, step);
+    let config = cpal::StreamConfig {
+        channels: device_channels as u16,
+        sample_rate: device_rate,
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => build_cpal_stream::<f32, _>(&device, &config, source, error),
+        cpal::SampleFormat::I16 => build_cpal_stream::<i16, _>(&device, &config, source, error),
+        cpal::SampleFormat::U16 => build_cpal_stream::<u16, _>(&device, &config, source, error),
+        cpal::SampleFormat::U8 => build_cpal_stream::<u8, _>(&device, &config, source, error),
+        f => return Err(format!("unsupported sample format {}", f)),
+    }
+    .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+/// Names of the output devices of the platform's audio API
+#[cfg(not(target_arch = "wasm32"))]
+pub fn output_device_names() -> Vec<String> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+        Err(e) => {
+            warn!("Failed to list audio output devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_output_device_name() -> Option<String> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    cpal::default_host().default_output_device()?.name().ok()
+}
+
+// How often to check whether the default output device has changed
+#[cfg(not(target_arch = "wasm32"))]
+const DEVICE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Plays audio through the platform's audio API, on any platform cpal supports.
+/// Follows the default device as it changes, and falls back to it if the chosen
+/// device goes away.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct CpalBackend {
+    sender: Sender<MixerCommand>,
+    playhead: Playhead,
+    error: Arc<Mutex<Option<String>>>,
+    _stream: cpal::Stream,
+    // part of the name of the chosen device, or None to use the default device
+    device_name: Option<String>,
+    channels: usize,
+    sample_rate: usize,
+    // the default device when the stream was opened on it
+    default_device: Option<String>,
+    last_device_check: std::time::Instant,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CpalBackend {
+    /// Plays on the first output device whose name contains `device_name`, or the default device
+    pub fn new(
+        device_name: Option<&str>,
+        channels: usize,
+        sample_rate: usize,
+    ) -> Result<CpalBackend, String> {
+        let error = Arc::new(Mutex::new(None));
+        let (sender, playhead, stream) = Self::open(device_name, channels, sample_rate, &error)?;
+        Ok(CpalBackend {
+            sender,
+            playhead,
+            error,
+            _stream: stream,
+            device_name: device_name.map(str::to_string),
+            channels,
+            sample_rate,
+            default_device: default_output_device_name(),
+            last_device_check: std::time::Instant::now(),
+        })
+    }
+
+    /// Opens a stream with a mixer of its own, so whatever was playing on the
+    /// last stream stops
+    fn open(
+        device_name: Option<&str>,
+        channels: usize,
+        sample_rate: usize,
+        error: &Arc<Mutex<Option<String>>>,
+    ) -> Result<(Sender<MixerCommand>, Playhead, cpal::Stream), String> {
+        let (sender, receiver) = channel::<MixerCommand>();
+        let mut mixer = VoiceMixer::new();
+        let playhead = mixer.playhead();
+        let stream = open_cpal_stream(
+            device_name,
+            channels,
+            sample_rate,
+            move |output| {
+                while let Ok(command) = receiver.try_recv() {
+                    mixer.apply(command);
+                }
+                mixer.mix(output);
+            },
+            Arc::clone(error),
+        )?;
+        Ok((sender, playhead, stream))
+    }
+
+    /// Replaces the stream with one on the chosen device, or the default
+    /// device if the chosen one can't be opened
+    fn reopen(&mut self) -> Result<(), String> {
+        let opened = Self::open(
+            self.device_name.as_deref(),
+            self.channels,
+            self.sample_rate,
+            &self.error,
+        );
+        let (sender, playhead, stream) = match (opened, &self.device_name) {
+            (Ok(opened), _) => opened,
+            (Err(e), Some(name)) => {
+                warn!(
+                    "Failed to open \"{}\" ({}), using the default device",
+                    name, e
+                );
+                Self::open(None, self.channels, self.sample_rate, &self.error)?
+            }
+            (Err(e), None) => return Err(e),
+        };
+        self.sender = sender;
+        self.playhead = playhead;
+        self._stream = stream;
+        self.default_device = default_output_device_name();
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AudioBackend for CpalBackend {
+    fn play(&mut self, playback: Playback) {
+        let _ = self.sender.send(MixerCommand::Play(playback));
+    }
+
+    fn stop(&mut self) {
+        let _ = self.sender.send(MixerCommand::StopAll);
+    }
+
+    fn trigger(&mut self, data: Arc<[u8]>, gain: f32) {
+        let _ = self.sender.send(MixerCommand::Trigger(data, gain));
+    }
+
+    fn playhead(&self) -> Option<usize> {
+        self.playhead.get()
+    }
+
+    /// Also reopens the stream if it failed, such as when its device was
+    /// unplugged, or if it's on the default device and that changed.
+    /// Only reports errors which reopening doesn't fix.
+    fn take_error(&mut self) -> Option<String> {
+        let failure = self.error.lock().unwrap().take();
+        let mut default_changed = false;
+        if self.device_name.is_none() && self.last_device_check.elapsed() > DEVICE_CHECK_INTERVAL {
+            self.last_device_check = std::time::Instant::now();
+            default_changed = default_output_device_name() != self.default_device;
+        }
+        match &failure {
+            Some(e) => warn!("{}, reopening the audio output", e),
+            None if default_changed => info!("The default audio output device changed"),
+            None => return None,
+        }
+        self.reopen()
+            .err()
+            .map(|e| format!("Failed to reopen the audio output: {}", e))
+    }
+
+    fn output_devices(&self) -> Vec<String> {
+        output_device_names()
+    }
+
+    fn output_device(&self) -> Option<&str> {
+        self.device_name.as_deref()
+    }
+
+    fn set_output_device(&mut self, device_name: Option<&str>) -> Result<(), String> {
+        let (sender, playhead, stream) =
+            Self::open(device_name, self.channels, self.sample_rate, &self.error)?;
+        self.sender = sender;
+        self.playhead = playhead;
+        self._stream = stream;
+        self.device_name = device_name.map(str::to_string);
+        self.default_device = default_output_device_name();
+        Ok(())
+    }
+}
+
+/// Records JACK shutting down, for `JackBackend::take_error`
+#[cfg(feature = "jack")]
+struct JackNotifications {
+    error: Arc<Mutex<Option<String>>>,
+}
+
+#[cfg(feature = "jack")]
+impl jack::NotificationHandler for JackNotifications {
+    fn shutdown(&mut self, _status: jack::ClientStatus, reason: &str) {
+        *self.error.lock().unwrap() = Some(format!("JACK shut down: {}", reason));
+    }
+}
+
+#[cfg(feature = "jack")]
+type JackProcess = Box<dyn FnMut(&jack::Client, &jack::ProcessScope) -> jack::Control + Send>;
+
+/// Plays audio as a JACK client with one output port per channel, named
+/// out_1, out_2 and so on, which start out connected to the system's playback ports
+#[cfg(feature = "jack")]
+pub struct JackBackend {
+    sender: Sender<MixerCommand>,
+    playhead: Playhead,
+    error: Arc<Mutex<Option<String>>>,
+    _client: jack::AsyncClient<JackNotifications, jack::ClosureProcessHandler<JackProcess>>,
+}
+
+#[cfg(feature = "jack")]
+impl JackBackend {
+    /// Connects to a running JACK server. Output is resampled from `sample_rate`
+    /// to the server's rate by holding each frame.
+    pub fn new(channels: usize, sample_rate: usize) -> Result<JackBackend, String> {
+        use jack::PortSpec;
+
+        let (client, _status) = jack::Client::new("lemurs", jack::ClientOptions::NO_START_SERVER)
+            .map_err(|e| format!("Failed to connect to JACK: {}", e))?;
+        let mut ports = (0..channels)
+            .map(|i| client.register_port(&format!("out_{}", i + 1), jack::AudioOut))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to register JACK ports: {}", e))?;
+        let port_names: Vec<String> = ports.iter().map(|p| p.name().unwrap()).collect();
+
+        let (sender, receiver) = channel::<MixerCommand>();
+        let mut mixer = VoiceMixer::new();
+        let playhead = mixer.playhead();
+        let step = sample_rate as f64 / client.sample_rate() as f64;
+        let mut phase = 1.0;
+        let mut frame = vec![128; channels];
+        // interleaved output of the current block, sized up front so the
+        // process callback never allocates unless the server's block size grows
+        let mut block: Vec<u8> = Vec::with_capacity(client.buffer_size() as usize * channels);
+        let process: JackProcess = Box::new(move |_, scope| {
+            while let Ok(command) = receiver.try_recv() {
+                mixer.apply(command);
+            }
+            let num_frames = scope.n_frames() as usize;
+            block.clear();
+            for _ in 0..num_frames {
+                while phase >= 1.0 {
+                    mixer.mix(&mut frame);
+                    phase -= 1.0;
+                }
+                block.extend_from_slice(&frame);
+                phase += step;
+            }
+            for (channel, port) in ports.iter_mut().enumerate() {
+                let samples = block[channel..].iter().step_by(channels);
+                for (sample, byte) in port.as_mut_slice(scope).iter_mut().zip(samples) {
+                    *sample = (*byte as f32 - 128.0) / 128.0;
+                }
+            }
+            jack::Control::Continue
+        });
+
+        let error = Arc::new(Mutex::new(None));
+        let notifications = JackNotifications {
+            error: Arc::clone(&error),
+        };
+        let client = client
+            .activate_async(notifications, jack::ClosureProcessHandler::new(process))
+            .map_err(|e| format!("Failed to start JACK client: {}", e))?;
+
+        let playback_ports = client.as_client().ports(
+            None,
+            Some(jack::AudioIn.jack_port_type()),
+            jack::PortFlags::IS_INPUT | jack::PortFlags::IS_PHYSICAL,
+        );
+        if !playback_ports.is_empty() {
+            for (i, name) in port_names.iter().enumerate() {
+                let playback_port = &playback_ports[i % playback_ports.len()];
+                if let Err(e) = client
+                    .as_client()
+                    .connect_ports_by_name(name, playback_port)
+                {
+                    warn!("Failed to connect {} to {}: {}", name, playback_port, e);
+                }
+            }
+        }
+
+        Ok(JackBackend {
+            sender,
+            playhead,
+            error,
+            _client: client,
+        })
+    }
+}
+
+#[cfg(feature = "jack")]
+impl AudioBackend for JackBackend {
+    fn play(&mut self, playback: Playback) {
+        let _ = self.sender.send(MixerCommand::Play(playback));
+    }
+
+    fn stop(&mut self) {
+        let _ = self.sender.send(MixerCommand::StopAll);
+    }
+
+    fn trigger(&mut self, data: Arc<[u8]>, gain: f32) {
+        let _ = self.sender.send(MixerCommand::Trigger(data, gain));
+    }
+
+    fn playhead(&self) -> Option<usize> {
+        self.playhead.get()
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+/// Plays audio through the browser's WebAudio API
+#[cfg(target_arch = "wasm32")]
+pub struct WebAudioBackend {
+    context: web_sys::AudioContext,
+    channels: usize,
+    sample_rate: usize,
+    source: Option<web_sys::AudioBufferSourceNode>,
+    error: Option<String>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WebAudioBackend {
+    pub fn new(channels: usize, sample_rate: usize) -> Result<WebAudioBackend, String> {
+        let mut options = web_sys::AudioContextOptions::new();
+        options.sample_rate(sample_rate as f32);
+        let context = web_sys::AudioContext::new_with_context_options(&options)
+            .map_err(|e| format!("{:?}", e))?;
+        Ok(WebAudioBackend {
+            context,
+            channels,
+            sample_rate,
+            source: None,
+            error: None,
+        })
+    }
+
+    fn start(&mut self, playback: &Playback) -> Result<(), wasm_bindgen::JsValue> {
+        let data = &playback.data[playback.start.min(playback.data.len())..];
+        // Browsers suspend contexts created before the user interacted with the page
+        let _ = self.context.resume()?;
+        let num_frames = data.len() / self.channels;
+        let buffer = self.context.create_buffer(
+            self.channels as u32,
+            num_frames as u32,
+            self.sample_rate as f32,
+        )?;
+        for channel in 0..self.channels {
+            let samples: Vec<f32> = data[channel..]
+                .iter()
+                .step_by(self.channels)
+                .take(num_frames)
+                .map(|b| (*b as f32 - 128.0) / 128.0 * playback.gain)
+                .collect();
+            buffer.copy_to_channel(&samples, channel as i32)?;
+        }
+        let source = self.context.create_buffer_source()?;
+        source.set_buffer(Some(&buffer));
+        source.set_loop(playback.looping);
+        source.connect_with_audio_node(&self.context.destination())?;
+        source.start()?;
+        self.source = Some(source);
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl AudioBackend for WebAudioBackend {
+    fn play(&mut self, playback: Playback) {
+        self.stop();
+        if playback.data.len() < playback.start + self.channels {
+            return;
+        }
+        if let Err(e) = self.start(&playback) {
+            self.error = Some(format!("Audio output failed: {:?}", e));
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(source) = self.source.take() {
+            // fails only if it was never started
+            #[allow(deprecated)]
+            let _ = source.stop();
+        }
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+}
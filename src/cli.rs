@@ -0,0 +1,69 @@
+//! The command line tools, which run as subcommands of `lemurs` or as their own binaries
+
+use std::io::{stdin, Read};
+use std::path::Path;
+
+use clap::ValueEnum;
+
+#[cfg(feature = "jack")]
+use crate::audio::JackBackend;
+use crate::audio::{AplayBackend, AudioBackend, CpalBackend};
+use crate::corpus::read_program_file;
+use crate::program::Program;
+
+pub mod asm;
+pub mod bench;
+pub mod corpus;
+pub mod diff;
+pub mod disasm;
+pub mod evolve;
+pub mod interpret;
+pub mod render;
+pub mod serve;
+pub mod stats;
+
+/// Where audio is played
+#[derive(Clone, Copy, ValueEnum)]
+pub enum AudioBackendKind {
+    /// Pipe audio to ALSA's aplay
+    Aplay,
+    /// Play on the default output device through the platform's audio API
+    Cpal,
+    /// Play as a JACK client, with a port per channel
+    #[cfg(feature = "jack")]
+    Jack,
+}
+
+pub fn open_audio_backend(
+    kind: AudioBackendKind,
+    channels: usize,
+    sample_rate: usize,
+) -> Result<Box<dyn AudioBackend>, String> {
+    Ok(match kind {
+        AudioBackendKind::Aplay => Box::new(
+            AplayBackend::new(channels, sample_rate)
+                .map_err(|e| format!("Failed to start aplay: {}", e))?,
+        ),
+        AudioBackendKind::Cpal => Box::new(
+            CpalBackend::new(None, channels, sample_rate)
+                .map_err(|e| format!("Failed to open audio output: {}", e))?,
+        ),
+        #[cfg(feature = "jack")]
+        AudioBackendKind::Jack => Box::new(JackBackend::new(channels, sample_rate)?),
+    })
+}
+
+/// Reads a program named on the command line, like `read_program_file`,
+/// except that - reads a binary or .lprog from stdin
+pub fn read_program_argument(path: &Path) -> Result<Program, String> {
+    if path.as_os_str() != "-" {
+        return read_program_file(path);
+    }
+    let mut data = Vec::new();
+    stdin().read_to_end(&mut data).map_err(|e| e.to_string())?;
+    if Program::is_container(&data) {
+        Program::read_container(&data).map(|(p, _)| p)
+    } else {
+        Program::new(data)
+    }
+}
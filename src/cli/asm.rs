@@ -0,0 +1,73 @@
+use std::fs;
+use std::io::{stdin, Read};
+use std::path::PathBuf;
+use std::process::exit;
+
+use crate::instruction::{assemble, disassemble_lines, IsaFeatures};
+use crate::logging;
+use clap::Parser;
+use log::error;
+
+/// Assemble a program into the binary the machine runs
+#[derive(Parser)]
+pub struct Args {
+    /// Assembly to read, or - for stdin
+    input: PathBuf,
+
+    /// Binary file to write
+    #[arg(long, short)]
+    output: PathBuf,
+
+    /// Also write a listing of each instruction's offset and encoded bytes to this file
+    #[arg(long)]
+    listing: Option<PathBuf>,
+
+    /// Decode the DSP instructions in the listing
+    #[arg(long)]
+    dsp: bool,
+}
+
+/// One line per instruction: its offset, its bytes in hex and how it disassembles
+fn listing(program: &[u8], features: IsaFeatures) -> String {
+    let lines = disassemble_lines(program, features);
+    let mut text = String::new();
+    for (i, (offset, line)) in lines.iter().enumerate() {
+        let end = lines.get(i + 1).map_or(program.len(), |(o, _)| *o);
+        let bytes: Vec<String> = program[*offset..end]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        text += &format!("{:04x}  {:<24}{}\n", offset, bytes.join(" "), line);
+    }
+    text
+}
+
+pub fn run(args: Args) {
+    logging::init(false);
+
+    let text = if args.input.as_os_str() == "-" {
+        let mut text = String::new();
+        stdin().read_to_string(&mut text).map(|_| text)
+    } else {
+        fs::read_to_string(&args.input)
+    };
+    let text = text.unwrap_or_else(|e| {
+        error!("Failed to read {}: {}", args.input.display(), e);
+        exit(1);
+    });
+    let program = assemble(&text).unwrap_or_else(|e| {
+        error!("{}: {}", args.input.display(), e);
+        exit(1);
+    });
+
+    if let Err(e) = fs::write(&args.output, &program) {
+        error!("Failed to write {}: {}", args.output.display(), e);
+        exit(1);
+    }
+    if let Some(path) = &args.listing {
+        if let Err(e) = fs::write(path, listing(&program, IsaFeatures { dsp: args.dsp })) {
+            error!("Failed to write {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::app::random_program;
+use crate::corpus::load_program_directory;
+use crate::evaluate::{evaluate, EvalConfig};
+use crate::logging;
+use crate::machine::Machine;
+use crate::program::Program;
+use crate::spectrogram::SpectrogramRenderer;
+use clap::Parser;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Measure how fast the VM runs and spectrograms are computed
+#[derive(Parser)]
+pub struct Args {
+    /// Directory of .bin, .lprog, .asm and .bytebeat programs to run, e.g. programs saved from evolve.
+    /// Uses random programs if omitted.
+    corpus: Option<PathBuf>,
+
+    /// Number of random programs to use when no corpus is given
+    #[arg(long, default_value_t = 32)]
+    random: usize,
+
+    /// Seed for generating random programs
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Number of instructions to run per program in each sample
+    #[arg(long, default_value_t = 1_000_000)]
+    steps: usize,
+
+    /// Number of timed samples of each benchmark
+    #[arg(long, default_value_t = 10)]
+    samples: usize,
+
+    /// Write the results to this file, to compare against later with --baseline
+    #[arg(long)]
+    save: Option<PathBuf>,
+
+    /// Results saved by an earlier run, to report the change against
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+}
+
+/// Throughput over several samples
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Measurement {
+    mean: f64,
+    std_dev: f64,
+}
+
+impl Measurement {
+    fn from_samples(samples: &[f64]) -> Measurement {
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / n;
+        Measurement {
+            mean,
+            std_dev: variance.sqrt(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Results {
+    instructions_per_sec: Measurement,
+    spectrogram_columns_per_sec: Measurement,
+}
+
+/// Runs `f` once to warm up, then `samples` more times, returning the
+/// throughput of each timed run given the amount of work `f` reports
+fn measure<F: FnMut() -> usize>(samples: usize, mut f: F) -> Measurement {
+    f();
+    let throughputs: Vec<f64> = (0..samples)
+        .map(|_| {
+            let start = Instant::now();
+            let work = f();
+            work as f64 / start.elapsed().as_secs_f64()
+        })
+        .collect();
+    Measurement::from_samples(&throughputs)
+}
+
+fn report(name: &str, unit: &str, after: Measurement, before: Option<Measurement>) {
+    println!(
+        "{:<24} {:>14.0} {}/s ± {:.1}%",
+        name,
+        after.mean,
+        unit,
+        100.0 * after.std_dev / after.mean
+    );
+    if let Some(before) = before {
+        println!(
+            "{:<24} {:>14.0} {}/s before, {:+.1}%",
+            "",
+            before.mean,
+            unit,
+            100.0 * (after.mean / before.mean - 1.0)
+        );
+    }
+}
+
+pub fn run(args: Args) {
+    logging::init(false);
+
+    let programs: Vec<Program> = match &args.corpus {
+        Some(dir) => match load_program_directory(dir) {
+            Ok(programs) => programs.into_iter().map(|(_, p)| p).collect(),
+            Err(e) => {
+                println!("Failed to read {}: {}", dir.display(), e);
+                return;
+            }
+        },
+        None => {
+            let mut rng = StdRng::seed_from_u64(args.seed);
+            (0..args.random)
+                .map(|_| Program::new(random_program(256, &mut rng)).unwrap())
+                .collect()
+        }
+    };
+    if programs.is_empty() || args.samples == 0 {
+        println!("Nothing to measure");
+        return;
+    }
+
+    let baseline: Option<Results> = match &args.baseline {
+        Some(path) => {
+            match fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| toml::from_str(&text).map_err(|e| e.to_string()))
+            {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    println!("Failed to read baseline {}: {}", path.display(), e);
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+
+    println!(
+        "{} programs, {} steps each, {} samples",
+        programs.len(),
+        args.steps,
+        args.samples
+    );
+
+    let instructions_per_sec = measure(args.samples, || {
+        for program in &programs {
+            let mut machine =
+                Machine::new_with_features(program.bytes().to_vec(), program.features());
+            machine.run(args.steps, &mut io::sink());
+        }
+        programs.len() * args.steps
+    });
+
+    // Run each program once, the same way evolve does, to get outputs to analyse
+    let config = EvalConfig::default();
+    let outputs: Vec<Arc<[u8]>> = programs
+        .iter()
+        .map(|program| evaluate(program, &config).output)
+        .collect();
+    let spectrogram_renderer = SpectrogramRenderer::new();
+    let spectrogram_columns_per_sec = measure(args.samples, || {
+        outputs
+            .iter()
+            .map(|output| spectrogram_renderer.compute(output).width)
+            .sum()
+    });
+
+    let results = Results {
+        instructions_per_sec,
+        spectrogram_columns_per_sec,
+    };
+    report(
+        "Machine::run",
+        "instructions",
+        results.instructions_per_sec,
+        baseline.as_ref().map(|b| b.instructions_per_sec),
+    );
+    report(
+        "Spectrogram",
+        "columns",
+        results.spectrogram_columns_per_sec,
+        baseline.as_ref().map(|b| b.spectrogram_columns_per_sec),
+    );
+
+    if let Some(path) = &args.save {
+        if let Err(e) = fs::write(path, toml::to_string(&results).unwrap()) {
+            println!("Failed to save results to {}: {}", path.display(), e);
+        }
+    }
+}
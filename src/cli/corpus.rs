@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+use std::process::exit;
+
+use crate::cli::read_program_argument;
+use crate::corpus::{format_date, Corpus, CorpusEntry};
+use crate::logging;
+use crate::program::{Program, ProgramHash, Provenance};
+use clap::{Parser, Subcommand};
+use log::{error, warn};
+
+/// Manage a directory of saved programs
+#[derive(Parser)]
+pub struct Args {
+    /// Corpus directory, created if it doesn't exist
+    dir: PathBuf,
+
+    #[command(subcommand)]
+    command: CorpusCommand,
+
+    /// Also show debug messages in the log
+    #[arg(long, short, global = true)]
+    verbose: bool,
+}
+
+#[derive(Subcommand)]
+enum CorpusCommand {
+    /// List every program
+    List,
+    /// List the programs matching every word: a tag, part of a file name or the start of a hash
+    Search {
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+    /// Copy programs into the corpus, skipping ones it already has
+    Import {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Tag every imported program. Can be given more than once.
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// List programs which are saved more than once
+    Duplicates,
+    /// Replace the tags of a program, given the start of its hash
+    Tag { hash: String, tags: Vec<String> },
+    /// List the ancestors of a program which are in the corpus, given the start of its hash
+    Lineage { hash: String },
+}
+
+fn print_entry(entry: &CorpusEntry) {
+    println!(
+        "{}  {}  {:>6} bytes  generation {:<4} {}  {}",
+        entry.hash,
+        format_date(entry.added),
+        entry.length,
+        entry.provenance.generation,
+        entry.file,
+        entry.tags.join(" ")
+    );
+}
+
+/// The one program whose hash starts with `prefix`
+fn find_hash(corpus: &Corpus, prefix: &str) -> Result<ProgramHash, String> {
+    let mut hashes: Vec<ProgramHash> = corpus
+        .entries()
+        .iter()
+        .map(|e| e.hash)
+        .filter(|h| h.to_string().starts_with(prefix))
+        .collect();
+    hashes.dedup();
+    match hashes[..] {
+        [hash] => Ok(hash),
+        [] => Err(format!("no program's hash starts with {}", prefix)),
+        _ => Err(format!(
+            "more than one program's hash starts with {}",
+            prefix
+        )),
+    }
+}
+
+fn run_command(corpus: &mut Corpus, command: CorpusCommand) -> Result<(), String> {
+    match command {
+        CorpusCommand::List => corpus.entries().iter().for_each(print_entry),
+        CorpusCommand::Search { query } => corpus
+            .search(&query.join(" "))
+            .into_iter()
+            .for_each(print_entry),
+        CorpusCommand::Import { files, tags } => {
+            for path in files {
+                let provenance = std::fs::read(&path)
+                    .ok()
+                    .and_then(|data| Program::read_container(&data).ok())
+                    .map(|(_, p)| p)
+                    .unwrap_or_else(Provenance::default);
+                let program = read_program_argument(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                match corpus.add(&program, &provenance, tags.clone()) {
+                    Ok(entry) => print_entry(entry),
+                    Err(e) => warn!("Skipping {}: {}", path.display(), e),
+                }
+            }
+        }
+        CorpusCommand::Duplicates => {
+            for group in corpus.duplicates() {
+                let files: Vec<&str> = group.iter().map(|e| e.file.as_str()).collect();
+                println!("{}  {}", group[0].hash, files.join(" "));
+            }
+        }
+        CorpusCommand::Tag { hash, tags } => {
+            let hash = find_hash(corpus, &hash)?;
+            corpus.set_tags(hash, tags)?;
+        }
+        CorpusCommand::Lineage { hash } => {
+            let hash = find_hash(corpus, &hash)?;
+            for entry in corpus.lineage(hash) {
+                print_entry(entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run(args: Args) {
+    logging::init(args.verbose);
+
+    let mut corpus = Corpus::open(&args.dir).unwrap_or_else(|e| {
+        error!("Failed to open {}: {}", args.dir.display(), e);
+        exit(1);
+    });
+    if let Err(e) = run_command(&mut corpus, args.command) {
+        error!("{}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,218 @@
+use std::io::{stdout, IsTerminal};
+use std::path::PathBuf;
+use std::process::exit;
+
+use crate::cli::read_program_argument;
+use crate::diff::{diff, Change};
+use crate::instruction::{disassemble_lines, IsaFeatures};
+use crate::logging;
+use clap::Parser;
+use log::error;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Show the instructions which differ between two programs, side by side
+#[derive(Parser)]
+pub struct Args {
+    /// The program before
+    a: PathBuf,
+
+    /// The program after
+    b: PathBuf,
+
+    /// Unchanged lines to show around each change. Longer runs of unchanged lines are left out.
+    #[arg(long, default_value_t = 3)]
+    context: usize,
+
+    /// Width of each side, in characters
+    #[arg(long, default_value_t = 40)]
+    width: usize,
+
+    /// Mark changed operands with brackets rather than colour, even in a terminal
+    #[arg(long)]
+    no_colour: bool,
+}
+
+/// A line of the side-by-side view. Either side may be missing.
+struct Row {
+    a: Option<usize>,
+    b: Option<usize>,
+}
+
+/// Pairs up the lines removed from `a` with the lines added in `b` in each
+/// run of changes, so small edits show up as one changed line
+fn pair_rows(changes: &[Change]) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let flush = |rows: &mut Vec<Row>, removed: &mut Vec<usize>, added: &mut Vec<usize>| {
+        for i in 0..removed.len().max(added.len()) {
+            rows.push(Row {
+                a: removed.get(i).copied(),
+                b: added.get(i).copied(),
+            });
+        }
+        removed.clear();
+        added.clear();
+    };
+    for change in changes {
+        match *change {
+            Change::Same(a, b) => {
+                flush(&mut rows, &mut removed, &mut added);
+                rows.push(Row {
+                    a: Some(a),
+                    b: Some(b),
+                });
+            }
+            Change::Removed(a) => removed.push(a),
+            Change::Added(b) => added.push(b),
+        }
+    }
+    flush(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+/// Marks the words of `line` which aren't at the same position in `other`
+fn highlight(line: &str, other: &str, colour: Option<&str>) -> (String, usize) {
+    let other: Vec<&str> = other.split_whitespace().collect();
+    let mut text = String::new();
+    let mut visible_length = 0;
+    for (i, word) in line.split_whitespace().enumerate() {
+        if i > 0 {
+            text.push(' ');
+            visible_length += 1;
+        }
+        if other.get(i) == Some(&word) {
+            text += word;
+            visible_length += word.len();
+            continue;
+        }
+        match colour {
+            Some(colour) => {
+                text += &format!("{}{}{}", colour, word, RESET);
+                visible_length += word.len();
+            }
+            None => {
+                text += &format!("[{}]", word);
+                visible_length += word.len() + 2;
+            }
+        }
+    }
+    (text, visible_length)
+}
+
+/// Describes the runs of bytes removed from `a` and added in `b`
+fn summarise_regions(changes: &[Change]) -> Vec<String> {
+    let mut regions = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let start = i;
+        match changes[i] {
+            Change::Same(..) => {
+                i += 1;
+                continue;
+            }
+            Change::Removed(a) => {
+                while matches!(changes.get(i), Some(Change::Removed(_))) {
+                    i += 1;
+                }
+                regions.push(format!("deleted {} bytes at {:04x} of a", i - start, a));
+            }
+            Change::Added(b) => {
+                while matches!(changes.get(i), Some(Change::Added(_))) {
+                    i += 1;
+                }
+                regions.push(format!("inserted {} bytes at {:04x} of b", i - start, b));
+            }
+        }
+    }
+    regions
+}
+
+pub fn run(args: Args) {
+    logging::init(false);
+
+    let read = |path: &PathBuf| {
+        read_program_argument(path).unwrap_or_else(|e| {
+            error!("Failed to read {}: {}", path.display(), e);
+            exit(1);
+        })
+    };
+    let program_a = read(&args.a);
+    let program_b = read(&args.b);
+    let colour = !args.no_colour && stdout().is_terminal();
+
+    let lines_a = disassemble_lines(program_a.bytes(), IsaFeatures::default());
+    let lines_b = disassemble_lines(program_b.bytes(), IsaFeatures::default());
+    let text_a: Vec<&str> = lines_a.iter().map(|(_, l)| l.as_str()).collect();
+    let text_b: Vec<&str> = lines_b.iter().map(|(_, l)| l.as_str()).collect();
+    let rows = pair_rows(&diff(&text_a, &text_b));
+
+    let is_changed = |row: &Row| match (row.a, row.b) {
+        (Some(a), Some(b)) => text_a[a] != text_b[b],
+        _ => true,
+    };
+    let changed: Vec<bool> = rows.iter().map(is_changed).collect();
+    let shown: Vec<bool> = (0..rows.len())
+        .map(|i| {
+            let start = i.saturating_sub(args.context);
+            let end = (i + args.context + 1).min(rows.len());
+            changed[start..end].iter().any(|c| *c)
+        })
+        .collect();
+
+    let (mut num_changed, mut num_deleted, mut num_inserted) = (0, 0, 0);
+    for (i, row) in rows.iter().enumerate() {
+        match (row.a, row.b) {
+            (Some(_), Some(_)) if changed[i] => num_changed += 1,
+            (Some(_), None) => num_deleted += 1,
+            (None, Some(_)) => num_inserted += 1,
+            _ => (),
+        }
+        if !shown[i] {
+            if i > 0 && shown[i - 1] {
+                println!("{:>width$}", "...", width = args.width / 2);
+            }
+            continue;
+        }
+        let marker = match (row.a, row.b) {
+            _ if !changed[i] => ' ',
+            (Some(_), Some(_)) => '~',
+            (Some(_), None) => '-',
+            _ => '+',
+        };
+        let side = |line: Option<usize>,
+                    lines: &[(usize, String)],
+                    other: Option<&str>,
+                    highlight_colour: &str| {
+            let Some(line) = line else {
+                return (String::new(), 0);
+            };
+            let (offset, text) = &lines[line];
+            let colour = colour.then_some(highlight_colour);
+            let (text, length) = match other {
+                Some(other) if other != text.as_str() => highlight(text, other, colour),
+                None if colour.is_some() => {
+                    (format!("{}{}{}", highlight_colour, text, RESET), text.len())
+                }
+                _ => (text.clone(), text.len()),
+            };
+            (format!("{:04x}  {}", offset, text), length + 6)
+        };
+        let (left, left_length) = side(row.a, &lines_a, row.b.map(|b| text_b[b]), RED);
+        let (right, _) = side(row.b, &lines_b, row.a.map(|a| text_a[a]), GREEN);
+        let padding = args.width.saturating_sub(left_length);
+        println!("{}{}{} | {}", marker, left, " ".repeat(padding), right);
+    }
+
+    println!();
+    println!(
+        "{} instructions changed, {} deleted, {} inserted",
+        num_changed, num_deleted, num_inserted
+    );
+    for region in summarise_regions(&diff(program_a.bytes(), program_b.bytes())) {
+        println!("{}", region);
+    }
+}
@@ -0,0 +1,47 @@
+use std::fs;
+use std::io::{stdout, Write};
+use std::path::PathBuf;
+use std::process::exit;
+
+use crate::cli::read_program_argument;
+use crate::instruction::{disassemble, IsaFeatures};
+use crate::logging;
+use clap::Parser;
+use log::error;
+
+/// Disassemble a program into assembly which assembles back to the same bytes
+#[derive(Parser)]
+pub struct Args {
+    /// Raw binary, .lprog, .asm or .bytebeat file to read, or - to read a binary or .lprog from stdin
+    input: PathBuf,
+
+    /// File to write the assembly to. Writes to stdout if omitted.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Decode the DSP instructions, even if the program doesn't declare that it needs them
+    #[arg(long)]
+    dsp: bool,
+}
+
+pub fn run(args: Args) {
+    logging::init(false);
+
+    let program = read_program_argument(&args.input).unwrap_or_else(|e| {
+        error!("Failed to read {}: {}", args.input.display(), e);
+        exit(1);
+    });
+
+    let text = disassemble(
+        program.bytes(),
+        program.features().union(IsaFeatures { dsp: args.dsp }),
+    );
+    let result = match &args.output {
+        Some(path) => fs::write(path, text),
+        None => stdout().write_all(text.as_bytes()),
+    };
+    if let Err(e) = result {
+        error!("Failed to write the assembly: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,446 @@
+use std::fs;
+use std::io::{stdin, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{panic, process};
+
+use crate::app::{random_program, InitialPopulation, LemursApp, Session, Settings};
+use crate::audio::{AudioBackend, NullBackend};
+use crate::cli::{open_audio_backend, AudioBackendKind};
+use crate::constraints::Constraints;
+use crate::corpus::{load_program_directory, Corpus};
+use crate::evaluate::{EvalConfig, OutputMode, AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE};
+use crate::import::{decode_audio, samples_to_memory, SampleFormat};
+use crate::instruction::compile_bytebeat;
+use crate::logging;
+#[cfg(feature = "midi")]
+use crate::midi::MidiOutputBackend;
+use crate::osc::OscServer;
+use crate::profile::load_profiles;
+use crate::program::Program;
+use crate::share::Sharing;
+use crate::templates::{find_template, TEMPLATES};
+use clap::Parser;
+use log::{error, info};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Reads the starting program named on the command line, or makes a random one
+fn read_program(
+    path: Option<&str>,
+    is_assembly: bool,
+    rng: &mut StdRng,
+) -> Result<Program, String> {
+    let memory = match path {
+        None => return Program::new(random_program(256, rng)),
+        Some("-") => {
+            let mut v = Vec::new();
+            stdin()
+                .read_to_end(&mut v)
+                .map_err(|e| format!("Failed to read from stdin: {}", e))?;
+            v
+        }
+        Some(path) => fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?,
+    };
+    if Program::is_container(&memory) {
+        let (program, _) = Program::read_container(&memory)
+            .map_err(|e| format!("Failed to read {}: {}", path.unwrap_or("-"), e))?;
+        return Ok(program);
+    }
+    if !is_assembly {
+        return Program::new(memory);
+    }
+    let name = path.unwrap();
+    let text = String::from_utf8(memory).map_err(|_| format!("{} is not UTF-8 text", name))?;
+    Program::assemble(&text).map_err(|e| format!("Failed to assemble {}: {}", name, e))
+}
+
+/// Reads an audio file as the starting program, per the --seed-* arguments
+fn read_audio_seed(path: &Path, args: &Args) -> Result<Vec<u8>, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let samples =
+        decode_audio(&data).map_err(|e| format!("Failed to decode {}: {}", path.display(), e))?;
+    let memory = samples_to_memory(
+        &samples,
+        args.seed_format,
+        args.seed_decimate,
+        args.seed_length,
+    );
+    if memory.is_empty() {
+        return Err(format!("{} has no samples", path.display()));
+    }
+    info!(
+        "Seeded from {} samples of {}",
+        samples.len(),
+        path.display()
+    );
+    Ok(memory)
+}
+
+/// Interactively evolve lemurs programs by ear
+#[derive(Parser)]
+pub struct Args {
+    /// Program to start from: a binary file (raw, or a .lprog saved from evolve),
+    /// an assembly file with --assemble,
+    /// or - to read a binary from stdin until EOF. Starts from a random program if omitted.
+    /// If this is a directory, it is loaded like --population-dir.
+    program: Option<String>,
+
+    /// Use every .bin, .lprog, .asm and .bytebeat file in this directory as the first generation
+    #[arg(long, conflicts_with = "program")]
+    population_dir: Option<PathBuf>,
+
+    /// Continue a session saved with the "Save session" button
+    #[arg(long, conflicts_with_all = ["program", "population_dir"])]
+    resume: Option<PathBuf>,
+
+    /// Start from a WAV or FLAC file converted to bytes, rather than a program
+    #[arg(long, conflicts_with_all = ["program", "population_dir", "resume"])]
+    seed_audio: Option<PathBuf>,
+
+    /// Start from a classic bytebeat formula over t, e.g. "t*(t>>8|t>>13)&63"
+    #[arg(long, conflicts_with_all = ["program", "population_dir", "resume", "seed_audio"])]
+    bytebeat: Option<String>,
+
+    /// Start from one of the built-in templates: counter, noise, two-voice or feedback
+    #[arg(long, conflicts_with_all = ["program", "population_dir", "resume", "seed_audio", "bytebeat"])]
+    template: Option<String>,
+
+    /// Start with one instance of each template and a few random programs,
+    /// for a varied first generation
+    #[arg(long, conflicts_with_all = ["program", "population_dir", "resume", "seed_audio", "bytebeat", "template"])]
+    onboarding: bool,
+
+    /// How --seed-audio samples are converted to bytes
+    #[arg(long, value_enum, default_value_t = SampleFormat::Unsigned8)]
+    seed_format: SampleFormat,
+
+    /// Keep only every Nth sample of --seed-audio
+    #[arg(long, default_value_t = 1)]
+    seed_decimate: usize,
+
+    /// Longest program made from --seed-audio, in bytes
+    #[arg(long, default_value_t = 65536)]
+    seed_length: usize,
+
+    /// Assemble the program file before evolving it
+    #[arg(long)]
+    assemble: bool,
+
+    /// Number of instances in each generation
+    #[arg(long)]
+    population: Option<usize>,
+
+    /// Number of mutations applied to each child
+    #[arg(long)]
+    mutation: Option<usize>,
+
+    /// Instructions or operations which children must contain, separated by
+    /// spaces. Prefix one with ! to forbid it, e.g. "jmp !outputw".
+    #[arg(long)]
+    constraints: Option<Constraints>,
+
+    /// Rhai script scoring instances from their features, for sorting and
+    /// auto-evolve, see lemurs::fitness
+    #[arg(long)]
+    fitness: Option<PathBuf>,
+
+    /// Start with the settings of a profile from lemurs.toml, e.g. "chip".
+    /// Other arguments override it.
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Seed for the random number generator, to make a run reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Length of the rendered output of each instance, in seconds
+    #[arg(long)]
+    preview_secs: Option<f32>,
+
+    /// Longest time to spend running each program, in seconds.
+    /// Output after that is silent.
+    #[arg(long)]
+    time_budget_secs: Option<f32>,
+
+    /// Also show debug messages in the log
+    #[arg(long, short)]
+    verbose: bool,
+
+    /// Sample rate for rendering and playback, in Hz. Defaults to the
+    /// profile's, or 64000.
+    #[arg(long)]
+    sample_rate: Option<usize>,
+
+    /// Where to play audio
+    #[arg(long, value_enum, default_value_t = AudioBackendKind::Aplay)]
+    audio_backend: AudioBackendKind,
+
+    /// Listen for OSC remote control messages on this UDP port, e.g. /lemurs/mutate
+    #[arg(long)]
+    osc_port: Option<u16>,
+
+    /// Receive programs from other running instances on this TCP port
+    #[arg(long)]
+    share_port: Option<u16>,
+
+    /// Another instance to send programs to, as host:port. Can be given more than once.
+    #[arg(long = "peer", requires = "share_port")]
+    peers: Vec<String>,
+
+    /// Save programs into this corpus directory, and browse it from the toolbar
+    #[arg(long)]
+    corpus: Option<PathBuf>,
+
+    /// Part of the name of the MIDI input port to perform with. Uses the first port if omitted.
+    #[cfg(feature = "midi")]
+    #[arg(long)]
+    midi_port: Option<String>,
+
+    /// Play output by decoding it as MIDI and sending it to the first MIDI output port
+    /// whose name contains this, or to the first port if no name is given.
+    /// Instances show piano rolls instead of spectrograms.
+    #[cfg(feature = "midi")]
+    #[arg(long, num_args = 0..=1, default_missing_value = "")]
+    midi_out: Option<String>,
+}
+
+/// Opens the audio output, or the MIDI output with --midi-out. On failure, returns
+/// a backend which discards everything along with the error, so the app can
+/// carry on without sound rather than not at all.
+fn open_output(args: &Args, sample_rate: usize) -> (Box<dyn AudioBackend>, Option<String>) {
+    #[cfg(feature = "midi")]
+    if let Some(port) = &args.midi_out {
+        return match MidiOutputBackend::connect(Some(port)) {
+            Ok(m) => {
+                info!("Sending MIDI to {}", m.port_name());
+                (Box::new(m), None)
+            }
+            Err(e) => (
+                Box::new(NullBackend),
+                Some(format!(
+                    "Failed to open MIDI output, output is disabled: {}",
+                    e
+                )),
+            ),
+        };
+    }
+    match open_audio_backend(args.audio_backend, AUDIO_CHANNELS, sample_rate) {
+        Ok(a) => (a, None),
+        Err(e) => (
+            Box::new(NullBackend),
+            Some(format!("{}, audio is disabled", e)),
+        ),
+    }
+}
+
+pub fn run(args: Args) {
+    logging::init(args.verbose);
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let population_dir = args.population_dir.clone().or_else(|| {
+        args.program
+            .as_ref()
+            .map(PathBuf::from)
+            .filter(|p| p.is_dir())
+    });
+    let mut settings = Settings::load();
+
+    let initial_population = if let Some(path) = &args.resume {
+        let mut session = match Session::load(path) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to load session {}: {}", path.display(), e);
+                return;
+            }
+        };
+        // keep the window where it is now rather than where it was when saving
+        session.settings.window_size = settings.window_size;
+        session.settings.window_position = settings.window_position;
+        settings = std::mem::take(&mut session.settings);
+        InitialPopulation::Session(Box::new(session))
+    } else if let Some(dir) = population_dir {
+        let programs = match load_program_directory(&dir) {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Failed to read {}: {}", dir.display(), e);
+                return;
+            }
+        };
+        if programs.is_empty() {
+            error!("No programs found in {}", dir.display());
+            return;
+        }
+        info!("Loaded {} programs from {}", programs.len(), dir.display());
+        InitialPopulation::Programs(programs)
+    } else if let Some(path) = &args.seed_audio {
+        match read_audio_seed(path, &args).and_then(Program::new) {
+            Ok(program) => InitialPopulation::Seed(program),
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        }
+    } else if let Some(formula) = &args.bytebeat {
+        match compile_bytebeat(formula).and_then(Program::new) {
+            Ok(program) => InitialPopulation::Seed(program),
+            Err(e) => {
+                error!("Failed to compile the bytebeat formula: {}", e);
+                return;
+            }
+        }
+    } else if args.onboarding {
+        InitialPopulation::Onboarding
+    } else if let Some(name) = &args.template {
+        match find_template(name) {
+            Some(template) => InitialPopulation::Seed(Program::new(template.program()).unwrap()),
+            None => {
+                let names: Vec<&str> = TEMPLATES.iter().map(|t| t.name).collect();
+                error!(
+                    "Unknown template \"{}\", expected one of {}",
+                    name,
+                    names.join(", ")
+                );
+                return;
+            }
+        }
+    } else {
+        match read_program(args.program.as_deref(), args.assemble, &mut rng) {
+            Ok(program) => InitialPopulation::Seed(program),
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        }
+    };
+
+    let profile = match &args.profile {
+        Some(name) => {
+            let mut profiles = match load_profiles() {
+                Ok(p) => p,
+                Err(e) => {
+                    error!("Failed to read profiles from {}", e);
+                    return;
+                }
+            };
+            match profiles.remove(name) {
+                Some(profile) => Some(profile),
+                None => {
+                    let names: Vec<&str> = profiles.keys().map(|n| n.as_str()).collect();
+                    error!(
+                        "Unknown profile \"{}\", expected one of {}",
+                        name,
+                        names.join(", ")
+                    );
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+    if let Some(profile) = &profile {
+        settings.apply_profile(profile);
+    }
+
+    if let Some(population) = args.population {
+        settings.population_size = population;
+    }
+    if let Some(mutation) = args.mutation {
+        settings.mutation_amount = mutation;
+    }
+    if let Some(constraints) = &args.constraints {
+        settings.constraints = constraints.clone();
+    }
+    if let Some(path) = &args.fitness {
+        match std::fs::read_to_string(path) {
+            Ok(source) => settings.fitness_script = source,
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                return;
+            }
+        }
+    }
+
+    #[cfg(feature = "midi")]
+    let output_mode = match args.midi_out {
+        Some(_) => OutputMode::Midi,
+        None => OutputMode::Audio,
+    };
+    #[cfg(not(feature = "midi"))]
+    let output_mode = OutputMode::Audio;
+    let profile = profile.unwrap_or_default();
+    let sample_rate = args
+        .sample_rate
+        .or(profile.sample_rate)
+        .unwrap_or(DEFAULT_SAMPLE_RATE);
+    let preview_secs = args.preview_secs.or(profile.preview_secs);
+    let mut eval_config = EvalConfig::new(sample_rate, preview_secs, output_mode);
+    if let Some(secs) = args.time_budget_secs.or(profile.time_budget_secs) {
+        eval_config.time_budget = Duration::from_secs_f32(secs);
+    }
+    let (audio, audio_error) = open_output(&args, sample_rate);
+
+    let orig_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        orig_hook(panic_info);
+        process::exit(-1);
+    }));
+
+    let native_options = eframe::NativeOptions {
+        initial_window_size: settings.window_size.map(|s| s.into()),
+        initial_window_pos: settings.window_position.map(|p| p.into()),
+        ..Default::default()
+    };
+    let result = eframe::run_native(
+        "Lemurs",
+        native_options,
+        Box::new(move |cc| {
+            let mut app = LemursApp::new(initial_population, settings, eval_config, audio, rng);
+            if let Some(port) = args.share_port {
+                let ctx = cc.egui_ctx.clone();
+                match Sharing::start(port, move || ctx.request_repaint()) {
+                    Ok(sharing) => app.set_sharing(sharing, args.peers),
+                    Err(e) => app.report_error(format!(
+                        "Failed to listen for peers on port {}: {}",
+                        port, e
+                    )),
+                }
+            }
+            if let Some(dir) = &args.corpus {
+                match Corpus::open(dir) {
+                    Ok(corpus) => app.set_corpus(corpus),
+                    Err(e) => app.report_error(format!(
+                        "Failed to open the corpus in {}: {}",
+                        dir.display(),
+                        e
+                    )),
+                }
+            }
+            if let Some(port) = args.osc_port {
+                let ctx = cc.egui_ctx.clone();
+                match OscServer::bind(port, move || ctx.request_repaint()) {
+                    Ok(server) => app.set_osc_server(server),
+                    Err(e) => app.report_error(format!(
+                        "Failed to start OSC server on port {}: {}",
+                        port, e
+                    )),
+                }
+            }
+            if let Some(name) = args.profile {
+                app.set_profile_name(name);
+            }
+            #[cfg(feature = "midi")]
+            app.set_midi_port(args.midi_port);
+            if let Some(e) = audio_error {
+                app.report_error(e);
+            }
+            Box::new(app)
+        }),
+    );
+    if let Err(e) = result {
+        error!("Failed to start: {}", e);
+    }
+}
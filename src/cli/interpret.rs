@@ -0,0 +1,452 @@
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::io::{stdin, stdout, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audio::open_cpal_stream;
+use crate::cli::read_program_argument;
+use crate::evaluate::{AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE};
+use crate::instruction::{Instruction, IsaFeatures, RegId, RegWId};
+use crate::logging;
+use crate::machine::{Machine, TraceStep};
+use crate::program::Program;
+use crate::rewind::Rewind;
+use clap::Parser;
+use log::{error, info};
+
+// Bytes of output handed to the audio thread at a time, and how many of those may be waiting
+const CHUNK_SIZE: usize = 1024;
+const QUEUED_CHUNKS: usize = 16;
+
+const STEPS_PER_RUN: usize = 2048;
+
+/// Run a program, playing its output or writing it to stdout
+#[derive(Parser)]
+pub struct Args {
+    /// Program to run: a raw binary, a .lprog saved from evolve, .asm assembly
+    /// or a .bytebeat formula. Use - to read a binary or .lprog from stdin.
+    program: PathBuf,
+
+    /// Treat the program as assembly, whatever its extension
+    #[arg(long)]
+    assemble: bool,
+
+    /// Play on the first output device whose name contains this, instead of the default one
+    #[arg(long, conflicts_with = "stdout")]
+    device: Option<String>,
+
+    /// Sample rate the output is played at, in Hz
+    #[arg(long, default_value_t = DEFAULT_SAMPLE_RATE)]
+    sample_rate: usize,
+
+    /// Number of interleaved channels the output is played as
+    #[arg(long, default_value_t = AUDIO_CHANNELS)]
+    channels: usize,
+
+    /// Write the raw output bytes to stdout instead of playing them, for piping into other tools
+    #[arg(long)]
+    stdout: bool,
+
+    /// Stop after running this many instructions
+    #[arg(long)]
+    max_steps: Option<u64>,
+
+    /// Stop after producing this many bytes of output
+    #[arg(long)]
+    max_output: Option<u64>,
+
+    /// Run the program with the DSP instructions, even if it doesn't declare that it needs them
+    #[arg(long)]
+    dsp: bool,
+
+    /// Print each instruction to stderr as it runs, with its address and the registers it changed
+    #[arg(long)]
+    trace: bool,
+
+    /// Step through the program with commands typed on stdin instead of playing it.
+    /// Type help for the list of commands.
+    #[arg(long, conflicts_with_all = ["stdout", "device", "trace"])]
+    debug: bool,
+
+    /// Also show debug messages in the log
+    #[arg(long, short)]
+    verbose: bool,
+}
+
+fn read_program(args: &Args) -> Result<Program, String> {
+    if args.assemble {
+        let text = if args.program.as_os_str() == "-" {
+            let mut text = String::new();
+            stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| e.to_string())?;
+            text
+        } else {
+            std::fs::read_to_string(&args.program).map_err(|e| e.to_string())?
+        };
+        return Program::assemble(&text);
+    }
+    read_program_argument(&args.program)
+}
+
+/// Fills `output` from the chunks the machine has produced so far, and with silence if it's behind
+fn drain_chunks(receiver: &Receiver<Vec<u8>>, queue: &mut VecDeque<u8>, output: &mut [u8]) {
+    while queue.len() < output.len() {
+        match receiver.try_recv() {
+            Ok(chunk) => queue.extend(chunk),
+            Err(_) => break,
+        }
+    }
+    for byte in output.iter_mut() {
+        *byte = queue.pop_front().unwrap_or(128);
+    }
+}
+
+fn format_trace(step: &TraceStep) -> String {
+    let changes: Vec<String> = step
+        .register_changes
+        .iter()
+        .map(|(r, old, new)| format!("r{}: {} -> {}", r.0, old, new))
+        .collect();
+    format!(
+        "{:04x}  {:<24} {}",
+        step.program_counter,
+        step.instruction.to_string(),
+        changes.join(", ")
+    )
+}
+
+/// Runs the machine and keeps count of what it did against the --max-* limits
+struct Runner {
+    machine: Machine,
+    trace: bool,
+    steps: u64,
+    output: u64,
+    max_steps: u64,
+    max_output: u64,
+}
+
+impl Runner {
+    /// Runs up to `STEPS_PER_RUN` instructions, appending their output to `chunk`.
+    /// Returns false once a limit has been reached.
+    fn run(&mut self, chunk: &mut Vec<u8>) -> bool {
+        let length_before = chunk.len();
+        let steps = (STEPS_PER_RUN as u64).min(self.max_steps - self.steps);
+        if self.trace {
+            for _ in 0..steps {
+                eprintln!("{}", format_trace(&self.machine.step_traced(chunk)));
+            }
+        } else {
+            self.machine.run(steps as usize, chunk);
+        }
+        self.steps += steps;
+        self.output += (chunk.len() - length_before) as u64;
+        if self.output >= self.max_output {
+            chunk.truncate(chunk.len() - (self.output - self.max_output) as usize);
+            self.output = self.max_output;
+        }
+        self.steps < self.max_steps && self.output < self.max_output
+    }
+}
+
+const DEBUG_HELP: &str = "\
+step [N]         run N instructions, printing each. 1 if N is omitted.
+back [N]         undo N instructions, 1 if N is omitted
+continue         run until a breakpoint or a --max-* limit
+break [ADDR]     stop before the instruction at ADDR, or list the breakpoints
+delete ADDR      remove a breakpoint
+print REG        print a register as both small and wide, e.g. print r3
+registers        print every small register which isn't zero
+memory ADDR [N]  print N bytes of memory from ADDR, 16 if N is omitted
+list [N]         disassemble N instructions from the program counter, 8 if omitted
+dump PATH        write the whole memory to a file
+quit
+Addresses are decimal, or hexadecimal with a 0x prefix. An empty line repeats the last command.";
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("\"{}\" isn't a number", text))
+}
+
+/// Runs one debugger command, returning false to quit
+fn debug_command(
+    runner: &mut Runner,
+    rewind: &mut Rewind,
+    breakpoints: &mut BTreeSet<usize>,
+    line: &str,
+) -> Result<bool, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let argument = |i: usize| words.get(i).map(|w| parse_number(w)).transpose();
+    let Some(command) = words.first() else {
+        return Ok(true);
+    };
+    match *command {
+        "s" | "step" => {
+            for _ in 0..argument(1)?.unwrap_or(1) {
+                let mut output = Vec::new();
+                let step = runner.machine.step_traced(&mut output);
+                runner.steps += 1;
+                runner.output += output.len() as u64;
+                rewind.record(&runner.machine, runner.steps, runner.output);
+                print!("{}", format_trace(&step));
+                if !output.is_empty() {
+                    print!("  output {:02x?}", output);
+                }
+                println!();
+            }
+        }
+        "back" => {
+            let target = runner
+                .steps
+                .saturating_sub(argument(1)?.unwrap_or(1) as u64);
+            (runner.steps, runner.output) = rewind.rewind(&mut runner.machine, target);
+            if runner.steps > target {
+                println!("Can't go back further than step {}", runner.steps);
+            }
+            println!(
+                "{:04x} after {} steps",
+                runner.machine.program_counter(),
+                runner.steps
+            );
+        }
+        "c" | "continue" => {
+            let mut output = Vec::new();
+            loop {
+                output.clear();
+                runner.machine.run(1, &mut output);
+                runner.steps += 1;
+                runner.output += output.len() as u64;
+                rewind.record(&runner.machine, runner.steps, runner.output);
+                let pc = runner.machine.program_counter();
+                if breakpoints.contains(&pc) {
+                    println!(
+                        "Stopped at breakpoint {:04x} after {} steps",
+                        pc, runner.steps
+                    );
+                    break;
+                }
+                if runner.steps >= runner.max_steps || runner.output >= runner.max_output {
+                    println!("Stopped at {:04x} by the --max-* limits", pc);
+                    break;
+                }
+            }
+        }
+        "b" | "break" => match argument(1)? {
+            Some(address) => {
+                breakpoints.insert(address % runner.machine.memory().len());
+            }
+            None => {
+                for address in breakpoints.iter() {
+                    println!("{:04x}", address);
+                }
+            }
+        },
+        "d" | "delete" => {
+            let address =
+                argument(1)?.ok_or("delete needs an address")? % runner.machine.memory().len();
+            if !breakpoints.remove(&address) {
+                return Err(format!("there's no breakpoint at {:04x}", address));
+            }
+        }
+        "p" | "print" => {
+            let register = words
+                .get(1)
+                .and_then(|w| w.strip_prefix('r'))
+                .and_then(|i| i.parse::<u8>().ok())
+                .filter(|i| *i < 16)
+                .ok_or("print needs a register from r0 to r15")?;
+            println!(
+                "r{} = {}, wide r{} = {}",
+                register,
+                runner.machine.read_register(RegId(register)),
+                register,
+                runner.machine.read_register_wide(RegWId(register))
+            );
+        }
+        "r" | "registers" => {
+            for register in 0..16 {
+                let value = runner.machine.read_register(RegId(register));
+                if value != 0 {
+                    println!("r{} = {}", register, value);
+                }
+            }
+        }
+        "m" | "memory" => {
+            let memory = runner.machine.memory();
+            let start = argument(1)?.ok_or("memory needs an address")?;
+            let bytes: Vec<u8> = (start..start + argument(2)?.unwrap_or(16))
+                .map(|a| memory[a % memory.len()])
+                .collect();
+            for (row, chunk) in bytes.chunks(16).enumerate() {
+                let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                println!(
+                    "{:04x}  {}",
+                    (start + row * 16) % memory.len(),
+                    hex.join(" ")
+                );
+            }
+        }
+        "l" | "list" => {
+            let memory = runner.machine.memory();
+            let features = runner.machine.features();
+            let mut address = runner.machine.program_counter();
+            for _ in 0..argument(1)?.unwrap_or(8) {
+                let start = address;
+                let instruction = Instruction::decode(features, || {
+                    let b = memory[address];
+                    address = (address + 1) % memory.len();
+                    b
+                });
+                let marker = if breakpoints.contains(&start) {
+                    "*"
+                } else {
+                    " "
+                };
+                println!("{}{:04x}  {}", marker, start, instruction);
+            }
+        }
+        "dump" => {
+            let path = words.get(1).ok_or("dump needs a file name")?;
+            std::fs::write(path, runner.machine.memory()).map_err(|e| e.to_string())?;
+        }
+        "q" | "quit" => return Ok(false),
+        "h" | "help" => println!("{}", DEBUG_HELP),
+        _ => {
+            return Err(format!(
+                "Unknown command \"{}\", type help for a list",
+                command
+            ))
+        }
+    }
+    Ok(true)
+}
+
+/// Reads debugger commands from stdin until it closes or the user quits
+fn debug(mut runner: Runner) {
+    let mut breakpoints = BTreeSet::new();
+    let mut rewind = Rewind::new(&runner.machine);
+    let mut last_line = String::new();
+    let mut lines = stdin().lock().lines();
+    loop {
+        print!("{:04x}> ", runner.machine.program_counter());
+        stdout().flush().unwrap();
+        let Some(Ok(mut line)) = lines.next() else {
+            break;
+        };
+        if line.trim().is_empty() {
+            line = last_line.clone();
+        }
+        match debug_command(&mut runner, &mut rewind, &mut breakpoints, &line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => println!("{}", e),
+        }
+        last_line = line;
+    }
+    println!(
+        "Ran {} steps, {} bytes of output",
+        runner.steps, runner.output
+    );
+}
+
+pub fn run(args: Args) {
+    logging::init(args.verbose);
+
+    let program = match read_program(&args) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Failed to read {}: {}", args.program.display(), e);
+            return;
+        }
+    };
+    let features = program.features().union(IsaFeatures { dsp: args.dsp });
+    let mut runner = Runner {
+        machine: Machine::new_with_features(program.into_bytes(), features),
+        trace: args.trace,
+        steps: 0,
+        output: 0,
+        max_steps: args.max_steps.unwrap_or(u64::MAX),
+        max_output: args.max_output.unwrap_or(u64::MAX),
+    };
+
+    if args.debug {
+        if args.program.as_os_str() == "-" {
+            error!(
+                "--debug reads commands from stdin, so the program can't be read from there too"
+            );
+            return;
+        }
+        debug(runner);
+        return;
+    }
+
+    if args.stdout {
+        let mut stdout = stdout().lock();
+        let mut chunk = Vec::new();
+        loop {
+            chunk.clear();
+            let running = runner.run(&mut chunk);
+            // stop quietly once whatever reads the output goes away
+            if stdout.write_all(&chunk).is_err() || !running {
+                break;
+            }
+        }
+        info!(
+            "Ran {} steps, {} bytes of output",
+            runner.steps, runner.output
+        );
+        return;
+    }
+
+    let (sender, receiver) = sync_channel::<Vec<u8>>(QUEUED_CHUNKS);
+    let mut queue = VecDeque::new();
+    let stream_error = Arc::new(Mutex::new(None));
+    let _stream = match open_cpal_stream(
+        args.device.as_deref(),
+        args.channels,
+        args.sample_rate,
+        move |output| drain_chunks(&receiver, &mut queue, output),
+        Arc::clone(&stream_error),
+    ) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Failed to open audio output: {}", e);
+            return;
+        }
+    };
+
+    let start = Instant::now();
+    let mut chunk = Vec::new();
+    loop {
+        let running = runner.run(&mut chunk);
+        if chunk.len() < CHUNK_SIZE && running {
+            continue;
+        }
+        // blocks while the audio thread has enough to play
+        if sender.send(std::mem::take(&mut chunk)).is_err() {
+            return;
+        }
+        if let Some(e) = stream_error.lock().unwrap().take() {
+            error!("{}", e);
+            return;
+        }
+        if !running {
+            break;
+        }
+    }
+    info!(
+        "Ran {} steps, {} bytes of output",
+        runner.steps, runner.output
+    );
+    // let the queued output finish playing
+    let length = runner.output as f64 / (args.sample_rate * args.channels) as f64;
+    let remaining = Duration::from_secs_f64(length).saturating_sub(start.elapsed());
+    thread::sleep(remaining);
+}
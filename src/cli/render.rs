@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::cli::read_program_argument;
+use crate::evaluate::{
+    evaluate, EvalConfig, OutputMode, StopReason, AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE,
+};
+#[cfg(feature = "ogg")]
+use crate::export::write_ogg;
+use crate::export::{write_flac, write_midi_file, write_wav, ExportFormat};
+use crate::instruction::IsaFeatures;
+use crate::logging;
+use crate::physics::Physics;
+use crate::sequence::{decode_midi, MIDI_BYTES_PER_SECOND};
+use clap::Parser;
+use log::{error, info, warn};
+
+/// Run a program without a window and write its output to an audio file
+#[derive(Parser)]
+pub struct Args {
+    /// Program to run: a raw binary, a .lprog saved from evolve, .asm assembly or a .bytebeat formula.
+    /// Use - to read a binary or .lprog from stdin.
+    program: PathBuf,
+
+    /// File to write. The format follows the extension: .wav, .flac, .ogg (with
+    /// the ogg feature), or .mid to decode the output as MIDI.
+    #[arg(long, short)]
+    output: PathBuf,
+
+    /// Length of the output, in seconds
+    #[arg(long, default_value_t = 10.0, value_parser = parse_secs)]
+    seconds: f32,
+
+    /// Sample rate the output is played at, in Hz
+    #[arg(long, default_value_t = DEFAULT_SAMPLE_RATE)]
+    sample_rate: usize,
+
+    /// Number of interleaved channels the output is played as
+    #[arg(long, default_value_t = AUDIO_CHANNELS as u16, value_parser = clap::value_parser!(u16).range(1..))]
+    channels: u16,
+
+    /// Longest time to spend running the program, in seconds.
+    /// Output after that is silent.
+    #[arg(long, default_value_t = 600.0, value_parser = parse_secs)]
+    time_budget_secs: f32,
+
+    /// Run the program with the DSP instructions, even if it doesn't declare that it needs them
+    #[arg(long)]
+    dsp: bool,
+
+    /// Also show debug messages in the log
+    #[arg(long, short)]
+    verbose: bool,
+}
+
+// Parses a finite, non-negative number of seconds
+fn parse_secs(text: &str) -> Result<f32, String> {
+    let secs: f32 = text.parse().map_err(|e| format!("{}", e))?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(format!("{} isn't a non-negative number of seconds", text));
+    }
+    Ok(secs)
+}
+
+pub fn run(args: Args) {
+    logging::init(args.verbose);
+
+    let Some(format) = args
+        .output
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(ExportFormat::from_extension)
+    else {
+        error!("Unknown output format, expected a .wav, .flac, .ogg or .mid file name");
+        return;
+    };
+    let program = match read_program_argument(&args.program) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Failed to read {}: {}", args.program.display(), e);
+            return;
+        }
+    };
+
+    let (output_mode, bytes_per_second) = match format {
+        ExportFormat::Midi => (OutputMode::Midi, MIDI_BYTES_PER_SECOND),
+        _ => (OutputMode::Audio, args.sample_rate * args.channels as usize),
+    };
+    let frame_size = match format {
+        ExportFormat::Midi => 1,
+        _ => args.channels as usize,
+    };
+    let frames = (args.seconds * bytes_per_second as f32) as usize / frame_size;
+    // physics lay the output out as `AUDIO_CHANNELS` channels
+    let program = if frame_size == AUDIO_CHANNELS || program.physics().is_default() {
+        program
+    } else {
+        warn!(
+            "Ignoring the program's physics ({}), which only work with {} channels",
+            program.physics(),
+            AUDIO_CHANNELS
+        );
+        program.with_physics(Physics::default())
+    };
+    let config = EvalConfig {
+        sample_rate: args.sample_rate,
+        preview_length: frames * frame_size,
+        time_budget: Duration::from_secs_f32(args.time_budget_secs),
+        max_steps: usize::MAX,
+        output_mode,
+        extra_isa: IsaFeatures { dsp: args.dsp },
+    };
+    let evaluation = evaluate(&program, &config);
+    if evaluation.stop_reason == StopReason::TimedOut {
+        warn!(
+            "Ran out of time after {:.1} of {} seconds, the rest is silent",
+            evaluation.output_produced as f32 / bytes_per_second as f32,
+            args.seconds
+        );
+    }
+
+    let file = match File::create(&args.output) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("Failed to create {}: {}", args.output.display(), e);
+            return;
+        }
+    };
+    let mut writer = BufWriter::new(file);
+    let output = &evaluation.output;
+    let sample_rate = args.sample_rate as u32;
+    let result = match format {
+        ExportFormat::Wav => write_wav(&mut writer, output, args.channels, sample_rate),
+        ExportFormat::Flac => write_flac(&mut writer, output, args.channels, sample_rate),
+        #[cfg(feature = "ogg")]
+        ExportFormat::Ogg => write_ogg(&mut writer, output, args.channels, sample_rate),
+        ExportFormat::Midi => write_midi_file(&mut writer, &decode_midi(output)),
+    };
+    match result {
+        Ok(()) => info!(
+            "Rendered {} seconds to {} in {:.1} s",
+            args.seconds,
+            args.output.display(),
+            evaluation.elapsed.as_secs_f32()
+        ),
+        Err(e) => error!("Failed to write {}: {}", args.output.display(), e),
+    }
+}
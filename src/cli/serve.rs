@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::app::random_program;
+use crate::corpus::load_program_directory;
+use crate::evaluate::{evaluate_and_analyse, EvalConfig, AUDIO_CHANNELS};
+use crate::export::write_wav;
+use crate::features::Features;
+use crate::logging;
+use crate::mutation::{ChangePhysics, MutationRegistry};
+use crate::parallel::ParallelMap;
+use crate::physics::PHYSICS_MUTATION_WEIGHT;
+use crate::program::Program;
+use crate::spectrogram::{gradient_colour, Spectrogram, SpectrogramRenderer, SPECTROGRAM_COLOURS};
+use clap::Parser;
+use log::{error, info, warn};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use threadpool::ThreadPool;
+
+// Longest request body accepted, which is plenty for a list of indices
+const MAX_BODY_LENGTH: usize = 1 << 16;
+
+// Longest request line and headers accepted, together
+const MAX_HEADER_LENGTH: u64 = 1 << 14;
+
+// How long a client may take to send its whole request, or to read the
+// response, before it's dropped, since a slow client would otherwise hold up
+// the server
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const VIEWER_HTML: &str = include_str!("serve.html");
+
+/// Evolve programs without a window, curating them from a browser
+#[derive(Parser)]
+pub struct Args {
+    /// Address to serve the viewer and API on. Use 0.0.0.0 to reach it from other devices.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    address: String,
+
+    /// Use every .bin, .lprog, .asm and .bytebeat file in this directory as the first
+    /// generation. Starts from random programs if omitted.
+    #[arg(long)]
+    population_dir: Option<PathBuf>,
+
+    /// Number of instances in each generation
+    #[arg(long, default_value_t = 16)]
+    population: usize,
+
+    /// Number of mutations applied to each child
+    #[arg(long, default_value_t = 8)]
+    mutation: usize,
+
+    /// Also mutate whole instructions, like the "Smart mutations" setting
+    #[arg(long)]
+    smart_mutations: bool,
+
+    /// Also mutate the clock divider, channel count and cycles per sample
+    /// programs run with, like the "Evolve physics" setting
+    #[arg(long)]
+    evolve_physics: bool,
+
+    /// Seed for the random number generator, to make a run reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Also show debug messages in the log
+    #[arg(long, short)]
+    verbose: bool,
+}
+
+struct ServedInstance {
+    program: Program,
+    output: Arc<[u8]>,
+    spectrogram: Spectrogram,
+    features: Features,
+    is_selected: bool,
+}
+
+/// The population, evolved one generation at a time as selections come in
+struct Engine {
+    generation: usize,
+    instances: Vec<ServedInstance>,
+    population_size: usize,
+    mutation_amount: usize,
+    mutations: MutationRegistry,
+    rng: StdRng,
+    eval_config: EvalConfig,
+    spectrogram_renderer: SpectrogramRenderer,
+    threadpool: ThreadPool,
+}
+
+impl Engine {
+    fn render(&mut self, programs: Vec<Program>) -> Vec<ServedInstance> {
+        let config = &self.eval_config;
+        let renderer = &self.spectrogram_renderer;
+        let threadpool = &mut self.threadpool;
+        threadpool.map_balanced(programs, |program| {
+            let evaluation = evaluate_and_analyse(&program, config, renderer);
+            ServedInstance {
+                program,
+                output: evaluation.output,
+                spectrogram: evaluation.spectrogram.unwrap(),
+                features: evaluation.features.unwrap(),
+                is_selected: false,
+            }
+        })
+    }
+
+    /// Replaces the population with mutated children of the selected
+    /// instances, or of every instance if none are selected
+    fn next_generation(&mut self) {
+        let selected: Vec<&Program> = self
+            .instances
+            .iter()
+            .filter(|i| i.is_selected)
+            .map(|i| &i.program)
+            .collect();
+        let parents = if selected.is_empty() {
+            self.instances.iter().map(|i| &i.program).collect()
+        } else {
+            selected
+        };
+        let mut children = Vec::with_capacity(self.population_size);
+        for _ in 0..self.population_size {
+            let mut child = (*parents.choose(&mut self.rng).unwrap()).clone();
+            for _ in 0..self.mutation_amount {
+                self.mutations.mutate(&mut child, &mut self.rng);
+            }
+            children.push(child);
+        }
+        self.instances = self.render(children);
+        self.generation += 1;
+        info!("Generation {}", self.generation);
+    }
+}
+
+#[derive(Serialize)]
+struct InstanceSummary {
+    index: usize,
+    length: usize,
+    hash: String,
+    selected: bool,
+    summary: String,
+    // how the program's output becomes audio, "native" unless it evolved
+    physics: String,
+    spectrogram: String,
+    preview: String,
+}
+
+#[derive(Serialize)]
+struct GenerationSummary {
+    generation: usize,
+    instances: Vec<InstanceSummary>,
+}
+
+#[derive(Deserialize)]
+struct Selection {
+    selected: Vec<usize>,
+    // also breed the next generation from the selection
+    #[serde(default)]
+    next: bool,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Response {
+        Response {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn json<T: Serialize>(value: &T) -> Response {
+        Response::ok("application/json", serde_json::to_vec(value).unwrap())
+    }
+
+    fn error(status: &'static str, message: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: message.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Reads from a stream until a deadline, shortening the stream's read timeout
+/// so that it can't be restarted by a trickle of bytes
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request took too long to send",
+            ));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+// Reads a line of the request's head, which must end before the header limit
+fn read_header_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request headers are too long or incomplete",
+        ));
+    }
+    Ok(line)
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let deadline = Instant::now() + CLIENT_TIMEOUT;
+    let mut reader = BufReader::new(DeadlineReader { stream, deadline }.take(MAX_HEADER_LENGTH));
+    let request_line = read_header_line(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed request");
+    let method = parts.next().ok_or_else(invalid)?.to_string();
+    let path = parts.next().ok_or_else(invalid)?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let line = read_header_line(&mut reader)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    let content_length: usize = headers
+        .get("content-length")
+        .map_or(Ok(0), |l| l.parse())
+        .map_err(|_| invalid())?;
+    if content_length > MAX_BODY_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request body is too long",
+        ));
+    }
+    // the body may be partly buffered already, so this only ever allows more
+    reader.get_mut().set_limit(content_length as u64);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request { method, path, body })
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)
+}
+
+fn encode_png(spectrogram: &Spectrogram) -> Vec<u8> {
+    let pixels: Vec<u8> = spectrogram
+        .values
+        .iter()
+        .flat_map(|t| gradient_colour(*t, &SPECTROGRAM_COLOURS))
+        .collect();
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(
+        &mut data,
+        spectrogram.width as u32,
+        spectrogram.height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&pixels).unwrap();
+    writer.finish().unwrap();
+    data
+}
+
+fn summarize(engine: &Engine) -> GenerationSummary {
+    // the generation is part of each url, so browsers never show a stale image or preview
+    let generation = engine.generation;
+    GenerationSummary {
+        generation,
+        instances: engine
+            .instances
+            .iter()
+            .enumerate()
+            .map(|(index, i)| InstanceSummary {
+                index,
+                length: i.program.len(),
+                hash: i.program.content_hash().to_string(),
+                selected: i.is_selected,
+                summary: i.features.summary(),
+                physics: i.program.physics().to_string(),
+                spectrogram: format!("/instances/{}/spectrogram.png?g={}", index, generation),
+                preview: format!("/instances/{}/preview.wav?g={}", index, generation),
+            })
+            .collect(),
+    }
+}
+
+fn handle(engine: &mut Engine, request: Request) -> Response {
+    let path = request.path.split('?').next().unwrap();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", []) => Response::ok("text/html; charset=utf-8", VIEWER_HTML.as_bytes().to_vec()),
+        ("GET", ["api", "generation"]) => Response::json(&summarize(engine)),
+        ("POST", ["api", "selection"]) => {
+            let selection: Selection = match serde_json::from_slice(&request.body) {
+                Ok(s) => s,
+                Err(e) => return Response::error("400 Bad Request", &e.to_string()),
+            };
+            if let Some(i) = selection
+                .selected
+                .iter()
+                .find(|i| **i >= engine.instances.len())
+            {
+                return Response::error("400 Bad Request", &format!("no instance {}", i));
+            }
+            for (i, instance) in engine.instances.iter_mut().enumerate() {
+                instance.is_selected = selection.selected.contains(&i);
+            }
+            if selection.next {
+                engine.next_generation();
+            }
+            Response::json(&summarize(engine))
+        }
+        ("GET", ["instances", index, file]) => {
+            let Some(instance) = index
+                .parse()
+                .ok()
+                .and_then(|i: usize| engine.instances.get(i))
+            else {
+                return Response::error("404 Not Found", "no such instance");
+            };
+            match *file {
+                "spectrogram.png" => Response::ok("image/png", encode_png(&instance.spectrogram)),
+                "preview.wav" => {
+                    let mut data = Vec::new();
+                    write_wav(
+                        &mut data,
+                        &instance.output,
+                        AUDIO_CHANNELS as u16,
+                        engine.eval_config.sample_rate as u32,
+                    )
+                    .unwrap();
+                    Response::ok("audio/wav", data)
+                }
+                _ => Response::error("404 Not Found", "not found"),
+            }
+        }
+        _ => Response::error("404 Not Found", "not found"),
+    }
+}
+
+pub fn run(args: Args) {
+    logging::init(args.verbose);
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let programs: Vec<Program> = match &args.population_dir {
+        Some(dir) => match load_program_directory(dir) {
+            Ok(p) => p.into_iter().map(|(_, p)| p).collect(),
+            Err(e) => {
+                error!("Failed to read {}: {}", dir.display(), e);
+                return;
+            }
+        },
+        None => (0..args.population)
+            .map(|_| Program::new(random_program(256, &mut rng)).unwrap())
+            .collect(),
+    };
+    if programs.is_empty() {
+        error!("No programs to start from");
+        return;
+    }
+
+    let listener = match TcpListener::bind(&args.address) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Failed to listen on {}: {}", args.address, e);
+            return;
+        }
+    };
+
+    let mut mutations = if args.smart_mutations {
+        MutationRegistry::instruction_aware()
+    } else {
+        MutationRegistry::default()
+    };
+    if args.evolve_physics {
+        mutations.register(ChangePhysics, PHYSICS_MUTATION_WEIGHT);
+    }
+    let mut engine = Engine {
+        generation: 0,
+        instances: Vec::new(),
+        population_size: args.population,
+        mutation_amount: args.mutation,
+        mutations,
+        rng,
+        eval_config: EvalConfig::default(),
+        spectrogram_renderer: SpectrogramRenderer::new(),
+        threadpool: ThreadPool::new(std::thread::available_parallelism().map_or(1, |n| n.get())),
+    };
+    engine.instances = engine.render(programs);
+    info!("Serving generation 0 on http://{}", args.address);
+
+    // Requests are handled one at a time, so a new generation is never served half-rendered
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let timeouts = stream
+            .set_read_timeout(Some(CLIENT_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(CLIENT_TIMEOUT)));
+        if let Err(e) = timeouts {
+            warn!("Failed to set the connection's timeouts: {}", e);
+            continue;
+        }
+        let response = match read_request(&mut stream) {
+            Ok(request) => handle(&mut engine, request),
+            Err(e) => Response::error("400 Bad Request", &e.to_string()),
+        };
+        if let Err(e) = write_response(&mut stream, &response) {
+            warn!("Failed to respond: {}", e);
+        }
+    }
+}
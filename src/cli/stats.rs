@@ -0,0 +1,133 @@
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
+
+use crate::cli::read_program_argument;
+use crate::corpus::load_program_directory;
+use crate::evaluate::{
+    evaluate_and_analyse, EvalConfig, OutputMode, StopReason, DEFAULT_SAMPLE_RATE,
+};
+use crate::instruction::{decode_instructions, Instruction};
+use crate::logging;
+use crate::program::Program;
+use crate::spectrogram::SpectrogramRenderer;
+use clap::Parser;
+use log::error;
+use serde::Serialize;
+
+/// Run programs and print what they and their output are like, as one line of JSON per program.
+/// Spectral measurements are fractions of the spectrogram's frequency range.
+#[derive(Parser)]
+pub struct Args {
+    /// Programs to describe, or directories of .bin, .lprog, .asm and .bytebeat programs
+    #[arg(required = true)]
+    programs: Vec<PathBuf>,
+
+    /// Length of the output to analyse, in seconds
+    #[arg(long)]
+    preview_secs: Option<f32>,
+
+    /// Sample rate the output is analysed at, in Hz
+    #[arg(long, default_value_t = DEFAULT_SAMPLE_RATE)]
+    sample_rate: usize,
+
+    /// Longest time to spend running each program, in seconds.
+    /// Output after that is silent.
+    #[arg(long)]
+    time_budget_secs: Option<f32>,
+
+    /// Also show debug messages in the log
+    #[arg(long, short)]
+    verbose: bool,
+}
+
+#[derive(Serialize)]
+struct ProgramStats {
+    path: String,
+    length: usize,
+    instructions: usize,
+    outputs: usize,
+    jumps: usize,
+    stop_reason: &'static str,
+    // bytes the program produced itself, before padding with silence
+    output_length: usize,
+    steps: usize,
+    elapsed_secs: f32,
+    rms: f32,
+    peak: f32,
+    spectral_centroid: f32,
+    spectral_rolloff: f32,
+    spectral_flatness: f32,
+    zero_crossing_rate: f32,
+    // in Hz, null if unpitched
+    pitch: Option<f32>,
+}
+
+fn program_stats(
+    path: &Path,
+    program: &Program,
+    config: &EvalConfig,
+    renderer: &SpectrogramRenderer,
+) -> ProgramStats {
+    let instructions = decode_instructions(program.bytes(), config.isa(program));
+    let count = |f: fn(&Instruction) -> bool| instructions.iter().filter(|(_, i)| f(i)).count();
+    let evaluation = evaluate_and_analyse(program, config, renderer);
+    let features = evaluation.features.unwrap();
+    ProgramStats {
+        path: path.display().to_string(),
+        length: program.len(),
+        instructions: instructions.len(),
+        outputs: count(|i| matches!(i, Instruction::Output(_) | Instruction::OutputW(_))),
+        jumps: count(|i| matches!(i, Instruction::Jmp(_) | Instruction::Jo(..))),
+        stop_reason: match evaluation.stop_reason {
+            StopReason::Complete => "complete",
+            StopReason::OutOfSteps => "out_of_steps",
+            StopReason::TimedOut => "timed_out",
+        },
+        output_length: evaluation.output_produced,
+        steps: evaluation.steps,
+        elapsed_secs: evaluation.elapsed.as_secs_f32(),
+        rms: features.rms,
+        peak: features.peak,
+        spectral_centroid: features.spectral_centroid,
+        spectral_rolloff: features.spectral_rolloff,
+        spectral_flatness: features.spectral_flatness,
+        zero_crossing_rate: features.zero_crossing_rate,
+        pitch: features.fundamental,
+    }
+}
+
+pub fn run(args: Args) {
+    logging::init(args.verbose);
+
+    let mut config = EvalConfig::new(args.sample_rate, args.preview_secs, OutputMode::Audio);
+    if let Some(secs) = args.time_budget_secs {
+        config.time_budget = Duration::from_secs_f32(secs);
+    }
+    let renderer = SpectrogramRenderer::new();
+
+    let mut programs = Vec::new();
+    for path in &args.programs {
+        if path.is_dir() {
+            match load_program_directory(path) {
+                Ok(p) => programs.extend(p),
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    exit(1);
+                }
+            }
+        } else {
+            match read_program_argument(path) {
+                Ok(p) => programs.push((path.clone(), p)),
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    exit(1);
+                }
+            }
+        }
+    }
+    for (path, program) in &programs {
+        let stats = program_stats(path, program, &config, &renderer);
+        println!("{}", serde_json::to_string(&stats).unwrap());
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::instruction::{decode_instructions, DspOperation, Instruction, IsaFeatures, Operation};
+
+/// A kind of instruction that a constraint can refer to. Written as the
+/// instruction's assembly name, like "jmp" or "outputw", or as an operation's
+/// mnemonic like "xor", which matches the operation in any of its forms.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InstructionClass {
+    Output,
+    OutputW,
+    LoadMem,
+    LoadMemW,
+    StoreMem,
+    StoreMemW,
+    Jmp,
+    Jo,
+    Operation(Operation),
+    Dsp(DspOperation),
+}
+
+const NAMED_CLASSES: [(&str, InstructionClass); 8] = [
+    ("output", InstructionClass::Output),
+    ("outputw", InstructionClass::OutputW),
+    ("loadmem", InstructionClass::LoadMem),
+    ("loadmemw", InstructionClass::LoadMemW),
+    ("storemem", InstructionClass::StoreMem),
+    ("storememw", InstructionClass::StoreMemW),
+    ("jmp", InstructionClass::Jmp),
+    ("jo", InstructionClass::Jo),
+];
+
+impl InstructionClass {
+    pub fn of(instruction: &Instruction) -> InstructionClass {
+        match instruction {
+            Instruction::Output(..) => InstructionClass::Output,
+            Instruction::OutputW(..) => InstructionClass::OutputW,
+            Instruction::LoadMem(..) => InstructionClass::LoadMem,
+            Instruction::LoadMemW(..) => InstructionClass::LoadMemW,
+            Instruction::StoreMem(..) => InstructionClass::StoreMem,
+            Instruction::StoreMemW(..) => InstructionClass::StoreMemW,
+            Instruction::Jmp(..) => InstructionClass::Jmp,
+            Instruction::Jo(..) => InstructionClass::Jo,
+            Instruction::Op(o, ..)
+            | Instruction::OpW(o, ..)
+            | Instruction::OpImm(o, ..)
+            | Instruction::OpImmW(o, ..) => InstructionClass::Operation(*o),
+            Instruction::Dsp(o, ..) => InstructionClass::Dsp(*o),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InstructionClass> {
+        NAMED_CLASSES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| *c)
+            .or_else(|| Operation::from_mnemonic(name).map(InstructionClass::Operation))
+            .or_else(|| DspOperation::from_mnemonic(name).map(InstructionClass::Dsp))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstructionClass::Operation(o) => o.mnemonic(),
+            InstructionClass::Dsp(o) => o.mnemonic(),
+            class => NAMED_CLASSES.iter().find(|(_, c)| c == class).unwrap().0,
+        }
+    }
+}
+
+/// Something a program must or must not contain
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Constraint {
+    Require(InstructionClass),
+    Forbid(InstructionClass),
+}
+
+/// Constraints which mutated children have to meet. Written separated by
+/// spaces, with forbidden classes prefixed by "!", like "jmp !outputw".
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Constraints(Vec<Constraint>);
+
+impl Constraints {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether the program meets every constraint. Only canonically encoded
+    /// instructions count, like in the disassembly.
+    pub fn allow(&self, program: &[u8], features: IsaFeatures) -> bool {
+        if self.0.is_empty() {
+            return true;
+        }
+        let classes: Vec<InstructionClass> = decode_instructions(program, features)
+            .iter()
+            .map(|(_, i)| InstructionClass::of(i))
+            .collect();
+        self.0.iter().all(|constraint| match constraint {
+            Constraint::Require(class) => classes.contains(class),
+            Constraint::Forbid(class) => !classes.contains(class),
+        })
+    }
+}
+
+impl fmt::Display for Constraints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, constraint) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            match constraint {
+                Constraint::Require(class) => write!(f, "{}", class.name())?,
+                Constraint::Forbid(class) => write!(f, "!{}", class.name())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Constraints> for String {
+    fn from(constraints: Constraints) -> String {
+        constraints.to_string()
+    }
+}
+
+impl TryFrom<String> for Constraints {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Constraints, String> {
+        text.parse()
+    }
+}
+
+impl std::str::FromStr for Constraints {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Constraints, String> {
+        text.split_whitespace()
+            .map(|word| {
+                let (forbid, name) = match word.strip_prefix('!') {
+                    Some(name) => (true, name),
+                    None => (false, word),
+                };
+                let class = InstructionClass::from_name(&name.to_lowercase())
+                    .ok_or_else(|| format!("unknown instruction or operation \"{}\"", name))?;
+                Ok(if forbid {
+                    Constraint::Forbid(class)
+                } else {
+                    Constraint::Require(class)
+                })
+            })
+            .collect::<Result<Vec<Constraint>, String>>()
+            .map(Constraints)
+    }
+}
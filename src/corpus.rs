@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::instruction::compile_bytebeat;
+use crate::program::{Program, ProgramHash, Provenance};
+
+// Kept in the corpus directory, next to the programs it describes
+const INDEX_FILE_NAME: &str = "corpus.toml";
+
+/// Reads a program from a file, by its extension: a .lprog container, .asm
+/// assembly, a .bytebeat formula (see `compile_bytebeat`) or raw bytes otherwise
+pub fn read_program_file(path: &Path) -> Result<Program, String> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("lprog") => fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| Program::read_container(&data))
+            .map(|(p, _)| p),
+        Some("asm") => fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Program::assemble(&text)),
+        Some("bytebeat") => fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| compile_bytebeat(text.trim()))
+            .and_then(Program::new),
+        _ => fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(Program::new),
+    }
+}
+
+/// Reads every .bin, .lprog, .asm and .bytebeat file in a directory, in order
+/// of file name. Files that can't be read, assembled or compiled are skipped.
+pub fn load_program_directory(dir: &Path) -> io::Result<Vec<(PathBuf, Program)>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    paths.sort();
+    let mut programs = Vec::new();
+    for path in paths {
+        if !is_program_file(&path) {
+            continue;
+        }
+        match read_program_file(&path) {
+            Ok(p) => programs.push((path, p)),
+            Err(e) => warn!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    Ok(programs)
+}
+
+fn is_program_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("bin" | "lprog" | "asm" | "bytebeat")
+    )
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Formats seconds since the Unix epoch as a UTC date, e.g. 2024-03-01
+pub fn format_date(secs: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// A program in a corpus, and what is known about it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CorpusEntry {
+    // name of the file within the corpus directory
+    pub file: String,
+    pub hash: ProgramHash,
+    pub length: usize,
+    // when the program was added, in seconds since the Unix epoch
+    pub added: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub provenance: Provenance,
+}
+
+impl CorpusEntry {
+    /// Whether every word of `query` is one of the tags, part of the file
+    /// name or the start of the hash
+    pub fn matches(&self, query: &str) -> bool {
+        let hash = self.hash.to_string();
+        query.split_whitespace().all(|word| {
+            self.tags.iter().any(|t| t == word)
+                || self.file.contains(word)
+                || hash.starts_with(word)
+        })
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CorpusIndex {
+    #[serde(default)]
+    entries: Vec<CorpusEntry>,
+}
+
+/// A directory of saved programs with an index of their hashes, tags and lineage.
+/// Programs copied into the directory by hand are indexed when it is next opened.
+pub struct Corpus {
+    dir: PathBuf,
+    entries: Vec<CorpusEntry>,
+}
+
+impl Corpus {
+    /// Opens a corpus, creating the directory if it doesn't exist yet
+    pub fn open(dir: &Path) -> Result<Corpus, String> {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let index = match fs::read_to_string(dir.join(INDEX_FILE_NAME)) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|e| format!("Failed to read {}: {}", INDEX_FILE_NAME, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => CorpusIndex::default(),
+            Err(e) => return Err(format!("Failed to read {}: {}", INDEX_FILE_NAME, e)),
+        };
+        let mut corpus = Corpus {
+            dir: dir.to_path_buf(),
+            entries: index.entries,
+        };
+        if corpus.refresh().map_err(|e| e.to_string())? {
+            corpus.save()?;
+        }
+        Ok(corpus)
+    }
+
+    /// Indexes files which aren't in the index yet and forgets ones which have
+    /// been deleted. Returns whether anything changed.
+    fn refresh(&mut self) -> io::Result<bool> {
+        let length_before = self.entries.len();
+        let dir = &self.dir;
+        self.entries.retain(|e| dir.join(&e.file).exists());
+        let mut changed = self.entries.len() != length_before;
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        paths.sort();
+        for path in paths {
+            let file = path.file_name().unwrap().to_string_lossy().to_string();
+            if !is_program_file(&path) || self.entries.iter().any(|e| e.file == file) {
+                continue;
+            }
+            let program = match read_program_file(&path) {
+                Ok(p) => p,
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let provenance = fs::read(&path)
+                .ok()
+                .and_then(|data| Program::read_container(&data).ok())
+                .map(|(_, p)| p)
+                .unwrap_or_default();
+            let added = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .map_or(0, unix_time);
+            self.entries.push(CorpusEntry {
+                file,
+                hash: program.content_hash(),
+                length: program.len(),
+                added,
+                tags: Vec::new(),
+                provenance,
+            });
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let index = CorpusIndex {
+            entries: self.entries.clone(),
+        };
+        fs::write(
+            self.dir.join(INDEX_FILE_NAME),
+            toml::to_string(&index).unwrap(),
+        )
+        .map_err(|e| format!("Failed to write {}: {}", INDEX_FILE_NAME, e))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// In the order they were indexed
+    pub fn entries(&self) -> &[CorpusEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, hash: ProgramHash) -> Option<&CorpusEntry> {
+        self.entries.iter().find(|e| e.hash == hash)
+    }
+
+    pub fn search(&self, query: &str) -> Vec<&CorpusEntry> {
+        self.entries.iter().filter(|e| e.matches(query)).collect()
+    }
+
+    /// Groups of entries holding the same program, e.g. from saving it twice
+    pub fn duplicates(&self) -> Vec<Vec<&CorpusEntry>> {
+        let mut by_hash: HashMap<ProgramHash, Vec<&CorpusEntry>> = HashMap::new();
+        for entry in &self.entries {
+            by_hash.entry(entry.hash).or_default().push(entry);
+        }
+        let mut groups: Vec<Vec<&CorpusEntry>> =
+            by_hash.into_values().filter(|g| g.len() > 1).collect();
+        groups.sort_by(|a, b| a[0].file.cmp(&b[0].file));
+        groups
+    }
+
+    /// The known ancestors of a program, parent first, as far back as the corpus goes
+    pub fn lineage(&self, hash: ProgramHash) -> Vec<&CorpusEntry> {
+        let mut ancestors: Vec<&CorpusEntry> = Vec::new();
+        let mut next = self.find(hash);
+        while let Some(entry) = next {
+            next = entry
+                .provenance
+                .parents
+                .first()
+                .and_then(|p| self.find(*p))
+                .filter(|p| ancestors.iter().all(|a| a.hash != p.hash) && p.hash != hash);
+            if let Some(parent) = next {
+                ancestors.push(parent);
+            }
+        }
+        ancestors
+    }
+
+    /// Saves a program into the corpus as a .lprog file, unless it's already there
+    pub fn add(
+        &mut self,
+        program: &Program,
+        provenance: &Provenance,
+        tags: Vec<String>,
+    ) -> Result<&CorpusEntry, String> {
+        let hash = program.content_hash();
+        if let Some(existing) = self.find(hash) {
+            return Err(format!("already in the corpus as {}", existing.file));
+        }
+        let file = format!("{}.lprog", hash);
+        let mut data = Vec::new();
+        program.write_container(&mut data, provenance).unwrap();
+        fs::write(self.dir.join(&file), data).map_err(|e| e.to_string())?;
+        info!("Added {} to the corpus", file);
+        self.entries.push(CorpusEntry {
+            file,
+            hash,
+            length: program.len(),
+            added: unix_time(SystemTime::now()),
+            tags,
+            provenance: provenance.clone(),
+        });
+        self.save()?;
+        Ok(self.entries.last().unwrap())
+    }
+
+    /// Replaces the tags of every entry holding the program
+    pub fn set_tags(&mut self, hash: ProgramHash, tags: Vec<String>) -> Result<(), String> {
+        let mut found = false;
+        for entry in self.entries.iter_mut().filter(|e| e.hash == hash) {
+            entry.tags = tags.clone();
+            found = true;
+        }
+        if !found {
+            return Err(format!("{} is not in the corpus", hash));
+        }
+        self.save()
+    }
+
+    pub fn load(&self, entry: &CorpusEntry) -> Result<Program, String> {
+        read_program_file(&self.dir.join(&entry.file))
+    }
+}
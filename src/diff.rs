@@ -0,0 +1,84 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Change {
+    /// Element `.0` of `a` is equal to element `.1` of `b`
+    Same(usize, usize),
+    /// Element `.0` of `a` is not present in `b`
+    Removed(usize),
+    /// Element `.0` of `b` is not present in `a`
+    Added(usize),
+}
+
+/// Computes a shortest edit script turning `a` into `b` using Myers' algorithm.
+/// Every element of both sequences appears exactly once in the result, in order.
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Change> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+
+    // v[k + offset] is the furthest x reached on diagonal k. Only diagonals
+    // -d..=d are reachable after d edits, so each step of the trace only
+    // stores that range (plus one on either side).
+    let offset = max + 1;
+    let mut v: Vec<isize> = vec![0; (2 * offset + 1) as usize];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let furthest = |v: &[isize], k: isize| v[(k + offset) as usize];
+    let came_from_above = |d: isize, k: isize, v: &[isize]| -> bool {
+        k == -d || (k != d && furthest(v, k - 1) < furthest(v, k + 1))
+    };
+
+    'search: for d in 0..=max {
+        trace.push(v[((offset - d - 1) as usize)..=((offset + d + 1) as usize)].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let mut x = if came_from_above(d, k, &v) {
+                furthest(&v, k + 1)
+            } else {
+                furthest(&v, k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    let mut changes = Vec::with_capacity((n.max(m)) as usize);
+    let (mut x, mut y) = (n, m);
+    for (d, trimmed) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        // Re-expand the trimmed snapshot so that diagonals index the same way
+        let base = offset - d - 1;
+        let lookup = |k: isize| trimmed[(k + offset - base) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && lookup(k - 1) < lookup(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = lookup(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            changes.push(Change::Same(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                changes.push(Change::Added(y as usize));
+            } else {
+                x -= 1;
+                changes.push(Change::Removed(x as usize));
+            }
+        }
+    }
+    changes.reverse();
+    changes
+}
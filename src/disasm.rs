@@ -0,0 +1,6 @@
+use clap::Parser;
+use lemurs::cli::disasm::{run, Args};
+
+fn main() {
+    run(Args::parse());
+}
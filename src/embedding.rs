@@ -0,0 +1,65 @@
+/// Places points in 2-D so that their euclidean distances approximate the
+/// given distance matrix, using classical multidimensional scaling.
+/// `distances` must be square and symmetric. The result is centred on the
+/// origin but otherwise unnormalized.
+pub fn classical_mds(distances: &[Vec<f32>]) -> Vec<[f32; 2]> {
+    let n = distances.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    // Double-centre the squared distances to get the Gram matrix
+    let squared: Vec<Vec<f32>> = distances
+        .iter()
+        .map(|row| row.iter().map(|d| d * d).collect())
+        .collect();
+    let row_means: Vec<f32> = squared
+        .iter()
+        .map(|row| row.iter().sum::<f32>() / n as f32)
+        .collect();
+    let grand_mean = row_means.iter().sum::<f32>() / n as f32;
+    let mut gram: Vec<Vec<f32>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| -0.5 * (squared[i][j] - row_means[i] - row_means[j] + grand_mean))
+                .collect()
+        })
+        .collect();
+
+    let mut coordinates = vec![[0.0; 2]; n];
+    for axis in 0..2 {
+        let (eigenvalue, eigenvector) = dominant_eigenvector(&gram);
+        let scale = eigenvalue.max(0.0).sqrt();
+        for (c, v) in coordinates.iter_mut().zip(&eigenvector) {
+            c[axis] = v * scale;
+        }
+        // Deflate so that the next iteration finds the next largest eigenvalue
+        for i in 0..n {
+            for j in 0..n {
+                gram[i][j] -= eigenvalue * eigenvector[i] * eigenvector[j];
+            }
+        }
+    }
+    coordinates
+}
+
+/// Power iteration on a symmetric matrix. Returns the eigenvalue and unit eigenvector.
+fn dominant_eigenvector(matrix: &[Vec<f32>]) -> (f32, Vec<f32>) {
+    let n = matrix.len();
+    // Deterministic and unlikely to be orthogonal to the eigenvector we want
+    let mut v: Vec<f32> = (0..n).map(|i| (i as f32 * 12.9898 + 1.0).sin()).collect();
+    let mut eigenvalue = 0.0;
+    for _ in 0..100 {
+        let w: Vec<f32> = matrix
+            .iter()
+            .map(|row| row.iter().zip(&v).map(|(a, b)| a * b).sum())
+            .collect();
+        let norm = w.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm < 1e-12 {
+            return (0.0, vec![0.0; n]);
+        }
+        eigenvalue = v.iter().zip(&w).map(|(a, b)| a * b).sum();
+        v = w.into_iter().map(|x| x / norm).collect();
+    }
+    (eigenvalue, v)
+}
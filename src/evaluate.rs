@@ -0,0 +1,272 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use web_time::Instant;
+
+use crate::features::{pitch_track, Features};
+use crate::instruction::{IsaFeatures, RegId, Value};
+use crate::machine::Machine;
+use crate::physics::{Clock, Physics};
+use crate::program::Program;
+use crate::sequence::{chroma_roll, piano_roll, MIDI_BYTES_PER_SECOND};
+use crate::spectrogram::{chromagram, Spectrogram, SpectrogramRenderer, FFT_WINDOW_SIZE};
+
+pub const AUDIO_CHANNELS: usize = 4;
+pub const DEFAULT_SAMPLE_RATE: usize = 64_000;
+pub const DEFAULT_TIME_BUDGET: Duration = Duration::from_secs(2);
+
+// Default length of each program's output, in bytes
+const DEFAULT_OUTPUT_LENGTH: usize = 65536 * 8 * 8;
+const DEFAULT_MAX_STEPS: usize = 2048 * 2048 * 8 * 8;
+
+// The machine runs this many steps between checks of the output length and the clock
+const STEPS_PER_CHUNK: usize = 2048;
+
+/// How program output is heard and shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OutputMode {
+    // audio with `AUDIO_CHANNELS` interleaved channels, shown as a spectrogram
+    #[default]
+    Audio,
+    // a MIDI byte stream, shown as a piano roll
+    Midi,
+}
+
+/// How programs are run
+#[derive(Clone)]
+pub struct EvalConfig {
+    pub sample_rate: usize,
+    // bytes of output to produce
+    pub preview_length: usize,
+    // wall-clock limit on running a program, after which the rest of its output is silent
+    pub time_budget: Duration,
+    // most instructions to run, after which the rest of the output is silent
+    pub max_steps: usize,
+    pub output_mode: OutputMode,
+    // instruction set extensions programs run with on top of their own, e.g.
+    // from `render --dsp`
+    pub extra_isa: IsaFeatures,
+}
+
+impl EvalConfig {
+    /// Produces `preview_secs` of output per program, or the default length if None
+    pub fn new(
+        sample_rate: usize,
+        preview_secs: Option<f32>,
+        output_mode: OutputMode,
+    ) -> EvalConfig {
+        let mut config = EvalConfig {
+            sample_rate,
+            preview_length: DEFAULT_OUTPUT_LENGTH,
+            time_budget: DEFAULT_TIME_BUDGET,
+            max_steps: DEFAULT_MAX_STEPS,
+            output_mode,
+            extra_isa: IsaFeatures::default(),
+        };
+        if let Some(secs) = preview_secs {
+            // whole frames, and at least one full spectrogram window
+            let frames = (secs * config.bytes_per_second() as f32) as usize / AUDIO_CHANNELS;
+            config.preview_length = (frames * AUDIO_CHANNELS).max(FFT_WINDOW_SIZE);
+        }
+        config
+    }
+
+    /// Bytes of output per second of playback
+    pub fn bytes_per_second(&self) -> usize {
+        match self.output_mode {
+            OutputMode::Audio => self.sample_rate * AUDIO_CHANNELS,
+            OutputMode::Midi => MIDI_BYTES_PER_SECOND,
+        }
+    }
+
+    /// The instruction set extensions the program runs with
+    pub fn isa(&self, program: &Program) -> IsaFeatures {
+        program.features().union(self.extra_isa)
+    }
+
+    /// The physics the program actually runs with, which are always the
+    /// default for MIDI, since its bytes are messages rather than samples
+    pub fn physics(&self, program: &Program) -> Physics {
+        match self.output_mode {
+            OutputMode::Audio => program.physics(),
+            OutputMode::Midi => Physics::default(),
+        }
+    }
+}
+
+impl Default for EvalConfig {
+    fn default() -> EvalConfig {
+        EvalConfig::new(DEFAULT_SAMPLE_RATE, None, OutputMode::Audio)
+    }
+}
+
+/// Why a program stopped running
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    // produced all of the requested output
+    Complete,
+    // ran `max_steps` instructions first
+    OutOfSteps,
+    // ran past the time budget first
+    TimedOut,
+}
+
+/// The result of running a program
+pub struct Evaluation {
+    // always exactly `preview_length` bytes, padded with silence. Shared rather
+    // than copied by everything that plays or shows it.
+    pub output: Arc<[u8]>,
+    pub stop_reason: StopReason,
+    pub steps: usize,
+    // output bytes the program produced itself, before padding
+    pub output_produced: usize,
+    // time spent running the program
+    pub elapsed: Duration,
+    // time spent analysing the output. Only filled in by `evaluate_and_analyse`.
+    pub analysis_elapsed: Duration,
+    // only filled in by `evaluate_and_analyse`. A piano roll in MIDI mode.
+    pub spectrogram: Option<Spectrogram>,
+    pub features: Option<Features>,
+    // spectral flux of the preview, one value per column. Only filled in by `evaluate_and_analyse`.
+    pub onset_strength: Option<Vec<f32>>,
+    // pitch of the first channel in Hz at each column, empty in MIDI mode.
+    // Only filled in by `evaluate_and_analyse`.
+    pub pitch_track: Option<Vec<Option<f32>>>,
+    // energy of each pitch class over time. Only filled in by `evaluate_and_analyse`.
+    pub chromagram: Option<Spectrogram>,
+}
+
+/// Runs a program, with its instruction set and physics, until it has produced
+/// `config.preview_length` bytes of output, or runs out of steps or time
+pub fn evaluate(program: &Program, config: &EvalConfig) -> Evaluation {
+    let preview_length = config.preview_length;
+    let physics = config.physics(program);
+    let raw_length = physics.raw_length(preview_length);
+    let mut output = Vec::with_capacity(raw_length);
+    let mut machine = Machine::new_with_features(program.bytes().to_vec(), config.isa(program));
+    let mut clock = Clock::new(physics);
+
+    let start = Instant::now();
+    let deadline = start + config.time_budget;
+    let mut steps = 0;
+    let stop_reason = loop {
+        if output.len() >= raw_length {
+            break StopReason::Complete;
+        }
+        if steps >= config.max_steps {
+            break StopReason::OutOfSteps;
+        }
+        if Instant::now() > deadline {
+            break StopReason::TimedOut;
+        }
+        let chunk = STEPS_PER_CHUNK.min(config.max_steps - steps);
+        clock.run(&mut machine, chunk, &mut output);
+        steps += chunk;
+    };
+
+    let output_produced = physics.output_length(output.len()).min(preview_length);
+
+    Evaluation {
+        output: physics.apply(output, preview_length).into(),
+        stop_reason,
+        steps,
+        output_produced,
+        elapsed: start.elapsed(),
+        analysis_elapsed: Duration::ZERO,
+        spectrogram: None,
+        features: None,
+        onset_strength: None,
+        pitch_track: None,
+        chromagram: None,
+    }
+}
+
+/// Runs a program like `evaluate`, recording the values of `registers` at
+/// `num_points` evenly spaced points through the output, so that they line up
+/// with it. Points after the program stopped hold its final values.
+pub fn register_timeline(
+    program: &Program,
+    config: &EvalConfig,
+    registers: &[RegId],
+    num_points: usize,
+) -> Vec<Vec<Value>> {
+    let mut timeline = vec![Vec::with_capacity(num_points); registers.len()];
+    let mut machine = Machine::new_with_features(program.bytes().to_vec(), config.isa(program));
+    let physics = config.physics(program);
+    let raw_length = physics.raw_length(config.preview_length);
+    let bytes_per_point = (raw_length / num_points.max(1)).max(1);
+    let deadline = Instant::now() + config.time_budget;
+    let mut output = Vec::new();
+    let mut output_len = 0;
+    let mut steps = 0;
+    let mut points = 0;
+    while points < num_points {
+        let stopped = steps >= config.max_steps
+            || (steps % STEPS_PER_CHUNK == 0 && Instant::now() > deadline);
+        let produced = physics.samples(steps, output_len);
+        if stopped || produced >= bytes_per_point * (points + 1) {
+            for (values, register) in timeline.iter_mut().zip(registers) {
+                values.push(machine.read_register(*register));
+            }
+            points += 1;
+            continue;
+        }
+        output.clear();
+        machine.run(1, &mut output);
+        output_len += output.len();
+        steps += 1;
+    }
+    timeline
+}
+
+/// The spectrogram of the output, or its piano roll in MIDI mode
+pub fn preview(
+    output: &[u8],
+    config: &EvalConfig,
+    spectrogram_renderer: &SpectrogramRenderer,
+) -> Spectrogram {
+    match config.output_mode {
+        OutputMode::Audio => spectrogram_renderer.compute(output),
+        OutputMode::Midi => piano_roll(output),
+    }
+}
+
+/// Like `evaluate`, but also computes the output's preview and features
+pub fn evaluate_and_analyse(
+    program: &Program,
+    config: &EvalConfig,
+    spectrogram_renderer: &SpectrogramRenderer,
+) -> Evaluation {
+    let mut evaluation = evaluate(program, config);
+    let start = Instant::now();
+    let spectrogram = preview(&evaluation.output, config, spectrogram_renderer);
+    evaluation.features = Some(Features::compute(
+        program.bytes(),
+        &evaluation.output,
+        &spectrogram,
+        config.sample_rate,
+        AUDIO_CHANNELS,
+    ));
+    evaluation.onset_strength = Some(spectrogram.spectral_flux());
+    evaluation.pitch_track = Some(match config.output_mode {
+        OutputMode::Audio => pitch_track(
+            &evaluation.output,
+            AUDIO_CHANNELS,
+            config.sample_rate,
+            &spectrogram,
+        ),
+        OutputMode::Midi => Vec::new(),
+    });
+    evaluation.chromagram = Some(match config.output_mode {
+        OutputMode::Audio => chromagram(
+            &evaluation.output,
+            AUDIO_CHANNELS,
+            config.sample_rate,
+            spectrogram.width,
+        ),
+        OutputMode::Midi => chroma_roll(&evaluation.output),
+    });
+    evaluation.spectrogram = Some(spectrogram);
+    evaluation.analysis_elapsed = start.elapsed();
+    evaluation
+}
@@ -0,0 +1,73 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::features::Features;
+use crate::program::ProgramHash;
+
+/// A child in a new generation
+#[derive(Serialize)]
+pub struct ChildEvent<'a> {
+    pub program: ProgramHash,
+    pub parent: Option<ProgramHash>,
+    // names of the mutation operators applied, in order
+    pub operators: &'a [String],
+    pub features: &'a Features,
+}
+
+/// How an instance was judged before the next generation was bred from it
+#[derive(Serialize)]
+pub struct JudgementEvent {
+    pub program: ProgramHash,
+    pub selected: bool,
+    pub pinned: bool,
+    pub rating: Option<u8>,
+}
+
+/// Something which happened while evolving
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Generation {
+        generation: usize,
+        parents: Vec<ProgramHash>,
+        children: Vec<ChildEvent<'a>>,
+    },
+    Judged {
+        generation: usize,
+        instances: Vec<JudgementEvent>,
+    },
+    Exported {
+        file: &'a str,
+        programs: Vec<ProgramHash>,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    // seconds since the Unix epoch
+    time: f64,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Writes events as JSON, one per line, for analysing a session afterwards
+pub struct EventLog {
+    out: Box<dyn Write>,
+}
+
+impl EventLog {
+    pub fn new(out: Box<dyn Write>) -> EventLog {
+        EventLog { out }
+    }
+
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64());
+        serde_json::to_writer(&mut self.out, &Line { time, event })?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+}
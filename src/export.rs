@@ -0,0 +1,474 @@
+use std::io::{self, Write};
+
+use crate::sequence::MidiEvent;
+
+/// File formats instance output can be exported as
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Wav,
+    Flac,
+    #[cfg(feature = "ogg")]
+    Ogg,
+    // output decoded as MIDI, see `sequence::decode_midi`
+    Midi,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Wav => "wav",
+            ExportFormat::Flac => "flac",
+            #[cfg(feature = "ogg")]
+            ExportFormat::Ogg => "ogg",
+            ExportFormat::Midi => "mid",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<ExportFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "wav" => Some(ExportFormat::Wav),
+            "flac" => Some(ExportFormat::Flac),
+            #[cfg(feature = "ogg")]
+            "ogg" | "oga" => Some(ExportFormat::Ogg),
+            "mid" | "midi" => Some(ExportFormat::Midi),
+            _ => None,
+        }
+    }
+}
+
+/// Writes raw machine output as an 8-bit unsigned PCM WAV file, which is
+/// exactly how the output bytes are played back. Any trailing bytes which
+/// don't fill a whole frame are dropped.
+pub fn write_wav<W: Write>(
+    writer: &mut W,
+    data: &[u8],
+    channels: u16,
+    sample_rate: u32,
+) -> io::Result<()> {
+    let data = &data[..(data.len() - data.len() % channels as usize)];
+    let data_len = data.len() as u32;
+    let block_align = channels;
+    let byte_rate = sample_rate * block_align as u32;
+    let bits_per_sample: u16 = 8;
+    let padding = data_len % 2;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len + padding).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&byte_rate.to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&bits_per_sample.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    writer.write_all(data)?;
+    if padding == 1 {
+        writer.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+/// Joins raw machine outputs one after another, fading each into the next
+/// over `crossfade` bytes, which should be a whole number of frames
+pub fn crossfade_concatenate(parts: &[&[u8]], crossfade: usize, channels: usize) -> Vec<u8> {
+    let mut joined: Vec<u8> = Vec::new();
+    for part in parts {
+        let overlap = crossfade.min(joined.len()).min(part.len());
+        let offset = joined.len() - overlap;
+        let frames = (overlap / channels).max(1) as f32;
+        for (i, b) in part[..overlap].iter().enumerate() {
+            let t = ((i / channels) as f32 + 0.5) / frames;
+            // equal power, since consecutive parts are unrelated sounds
+            let angle = t * std::f32::consts::FRAC_PI_2;
+            let a = joined[offset + i] as f32 - 128.0;
+            let b = *b as f32 - 128.0;
+            let mixed = a * angle.cos() + b * angle.sin();
+            joined[offset + i] = (mixed + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+        joined.extend_from_slice(&part[overlap..]);
+    }
+    joined
+}
+
+// FLAC frames hold this many samples of each channel, except the last
+const FLAC_BLOCK_SIZE: usize = 4096;
+
+// Highest Rice parameter tried, below the escape code
+const FLAC_MAX_RICE_PARAMETER: u32 = 14;
+
+/// Accumulates bits most significant first, as FLAC lays them out
+struct BitWriter {
+    bytes: Vec<u8>,
+    // bits waiting to fill the next byte, in the low bits
+    pending: u64,
+    num_pending: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            pending: 0,
+            num_pending: 0,
+        }
+    }
+
+    fn write(&mut self, value: u64, num_bits: u32) {
+        for i in (0..num_bits).rev() {
+            self.pending = (self.pending << 1) | ((value >> i) & 1);
+            self.num_pending += 1;
+            if self.num_pending == 8 {
+                self.bytes.push(self.pending as u8);
+                self.pending = 0;
+                self.num_pending = 0;
+            }
+        }
+    }
+
+    fn write_signed(&mut self, value: i32, num_bits: u32) {
+        self.write(value as u64 & ((1 << num_bits) - 1), num_bits);
+    }
+
+    /// Pads with zeros up to the next whole byte
+    fn align(&mut self) {
+        if self.num_pending > 0 {
+            self.write(0, 8 - self.num_pending);
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The residual of FLAC's fixed polynomial predictor of the given order
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i32> {
+    let mut residual = samples.to_vec();
+    for _ in 0..order {
+        for i in (1..residual.len()).rev() {
+            residual[i] -= residual[i - 1];
+        }
+    }
+    residual.split_off(order)
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// The best Rice parameter for the residual and the number of bits it codes to
+fn best_rice_parameter(residual: &[i32]) -> (u32, u64) {
+    (0..=FLAC_MAX_RICE_PARAMETER)
+        .map(|k| {
+            let bits = residual
+                .iter()
+                .map(|r| (zigzag(*r) >> k) as u64 + 1 + k as u64)
+                .sum();
+            (k, bits)
+        })
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap()
+}
+
+fn write_subframe(bits: &mut BitWriter, samples: &[i32], bits_per_sample: u32) {
+    if samples.iter().all(|s| *s == samples[0]) {
+        // subframe type CONSTANT
+        bits.write(0b0000_0000, 8);
+        bits.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+    let best = (0..=4.min(samples.len() - 1))
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let (k, residual_bits) = best_rice_parameter(&residual);
+            let total = order as u64 * bits_per_sample as u64 + 10 + residual_bits;
+            (order, residual, k, total)
+        })
+        .min_by_key(|(_, _, _, total)| *total)
+        .unwrap();
+    let (order, residual, k, total) = best;
+    if total >= verbatim_bits {
+        // subframe type VERBATIM
+        bits.write(0b0000_0010, 8);
+        for sample in samples {
+            bits.write_signed(*sample, bits_per_sample);
+        }
+        return;
+    }
+    // subframe type FIXED, with the predictor order
+    bits.write(0b0001_0000 | (order as u64) << 1, 8);
+    for sample in &samples[..order] {
+        bits.write_signed(*sample, bits_per_sample);
+    }
+    // Rice coding with a 4 bit parameter, in a single partition
+    bits.write(0b00, 2);
+    bits.write(0, 4);
+    bits.write(k as u64, 4);
+    for r in residual {
+        let u = zigzag(r);
+        let quotient = u >> k;
+        // unary: that many zeros, then a one
+        for _ in 0..quotient {
+            bits.write(0, 1);
+        }
+        bits.write(1, 1);
+        bits.write(u as u64, k);
+    }
+}
+
+/// FLAC's variant of UTF-8, which codes frame numbers of up to 31 bits
+fn write_utf8_number(bits: &mut BitWriter, value: u32) {
+    if value < 0x80 {
+        bits.write(value as u64, 8);
+        return;
+    }
+    let mut num_continuation = 1;
+    while value >= 1 << (6 + 5 * num_continuation) {
+        num_continuation += 1;
+    }
+    let lead_marker = (0xff00u32 >> (num_continuation + 1)) & 0xff;
+    bits.write((lead_marker | (value >> (6 * num_continuation))) as u64, 8);
+    for i in (0..num_continuation).rev() {
+        bits.write((0x80 | ((value >> (6 * i)) & 0x3f)) as u64, 8);
+    }
+}
+
+/// Writes raw machine output as a lossless FLAC file, with each channel coded
+/// independently by the best of FLAC's fixed predictors. Any trailing bytes
+/// which don't fill a whole frame are dropped, as in `write_wav`.
+pub fn write_flac<W: Write>(
+    writer: &mut W,
+    data: &[u8],
+    channels: u16,
+    sample_rate: u32,
+) -> io::Result<()> {
+    if !(1..=8).contains(&channels) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "FLAC supports 1 to 8 channels",
+        ));
+    }
+    let channels = channels as usize;
+    let bits_per_sample = 8;
+    let num_frames = data.len() / channels;
+
+    let mut header = BitWriter::new();
+    // minimum and maximum block size, which the last block is allowed to be shorter than
+    header.write(FLAC_BLOCK_SIZE as u64, 16);
+    header.write(FLAC_BLOCK_SIZE as u64, 16);
+    // minimum and maximum frame size, unknown
+    header.write(0, 24);
+    header.write(0, 24);
+    header.write(sample_rate as u64, 20);
+    header.write(channels as u64 - 1, 3);
+    header.write(bits_per_sample as u64 - 1, 5);
+    header.write(num_frames as u64, 36);
+    // an MD5 of all zeros means none was computed
+    header.write(0, 64);
+    header.write(0, 64);
+
+    writer.write_all(b"fLaC")?;
+    // the only and last metadata block, STREAMINFO
+    writer.write_all(&[0x80])?;
+    writer.write_all(&(header.bytes.len() as u32).to_be_bytes()[1..])?;
+    writer.write_all(&header.bytes)?;
+
+    let samples: Vec<i32> = data[..(num_frames * channels)]
+        .iter()
+        .map(|b| *b as i32 - 128)
+        .collect();
+    for (frame_number, block) in samples.chunks(FLAC_BLOCK_SIZE * channels).enumerate() {
+        let block_size = block.len() / channels;
+        let mut bits = BitWriter::new();
+        // sync code and fixed block size
+        bits.write(0xfff8, 16);
+        // block size follows the frame number, sample rate is in STREAMINFO
+        bits.write(0b0111_0000, 8);
+        // independent channels, 8 bits per sample
+        bits.write(((channels as u64 - 1) << 4) | 0b0010, 8);
+        write_utf8_number(&mut bits, frame_number as u32);
+        bits.write(block_size as u64 - 1, 16);
+        let crc = crc8(&bits.bytes);
+        bits.write(crc as u64, 8);
+
+        for channel in 0..channels {
+            let channel_samples: Vec<i32> =
+                block[channel..].iter().step_by(channels).copied().collect();
+            write_subframe(&mut bits, &channel_samples, bits_per_sample);
+        }
+        bits.align();
+        let crc = crc16(&bits.bytes);
+        bits.write(crc as u64, 16);
+        writer.write_all(&bits.bytes)?;
+    }
+    Ok(())
+}
+
+// Frames passed to the Vorbis encoder at a time
+#[cfg(feature = "ogg")]
+const OGG_BLOCK_SIZE: usize = 4096;
+
+/// Writes raw machine output as an Ogg Vorbis file, which is lossy but much
+/// smaller than FLAC. Any trailing bytes which don't fill a whole frame are
+/// dropped, as in `write_wav`.
+#[cfg(feature = "ogg")]
+pub fn write_ogg<W: Write>(
+    writer: &mut W,
+    data: &[u8],
+    channels: u16,
+    sample_rate: u32,
+) -> io::Result<()> {
+    use std::num::{NonZeroU32, NonZeroU8};
+    use vorbis_rs::{VorbisEncoderBuilder, VorbisError};
+
+    let to_io_error = |e: VorbisError| io::Error::other(e.to_string());
+    let Some(num_channels) = u8::try_from(channels).ok().and_then(NonZeroU8::new) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Vorbis supports 1 to 255 channels",
+        ));
+    };
+    let Some(sample_rate) = NonZeroU32::new(sample_rate) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the sample rate can't be 0",
+        ));
+    };
+    let mut encoder = VorbisEncoderBuilder::new(sample_rate, num_channels, writer)
+        .map_err(to_io_error)?
+        .build()
+        .map_err(to_io_error)?;
+    let channels = channels as usize;
+    let num_frames = data.len() / channels;
+    for block in data[..(num_frames * channels)].chunks(OGG_BLOCK_SIZE * channels) {
+        let planes: Vec<Vec<f32>> = (0..channels)
+            .map(|channel| {
+                block[channel..]
+                    .iter()
+                    .step_by(channels)
+                    .map(|b| (*b as f32 - 128.0) / 128.0)
+                    .collect()
+            })
+            .collect();
+        encoder.encode_audio_block(&planes).map_err(to_io_error)?;
+    }
+    encoder.finish().map_err(to_io_error)?;
+    Ok(())
+}
+
+// Standard MIDI file timing. At the default tempo of 120 bpm, a tick is 1/960 s.
+const TICKS_PER_QUARTER_NOTE: u16 = 480;
+const TICKS_PER_SECOND: f32 = 960.0;
+
+/// Writes MIDI events as a single track, format 0 standard MIDI file
+pub fn write_midi_file<W: Write>(writer: &mut W, events: &[MidiEvent]) -> io::Result<()> {
+    let mut track = Vec::new();
+    let mut previous_tick = 0;
+    for event in events {
+        let tick = (event.time() * TICKS_PER_SECOND) as u32;
+        write_variable_length(&mut track, tick - previous_tick);
+        track.extend_from_slice(event.bytes());
+        previous_tick = tick;
+    }
+    // end of track
+    track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+    writer.write_all(b"MThd")?;
+    writer.write_all(&6u32.to_be_bytes())?;
+    writer.write_all(&0u16.to_be_bytes())?;
+    writer.write_all(&1u16.to_be_bytes())?;
+    writer.write_all(&TICKS_PER_QUARTER_NOTE.to_be_bytes())?;
+
+    writer.write_all(b"MTrk")?;
+    writer.write_all(&(track.len() as u32).to_be_bytes())?;
+    writer.write_all(&track)
+}
+
+/// Seven bits per byte, most significant first, with the top bit set on all but the last
+fn write_variable_length(data: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    data.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Noise with runs of silence and slow ramps, so every kind of subframe gets used
+    fn test_output(len: usize) -> Vec<u8> {
+        let mut state: u32 = 12345;
+        (0..len)
+            .map(|i| match (i / 1000) % 3 {
+                0 => {
+                    state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                    (state >> 16) as u8
+                }
+                1 => 128,
+                _ => (i / 7) as u8,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flac_round_trip() {
+        for channels in [1, 2, 4, 8] {
+            // two whole blocks, a partial last block and a trailing partial frame
+            let num_frames = 2 * FLAC_BLOCK_SIZE + 123;
+            let output = test_output(num_frames * channels + channels - 1);
+            let mut data = Vec::new();
+            write_flac(&mut data, &output, channels as u16, 44100).unwrap();
+
+            let mut reader = claxon::FlacReader::new(Cursor::new(data)).unwrap();
+            let info = reader.streaminfo();
+            assert_eq!(info.channels as usize, channels);
+            assert_eq!(info.sample_rate, 44100);
+            assert_eq!(info.bits_per_sample, 8);
+            assert_eq!(info.samples, Some(num_frames as u64));
+            let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+            let expected: Vec<i32> = output[..(num_frames * channels)]
+                .iter()
+                .map(|b| *b as i32 - 128)
+                .collect();
+            assert_eq!(samples, expected, "{} channels", channels);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use serde::Serialize;
+
+use crate::spectrogram::{linear_magnitude, Spectrogram, FFT_HOP_SIZE, FFT_WINDOW_SIZE};
+
+const NUM_MEL_BANDS: usize = 24;
+pub const NUM_MFCC: usize = 13;
+
+// Fraction of the spectral energy below the rolloff frequency
+const ROLLOFF_FRACTION: f32 = 0.85;
+
+// Loudness normalisation aims for this RMS, relative to full scale, without
+// amplifying quiet output more than MAX_NORMALISING_GAIN or pushing it past full scale
+const TARGET_RMS: f32 = 0.2;
+const MAX_NORMALISING_GAIN: f32 = 8.0;
+
+// The fundamental is searched for between these frequencies, in Hz
+const MIN_FUNDAMENTAL: f32 = 40.0;
+const MAX_FUNDAMENTAL: f32 = 4000.0;
+// Number of samples of one channel compared against each lagged copy
+const PITCH_WINDOW: usize = 4096;
+// Below this normalized autocorrelation, output is considered unpitched
+const MIN_PERIODICITY: f32 = 0.5;
+
+/// Mean over time of each frequency bin, lowest frequency first.
+/// `magnitude` maps each spectrogram value before averaging.
+fn mean_spectrum<F: Fn(f32) -> f32>(spectrogram: &Spectrogram, magnitude: F) -> Vec<f32> {
+    let mut spectrum: Vec<f32> = spectrogram
+        .values
+        .chunks(spectrogram.width)
+        .map(|row| row.iter().map(|t| magnitude(*t)).sum::<f32>() / spectrogram.width as f32)
+        .collect();
+    spectrum.reverse();
+    spectrum
+}
+
+/// Cepstral coefficients of the time-averaged spectrogram, after pooling it
+/// into triangular bands evenly spaced on the mel scale
+fn mel_cepstrum(spectrogram: &Spectrogram, sample_rate: usize) -> [f32; NUM_MFCC] {
+    let mel = |hz: f32| 2595.0 * (1.0 + hz / 700.0).log10();
+    let nyquist = sample_rate as f32 * 0.5;
+
+    let spectrum = mean_spectrum(spectrogram, |t| t);
+
+    let max_mel = mel(nyquist);
+    let band_width = max_mel / (NUM_MEL_BANDS + 1) as f32;
+    let mut bands = [0.0; NUM_MEL_BANDS];
+    for (i, band) in bands.iter_mut().enumerate() {
+        let centre = (i + 1) as f32 * band_width;
+        let mut total_weight = 0.0;
+        for (bin, value) in spectrum.iter().enumerate() {
+            let m = mel(bin as f32 / spectrum.len() as f32 * nyquist);
+            let weight = (1.0 - (m - centre).abs() / band_width).max(0.0);
+            *band += weight * value;
+            total_weight += weight;
+        }
+        if total_weight > 0.0 {
+            *band /= total_weight;
+        }
+    }
+
+    let mut coefficients = [0.0; NUM_MFCC];
+    for (k, c) in coefficients.iter_mut().enumerate() {
+        *c = bands
+            .iter()
+            .enumerate()
+            .map(|(m, b)| {
+                b * (std::f32::consts::PI * k as f32 * (m as f32 + 0.5) / NUM_MEL_BANDS as f32)
+                    .cos()
+            })
+            .sum();
+    }
+    coefficients
+}
+
+// The pitch track uses a shorter window and a narrower range, so that it can
+// be estimated at every spectrogram column
+const TRACK_WINDOW: usize = 512;
+const TRACK_MIN_FUNDAMENTAL: f32 = 80.0;
+
+/// The samples of the first channel, centred on 0
+fn first_channel(output: &[u8], channels: usize) -> Vec<f32> {
+    output
+        .iter()
+        .step_by(channels)
+        .map(|b| *b as f32 - 128.0)
+        .collect()
+}
+
+/// Finds the period of `excerpt` by comparing its first `window` samples with
+/// copies lagged by up to `max_lag`, and returns its frequency in Hz.
+/// Returns None if it isn't clearly periodic.
+fn autocorrelation_pitch(
+    excerpt: &[f32],
+    window: usize,
+    min_lag: usize,
+    max_lag: usize,
+    sample_rate: usize,
+) -> Option<f32> {
+    let mean = excerpt.iter().sum::<f32>() / excerpt.len() as f32;
+    let x: Vec<f32> = excerpt.iter().map(|v| v - mean).collect();
+
+    let energy: f32 = x[..window].iter().map(|v| v * v).sum();
+    if energy <= 0.0 {
+        return None;
+    }
+    // The energy of the lagged window is updated as it slides along
+    let mut lagged_energy: f32 = x[min_lag..(min_lag + window)].iter().map(|v| v * v).sum();
+    let correlations: Vec<f32> = (min_lag..=max_lag)
+        .map(|lag| {
+            if lag > min_lag {
+                lagged_energy += x[lag + window - 1].powi(2) - x[lag - 1].powi(2);
+            }
+            let lagged = &x[lag..(lag + window)];
+            let product: f32 = x.iter().zip(lagged).map(|(a, b)| a * b).sum();
+            product / (energy * lagged_energy.max(0.0)).sqrt().max(f32::EPSILON)
+        })
+        .collect();
+    // Short lags correlate well with anything smooth, so only consider lags
+    // after the correlation first goes negative
+    let first_negative = correlations.iter().position(|r| *r < 0.0)?;
+    let candidates = &correlations[first_negative..];
+    let best = candidates.iter().cloned().fold(0.0, f32::max);
+    if best < MIN_PERIODICITY {
+        return None;
+    }
+    // Multiples of the period correlate about as well, so take the first
+    // peak that comes close to the best
+    let mut i = candidates.iter().position(|r| *r >= 0.9 * best)?;
+    while i + 1 < candidates.len() && candidates[i + 1] > candidates[i] {
+        i += 1;
+    }
+    Some(sample_rate as f32 / (min_lag + first_negative + i) as f32)
+}
+
+/// Estimates the pitch of the first channel from its autocorrelation, in Hz.
+/// Returns None if the output isn't clearly periodic.
+fn estimate_fundamental(output: &[u8], channels: usize, sample_rate: usize) -> Option<f32> {
+    let samples = first_channel(output, channels);
+    let min_lag = ((sample_rate as f32 / MAX_FUNDAMENTAL) as usize).max(1);
+    let max_lag = (sample_rate as f32 / MIN_FUNDAMENTAL) as usize;
+    let length = PITCH_WINDOW + max_lag;
+    if samples.len() < length {
+        return None;
+    }
+    // Take the middle of the output, away from any start-up transient
+    let start = (samples.len() - length) / 2;
+    let excerpt = &samples[start..(start + length)];
+    autocorrelation_pitch(excerpt, PITCH_WINDOW, min_lag, max_lag, sample_rate)
+}
+
+/// The pitch of the first channel around each spectrogram column, in Hz, or
+/// None where it isn't clearly periodic. `output` must be the output the
+/// spectrogram was computed from.
+pub fn pitch_track(
+    output: &[u8],
+    channels: usize,
+    sample_rate: usize,
+    spectrogram: &Spectrogram,
+) -> Vec<Option<f32>> {
+    let samples = first_channel(output, channels);
+    let min_lag = ((sample_rate as f32 / MAX_FUNDAMENTAL) as usize).max(1);
+    let max_lag = (sample_rate as f32 / TRACK_MIN_FUNDAMENTAL) as usize;
+    let length = TRACK_WINDOW + max_lag;
+    (0..spectrogram.width)
+        .map(|column| {
+            let centre = (column * FFT_HOP_SIZE + FFT_WINDOW_SIZE / 2) / channels;
+            let start = centre.saturating_sub(length / 2);
+            let excerpt = samples.get(start..(start + length))?;
+            autocorrelation_pitch(excerpt, TRACK_WINDOW, min_lag, max_lag, sample_rate)
+        })
+        .collect()
+}
+
+/// Summary statistics of a program and its output.
+/// Amplitudes are relative to full scale, spectral frequencies are fractions
+/// of the spectrogram's frequency range.
+#[derive(Clone, Copy, Serialize)]
+pub struct Features {
+    pub program_length: usize,
+    pub rms: f32,
+    pub peak: f32,
+    pub spectral_centroid: f32,
+    // below which most of the spectral energy lies
+    pub spectral_rolloff: f32,
+    // near 1 for noise, near 0 for a few pure tones
+    pub spectral_flatness: f32,
+    pub zero_crossing_rate: f32,
+    // in Hz, None if unpitched
+    pub fundamental: Option<f32>,
+    // mel-frequency cepstral coefficients of the average spectrum
+    pub mfcc: [f32; NUM_MFCC],
+}
+
+impl Features {
+    /// `output` holds `channels` interleaved channels, and `spectrogram` must
+    /// have been computed from it
+    pub fn compute(
+        program: &[u8],
+        output: &[u8],
+        spectrogram: &Spectrogram,
+        sample_rate: usize,
+        channels: usize,
+    ) -> Features {
+        let amplitude = |b: u8| (b as f32 - 128.0) / 128.0;
+
+        let mut sum_squares = 0.0;
+        let mut peak: f32 = 0.0;
+        for b in output {
+            let a = amplitude(*b);
+            sum_squares += a * a;
+            peak = peak.max(a.abs());
+        }
+        let rms = (sum_squares / output.len().max(1) as f32).sqrt();
+
+        // Channels are interleaved, so compare each sample with the previous
+        // sample of the same channel
+        let crossings = output
+            .iter()
+            .zip(output.iter().skip(channels))
+            .filter(|(a, b)| (**a >= 128) != (**b >= 128))
+            .count();
+        let zero_crossing_rate =
+            crossings as f32 / output.len().saturating_sub(channels).max(1) as f32;
+
+        let mut weighted_sum = 0.0;
+        let mut total = 0.0;
+        for (row, values) in spectrogram.values.chunks(spectrogram.width).enumerate() {
+            let frequency = (spectrogram.height - 1 - row) as f32 / spectrogram.height as f32;
+            for t in values {
+                weighted_sum += frequency * t;
+                total += t;
+            }
+        }
+        let spectral_centroid = if total > 0.0 {
+            weighted_sum / total
+        } else {
+            0.0
+        };
+
+        let spectrum = mean_spectrum(spectrogram, linear_magnitude);
+        let total_energy: f32 = spectrum.iter().map(|m| m * m).sum();
+        let mut cumulative_energy = 0.0;
+        let rolloff_bin = spectrum
+            .iter()
+            .position(|m| {
+                cumulative_energy += m * m;
+                cumulative_energy >= ROLLOFF_FRACTION * total_energy
+            })
+            .unwrap_or(0);
+        let spectral_rolloff = rolloff_bin as f32 / spectrum.len() as f32;
+
+        let log_mean = spectrum.iter().map(|m| m.ln()).sum::<f32>() / spectrum.len() as f32;
+        let mean = spectrum.iter().sum::<f32>() / spectrum.len() as f32;
+        let spectral_flatness = log_mean.exp() / mean;
+
+        Features {
+            program_length: program.len(),
+            rms,
+            peak,
+            spectral_centroid,
+            spectral_rolloff,
+            spectral_flatness,
+            zero_crossing_rate,
+            fundamental: estimate_fundamental(output, channels, sample_rate),
+            mfcc: mel_cepstrum(spectrogram, sample_rate),
+        }
+    }
+
+    /// The gain which brings the output to a common loudness
+    pub fn normalising_gain(&self) -> f32 {
+        if self.rms <= 0.0 {
+            return 1.0;
+        }
+        (TARGET_RMS / self.rms)
+            .min(MAX_NORMALISING_GAIN)
+            .min(1.0 / self.peak.max(f32::EPSILON))
+    }
+
+    pub fn timbre_distance(&self, other: &Features) -> f32 {
+        self.mfcc
+            .iter()
+            .zip(&other.mfcc)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// Distance in feature space, with each feature scaled to roughly [0, 1]
+    pub fn distance(&self, other: &Features) -> f32 {
+        let length = |f: &Features| (f.program_length as f32).ln_1p() / 10.0;
+        [
+            self.rms - other.rms,
+            self.peak - other.peak,
+            self.spectral_centroid - other.spectral_centroid,
+            self.spectral_rolloff - other.spectral_rolloff,
+            self.spectral_flatness - other.spectral_flatness,
+            self.zero_crossing_rate - other.zero_crossing_rate,
+            length(self) - length(other),
+        ]
+        .iter()
+        .map(|d| d * d)
+        .sum::<f32>()
+        .sqrt()
+    }
+
+    pub fn summary(&self) -> String {
+        let pitch = match self.fundamental {
+            Some(hz) => format!("fundamental {:.0} Hz", hz),
+            None => "unpitched".to_string(),
+        };
+        format!(
+            "{} bytes\nRMS {:.3}, peak {:.3}\ncentroid {:.3}, rolloff {:.3}\nflatness {:.3}\nzero crossings {:.3}\n{}",
+            self.program_length,
+            self.rms,
+            self.peak,
+            self.spectral_centroid,
+            self.spectral_rolloff,
+            self.spectral_flatness,
+            self.zero_crossing_rate,
+            pitch
+        )
+    }
+}
+
+/// How unlike the `k` nearest of `others` these features are, as the mean
+/// distance to them. Used to reward novelty when evolving without a listener.
+pub fn novelty(features: &Features, others: &[Features], k: usize) -> f32 {
+    let mut distances: Vec<f32> = others.iter().map(|o| features.distance(o)).collect();
+    distances.sort_by(|a, b| a.total_cmp(b));
+    let nearest = &distances[..k.min(distances.len())];
+    nearest.iter().sum::<f32>() / nearest.len().max(1) as f32
+}
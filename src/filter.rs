@@ -0,0 +1,34 @@
+use std::f32::consts::TAU;
+
+// Cutoff of the high-pass which removes DC offset, in Hz
+const DC_BLOCK_CUTOFF: f32 = 20.0;
+
+// Cutoff of the low-pass which takes the edge off aliasing, in Hz
+const SOFTENING_CUTOFF: f32 = 8000.0;
+
+/// Filters interleaved, unsigned 8-bit audio to make it easier to listen to
+/// for a long time. A high-pass removes DC offset and a gentle one-pole
+/// low-pass softens harsh high frequencies. Each channel is filtered on its own.
+pub fn speaker_protection(data: &[u8], channels: usize, sample_rate: usize) -> Vec<u8> {
+    let sample_rate = sample_rate as f32;
+    let high_pass_pole = 1.0 - TAU * DC_BLOCK_CUTOFF / sample_rate;
+    let low_pass_coefficient = 1.0 - (-TAU * SOFTENING_CUTOFF / sample_rate).exp();
+
+    // starting from the first sample, so that a large offset doesn't click
+    let mut last_input: Vec<f32> = (0..channels)
+        .map(|c| data.get(c).map_or(0.0, |b| *b as f32 - 128.0))
+        .collect();
+    let mut high_passed = vec![0.0; channels];
+    let mut low_passed = vec![0.0; channels];
+
+    let mut filtered = Vec::with_capacity(data.len());
+    for (i, b) in data.iter().enumerate() {
+        let c = i % channels;
+        let x = *b as f32 - 128.0;
+        high_passed[c] = x - last_input[c] + high_pass_pole * high_passed[c];
+        last_input[c] = x;
+        low_passed[c] += low_pass_coefficient * (high_passed[c] - low_passed[c]);
+        filtered.push((low_passed[c] + 128.0).round().clamp(0.0, 255.0) as u8);
+    }
+    filtered
+}
@@ -0,0 +1,74 @@
+use rhai::{Array, Blob, Dynamic, Engine, Scope, AST};
+
+use crate::features::Features;
+
+// Most operations a script may run per instance, so that a runaway loop stops
+// with an error instead of hanging
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A fitness function written in Rhai (https://rhai.rs) which scores an
+/// instance, higher being better, like `centroid - abs(rms - 0.2)`.
+/// Scripts see these variables:
+/// - `rms` and `peak`, amplitudes relative to full scale
+/// - `centroid` and `rolloff`, as fractions of the frequency range
+/// - `flatness` and `zero_crossings`
+/// - `pitched`, and `fundamental` in Hz, which is 0 when unpitched
+/// - `length`, of the program in bytes
+/// - `mfcc`, an array of mel-frequency cepstral coefficients
+/// - `output`, the raw output as a blob
+pub struct FitnessScript {
+    engine: Engine,
+    ast: AST,
+    // the output is only copied into the scope for scripts which use it
+    uses_output: bool,
+}
+
+impl FitnessScript {
+    pub fn compile(source: &str) -> Result<FitnessScript, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        Ok(FitnessScript {
+            engine,
+            ast,
+            uses_output: source.contains("output"),
+        })
+    }
+
+    /// Runs the script on an instance's features and output. Integer results
+    /// are converted, anything else that isn't a number is an error.
+    pub fn score(&self, features: &Features, output: &[u8]) -> Result<f32, String> {
+        let mut scope = Scope::new();
+        scope.push("rms", features.rms as f64);
+        scope.push("peak", features.peak as f64);
+        scope.push("centroid", features.spectral_centroid as f64);
+        scope.push("rolloff", features.spectral_rolloff as f64);
+        scope.push("flatness", features.spectral_flatness as f64);
+        scope.push("zero_crossings", features.zero_crossing_rate as f64);
+        scope.push("pitched", features.fundamental.is_some());
+        scope.push("fundamental", features.fundamental.unwrap_or(0.0) as f64);
+        scope.push("length", features.program_length as i64);
+        let mfcc: Array = features
+            .mfcc
+            .iter()
+            .map(|c| Dynamic::from_float(*c as f64))
+            .collect();
+        scope.push("mfcc", mfcc);
+        if self.uses_output {
+            let output: Blob = output.to_vec();
+            scope.push("output", output);
+        }
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| e.to_string())?;
+        match (result.as_float(), result.as_int()) {
+            (Ok(score), _) => Ok(score as f32),
+            (_, Ok(score)) => Ok(score as f32),
+            _ => Err(format!(
+                "the script gave a {} instead of a number",
+                result.type_name()
+            )),
+        }
+    }
+}
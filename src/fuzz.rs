@@ -0,0 +1,67 @@
+use crate::instruction::{assemble, disassemble, Instruction, IsaFeatures};
+use crate::machine::Machine;
+use crate::program::MAX_PROGRAM_LENGTH;
+
+// Instructions run per input. Enough to reach most of a short program, few
+// enough that the fuzzer gets through thousands of inputs a second.
+const FUEL: usize = 1 << 14;
+
+/// Runs arbitrary bytes as a program, checking along the way that decoding,
+/// disassembly and execution hold up, with and without the DSP instructions.
+/// Any panic is a bug.
+pub fn run_arbitrary(data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    let memory = &data[..data.len().min(MAX_PROGRAM_LENGTH)];
+    for dsp in [false, true] {
+        check(memory, IsaFeatures { dsp });
+    }
+}
+
+fn check(memory: &[u8], features: IsaFeatures) {
+    // every instruction decodes, and re-encodes to something that decodes the same way
+    let mut offset = 0;
+    let mut encoded = Vec::new();
+    while offset < memory.len() {
+        let instruction = Instruction::decode(features, || {
+            let b = memory.get(offset).copied().unwrap_or(0);
+            offset += 1;
+            b
+        });
+        encoded.clear();
+        instruction.encode(&mut encoded);
+        let mut i = 0;
+        let decoded = Instruction::decode(features, || {
+            i += 1;
+            encoded[i - 1]
+        });
+        assert_eq!(
+            i,
+            encoded.len(),
+            "decoding {} read the wrong length",
+            instruction
+        );
+        assert_eq!(decoded.to_string(), instruction.to_string());
+    }
+
+    // disassembly always assembles back to the same bytes
+    let text = disassemble(memory, features);
+    match assemble(&text) {
+        Ok(bytes) => assert_eq!(bytes, memory, "disassembly didn't round trip:\n{}", text),
+        Err(e) => panic!("disassembly didn't assemble: {}\n{}", e, text),
+    }
+
+    // plain and traced execution agree
+    let mut machine = Machine::new_with_features(memory.to_vec(), features);
+    let mut traced = Machine::new_with_features(memory.to_vec(), features);
+    let mut output = Vec::new();
+    let mut traced_output = Vec::new();
+    for _ in 0..FUEL {
+        machine.run(1, &mut output);
+        traced.step_traced(&mut traced_output);
+        assert_eq!(machine.program_counter(), traced.program_counter());
+    }
+    assert_eq!(output, traced_output);
+    assert_eq!(machine.memory(), traced.memory());
+}
@@ -0,0 +1,165 @@
+use std::sync::mpsc::channel;
+
+use wgpu::util::DeviceExt;
+
+// Largest number of workgroups the downlevel limits allow in one dimension
+const MAX_WORKGROUPS_PER_DIMENSION: usize = 65535;
+const WORKGROUP_SIZE: usize = 64;
+
+/// Computes log-scaled spectrograms with a compute shader.
+/// Gives the same layout and scaling as the CPU path.
+pub struct GpuSpectrogram {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuSpectrogram {
+    /// Returns None if no suitable adapter is available
+    pub fn new() -> Option<GpuSpectrogram> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("spectrogram"),
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_defaults(),
+            },
+            None,
+        ))
+        .ok()?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("spectrogram"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("spectrogram.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("spectrogram"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        log::info!("Computing spectrograms on {}", adapter.get_info().name);
+        Some(GpuSpectrogram {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    /// Returns the spectrogram values row by row, highest frequency first,
+    /// or None if the output is too long to compute in one dispatch
+    pub fn compute(&self, output: &[u8], window: &[f32], hop_size: usize) -> Option<Vec<f32>> {
+        let window_size = window.len();
+        assert!(output.len() >= window_size);
+        let num_bins = window_size / 2;
+        let num_columns = (output.len() - window_size + hop_size) / hop_size;
+        if num_columns > MAX_WORKGROUPS_PER_DIMENSION {
+            return None;
+        }
+
+        let params = [
+            window_size as u32,
+            hop_size as u32,
+            num_columns as u32,
+            num_bins as u32,
+        ];
+        // Storage buffers hold whole words, so pad the samples to a multiple of four
+        let mut samples = output.to_vec();
+        samples.resize(output.len().div_ceil(4) * 4, 0);
+        let values_size = (num_columns * num_bins * std::mem::size_of::<f32>()) as u64;
+
+        let params_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("spectrogram params"),
+                contents: bytemuck::cast_slice(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let samples_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("spectrogram samples"),
+                contents: &samples,
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let window_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("spectrogram window"),
+                contents: bytemuck::cast_slice(window),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let values_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("spectrogram values"),
+            size: values_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("spectrogram staging"),
+            size: values_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("spectrogram"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: samples_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: window_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: values_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("spectrogram"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("spectrogram"),
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let num_workgroups_x = num_bins.div_ceil(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(num_workgroups_x as u32, num_columns as u32, 1);
+        }
+        encoder.copy_buffer_to_buffer(&values_buffer, 0, &staging_buffer, 0, values_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging_buffer.slice(..);
+        let (sender, receiver) = channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = receiver.recv().ok()? {
+            log::warn!("Failed to read back spectrogram: {}", e);
+            return None;
+        }
+        let values = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging_buffer.unmap();
+        Some(values)
+    }
+}
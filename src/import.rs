@@ -0,0 +1,77 @@
+use std::io::Cursor;
+
+/// How each audio sample is written into memory
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, clap::ValueEnum)]
+pub enum SampleFormat {
+    /// One unsigned byte, the way output is played
+    #[default]
+    #[value(name = "u8")]
+    Unsigned8,
+    /// One two's complement byte
+    #[value(name = "s8")]
+    Signed8,
+    /// Two unsigned bytes, big endian, the way wide registers are output
+    #[value(name = "u16")]
+    Unsigned16,
+}
+
+/// Decodes a WAV or FLAC file, mixing its channels down to mono samples from -1 to 1
+pub fn decode_audio(data: &[u8]) -> Result<Vec<f32>, String> {
+    let (channels, samples) = if data.starts_with(b"fLaC") {
+        let mut reader = claxon::FlacReader::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+        let info = reader.streaminfo();
+        let scale = (1u64 << (info.bits_per_sample - 1)) as f32;
+        let samples = reader
+            .samples()
+            .map(|s| s.map(|s| s as f32 / scale))
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| e.to_string())?;
+        (info.channels as usize, samples)
+    } else if data.starts_with(b"RIFF") {
+        let mut reader = hound::WavReader::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|s| s as f32 / scale))
+                    .collect()
+            }
+        }
+        .map_err(|e| e.to_string())?;
+        (spec.channels as usize, samples)
+    } else {
+        return Err("not a WAV or FLAC file".to_string());
+    };
+    Ok(samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect())
+}
+
+/// Writes every `decimation`th sample into memory in the given format,
+/// stopping after at most `max_length` bytes
+pub fn samples_to_memory(
+    samples: &[f32],
+    format: SampleFormat,
+    decimation: usize,
+    max_length: usize,
+) -> Vec<u8> {
+    let mut memory = Vec::new();
+    for sample in samples.iter().step_by(decimation.max(1)) {
+        let sample = sample.clamp(-1.0, 1.0);
+        match format {
+            SampleFormat::Unsigned8 => memory.push((sample * 127.5 + 127.5).round() as u8),
+            SampleFormat::Signed8 => memory.push((sample * 127.0).round() as i8 as u8),
+            SampleFormat::Unsigned16 => memory
+                .extend_from_slice(&((sample * 32767.5 + 32767.5).round() as u16).to_be_bytes()),
+        }
+        if memory.len() >= max_length {
+            break;
+        }
+    }
+    memory.truncate(max_length);
+    memory
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, str::SplitWhitespace};
+use std::{collections::HashMap, error::Error, fmt, str::SplitWhitespace};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::evaluate::{AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE};
 
 pub type Value = u32;
 pub type WideValue = u64;
@@ -18,41 +22,245 @@ pub struct ImmW(pub WideValue);
 #[derive(Clone, Copy)]
 pub struct Addr(pub u16);
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Operation {
-    Copy,
-    Not,
-    Neg,
-    Reverse,
-    Numzeros,
-    Numones,
-    And,
-    Or,
-    Xor,
-    Shl,
-    Shlm,
-    Shr,
-    Shrm,
-    Rotl,
-    Rotr,
-    Addc,
-    Addm,
-    Subc,
-    Subm,
-    Absdiff,
-    Mulc,
-    Mulm,
-    Div,
-    Mod,
-    Powm,
-    Powc,
-    Gt,
-    Ge,
-    Lt,
-    Le,
-    Eq,
-    Ne,
+    Copy = 0b00000,
+    Not = 0b00001,
+    Neg = 0b00010,
+    Reverse = 0b00011,
+    Numzeros = 0b00100,
+    Numones = 0b00101,
+    And = 0b00110,
+    Or = 0b00111,
+    Xor = 0b01000,
+    Shl = 0b01001,
+    Shlm = 0b01010,
+    Shr = 0b01011,
+    Shrm = 0b01100,
+    Rotl = 0b01101,
+    Rotr = 0b01110,
+    Addc = 0b01111,
+    Addm = 0b10000,
+    Subc = 0b10001,
+    Subm = 0b10010,
+    Absdiff = 0b10011,
+    Mulc = 0b10100,
+    Mulm = 0b10101,
+    Div = 0b10110,
+    Mod = 0b10111,
+    Powm = 0b11000,
+    Powc = 0b11001,
+    Gt = 0b11010,
+    Ge = 0b11011,
+    Lt = 0b11100,
+    Le = 0b11101,
+    Eq = 0b11110,
+    Ne = 0b11111,
+}
+
+impl Operation {
+    pub fn from_code(n: u8) -> Operation {
+        match n {
+            0b00000 => Operation::Copy,
+            0b00001 => Operation::Not,
+            0b00010 => Operation::Neg,
+            0b00011 => Operation::Reverse,
+            0b00100 => Operation::Numzeros,
+            0b00101 => Operation::Numones,
+            0b00110 => Operation::And,
+            0b00111 => Operation::Or,
+            0b01000 => Operation::Xor,
+            0b01001 => Operation::Shl,
+            0b01010 => Operation::Shlm,
+            0b01011 => Operation::Shr,
+            0b01100 => Operation::Shrm,
+            0b01101 => Operation::Rotl,
+            0b01110 => Operation::Rotr,
+            0b01111 => Operation::Addc,
+            0b10000 => Operation::Addm,
+            0b10001 => Operation::Subc,
+            0b10010 => Operation::Subm,
+            0b10011 => Operation::Absdiff,
+            0b10100 => Operation::Mulc,
+            0b10101 => Operation::Mulm,
+            0b10110 => Operation::Div,
+            0b10111 => Operation::Mod,
+            0b11000 => Operation::Powm,
+            0b11001 => Operation::Powc,
+            0b11010 => Operation::Gt,
+            0b11011 => Operation::Ge,
+            0b11100 => Operation::Lt,
+            0b11101 => Operation::Le,
+            0b11110 => Operation::Eq,
+            0b11111 => Operation::Ne,
+            _ => panic!(),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Operation::Copy => "copy",
+            Operation::Not => "not",
+            Operation::Neg => "neg",
+            Operation::Reverse => "reverse",
+            Operation::Numzeros => "numzeros",
+            Operation::Numones => "numones",
+            Operation::And => "and",
+            Operation::Or => "or",
+            Operation::Xor => "xor",
+            Operation::Shl => "shl",
+            Operation::Shlm => "shlm",
+            Operation::Shr => "shr",
+            Operation::Shrm => "shrm",
+            Operation::Rotl => "rotl",
+            Operation::Rotr => "rotr",
+            Operation::Addc => "addc",
+            Operation::Addm => "addm",
+            Operation::Subc => "subc",
+            Operation::Subm => "subm",
+            Operation::Absdiff => "absdiff",
+            Operation::Mulc => "mulc",
+            Operation::Mulm => "mulm",
+            Operation::Div => "div",
+            Operation::Mod => "mod",
+            Operation::Powm => "powm",
+            Operation::Powc => "powc",
+            Operation::Gt => "gt",
+            Operation::Ge => "ge",
+            Operation::Lt => "lt",
+            Operation::Le => "le",
+            Operation::Eq => "eq",
+            Operation::Ne => "ne",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Operation> {
+        (0..32)
+            .map(Operation::from_code)
+            .find(|op| op.mnemonic() == mnemonic)
+    }
+}
+
+/// Optional instructions which a machine can be built to run. Their encodings
+/// are unused by the base instruction set, where they run as something else,
+/// so a program only sounds the same on a machine with the same features.
+/// Written as a list of names, like `["dsp"]`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub struct IsaFeatures {
+    /// The DSP instructions, in place of `jmp` with its unused bits set
+    pub dsp: bool,
+}
+
+impl IsaFeatures {
+    /// Everything either of them has
+    pub fn union(self, other: IsaFeatures) -> IsaFeatures {
+        IsaFeatures {
+            dsp: self.dsp || other.dsp,
+        }
+    }
+
+    /// Whether a machine with these features can run a program which needs `other`
+    pub fn contains(self, other: IsaFeatures) -> bool {
+        self.union(other) == self
+    }
+
+    pub fn names(self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.dsp {
+            names.push("dsp");
+        }
+        names
+    }
+
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<IsaFeatures, String> {
+        let mut features = IsaFeatures::default();
+        for name in names {
+            match name.as_ref() {
+                "dsp" => features.dsp = true,
+                name => return Err(format!("unknown instruction set extension \"{}\"", name)),
+            }
+        }
+        Ok(features)
+    }
+}
+
+impl fmt::Display for IsaFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "base")?;
+        for name in self.names() {
+            write!(f, " + {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for IsaFeatures {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.names().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IsaFeatures {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<IsaFeatures, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        IsaFeatures::from_names(&names).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Signal processing on fixed point values, where 65536 is 1.0 and registers
+/// hold signed 32-bit numbers. The results are exact, so they sound the same
+/// everywhere. Each takes a byte parameter `k` after its registers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DspOperation {
+    /// a = sin(b), with b as a fraction of a full turn over the whole 32-bit
+    /// range, shifted right by k
+    Sin = 0b0001,
+    /// a = 2 to the power of -b / 2^k, decaying from 1.0 and halving every
+    /// 2^k as b counts up
+    Exp = 0b0010,
+    /// a = a * b, shifted right by k, saturating. A k of 16 multiplies two
+    /// fixed point values.
+    Mulq = 0b0011,
+    /// a += (b - a) >> k, a step of a one-pole low-pass filter towards b
+    Onepole = 0b0100,
+}
+
+impl DspOperation {
+    pub fn from_code(n: u8) -> Option<DspOperation> {
+        match n {
+            0b0001 => Some(DspOperation::Sin),
+            0b0010 => Some(DspOperation::Exp),
+            0b0011 => Some(DspOperation::Mulq),
+            0b0100 => Some(DspOperation::Onepole),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            DspOperation::Sin => "sin",
+            DspOperation::Exp => "exp",
+            DspOperation::Mulq => "mulq",
+            DspOperation::Onepole => "onepole",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<DspOperation> {
+        (0..16)
+            .filter_map(DspOperation::from_code)
+            .find(|op| op.mnemonic() == mnemonic)
+    }
 }
 
+#[derive(Clone, Copy)]
 pub enum Instruction {
     Output(RegId),
     OutputW(RegWId),
@@ -66,120 +274,357 @@ pub enum Instruction {
     OpW(Operation, RegWId, RegWId),
     OpImm(Operation, RegId, RegId, Imm),
     OpImmW(Operation, RegWId, RegWId, ImmW),
+    Dsp(DspOperation, RegId, RegId, u8),
+}
+
+impl Instruction {
+    /// Decodes a single instruction, pulling as many bytes as it needs
+    /// from `next_byte`.
+    pub fn decode<F: FnMut() -> u8>(features: IsaFeatures, mut next_byte: F) -> Instruction {
+        let b0 = next_byte();
+        let (n0a, n0b) = byte_to_nibbles(b0);
+        let mut next_addr = || Addr(u16::from_be_bytes([next_byte(), next_byte()]));
+        match n0a {
+            0b0000 => Instruction::Output(RegId(n0b)),
+            0b0001 => Instruction::OutputW(RegWId(n0b)),
+            0b0010 => Instruction::LoadMem(RegId(n0b), next_addr()),
+            0b0011 => Instruction::LoadMemW(RegWId(n0b), next_addr()),
+            0b0100 => Instruction::StoreMem(RegId(n0b), next_addr()),
+            0b0101 => Instruction::StoreMemW(RegWId(n0b), next_addr()),
+            0b0110 => match DspOperation::from_code(n0b).filter(|_| features.dsp) {
+                // the same length as jmp, so that enabling DSP doesn't move instructions
+                Some(op) => {
+                    let (a, b) = byte_to_nibbles(next_byte());
+                    Instruction::Dsp(op, RegId(a), RegId(b), next_byte())
+                }
+                None => Instruction::Jmp(next_addr()),
+            },
+            0b0111 => Instruction::Jo(RegId(n0b), next_addr()),
+            0b1000..=0b1111 => {
+                let op = Operation::from_code(((n0a & 1) << 4) | n0b);
+                let ab = next_byte();
+                let (a, b) = byte_to_nibbles(ab);
+                match n0a >> 1 {
+                    0b100 => Instruction::Op(op, RegId(a), RegId(b)),
+                    0b101 => Instruction::OpW(op, RegWId(a), RegWId(b)),
+                    0b110 => {
+                        let mut bytes = Value::default().to_be_bytes();
+                        for b in &mut bytes {
+                            *b = next_byte();
+                        }
+                        Instruction::OpImm(op, RegId(a), RegId(b), Imm(Value::from_be_bytes(bytes)))
+                    }
+                    0b111 => {
+                        let mut bytes = WideValue::default().to_be_bytes();
+                        for b in &mut bytes {
+                            *b = next_byte();
+                        }
+                        Instruction::OpImmW(
+                            op,
+                            RegWId(a),
+                            RegWId(b),
+                            ImmW(WideValue::from_be_bytes(bytes)),
+                        )
+                    }
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    pub fn encode(&self, data: &mut Vec<u8>) {
+        let push_addr = |data: &mut Vec<u8>, m: Addr| data.extend_from_slice(&m.0.to_be_bytes());
+        match *self {
+            Instruction::Output(a) => data.push(0b0000_0000 | a.0),
+            Instruction::OutputW(a) => data.push(0b0001_0000 | a.0),
+            Instruction::LoadMem(a, m) => {
+                data.push(0b0010_0000 | a.0);
+                push_addr(data, m);
+            }
+            Instruction::LoadMemW(a, m) => {
+                data.push(0b0011_0000 | a.0);
+                push_addr(data, m);
+            }
+            Instruction::StoreMem(a, m) => {
+                data.push(0b0100_0000 | a.0);
+                push_addr(data, m);
+            }
+            Instruction::StoreMemW(a, m) => {
+                data.push(0b0101_0000 | a.0);
+                push_addr(data, m);
+            }
+            Instruction::Jmp(m) => {
+                data.push(0b0110_0000);
+                push_addr(data, m);
+            }
+            Instruction::Jo(a, m) => {
+                data.push(0b0111_0000 | a.0);
+                push_addr(data, m);
+            }
+            Instruction::Op(o, a, b) => {
+                data.push(0b1000_0000 | o.code());
+                data.push((a.0 << 4) | b.0);
+            }
+            Instruction::OpW(o, a, b) => {
+                data.push(0b1010_0000 | o.code());
+                data.push((a.0 << 4) | b.0);
+            }
+            Instruction::OpImm(o, a, b, i) => {
+                data.push(0b1100_0000 | o.code());
+                data.push((a.0 << 4) | b.0);
+                data.extend_from_slice(&i.0.to_be_bytes());
+            }
+            Instruction::OpImmW(o, a, b, i) => {
+                data.push(0b1110_0000 | o.code());
+                data.push((a.0 << 4) | b.0);
+                data.extend_from_slice(&i.0.to_be_bytes());
+            }
+            Instruction::Dsp(o, a, b, k) => {
+                data.push(0b0110_0000 | o.code());
+                data.push((a.0 << 4) | b.0);
+                data.push(k);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Output(a) => write!(f, "output r{}", a.0),
+            Instruction::OutputW(a) => write!(f, "outputw r{}", a.0),
+            Instruction::LoadMem(a, m) => write!(f, "loadmem r{} {}", a.0, m.0),
+            Instruction::LoadMemW(a, m) => write!(f, "loadmemw r{} {}", a.0, m.0),
+            Instruction::StoreMem(a, m) => write!(f, "storemem r{} {}", a.0, m.0),
+            Instruction::StoreMemW(a, m) => write!(f, "storememw r{} {}", a.0, m.0),
+            Instruction::Jmp(m) => write!(f, "jmp {}", m.0),
+            Instruction::Jo(a, m) => write!(f, "jo r{} {}", a.0, m.0),
+            Instruction::Op(o, a, b) => write!(f, "{} r{} r{}", o.mnemonic(), a.0, b.0),
+            Instruction::OpW(o, a, b) => write!(f, "{}w r{} r{}", o.mnemonic(), a.0, b.0),
+            Instruction::OpImm(o, a, b, i) => {
+                write!(f, "{}imm r{} r{} {}", o.mnemonic(), a.0, b.0, i.0)
+            }
+            Instruction::OpImmW(o, a, b, i) => {
+                write!(f, "{}immw r{} r{} {}", o.mnemonic(), a.0, b.0, i.0)
+            }
+            Instruction::Dsp(o, a, b, k) => write!(f, "{} r{} r{} {}", o.mnemonic(), a.0, b.0, k),
+        }
+    }
+}
+
+fn byte_to_nibbles(b: u8) -> (u8, u8) {
+    ((b >> 4) & 0xf, b & 0xf)
+}
+
+/// Decodes the instruction at `offset`, returning it only if it is
+/// canonically encoded and fits in the program, along with the offset just
+/// past its bytes (or the end of the program)
+fn decode_canonical(
+    program: &[u8],
+    offset: usize,
+    features: IsaFeatures,
+    encoded: &mut Vec<u8>,
+) -> (Option<Instruction>, usize) {
+    let mut end = offset;
+    let instruction = Instruction::decode(features, || {
+        let b = program.get(end).cloned().unwrap_or(0);
+        end += 1;
+        b
+    });
+    encoded.clear();
+    instruction.encode(encoded);
+    let end = end.min(program.len());
+    if program[offset..end] == encoded[..] {
+        (Some(instruction), end)
+    } else {
+        (None, end)
+    }
+}
+
+/// Disassembles a program into `(offset, line)` pairs, one per instruction.
+/// Bytes that don't form a canonically-encoded instruction (e.g. a truncated
+/// instruction at the end, or a jmp with its unused bits set) are emitted as
+/// raw `byte` directives, so that the lines assemble back to exactly the same
+/// bytes.
+pub fn disassemble_lines(program: &[u8], features: IsaFeatures) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    let mut encoded = Vec::new();
+    while offset < program.len() {
+        let (instruction, end) = decode_canonical(program, offset, features, &mut encoded);
+        match instruction {
+            Some(instruction) => lines.push((offset, instruction.to_string())),
+            None => {
+                for (i, b) in program[offset..end].iter().enumerate() {
+                    lines.push((offset + i, format!("byte {}", b)));
+                }
+            }
+        }
+        offset = end;
+    }
+    lines
+}
+
+/// Every canonically-encoded instruction in a program with its offset,
+/// skipping the bytes that `disassemble_lines` would emit as `byte` directives
+pub fn decode_instructions(program: &[u8], features: IsaFeatures) -> Vec<(usize, Instruction)> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    let mut encoded = Vec::new();
+    while offset < program.len() {
+        let (instruction, end) = decode_canonical(program, offset, features, &mut encoded);
+        if let Some(instruction) = instruction {
+            instructions.push((offset, instruction));
+        }
+        offset = end;
+    }
+    instructions
+}
+
+/// Produces assembly text for a program which assembles back to exactly the
+/// same bytes, with each instruction's offset in a trailing comment.
+pub fn disassemble(program: &[u8], features: IsaFeatures) -> String {
+    disassemble_lines(program, features)
+        .into_iter()
+        .map(|(offset, line)| format!("    {:<36}; {}\n", line, offset))
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// The features needed by the instructions in some assembly, so that a program
+/// assembled from it can declare them
+pub fn required_features(text: &str) -> IsaFeatures {
+    let mut features = IsaFeatures::default();
+    for line in text.lines() {
+        let first_word = line.split(';').next().unwrap().split_whitespace().next();
+        if first_word.and_then(DspOperation::from_mnemonic).is_some() {
+            features.dsp = true;
+        }
+    }
+    features
 }
 
-pub fn assemble(text: String) -> Vec<u8> {
+pub fn assemble(text: &str) -> Result<Vec<u8>, AssemblyError> {
     let mut data: Vec<u8> = Vec::new();
 
     let mut labels: HashMap<String, usize> = HashMap::new();
-    let mut label_uses: Vec<(String, usize)> = Vec::new();
+    let mut label_uses: Vec<(String, usize, usize)> = Vec::new();
 
-    let encode_register = |words: &mut SplitWhitespace| -> u8 {
-        let w = words.next().unwrap();
-        assert!(w.starts_with("r"));
-        let i = (&w[1..]).parse::<u8>().unwrap();
-        i
+    let next_word = |words: &mut SplitWhitespace, what: &str| -> Result<String, String> {
+        words
+            .next()
+            .map(|w| w.to_string())
+            .ok_or_else(|| format!("expected {}", what))
     };
 
-    let encode_address =
-        |words: &mut SplitWhitespace, data: &mut Vec<u8>, label_uses: &mut Vec<(String, usize)>| {
-            let w = words.next().unwrap();
-            let [b0, b1] = if let Ok(i) = w.parse::<i16>() {
-                i.to_be_bytes()
-            } else {
-                label_uses.push((w.to_string(), data.len()));
-                [0, 0]
-            };
-            data.push(b0);
-            data.push(b1);
-        };
-
-    let encode_operation = |opstr: &str| -> u8 {
-        match opstr {
-            "copy" => 0b00000,
-            "not" => 0b00001,
-            "neg" => 0b00010,
-            "reverse" => 0b00011,
-            "numones" => 0b00100,
-            "numzeros" => 0b00101,
-            "and" => 0b00110,
-            "or" => 0b00111,
-            "xor" => 0b01000,
-            "shl" => 0b01001,
-            "shlm" => 0b01010,
-            "shr" => 0b01011,
-            "shrm" => 0b01100,
-            "rotl" => 0b01101,
-            "rotr" => 0b01110,
-            "addc" => 0b01111,
-            "addm" => 0b10000,
-            "subc" => 0b10001,
-            "subm" => 0b10010,
-            "absdiff" => 0b10011,
-            "mulc" => 0b10100,
-            "mulm" => 0b10101,
-            "div" => 0b10110,
-            "mod" => 0b10111,
-            "powm" => 0b11000,
-            "powc" => 0b11001,
-            "gt" => 0b11010,
-            "ge" => 0b11011,
-            "lt" => 0b11100,
-            "le" => 0b11101,
-            "eq" => 0b11110,
-            "ne" => 0b11111,
-            _ => panic!("{}", opstr),
+    let encode_register = |words: &mut SplitWhitespace| -> Result<u8, String> {
+        let w = next_word(words, "a register")?;
+        match w.strip_prefix('r').map(|i| i.parse::<u8>()) {
+            Some(Ok(i)) if i < 16 => Ok(i),
+            _ => Err(format!("invalid register \"{}\", expected r0 to r15", w)),
         }
     };
 
-    for line in text.lines() {
-        let line = line.trim().to_string();
-        let line = line.split(";").next().unwrap();
+    let encode_address = |words: &mut SplitWhitespace,
+                          data: &mut Vec<u8>,
+                          label_uses: &mut Vec<(String, usize, usize)>,
+                          line_number: usize|
+     -> Result<(), String> {
+        let w = next_word(words, "an address or label")?;
+        let [b0, b1] = if let Ok(i) = w.parse::<i16>() {
+            i.to_be_bytes()
+        } else if let Ok(i) = w.parse::<u16>() {
+            i.to_be_bytes()
+        } else {
+            label_uses.push((w, data.len(), line_number));
+            [0, 0]
+        };
+        data.push(b0);
+        data.push(b1);
+        Ok(())
+    };
+
+    let mut assemble_line = |line: &str, line_number: usize| -> Result<(), String> {
+        let line = line.split(';').next().unwrap().trim();
         if line.is_empty() {
-            continue;
+            return Ok(());
         }
         let mut words = line.split_whitespace();
 
         let first_word = words.next().unwrap();
 
-        if first_word.ends_with(":") {
-            let label_name = first_word[..(first_word.len() - 1)].to_string();
-            labels.insert(label_name, data.len());
-            continue;
+        if let Some(label_name) = first_word.strip_suffix(':') {
+            if labels.insert(label_name.to_string(), data.len()).is_some() {
+                return Err(format!("duplicate label \"{}\"", label_name));
+            }
+            return Ok(());
         }
 
         match first_word {
-            "output" => data.push(0b0000_0000 | encode_register(&mut words)),
-            "outputw" => data.push(0b0001_0000 | encode_register(&mut words)),
+            "byte" => {
+                let w = next_word(&mut words, "a byte value")?;
+                let b = w
+                    .parse::<u8>()
+                    .map_err(|_| format!("invalid byte value \"{}\"", w))?;
+                data.push(b);
+            }
+            "output" => data.push(0b0000_0000 | encode_register(&mut words)?),
+            "outputw" => data.push(0b0001_0000 | encode_register(&mut words)?),
             "loadmem" => {
-                data.push(0b0010_0000 | encode_register(&mut words));
-                encode_address(&mut words, &mut data, &mut label_uses);
+                data.push(0b0010_0000 | encode_register(&mut words)?);
+                encode_address(&mut words, &mut data, &mut label_uses, line_number)?;
             }
             "loadmemw" => {
-                data.push(0b0011_0000 | encode_register(&mut words));
-                encode_address(&mut words, &mut data, &mut label_uses);
+                data.push(0b0011_0000 | encode_register(&mut words)?);
+                encode_address(&mut words, &mut data, &mut label_uses, line_number)?;
             }
             "storemem" => {
-                data.push(0b0100_0000 | encode_register(&mut words));
-                encode_address(&mut words, &mut data, &mut label_uses);
+                data.push(0b0100_0000 | encode_register(&mut words)?);
+                encode_address(&mut words, &mut data, &mut label_uses, line_number)?;
             }
             "storememw" => {
-                data.push(0b0101_0000 | encode_register(&mut words));
-                encode_address(&mut words, &mut data, &mut label_uses);
+                data.push(0b0101_0000 | encode_register(&mut words)?);
+                encode_address(&mut words, &mut data, &mut label_uses, line_number)?;
             }
             "jmp" => {
                 data.push(0b0110_0000);
-                encode_address(&mut words, &mut data, &mut label_uses);
+                encode_address(&mut words, &mut data, &mut label_uses, line_number)?;
             }
             "jo" => {
-                data.push(0b0111_0000 | encode_register(&mut words));
-                encode_address(&mut words, &mut data, &mut label_uses);
+                data.push(0b0111_0000 | encode_register(&mut words)?);
+                encode_address(&mut words, &mut data, &mut label_uses, line_number)?;
+            }
+            _ if DspOperation::from_mnemonic(first_word).is_some() => {
+                let op = DspOperation::from_mnemonic(first_word).unwrap();
+                data.push(0b0110_0000 | op.code());
+                let a = encode_register(&mut words)?;
+                let b = encode_register(&mut words)?;
+                data.push((a << 4) | b);
+                let w = next_word(&mut words, "a parameter")?;
+                let k = w
+                    .parse::<u8>()
+                    .map_err(|_| format!("invalid parameter \"{}\", expected 0 to 255", w))?;
+                data.push(k);
             }
             _ => {
                 let mut opstr = first_word.to_string();
                 let mut wide = false;
                 let mut immediate = false;
-                if opstr.ends_with("w") {
+                if opstr.ends_with('w') {
                     opstr.remove(opstr.len() - 1);
                     wide = true;
                 }
@@ -194,34 +639,281 @@ pub fn assemble(text: String) -> Vec<u8> {
                 if immediate {
                     opcode |= 0b0100_0000;
                 }
-                opcode |= encode_operation(&opstr);
+                let Some(op) = Operation::from_mnemonic(&opstr) else {
+                    return Err(format!("unknown instruction \"{}\"", first_word));
+                };
+                opcode |= op.code();
                 data.push(opcode);
-                let a = encode_register(&mut words);
-                let b = encode_register(&mut words);
+                let a = encode_register(&mut words)?;
+                let b = encode_register(&mut words)?;
                 data.push((a << 4) | b);
                 if immediate {
+                    let w = next_word(&mut words, "an immediate value")?;
+                    let invalid = |_| format!("invalid immediate value \"{}\"", w);
                     if wide {
-                        let i = words.next().unwrap().parse::<WideValue>().unwrap();
-                        for b in i.to_be_bytes() {
-                            data.push(b);
-                        }
+                        let i = w.parse::<WideValue>().map_err(invalid)?;
+                        data.extend_from_slice(&i.to_be_bytes());
                     } else {
-                        let i = words.next().unwrap().parse::<Value>().unwrap();
-                        for b in i.to_be_bytes() {
-                            data.push(b);
-                        }
+                        let i = w.parse::<Value>().map_err(invalid)?;
+                        data.extend_from_slice(&i.to_be_bytes());
                     }
                 }
             }
         }
+
+        if let Some(w) = words.next() {
+            return Err(format!("unexpected \"{}\"", w));
+        }
+        Ok(())
+    };
+
+    for (i, line) in text.lines().enumerate() {
+        assemble_line(line, i + 1).map_err(|message| AssemblyError {
+            line: i + 1,
+            message,
+        })?;
     }
 
-    for (name, location) in label_uses {
-        let value = *labels.get(&name).unwrap();
-        let [m0, m1] = (value as u16).to_be_bytes();
-        data[location + 0] = m0;
+    for (name, location, line) in label_uses {
+        let Some(value) = labels.get(&name) else {
+            return Err(AssemblyError {
+                line,
+                message: format!("undefined label \"{}\"", name),
+            });
+        };
+        let [m0, m1] = (*value as u16).to_be_bytes();
+        data[location] = m0;
         data[location + 1] = m1;
     }
 
-    data
+    Ok(data)
+}
+
+// Classic bytebeat runs at 8 kHz
+const BYTEBEAT_SAMPLE_RATE: usize = 8000;
+
+// t lives in r0 and r1 counts repeats of each output, which r2 tests.
+// Everything from r3 on holds intermediate values.
+const BYTEBEAT_T: RegId = RegId(0);
+const BYTEBEAT_COUNTER: RegId = RegId(1);
+const BYTEBEAT_CONDITION: RegId = RegId(2);
+const BYTEBEAT_FIRST_TEMP: u8 = 3;
+
+enum BytebeatExpr {
+    T,
+    Constant(Value),
+    // `~`
+    Not(Box<BytebeatExpr>),
+    // unary `-`
+    Negate(Box<BytebeatExpr>),
+    // `!`
+    LogicalNot(Box<BytebeatExpr>),
+    Binary(Operation, Box<BytebeatExpr>, Box<BytebeatExpr>),
+}
+
+struct BytebeatParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+// Binary operators from loosest to tightest binding, as in C
+const BYTEBEAT_PRECEDENCE: [&[(&str, Operation)]; 8] = [
+    &[("|", Operation::Or)],
+    &[("^", Operation::Xor)],
+    &[("&", Operation::And)],
+    &[("==", Operation::Eq), ("!=", Operation::Ne)],
+    &[
+        ("<=", Operation::Le),
+        (">=", Operation::Ge),
+        ("<", Operation::Lt),
+        (">", Operation::Gt),
+    ],
+    &[("<<", Operation::Shlm), (">>", Operation::Shrm)],
+    &[("+", Operation::Addm), ("-", Operation::Subm)],
+    &[
+        ("*", Operation::Mulm),
+        ("/", Operation::Div),
+        ("%", Operation::Mod),
+    ],
+];
+
+impl<'a> BytebeatParser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let rest = &self.text[self.position..];
+        // `<` mustn't match the start of `<<` or `<=`, and so on
+        let is_prefix_of_longer = BYTEBEAT_PRECEDENCE
+            .iter()
+            .flat_map(|level| level.iter())
+            .any(|(t, _)| t.len() > token.len() && t.starts_with(token) && rest.starts_with(t));
+        if rest.starts_with(token) && !is_prefix_of_longer {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} at column {}", message, self.position + 1)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<BytebeatExpr, String> {
+        if level == BYTEBEAT_PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        'operators: loop {
+            for (token, op) in BYTEBEAT_PRECEDENCE[level] {
+                if self.eat(token) {
+                    let rhs = self.parse_binary(level + 1)?;
+                    lhs = BytebeatExpr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<BytebeatExpr, String> {
+        if self.eat("~") {
+            Ok(BytebeatExpr::Not(Box::new(self.parse_unary()?)))
+        } else if self.eat("-") {
+            Ok(BytebeatExpr::Negate(Box::new(self.parse_unary()?)))
+        } else if self.eat("!") {
+            Ok(BytebeatExpr::LogicalNot(Box::new(self.parse_unary()?)))
+        } else if self.eat("+") {
+            self.parse_unary()
+        } else {
+            self.parse_atom()
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<BytebeatExpr, String> {
+        if self.eat("(") {
+            let expr = self.parse_binary(0)?;
+            if !self.eat(")") {
+                return Err(self.error("expected \")\""));
+            }
+            return Ok(expr);
+        }
+        self.skip_whitespace();
+        let rest = &self.text[self.position..];
+        let length = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let word = &rest[..length];
+        let atom = if word == "t" {
+            BytebeatExpr::T
+        } else if let Some(hex) = word.strip_prefix("0x") {
+            BytebeatExpr::Constant(
+                Value::from_str_radix(hex, 16).map_err(|_| self.error("invalid number"))?,
+            )
+        } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+            BytebeatExpr::Constant(word.parse().map_err(|_| self.error("invalid number"))?)
+        } else if word.is_empty() {
+            return Err(self.error("expected t, a number or \"(\""));
+        } else {
+            return Err(self.error(&format!("unknown name \"{}\"", word)));
+        };
+        self.position += length;
+        Ok(atom)
+    }
+}
+
+/// Appends instructions which leave the value of `expr` in register `dest`,
+/// using only the registers after it for intermediate values
+fn compile_bytebeat_expr(
+    expr: &BytebeatExpr,
+    dest: u8,
+    code: &mut Vec<Instruction>,
+) -> Result<(), String> {
+    let d = RegId(dest);
+    match expr {
+        BytebeatExpr::T => code.push(Instruction::Op(Operation::Copy, d, BYTEBEAT_T)),
+        BytebeatExpr::Constant(c) => code.push(Instruction::OpImm(Operation::Copy, d, d, Imm(*c))),
+        BytebeatExpr::Not(operand) => {
+            compile_bytebeat_expr(operand, dest, code)?;
+            code.push(Instruction::Op(Operation::Not, d, d));
+        }
+        BytebeatExpr::Negate(operand) => {
+            // two's complement
+            compile_bytebeat_expr(operand, dest, code)?;
+            code.push(Instruction::Op(Operation::Not, d, d));
+            code.push(Instruction::OpImm(Operation::Addm, d, d, Imm(1)));
+        }
+        BytebeatExpr::LogicalNot(operand) => {
+            compile_bytebeat_expr(operand, dest, code)?;
+            code.push(Instruction::OpImm(Operation::Eq, d, d, Imm(0)));
+        }
+        BytebeatExpr::Binary(op, lhs, rhs) => {
+            compile_bytebeat_expr(lhs, dest, code)?;
+            match **rhs {
+                BytebeatExpr::Constant(c) => code.push(Instruction::OpImm(*op, d, d, Imm(c))),
+                BytebeatExpr::T => code.push(Instruction::Op(*op, d, BYTEBEAT_T)),
+                _ => {
+                    if dest == 15 {
+                        return Err("expression is too deeply nested".to_string());
+                    }
+                    compile_bytebeat_expr(rhs, dest + 1, code)?;
+                    code.push(Instruction::Op(*op, d, RegId(dest + 1)));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compiles a classic bytebeat formula over `t`, like `t*(t>>8|t>>13)&63`,
+/// into a program which outputs its low byte for t = 0, 1, 2 and so on.
+/// Supports C's integer operators and precedence on unsigned 32-bit values.
+/// Each value is repeated so that t advances 8000 times per second at the
+/// default sample rate, as in the original bytebeats.
+pub fn compile_bytebeat(formula: &str) -> Result<Vec<u8>, String> {
+    let mut parser = BytebeatParser {
+        text: formula,
+        position: 0,
+    };
+    let expr = parser.parse_binary(0)?;
+    parser.skip_whitespace();
+    if parser.position < formula.len() {
+        return Err(parser.error("unexpected text"));
+    }
+
+    let repeats = (DEFAULT_SAMPLE_RATE * AUDIO_CHANNELS / BYTEBEAT_SAMPLE_RATE) as Value;
+    let value = RegId(BYTEBEAT_FIRST_TEMP);
+    let mut code = Vec::new();
+    compile_bytebeat_expr(&expr, BYTEBEAT_FIRST_TEMP, &mut code)?;
+    code.push(Instruction::OpImm(
+        Operation::Copy,
+        BYTEBEAT_COUNTER,
+        BYTEBEAT_COUNTER,
+        Imm(repeats),
+    ));
+    let mut data = Vec::new();
+    for instruction in &code {
+        instruction.encode(&mut data);
+    }
+    // the loop's jumps must be able to reach the start
+    if data.len() > u16::MAX as usize - 32 {
+        return Err("formula is too long".to_string());
+    }
+    let repeat_start = Addr(data.len() as u16);
+    for instruction in [
+        Instruction::Output(value),
+        Instruction::OpImm(Operation::Subm, BYTEBEAT_COUNTER, BYTEBEAT_COUNTER, Imm(1)),
+        Instruction::OpImm(Operation::Ne, BYTEBEAT_CONDITION, BYTEBEAT_COUNTER, Imm(0)),
+        Instruction::Jo(BYTEBEAT_CONDITION, repeat_start),
+        Instruction::OpImm(Operation::Addm, BYTEBEAT_T, BYTEBEAT_T, Imm(1)),
+        Instruction::Jmp(Addr(0)),
+    ] {
+        instruction.encode(&mut data);
+    }
+    Ok(data)
 }
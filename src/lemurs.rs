@@ -0,0 +1,39 @@
+use clap::{Parser, Subcommand};
+use lemurs::cli;
+
+/// Evolve, run and inspect lemurs programs
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    Evolve(Box<cli::evolve::Args>),
+    Interpret(cli::interpret::Args),
+    Render(cli::render::Args),
+    Asm(cli::asm::Args),
+    Disasm(cli::disasm::Args),
+    Bench(cli::bench::Args),
+    Stats(cli::stats::Args),
+    Corpus(cli::corpus::Args),
+    Diff(cli::diff::Args),
+    Serve(cli::serve::Args),
+}
+
+fn main() {
+    match Cli::parse().command {
+        Command::Evolve(args) => cli::evolve::run(*args),
+        Command::Interpret(args) => cli::interpret::run(args),
+        Command::Render(args) => cli::render::run(args),
+        Command::Asm(args) => cli::asm::run(args),
+        Command::Disasm(args) => cli::disasm::run(args),
+        Command::Bench(args) => cli::bench::run(args),
+        Command::Stats(args) => cli::stats::run(args),
+        Command::Corpus(args) => cli::corpus::run(args),
+        Command::Diff(args) => cli::diff::run(args),
+        Command::Serve(args) => cli::serve::run(args),
+    }
+}
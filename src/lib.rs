@@ -1,2 +1,43 @@
+pub mod app;
+pub mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
+pub mod constraints;
+pub mod corpus;
+pub mod diff;
+pub mod embedding;
+pub mod evaluate;
+pub mod event_log;
+pub mod export;
+pub mod features;
+pub mod filter;
+pub mod fitness;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(feature = "gpu")]
+pub mod gpu_spectrogram;
+pub mod import;
 pub mod instruction;
+pub mod logging;
 pub mod machine;
+pub mod memory_watch;
+#[cfg(feature = "midi")]
+pub mod midi;
+pub mod mutation;
+pub mod osc;
+pub mod parallel;
+pub mod physics;
+pub mod plugin;
+pub mod population;
+pub mod profile;
+pub mod program;
+pub mod rewind;
+pub mod routing;
+pub mod sequence;
+pub mod share;
+pub mod shortcuts;
+pub mod spectrogram;
+pub mod storage;
+pub mod synth;
+pub mod tape;
+pub mod templates;
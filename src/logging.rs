@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// How many of the most recent messages are kept for display
+const MAX_RECENT_ENTRIES: usize = 500;
+
+pub struct LogEntry {
+    pub level: Level,
+    pub message: String,
+}
+
+static RECENT_ENTRIES: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+
+/// Writes messages to stderr and remembers the most recent ones
+struct RecentLogger;
+
+static LOGGER: RecentLogger = RecentLogger;
+
+impl Log for RecentLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        eprintln!("[{}] {}", record.level(), record.args());
+        let mut entries = RECENT_ENTRIES.lock().unwrap();
+        if entries.len() == MAX_RECENT_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(LogEntry {
+            level: record.level(),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger. Debug messages are only kept if `verbose` is set.
+pub fn init(verbose: bool) {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(if verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    });
+}
+
+/// Calls `f` with the most recent log messages, oldest first
+pub fn with_recent_entries<R, F: FnOnce(&VecDeque<LogEntry>) -> R>(f: F) -> R {
+    f(&RECENT_ENTRIES.lock().unwrap())
+}
@@ -4,25 +4,75 @@ use std::{
 };
 
 use crate::instruction::{
-    Addr, Imm, ImmW, Instruction, Operation, RegId, RegWId, Value, WideValue,
+    Addr, DspOperation, Instruction, IsaFeatures, Operation, RegId, RegWId, Value, WideValue,
 };
 
+// 2^(-i/16) for i from 0 to 16 in 16.16 fixed point, interpolated between by `exp2_neg`
+const EXP2_TABLE: [u32; 17] = [
+    65536, 62757, 60097, 57549, 55109, 52773, 50535, 48393, 46341, 44376, 42495, 40693, 38968,
+    37316, 35734, 34219, 32768,
+];
+
+/// What a single instruction did, from `Machine::step_traced`
+pub struct TraceStep {
+    /// Where the instruction started
+    pub program_counter: usize,
+    pub instruction: Instruction,
+    /// Every small register the instruction changed, with its old and new value
+    pub register_changes: Vec<(RegId, Value, Value)>,
+}
+
+/// The memory a single instruction used, from `Machine::step_watched`. Ranges
+/// are an address and a length in bytes, and wrap around the end of memory.
+pub struct MemoryUse {
+    /// Where the instruction itself was fetched from
+    pub fetched: (usize, usize),
+    pub read: Option<(usize, usize)>,
+    pub written: Option<(usize, usize)>,
+}
+
+#[derive(Clone)]
 pub struct Machine {
     memory: Vec<u8>,
     program_counter: usize,
     register_file: [u8; 256],
+    features: IsaFeatures,
 }
 
 impl Machine {
+    /// A machine with only the base instruction set
     pub fn new(memory: Vec<u8>) -> Machine {
+        Machine::new_with_features(memory, IsaFeatures::default())
+    }
+
+    pub fn new_with_features(memory: Vec<u8>, features: IsaFeatures) -> Machine {
         assert!(!memory.is_empty());
         Machine {
             memory,
             program_counter: 0,
             register_file: [0; 256],
+            features,
         }
     }
 
+    pub fn features(&self) -> IsaFeatures {
+        self.features
+    }
+
+    /// Where the next instruction will be fetched from
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The machine's memory, which is also its program, for changing while it runs
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
     pub fn run<T: Write>(&mut self, num_steps: usize, output: &mut T) {
         for _ in 0..num_steps {
             let i = self.fetch();
@@ -30,72 +80,65 @@ impl Machine {
         }
     }
 
-    fn fetch(&mut self) -> Instruction {
-        let b0 = self.next_instruction_byte();
-        let (n0a, n0b) = Self::byte_to_nibbles(b0);
-        match n0a {
-            0b0000 => Instruction::Output(RegId(n0b)),
-            0b0001 => Instruction::OutputW(RegWId(n0b)),
-            0b0010 => Instruction::LoadMem(
-                RegId(n0b),
-                Self::bytes_to_addr(self.next_instruction_byte(), self.next_instruction_byte()),
-            ),
-            0b0011 => Instruction::LoadMemW(
-                RegWId(n0b),
-                Self::bytes_to_addr(self.next_instruction_byte(), self.next_instruction_byte()),
-            ),
-            0b0100 => Instruction::StoreMem(
-                RegId(n0b),
-                Self::bytes_to_addr(self.next_instruction_byte(), self.next_instruction_byte()),
-            ),
-            0b0101 => Instruction::StoreMemW(
-                RegWId(n0b),
-                Self::bytes_to_addr(self.next_instruction_byte(), self.next_instruction_byte()),
-            ),
-            0b0110 => Instruction::Jmp(Addr(u16::from_be_bytes([
-                self.next_instruction_byte(),
-                self.next_instruction_byte(),
-            ]))),
-            0b0111 => Instruction::Jo(
-                RegId(n0b),
-                Addr(u16::from_be_bytes([
-                    self.next_instruction_byte(),
-                    self.next_instruction_byte(),
-                ])),
-            ),
-            0b1000..=0b1111 => {
-                let op = Self::decode_operation(((n0a & 1) << 4) | n0b);
-                let ab = self.next_instruction_byte();
-                let (a, b) = Self::byte_to_nibbles(ab);
-                match n0a >> 1 {
-                    0b100 => Instruction::Op(op, RegId(a), RegId(b)),
-                    0b101 => Instruction::OpW(op, RegWId(a), RegWId(b)),
-                    0b110 => {
-                        let mut bytes = Value::default().to_be_bytes();
-                        for b in &mut bytes {
-                            *b = self.next_instruction_byte();
-                        }
-                        Instruction::OpImm(op, RegId(a), RegId(b), Imm(Value::from_be_bytes(bytes)))
-                    }
-                    0b111 => {
-                        let mut bytes = WideValue::default().to_be_bytes();
-                        for b in &mut bytes {
-                            *b = self.next_instruction_byte();
-                        }
-                        Instruction::OpImmW(
-                            op,
-                            RegWId(a),
-                            RegWId(b),
-                            ImmW(WideValue::from_be_bytes(bytes)),
-                        )
-                    }
-                    _ => panic!(),
+    /// Runs one instruction and reports what it did
+    pub fn step_traced<T: Write>(&mut self, output: &mut T) -> TraceStep {
+        let program_counter = self.program_counter;
+        let registers_before = self.register_file;
+        let instruction = self.fetch();
+        self.execute(instruction, output);
+        // wide registers overlay pairs of small ones, so changes are all reported as small registers
+        let num_bytes = Value::default().to_be_bytes().len();
+        let register_changes = (0..(self.register_file.len() / num_bytes) as u8)
+            .map(RegId)
+            .filter_map(|r| {
+                let range = r.0 as usize * num_bytes..(r.0 as usize + 1) * num_bytes;
+                if registers_before[range.clone()] == self.register_file[range] {
+                    return None;
                 }
-            }
-            _ => panic!(),
+                let new = self.read_register(r);
+                let mut old = Value::default().to_be_bytes();
+                old.copy_from_slice(&registers_before[r.0 as usize * num_bytes..][..num_bytes]);
+                Some((r, Value::from_be_bytes(old), new))
+            })
+            .collect();
+        TraceStep {
+            program_counter,
+            instruction,
+            register_changes,
         }
     }
 
+    /// Runs one instruction and reports which memory it used
+    pub fn step_watched<T: Write>(&mut self, output: &mut T) -> MemoryUse {
+        let start = self.program_counter;
+        let mut fetched_len = 0;
+        let features = self.features;
+        let instruction = Instruction::decode(features, || {
+            fetched_len += 1;
+            self.next_instruction_byte()
+        });
+        let value_len = Value::default().to_be_bytes().len();
+        let wide_len = WideValue::default().to_be_bytes().len();
+        let (read, written) = match instruction {
+            Instruction::LoadMem(_, m) => (Some((m.0 as usize, value_len)), None),
+            Instruction::LoadMemW(_, m) => (Some((m.0 as usize, wide_len)), None),
+            Instruction::StoreMem(_, m) => (None, Some((m.0 as usize, value_len))),
+            Instruction::StoreMemW(_, m) => (None, Some((m.0 as usize, wide_len))),
+            _ => (None, None),
+        };
+        self.execute(instruction, output);
+        MemoryUse {
+            fetched: (start, fetched_len),
+            read,
+            written,
+        }
+    }
+
+    fn fetch(&mut self) -> Instruction {
+        let features = self.features;
+        Instruction::decode(features, || self.next_instruction_byte())
+    }
+
     fn execute<T: Write>(&mut self, instruction: Instruction, output: &mut T) {
         match instruction {
             Instruction::Output(a) => {
@@ -138,10 +181,14 @@ impl Machine {
                 a,
                 Self::evaluate_operation_wide(o, self.read_register_wide(b), i.0),
             ),
+            Instruction::Dsp(o, a, b, k) => self.write_register(
+                a,
+                Self::evaluate_dsp(o, self.read_register(a), self.read_register(b), k),
+            ),
         }
     }
 
-    fn read_register(&self, register: RegId) -> Value {
+    pub fn read_register(&self, register: RegId) -> Value {
         let mut bytes = Value::default().to_be_bytes();
         let num_bytes = bytes.len();
         for (i, b) in bytes.iter_mut().enumerate() {
@@ -149,7 +196,7 @@ impl Machine {
         }
         Value::from_be_bytes(bytes)
     }
-    fn read_register_wide(&self, register: RegWId) -> WideValue {
+    pub fn read_register_wide(&self, register: RegWId) -> WideValue {
         let mut bytes = WideValue::default().to_be_bytes();
         let num_bytes = bytes.len();
         for (i, b) in bytes.iter_mut().enumerate() {
@@ -213,52 +260,6 @@ impl Machine {
         b
     }
 
-    fn byte_to_nibbles(b: u8) -> (u8, u8) {
-        ((b >> 4) & 0xf, b & 0xf)
-    }
-
-    fn bytes_to_addr(b0: u8, b1: u8) -> Addr {
-        Addr(u16::from_be_bytes([b0, b1]))
-    }
-
-    fn decode_operation(n: u8) -> Operation {
-        match n {
-            0b00000 => Operation::Copy,
-            0b00001 => Operation::Not,
-            0b00010 => Operation::Neg,
-            0b00011 => Operation::Reverse,
-            0b00100 => Operation::Numzeros,
-            0b00101 => Operation::Numones,
-            0b00110 => Operation::And,
-            0b00111 => Operation::Or,
-            0b01000 => Operation::Xor,
-            0b01001 => Operation::Shl,
-            0b01010 => Operation::Shlm,
-            0b01011 => Operation::Shr,
-            0b01100 => Operation::Shrm,
-            0b01101 => Operation::Rotl,
-            0b01110 => Operation::Rotr,
-            0b01111 => Operation::Addc,
-            0b10000 => Operation::Addm,
-            0b10001 => Operation::Subc,
-            0b10010 => Operation::Subm,
-            0b10011 => Operation::Absdiff,
-            0b10100 => Operation::Mulc,
-            0b10101 => Operation::Mulm,
-            0b10110 => Operation::Div,
-            0b10111 => Operation::Mod,
-            0b11000 => Operation::Powm,
-            0b11001 => Operation::Powc,
-            0b11010 => Operation::Gt,
-            0b11011 => Operation::Ge,
-            0b11100 => Operation::Lt,
-            0b11101 => Operation::Le,
-            0b11110 => Operation::Eq,
-            0b11111 => Operation::Ne,
-            _ => panic!(),
-        }
-    }
-
     fn evaluate_operation(op: Operation, a: Value, b: Value) -> Value {
         match op {
             Operation::Copy => b,
@@ -332,4 +333,41 @@ impl Machine {
             Operation::Ne => a.ne(&b) as WideValue,
         }
     }
+
+    fn evaluate_dsp(op: DspOperation, a: Value, b: Value, k: u8) -> Value {
+        let (a, b) = (a as i32 as i64, b as i32 as i64);
+        let result = match op {
+            DspOperation::Sin => (sin(b as u32) >> k.min(31)) as i64,
+            DspOperation::Exp => {
+                let k = k.min(31) as u32;
+                let whole = (b as u32) >> k;
+                let fraction = ((b as u32 as u64 & ((1 << k) - 1)) << 16) >> k;
+                exp2_neg(fraction as u32).checked_shr(whole).unwrap_or(0) as i64
+            }
+            DspOperation::Mulq => (a * b) >> k.min(63),
+            DspOperation::Onepole => a + ((b - a) >> k.min(63)),
+        };
+        result.clamp(i32::MIN as i64, i32::MAX as i64) as i32 as Value
+    }
+}
+
+/// sin of `phase` as a fraction of a full turn, in 16.16 fixed point, using
+/// Bhaskara's approximation, which is within 0.002 of the true value
+fn sin(phase: u32) -> i32 {
+    // position within the half turn, from 0 to 65535
+    let h = ((phase << 1) >> 16) as u64;
+    let p = (h * (65536 - h)) >> 16;
+    let s = ((16 * p) << 16) / (5 * 65536 - 4 * p);
+    if phase >> 31 == 0 {
+        s as i32
+    } else {
+        -(s as i32)
+    }
+}
+
+/// 2^-x for x from 0 to 1 in 16.16 fixed point
+fn exp2_neg(x: u32) -> u32 {
+    let i = (x >> 12) as usize;
+    let fraction = x & 0xfff;
+    EXP2_TABLE[i] - (((EXP2_TABLE[i] - EXP2_TABLE[i + 1]) * fraction) >> 12)
 }
@@ -0,0 +1,89 @@
+use crate::instruction::IsaFeatures;
+use crate::machine::{Machine, MemoryUse};
+use crate::physics::Physics;
+
+// Heat each byte keeps per update, so that uses fade out over a few frames
+const HEAT_DECAY: f32 = 0.85;
+
+// Most instructions run per update, so that a program which outputs little
+// can't stall the UI
+const MAX_STEPS_PER_UPDATE: usize = 1 << 20;
+
+/// Runs a program again alongside playback of its output, keeping track of
+/// which bytes of memory it has recently fetched instructions from, read and
+/// written
+pub struct MemoryWatch {
+    program: Vec<u8>,
+    machine: Machine,
+    physics: Physics,
+    steps: usize,
+    output_len: usize,
+    // per byte, from 0 to 1, higher for more recent use
+    pub fetched: Vec<f32>,
+    pub read: Vec<f32>,
+    pub written: Vec<f32>,
+}
+
+impl MemoryWatch {
+    pub fn new(program: Vec<u8>, features: IsaFeatures, physics: Physics) -> MemoryWatch {
+        let len = program.len();
+        MemoryWatch {
+            machine: Machine::new_with_features(program.clone(), features),
+            program,
+            physics,
+            steps: 0,
+            output_len: 0,
+            fetched: vec![0.0; len],
+            read: vec![0.0; len],
+            written: vec![0.0; len],
+        }
+    }
+
+    /// The machine's memory as it is now, which differs from the program once
+    /// the program writes to itself
+    pub fn memory(&self) -> &[u8] {
+        self.machine.memory()
+    }
+
+    /// Runs the machine until it has made `position` bytes of output, starting
+    /// over if it's already past that, such as when playback loops
+    pub fn advance_to(&mut self, position: usize) {
+        let position = self.physics.raw_length(position);
+        if position < self.physics.samples(self.steps, self.output_len) {
+            self.machine =
+                Machine::new_with_features(self.program.clone(), self.machine.features());
+            self.steps = 0;
+            self.output_len = 0;
+        }
+        for heat in [&mut self.fetched, &mut self.read, &mut self.written] {
+            heat.iter_mut().for_each(|h| *h *= HEAT_DECAY);
+        }
+        let mut output = Vec::new();
+        for _ in 0..MAX_STEPS_PER_UPDATE {
+            if self.physics.samples(self.steps, self.output_len) >= position {
+                break;
+            }
+            output.clear();
+            let MemoryUse {
+                fetched,
+                read,
+                written,
+            } = self.machine.step_watched(&mut output);
+            self.steps += 1;
+            self.output_len += output.len();
+            heat_up(&mut self.fetched, fetched);
+            if let Some(range) = read {
+                heat_up(&mut self.read, range);
+            }
+            if let Some(range) = written {
+                heat_up(&mut self.written, range);
+            }
+        }
+    }
+}
+
+fn heat_up(heat: &mut [f32], (address, len): (usize, usize)) {
+    for i in address..address + len {
+        heat[i % heat.len()] = 1.0;
+    }
+}
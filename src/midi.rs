@@ -0,0 +1,186 @@
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use midir::MidiIO;
+
+use crate::audio::{AudioBackend, Playback};
+use crate::sequence::{decode_midi, MidiEvent};
+
+/// A key pressed on a MIDI controller. Note offs are ignored, since sounds
+/// always play to the end.
+#[derive(Clone, Copy)]
+pub struct NoteOn {
+    pub note: u8,
+    // 1 to 127
+    pub velocity: u8,
+}
+
+/// The first port whose name contains `name`, or the first port if None
+fn find_port<T: MidiIO>(io: &T, name: Option<&str>) -> Result<(T::Port, String), String> {
+    io.ports()
+        .into_iter()
+        .filter_map(|p| io.port_name(&p).ok().map(|n| (p, n)))
+        .find(|(_, n)| name.is_none_or(|name| n.contains(name)))
+        .ok_or_else(|| match name {
+            Some(name) => format!("no MIDI port matching \"{}\"", name),
+            None => "no MIDI ports".to_string(),
+        })
+}
+
+/// Notes arriving from a MIDI input port
+pub struct MidiInput {
+    port_name: String,
+    receiver: Receiver<NoteOn>,
+    _connection: midir::MidiInputConnection<()>,
+}
+
+impl MidiInput {
+    /// Connects to the first port whose name contains `port_name`, or to the
+    /// first port if None. `on_note` is called from midir's thread after each
+    /// note arrives, e.g. to wake up the UI.
+    pub fn connect<F: Fn() + Send + 'static>(
+        port_name: Option<&str>,
+        on_note: F,
+    ) -> Result<MidiInput, String> {
+        let input = midir::MidiInput::new("lemurs").map_err(|e| e.to_string())?;
+        let (port, port_name) = find_port(&input, port_name)?;
+
+        let (sender, receiver) = channel();
+        let connection = input
+            .connect(
+                &port,
+                "lemurs-input",
+                move |_, message, _| {
+                    // note on, on any channel. Zero velocity means note off.
+                    if let [status, note, velocity] = *message {
+                        if status & 0xf0 == 0x90 && velocity > 0 {
+                            let _ = sender.send(NoteOn { note, velocity });
+                            on_note();
+                        }
+                    }
+                },
+                (),
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(MidiInput {
+            port_name,
+            receiver,
+            _connection: connection,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Returns the next note received, if there is one
+    pub fn try_recv(&self) -> Option<NoteOn> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// e.g. "C4" for middle C
+pub fn note_name(note: u8) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    format!("{}{}", NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
+
+/// Plays output by decoding it as MIDI and sending it to an output port at
+/// the rate a MIDI cable would carry it
+pub struct MidiOutputBackend {
+    port_name: String,
+    // output to play and the offset to start from
+    sender: std::sync::mpsc::Sender<(Arc<[u8]>, usize)>,
+    error: Arc<Mutex<Option<String>>>,
+    _sender_thread: std::thread::JoinHandle<()>,
+}
+
+impl MidiOutputBackend {
+    /// Connects to the first port whose name contains `port_name`, or to the first port if None
+    pub fn connect(port_name: Option<&str>) -> Result<MidiOutputBackend, String> {
+        let output = midir::MidiOutput::new("lemurs").map_err(|e| e.to_string())?;
+        let (port, port_name) = find_port(&output, port_name)?;
+        let mut connection = output
+            .connect(&port, "lemurs-output")
+            .map_err(|e| e.to_string())?;
+
+        let (sender, receiver) = channel::<(Arc<[u8]>, usize)>();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = Arc::clone(&error);
+        let sender_thread = std::thread::spawn(move || {
+            let mut events: Vec<MidiEvent> = Vec::new();
+            let mut next_event = 0;
+            let mut start = Instant::now();
+            loop {
+                let received = match events.get(next_event) {
+                    Some(event) => {
+                        let due = start + Duration::from_secs_f32(event.time());
+                        receiver.recv_timeout(due.saturating_duration_since(Instant::now()))
+                    }
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                let result = match received {
+                    Ok((data, start_offset)) => {
+                        events = decode_midi(&data[start_offset..]);
+                        next_event = 0;
+                        start = Instant::now();
+                        all_notes_off(&mut connection)
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        next_event += 1;
+                        connection.send(events[next_event - 1].bytes())
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        let _ = all_notes_off(&mut connection);
+                        return;
+                    }
+                };
+                if let Err(e) = result {
+                    *thread_error.lock().unwrap() = Some(format!("MIDI output stopped: {}", e));
+                    return;
+                }
+            }
+        });
+
+        Ok(MidiOutputBackend {
+            port_name,
+            sender,
+            error,
+            _sender_thread: sender_thread,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+}
+
+/// Silences every channel, so notes from the previous output don't hang
+fn all_notes_off(connection: &mut midir::MidiOutputConnection) -> Result<(), midir::SendError> {
+    for channel in 0..16 {
+        connection.send(&[0xb0 | channel, 123, 0])?;
+    }
+    Ok(())
+}
+
+impl AudioBackend for MidiOutputBackend {
+    /// Output is messages rather than samples, so the gain is ignored, and it
+    /// always plays once
+    fn play(&mut self, playback: Playback) {
+        let start = playback.start.min(playback.data.len());
+        // fails only if the sender thread has stopped, which take_error reports
+        let _ = self.sender.send((playback.data, start));
+    }
+
+    fn stop(&mut self) {
+        let _ = self.sender.send((Arc::from([]), 0));
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.lock().unwrap().take()
+    }
+}
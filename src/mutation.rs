@@ -0,0 +1,510 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+use crate::instruction::{decode_instructions, DspOperation, Instruction, IsaFeatures, Operation};
+use crate::program::{Program, MAX_PROGRAM_LENGTH};
+
+// Operations which do similar things, for swapping one for another
+const RELATED_OPERATIONS: [&[Operation]; 6] = [
+    &[
+        Operation::Copy,
+        Operation::Not,
+        Operation::Neg,
+        Operation::Reverse,
+        Operation::Numzeros,
+        Operation::Numones,
+    ],
+    &[Operation::And, Operation::Or, Operation::Xor],
+    &[
+        Operation::Shl,
+        Operation::Shlm,
+        Operation::Shr,
+        Operation::Shrm,
+        Operation::Rotl,
+        Operation::Rotr,
+    ],
+    &[
+        Operation::Addc,
+        Operation::Addm,
+        Operation::Subc,
+        Operation::Subm,
+        Operation::Absdiff,
+    ],
+    &[
+        Operation::Mulc,
+        Operation::Mulm,
+        Operation::Div,
+        Operation::Mod,
+        Operation::Powm,
+        Operation::Powc,
+    ],
+    &[
+        Operation::Gt,
+        Operation::Ge,
+        Operation::Lt,
+        Operation::Le,
+        Operation::Eq,
+        Operation::Ne,
+    ],
+];
+
+// Largest change NudgeImmediate makes to an immediate value
+const MAX_NUDGE: i32 = 4;
+
+/// A random edit to a program
+pub trait MutationOperator {
+    fn name(&self) -> &str;
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore);
+}
+
+/// Combines two parent programs into a child
+pub trait CrossoverOperator {
+    fn name(&self) -> &str;
+
+    fn cross(&self, a: &Program, b: &Program, rng: &mut dyn RngCore) -> Program;
+}
+
+/// Inserts a random byte anywhere
+pub struct InsertByte;
+
+/// Removes a byte, unless the program is already short
+pub struct EraseByte {
+    pub min_length: usize,
+}
+
+/// Replaces a byte with a random one
+pub struct RandomizeByte;
+
+/// Flips one bit of a byte
+pub struct FlipBit;
+
+/// Replaces one register operand of an instruction
+pub struct ChangeRegister;
+
+/// Replaces the operation of an instruction with a related one, e.g. shl with
+/// shr, or one DSP operation with another
+pub struct SwapOperation;
+
+/// Points a jump at the start of another instruction
+pub struct RetargetJump;
+
+/// Adds or subtracts a small amount from an immediate value or a DSP
+/// instruction's parameter
+pub struct NudgeImmediate;
+
+/// Changes one of the program's physics, leaving its code as it is
+pub struct ChangePhysics;
+
+/// Joins the start of one parent to the end of the other, cutting each at
+/// the same fraction of its length. The child runs with the physics of the
+/// first.
+pub struct OnePointCrossover;
+
+/// Builds a child from segments of any number of parents. Each segment covers
+/// the same fraction of whichever parent it comes from, and is cut at
+/// instruction boundaries where the parent decodes. The child runs with the
+/// physics of whichever parent its first segment comes from.
+pub struct SegmentRecombination;
+
+impl MutationOperator for InsertByte {
+    fn name(&self) -> &str {
+        "Insert byte"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        let i = rng.gen_range(0..=program.len());
+        let b: u8 = rng.gen();
+        program.insert(i, b);
+    }
+}
+
+impl MutationOperator for EraseByte {
+    fn name(&self) -> &str {
+        "Erase byte"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        if program.len() <= self.min_length {
+            return;
+        }
+        let i = rng.gen_range(0..program.len());
+        program.remove(i);
+    }
+}
+
+impl MutationOperator for RandomizeByte {
+    fn name(&self) -> &str {
+        "Randomize byte"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        let i = rng.gen_range(0..program.len());
+        let b: u8 = rng.gen();
+        program.bytes_mut()[i] = b;
+    }
+}
+
+impl MutationOperator for FlipBit {
+    fn name(&self) -> &str {
+        "Flip bit"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        let i = rng.gen_range(0..program.len());
+        let b: u8 = 1 << rng.gen_range(0..=7);
+        program.bytes_mut()[i] ^= b;
+    }
+}
+
+/// Picks one of the program's instructions which `accepts` allows, changes it
+/// with `edit` and writes it back in place. `edit` is also given every
+/// instruction with its offset. It must keep the kind of instruction the same,
+/// so that the encoded length doesn't change. Only instructions in the
+/// program's features are decoded, so edits stay within them.
+fn edit_instruction<A, E>(program: &mut Program, rng: &mut dyn RngCore, accepts: A, edit: E)
+where
+    A: Fn(&Instruction) -> bool,
+    E: FnOnce(&mut Instruction, &mut dyn RngCore, &[(usize, Instruction)]),
+{
+    let instructions = decode_instructions(program.bytes(), program.features());
+    let candidates: Vec<usize> = (0..instructions.len())
+        .filter(|i| accepts(&instructions[*i].1))
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    let (offset, mut instruction) = instructions[candidates[rng.gen_range(0..candidates.len())]];
+    edit(&mut instruction, rng, &instructions);
+    let mut encoded = Vec::new();
+    instruction.encode(&mut encoded);
+    program.bytes_mut()[offset..(offset + encoded.len())].copy_from_slice(&encoded);
+}
+
+fn nudge(rng: &mut dyn RngCore) -> i32 {
+    let magnitude = rng.gen_range(1..=MAX_NUDGE);
+    if rng.gen() {
+        magnitude
+    } else {
+        -magnitude
+    }
+}
+
+impl MutationOperator for ChangeRegister {
+    fn name(&self) -> &str {
+        "Change register"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        edit_instruction(
+            program,
+            rng,
+            |i| !matches!(i, Instruction::Jmp(_)),
+            |instruction, rng, _| {
+                let r: u8 = rng.gen_range(0..16);
+                let first: bool = rng.gen();
+                match instruction {
+                    Instruction::Output(a)
+                    | Instruction::LoadMem(a, _)
+                    | Instruction::StoreMem(a, _)
+                    | Instruction::Jo(a, _) => a.0 = r,
+                    Instruction::OutputW(a)
+                    | Instruction::LoadMemW(a, _)
+                    | Instruction::StoreMemW(a, _) => a.0 = r,
+                    Instruction::Op(_, a, b)
+                    | Instruction::OpImm(_, a, b, _)
+                    | Instruction::Dsp(_, a, b, _) => {
+                        if first {
+                            a.0 = r
+                        } else {
+                            b.0 = r
+                        }
+                    }
+                    Instruction::OpW(_, a, b) | Instruction::OpImmW(_, a, b, _) => {
+                        if first {
+                            a.0 = r
+                        } else {
+                            b.0 = r
+                        }
+                    }
+                    Instruction::Jmp(_) => (),
+                }
+            },
+        );
+    }
+}
+
+impl MutationOperator for SwapOperation {
+    fn name(&self) -> &str {
+        "Swap operation"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        edit_instruction(
+            program,
+            rng,
+            |i| {
+                matches!(
+                    i,
+                    Instruction::Op(..)
+                        | Instruction::OpW(..)
+                        | Instruction::OpImm(..)
+                        | Instruction::OpImmW(..)
+                        | Instruction::Dsp(..)
+                )
+            },
+            |instruction, rng, _| {
+                if let Instruction::Dsp(op, ..) = instruction {
+                    let others: Vec<DspOperation> = (0..16)
+                        .filter_map(DspOperation::from_code)
+                        .filter(|o| o != op)
+                        .collect();
+                    *op = others[rng.gen_range(0..others.len())];
+                    return;
+                }
+                let (Instruction::Op(op, ..)
+                | Instruction::OpW(op, ..)
+                | Instruction::OpImm(op, ..)
+                | Instruction::OpImmW(op, ..)) = instruction
+                else {
+                    return;
+                };
+                let group = RELATED_OPERATIONS.iter().find(|g| g.contains(op)).unwrap();
+                let others: Vec<Operation> = group.iter().cloned().filter(|o| o != op).collect();
+                *op = others[rng.gen_range(0..others.len())];
+            },
+        );
+    }
+}
+
+impl MutationOperator for RetargetJump {
+    fn name(&self) -> &str {
+        "Retarget jump"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        edit_instruction(
+            program,
+            rng,
+            |i| matches!(i, Instruction::Jmp(_) | Instruction::Jo(..)),
+            |instruction, rng, instructions| {
+                let (target, _) = instructions[rng.gen_range(0..instructions.len())];
+                let (Instruction::Jmp(m) | Instruction::Jo(_, m)) = instruction else {
+                    return;
+                };
+                // programs are at most 2^16 bytes long, so every offset fits
+                m.0 = target as u16;
+            },
+        );
+    }
+}
+
+impl MutationOperator for NudgeImmediate {
+    fn name(&self) -> &str {
+        "Nudge immediate"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        edit_instruction(
+            program,
+            rng,
+            |i| {
+                matches!(
+                    i,
+                    Instruction::OpImm(..) | Instruction::OpImmW(..) | Instruction::Dsp(..)
+                )
+            },
+            |instruction, rng, _| match instruction {
+                Instruction::OpImm(_, _, _, i) => i.0 = i.0.wrapping_add_signed(nudge(rng)),
+                Instruction::OpImmW(_, _, _, i) => i.0 = i.0.wrapping_add_signed(nudge(rng) as i64),
+                Instruction::Dsp(_, _, _, k) => *k = k.wrapping_add_signed(nudge(rng) as i8),
+                _ => (),
+            },
+        );
+    }
+}
+
+impl MutationOperator for ChangePhysics {
+    fn name(&self) -> &str {
+        "Change physics"
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        let mut physics = program.physics();
+        physics.mutate(rng);
+        program.set_physics(physics);
+    }
+}
+
+impl CrossoverOperator for OnePointCrossover {
+    fn name(&self) -> &str {
+        "One-point crossover"
+    }
+
+    fn cross(&self, a: &Program, b: &Program, rng: &mut dyn RngCore) -> Program {
+        let t: f32 = rng.gen();
+        let cut_a = ((a.len() as f32 * t) as usize).clamp(1, a.len());
+        let cut_b = ((b.len() as f32 * t) as usize).min(b.len());
+        let mut bytes = a.bytes()[..cut_a].to_vec();
+        bytes.extend_from_slice(&b.bytes()[cut_b..]);
+        bytes.truncate(MAX_PROGRAM_LENGTH);
+        Program::new(bytes)
+            .unwrap()
+            .with_features(a.features().union(b.features()))
+            .with_physics(a.physics())
+    }
+}
+
+// Offsets where an instruction starts, along with the end of the program
+fn instruction_boundaries(program: &Program) -> Vec<usize> {
+    let mut boundaries: Vec<usize> = decode_instructions(program.bytes(), program.features())
+        .iter()
+        .map(|(offset, _)| *offset)
+        .collect();
+    boundaries.push(program.len());
+    boundaries
+}
+
+// The boundary nearest to `offset`
+fn snap(boundaries: &[usize], offset: usize) -> usize {
+    let i = boundaries.partition_point(|b| *b < offset);
+    match (i.checked_sub(1).map(|j| boundaries[j]), boundaries.get(i)) {
+        (Some(before), Some(after)) if offset - before < after - offset => before,
+        (_, Some(after)) => *after,
+        (Some(before), None) => before,
+        (None, None) => offset,
+    }
+}
+
+impl SegmentRecombination {
+    /// Cuts the parents into between one and two segments per parent, taking
+    /// each segment from the next of the parents in a random order, so that
+    /// every parent contributes. The child needs every feature any parent needs.
+    pub fn recombine(&self, parents: &[&Program], rng: &mut dyn RngCore) -> Program {
+        if parents.len() == 1 {
+            return parents[0].clone();
+        }
+        let num_segments = rng.gen_range(parents.len()..=(2 * parents.len()));
+        let mut cuts: Vec<f32> = (1..num_segments).map(|_| rng.gen()).collect();
+        cuts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        cuts.insert(0, 0.0);
+        cuts.push(1.0);
+
+        let mut order: Vec<usize> = (0..parents.len()).collect();
+        order.shuffle(rng);
+        let boundaries: Vec<Vec<usize>> =
+            parents.iter().map(|p| instruction_boundaries(p)).collect();
+
+        let mut bytes = Vec::new();
+        for (segment, range) in cuts.windows(2).enumerate() {
+            let i = order[segment % parents.len()];
+            let parent = parents[i];
+            let start = snap(&boundaries[i], (parent.len() as f32 * range[0]) as usize);
+            let end = snap(&boundaries[i], (parent.len() as f32 * range[1]) as usize);
+            if start < end {
+                bytes.extend_from_slice(&parent.bytes()[start..end]);
+            }
+        }
+        bytes.truncate(MAX_PROGRAM_LENGTH);
+        let features = parents
+            .iter()
+            .fold(IsaFeatures::default(), |f, p| f.union(p.features()));
+        Program::new(bytes)
+            .map(|p| {
+                p.with_features(features)
+                    .with_physics(parents[order[0]].physics())
+            })
+            .unwrap_or_else(|_| parents[order[0]].clone())
+    }
+}
+
+impl CrossoverOperator for SegmentRecombination {
+    fn name(&self) -> &str {
+        "Segment recombination"
+    }
+
+    fn cross(&self, a: &Program, b: &Program, rng: &mut dyn RngCore) -> Program {
+        self.recombine(&[a, b], rng)
+    }
+}
+
+/// Mutation operators to pick from, each with a relative weight
+pub struct MutationRegistry {
+    operators: Vec<(Box<dyn MutationOperator>, u32)>,
+}
+
+impl MutationRegistry {
+    /// A registry with no operators. Mutating with it does nothing.
+    pub fn empty() -> MutationRegistry {
+        MutationRegistry {
+            operators: Vec::new(),
+        }
+    }
+
+    /// The byte-level operators along with ones that edit whole instructions,
+    /// which are more likely to keep a program making sound
+    pub fn instruction_aware() -> MutationRegistry {
+        let mut registry = MutationRegistry::default();
+        registry.register(ChangeRegister, 6);
+        registry.register(SwapOperation, 6);
+        registry.register(RetargetJump, 3);
+        registry.register(NudgeImmediate, 5);
+        registry
+    }
+
+    pub fn register<M: MutationOperator + 'static>(&mut self, operator: M, weight: u32) {
+        self.operators.push((Box::new(operator), weight));
+    }
+
+    /// Changes the weight of the operator with this name. Returns false if
+    /// there isn't one.
+    pub fn set_weight(&mut self, name: &str, weight: u32) -> bool {
+        match self.operators.iter_mut().find(|(o, _)| o.name() == name) {
+            Some((_, w)) => {
+                *w = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The registered operators and their weights, in order of registration
+    pub fn operators(&self) -> impl Iterator<Item = (&dyn MutationOperator, u32)> {
+        self.operators.iter().map(|(o, w)| (o.as_ref(), *w))
+    }
+
+    /// Picks an operator with probability proportional to its weight
+    pub fn choose(&self, rng: &mut dyn RngCore) -> Option<&dyn MutationOperator> {
+        let total: u32 = self.operators.iter().map(|(_, w)| w).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut r = rng.gen_range(0..total);
+        for (operator, weight) in &self.operators {
+            if r < *weight {
+                return Some(operator.as_ref());
+            }
+            r -= weight;
+        }
+        unreachable!()
+    }
+
+    /// Applies one randomly chosen operator, returning its name
+    pub fn mutate(&self, program: &mut Program, rng: &mut dyn RngCore) -> Option<&str> {
+        let operator = self.choose(rng)?;
+        operator.apply(program, rng);
+        Some(operator.name())
+    }
+}
+
+impl Default for MutationRegistry {
+    /// The byte-level operators evolve has always used
+    fn default() -> MutationRegistry {
+        let mut registry = MutationRegistry::empty();
+        registry.register(InsertByte, 1);
+        registry.register(EraseByte { min_length: 16 }, 1);
+        registry.register(RandomizeByte, 8);
+        registry.register(FlipBit, 10);
+        registry
+    }
+}
@@ -0,0 +1,186 @@
+use std::io;
+use std::net::UdpSocket;
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{channel, Receiver};
+
+use log::warn;
+
+// Largest packet a UDP datagram can carry
+const MAX_PACKET_SIZE: usize = 65536;
+
+/// An argument of an OSC message
+#[derive(Clone, PartialEq, Debug)]
+pub enum OscArg {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    // nil and infinitum, which carry no data
+    Nil,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    /// The argument as a whole number. Floats are rounded and true is 1.
+    pub fn int(&self, index: usize) -> Option<i64> {
+        match self.args.get(index)? {
+            OscArg::Int(i) => Some(*i),
+            OscArg::Float(f) => Some(f.round() as i64),
+            OscArg::Bool(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+
+    /// The argument as a setting within `range`. Integers are used as they are,
+    /// while floats are knob positions from 0 to 1 across the range.
+    pub fn scaled(&self, index: usize, range: RangeInclusive<usize>) -> Option<usize> {
+        let (min, max) = range.into_inner();
+        let value = match self.args.get(index)? {
+            OscArg::Float(f) => min as f64 + f.clamp(0.0, 1.0) * (max - min) as f64,
+            _ => self.int(index)? as f64,
+        };
+        Some((value.round().max(0.0) as usize).clamp(min, max))
+    }
+}
+
+/// Reads a string padded with nulls to a multiple of four bytes
+fn read_string(data: &[u8], position: &mut usize) -> Result<String, String> {
+    let rest = data.get(*position..).ok_or("packet is truncated")?;
+    let length = rest
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or("string is not terminated")?;
+    let text = std::str::from_utf8(&rest[..length]).map_err(|_| "string is not UTF-8")?;
+    *position += (length + 4) & !3;
+    Ok(text.to_string())
+}
+
+fn read_bytes<const N: usize>(data: &[u8], position: &mut usize) -> Result<[u8; N], String> {
+    let bytes = data
+        .get(*position..(*position + N))
+        .ok_or("packet is truncated")?;
+    *position += N;
+    Ok(bytes.try_into().unwrap())
+}
+
+fn decode_message(data: &[u8]) -> Result<OscMessage, String> {
+    let mut position = 0;
+    let address = read_string(data, &mut position)?;
+    // old implementations may leave out the type tags when there are no arguments
+    if position >= data.len() {
+        return Ok(OscMessage {
+            address,
+            args: Vec::new(),
+        });
+    }
+    let tags = read_string(data, &mut position)?;
+    let tags = tags
+        .strip_prefix(',')
+        .ok_or("type tags don't start with ','")?;
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        let arg = match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(read_bytes(data, &mut position)?) as i64),
+            'h' => OscArg::Int(i64::from_be_bytes(read_bytes(data, &mut position)?)),
+            'f' => OscArg::Float(f32::from_be_bytes(read_bytes(data, &mut position)?) as f64),
+            'd' => OscArg::Float(f64::from_be_bytes(read_bytes(data, &mut position)?)),
+            's' | 'S' => OscArg::String(read_string(data, &mut position)?),
+            'b' => {
+                let length = u32::from_be_bytes(read_bytes(data, &mut position)?) as usize;
+                let blob = data
+                    .get(position..(position + length))
+                    .ok_or("packet is truncated")?;
+                position += (length + 3) & !3;
+                OscArg::Blob(blob.to_vec())
+            }
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'N' | 'I' => OscArg::Nil,
+            _ => return Err(format!("unsupported argument type '{}'", tag)),
+        };
+        args.push(arg);
+    }
+    Ok(OscMessage { address, args })
+}
+
+/// Decodes a message, or every message in a bundle. Bundle time tags are
+/// ignored and everything is handled as soon as it arrives.
+pub fn decode_packet(data: &[u8]) -> Result<Vec<OscMessage>, String> {
+    let Some(mut rest) = data.strip_prefix(b"#bundle\0") else {
+        return Ok(vec![decode_message(data)?]);
+    };
+    // skip the time tag
+    rest = rest.get(8..).ok_or("bundle is truncated")?;
+    let mut messages = Vec::new();
+    while !rest.is_empty() {
+        let mut position = 0;
+        let length = u32::from_be_bytes(read_bytes(rest, &mut position)?) as usize;
+        let element = rest
+            .get(4..(4 + length))
+            .ok_or("bundle element is truncated")?;
+        messages.extend(decode_packet(element)?);
+        rest = &rest[(4 + length)..];
+    }
+    Ok(messages)
+}
+
+/// Receives OSC messages over UDP on a thread of its own
+pub struct OscServer {
+    port: u16,
+    receiver: Receiver<OscMessage>,
+    _thread: std::thread::JoinHandle<()>,
+}
+
+impl OscServer {
+    /// Listens on `port` on every interface. `on_message` is called from the
+    /// server's thread after each message arrives, e.g. to wake up the UI.
+    pub fn bind<F: Fn() + Send + 'static>(port: u16, on_message: F) -> io::Result<OscServer> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        let port = socket.local_addr()?.port();
+        let (sender, receiver) = channel();
+        let thread = std::thread::spawn(move || {
+            let mut buffer = vec![0; MAX_PACKET_SIZE];
+            loop {
+                let (length, from) = match socket.recv_from(&mut buffer) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        warn!("OSC server stopped: {}", e);
+                        return;
+                    }
+                };
+                match decode_packet(&buffer[..length]) {
+                    Ok(messages) => {
+                        for message in messages {
+                            if sender.send(message).is_err() {
+                                return;
+                            }
+                        }
+                        on_message();
+                    }
+                    Err(e) => warn!("Ignoring OSC packet from {}: {}", from, e),
+                }
+            }
+        });
+        Ok(OscServer {
+            port,
+            receiver,
+            _thread: thread,
+        })
+    }
+
+    /// The port being listened on, which is chosen by the system if 0 was requested
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the next message received, if there is one
+    pub fn try_recv(&self) -> Option<OscMessage> {
+        self.receiver.try_recv().ok()
+    }
+}
@@ -0,0 +1,72 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[cfg(not(target_arch = "wasm32"))]
+use threadpool::ThreadPool;
+
+/// Parallel mapping over collections. Implementors only need to provide
+/// `map_slice`, so another executor (e.g. rayon) can stand in for the thread pool.
+pub trait ParallelMap {
+    /// Applies `f` to every item, returning the results in the same order
+    fn map_slice<T: Sync, U: Send, F: Fn(&T) -> U + Sync>(&mut self, items: &[T], f: F) -> Vec<U>;
+
+    /// Applies `f` to every index in the range, returning the results in order
+    fn map_range<U: Send, F: Fn(usize) -> U + Sync>(
+        &mut self,
+        range: Range<usize>,
+        f: F,
+    ) -> Vec<U> {
+        let indices: Vec<usize> = range.collect();
+        self.map_slice(&indices, |i| f(*i))
+    }
+
+    /// Like `map_slice`, but passes each item to `f` by value
+    fn map_into<T: Send, U: Send, F: Fn(T) -> U + Sync>(&mut self, items: Vec<T>, f: F) -> Vec<U> {
+        // Each item is taken exactly once, so the locks are never contended
+        let cells: Vec<Mutex<Option<T>>> = items.into_iter().map(|t| Mutex::new(Some(t))).collect();
+        self.map_slice(&cells, |cell| f(cell.lock().unwrap().take().unwrap()))
+    }
+
+    /// Like `map_into`, but workers take the next unstarted item whenever they
+    /// finish one, so a few slow items can't leave the other workers idle
+    fn map_balanced<T: Send, U: Send, F: Fn(T) -> U + Sync>(
+        &mut self,
+        items: Vec<T>,
+        f: F,
+    ) -> Vec<U> {
+        let num_items = items.len();
+        let cells: Vec<Mutex<Option<T>>> = items.into_iter().map(|t| Mutex::new(Some(t))).collect();
+        let results: Vec<Mutex<Option<U>>> = (0..num_items).map(|_| Mutex::new(None)).collect();
+        let next_item = AtomicUsize::new(0);
+        let num_workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.map_range(0..num_workers.min(num_items), |_| loop {
+            let i = next_item.fetch_add(1, Ordering::Relaxed);
+            if i >= num_items {
+                break;
+            }
+            let item = cells[i].lock().unwrap().take().unwrap();
+            *results[i].lock().unwrap() = Some(f(item));
+        });
+        results
+            .into_iter()
+            .map(|r| r.into_inner().unwrap().unwrap())
+            .collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ParallelMap for ThreadPool {
+    fn map_slice<T: Sync, U: Send, F: Fn(&T) -> U + Sync>(&mut self, items: &[T], f: F) -> Vec<U> {
+        self.map(items, f)
+    }
+}
+
+/// Maps on the calling thread, for targets without threads
+pub struct SerialMap;
+
+impl ParallelMap for SerialMap {
+    fn map_slice<T: Sync, U: Send, F: Fn(&T) -> U + Sync>(&mut self, items: &[T], f: F) -> Vec<U> {
+        items.iter().map(f).collect()
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::evaluate::AUDIO_CHANNELS;
+use crate::machine::Machine;
+
+// Largest clock divider, at which the output plays 16 times slower
+const MAX_CLOCK_DIVIDER: u8 = 16;
+
+// Range of the instructions run per sample once a program is clocked
+const MIN_CYCLES_PER_SAMPLE: u16 = 8;
+const MAX_CYCLES_PER_SAMPLE: u16 = 4096;
+
+// Weight of the physics mutation among the other operators, when it's on
+pub const PHYSICS_MUTATION_WEIGHT: u32 = 2;
+
+/// Machine constants which evolve along with a program, changing how its
+/// output becomes sound rather than what it computes. The default is how
+/// programs have always run, where every byte output is the next sample.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedPhysics")]
+pub struct Physics {
+    // instructions run per sample, which is whatever was output last, or 0 to
+    // take each byte as the program outputs it
+    pub cycles_per_sample: u16,
+    // frames each frame of output is held for, so 2 plays it an octave lower
+    pub clock_divider: u8,
+    // channels the output is interleaved as, repeated across `AUDIO_CHANNELS`
+    pub channels: u8,
+}
+
+// Physics as read from a file or a peer, before its constants are checked
+#[derive(Deserialize)]
+#[serde(default)]
+struct UncheckedPhysics {
+    cycles_per_sample: u16,
+    clock_divider: u8,
+    channels: u8,
+}
+
+impl Default for UncheckedPhysics {
+    fn default() -> UncheckedPhysics {
+        let physics = Physics::default();
+        UncheckedPhysics {
+            cycles_per_sample: physics.cycles_per_sample,
+            clock_divider: physics.clock_divider,
+            channels: physics.channels,
+        }
+    }
+}
+
+impl TryFrom<UncheckedPhysics> for Physics {
+    type Error = String;
+
+    fn try_from(unchecked: UncheckedPhysics) -> Result<Physics, String> {
+        Physics {
+            cycles_per_sample: unchecked.cycles_per_sample,
+            clock_divider: unchecked.clock_divider,
+            channels: unchecked.channels,
+        }
+        .validated()
+    }
+}
+
+impl Default for Physics {
+    fn default() -> Physics {
+        Physics {
+            cycles_per_sample: 0,
+            clock_divider: 1,
+            channels: AUDIO_CHANNELS as u8,
+        }
+    }
+}
+
+impl Physics {
+    pub fn is_default(&self) -> bool {
+        *self == Physics::default()
+    }
+
+    /// Returns the physics if every constant is in the range `mutate` keeps
+    /// it in, since a divider or channel count of 0 can't be evaluated
+    pub fn validated(self) -> Result<Physics, String> {
+        if !(1..=MAX_CLOCK_DIVIDER).contains(&self.clock_divider) {
+            return Err(format!(
+                "clock divider {} is not between 1 and {}",
+                self.clock_divider, MAX_CLOCK_DIVIDER
+            ));
+        }
+        if !(1..=AUDIO_CHANNELS as u8).contains(&self.channels) {
+            return Err(format!(
+                "{} channels is not between 1 and {}",
+                self.channels, AUDIO_CHANNELS
+            ));
+        }
+        if self.cycles_per_sample != 0
+            && !(MIN_CYCLES_PER_SAMPLE..=MAX_CYCLES_PER_SAMPLE).contains(&self.cycles_per_sample)
+        {
+            return Err(format!(
+                "{} cycles per sample is neither 0 nor between {} and {}",
+                self.cycles_per_sample, MIN_CYCLES_PER_SAMPLE, MAX_CYCLES_PER_SAMPLE
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Changes one of the constants: halves or doubles the clock divider or
+    /// the cycles per sample, clocks or unclocks the program, or picks another
+    /// channel count
+    pub fn mutate(&mut self, rng: &mut dyn RngCore) {
+        match rng.gen_range(0..3) {
+            0 => {
+                self.clock_divider = if self.clock_divider > 1 && rng.gen() {
+                    self.clock_divider / 2
+                } else {
+                    self.clock_divider.saturating_mul(2).min(MAX_CLOCK_DIVIDER)
+                };
+            }
+            1 => {
+                self.cycles_per_sample = match self.cycles_per_sample {
+                    0 => 1 << rng.gen_range(3..=12),
+                    _ if rng.gen_bool(0.25) => 0,
+                    c if rng.gen() => (c / 2).max(MIN_CYCLES_PER_SAMPLE),
+                    c => c.saturating_mul(2).min(MAX_CYCLES_PER_SAMPLE),
+                };
+            }
+            _ => {
+                let others: Vec<u8> = (1..=AUDIO_CHANNELS as u8)
+                    .filter(|c| *c != self.channels)
+                    .collect();
+                self.channels = *others.choose(rng).unwrap();
+            }
+        }
+    }
+
+    /// Bytes the machine has to produce for `length` bytes of output
+    pub fn raw_length(&self, length: usize) -> usize {
+        let frames = length.div_ceil(AUDIO_CHANNELS);
+        frames.div_ceil(self.clock_divider as usize) * self.channels as usize
+    }
+
+    /// Bytes of output that `raw_length` bytes from the machine make
+    pub fn output_length(&self, raw_length: usize) -> usize {
+        if self.is_default() {
+            return raw_length;
+        }
+        raw_length / self.channels as usize * self.clock_divider as usize * AUDIO_CHANNELS
+    }
+
+    /// Samples produced after running `steps` instructions which output `bytes`
+    pub fn samples(&self, steps: usize, bytes: usize) -> usize {
+        match self.cycles_per_sample {
+            0 => bytes,
+            cycles => steps / cycles as usize,
+        }
+    }
+
+    /// Turns the machine's output into exactly `length` bytes of
+    /// `AUDIO_CHANNELS` interleaved channels, padded with silence
+    pub fn apply(&self, mut raw: Vec<u8>, length: usize) -> Vec<u8> {
+        if self.is_default() {
+            raw.resize(length, 0);
+            return raw;
+        }
+        let channels = self.channels as usize;
+        let divider = self.clock_divider as usize;
+        (0..length)
+            .map(|i| {
+                let frame = i / AUDIO_CHANNELS / divider;
+                let channel = i % AUDIO_CHANNELS % channels;
+                raw.get(frame * channels + channel).copied().unwrap_or(0)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Physics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_default() {
+            return write!(f, "native");
+        }
+        let mut parts = Vec::new();
+        if self.clock_divider > 1 {
+            parts.push(format!("clock ÷{}", self.clock_divider));
+        }
+        if self.channels as usize != AUDIO_CHANNELS {
+            parts.push(format!("{} ch", self.channels));
+        }
+        if self.cycles_per_sample > 0 {
+            parts.push(format!("{} cycles/sample", self.cycles_per_sample));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Turns what a machine outputs into samples, under some physics
+pub struct Clock {
+    cycles_per_sample: usize,
+    // instructions left until the next sample is taken
+    until_sample: usize,
+    // the latest byte output, which is the sample until another one is
+    held: u8,
+    scratch: Vec<u8>,
+}
+
+impl Clock {
+    pub fn new(physics: Physics) -> Clock {
+        let cycles_per_sample = physics.cycles_per_sample as usize;
+        Clock {
+            cycles_per_sample,
+            until_sample: cycles_per_sample,
+            held: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Runs `steps` instructions, adding the samples they produce to `output`
+    pub fn run(&mut self, machine: &mut Machine, steps: usize, output: &mut Vec<u8>) {
+        if self.cycles_per_sample == 0 {
+            machine.run(steps, output);
+            return;
+        }
+        let mut left = steps;
+        while left > 0 {
+            let n = left.min(self.until_sample);
+            self.scratch.clear();
+            machine.run(n, &mut self.scratch);
+            if let Some(b) = self.scratch.last() {
+                self.held = *b;
+            }
+            left -= n;
+            self.until_sample -= n;
+            if self.until_sample == 0 {
+                output.push(self.held);
+                self.until_sample = self.cycles_per_sample;
+            }
+        }
+    }
+}
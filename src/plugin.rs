@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use log::{info, warn};
+use rand::RngCore;
+use wasmi::{
+    Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::features::Features;
+use crate::mutation::MutationOperator;
+use crate::program::{Program, MAX_PROGRAM_LENGTH};
+
+// Most fuel, roughly the number of instructions, a plugin may use per call,
+// so that a runaway loop traps instead of hanging
+const MAX_FUEL: u64 = 10_000_000;
+
+// Most memory a plugin may grow to, which is far more than any program needs
+const MAX_MEMORY: usize = 64 << 20;
+
+// Weight of each plugin's mutation among the built-in operators
+pub const PLUGIN_MUTATION_WEIGHT: u32 = 5;
+
+/// A WebAssembly module which mutates programs or scores them, or both. It
+/// gets no imports, so it can't do anything but compute, and each call is
+/// limited to `MAX_FUEL` and its memory to `MAX_MEMORY`. Plugins export:
+/// - `memory`, and `alloc(len: i32) -> i32` giving space for the host to write
+///   `len` bytes into
+/// - optionally `mutate(ptr: i32, len: i32, seed: i64) -> i64`, which is given
+///   a program and returns where its mutated copy is as `ptr << 32 | len`, or
+///   a negative number to leave the program as it was
+/// - optionally `fitness(ptr: i32, len: i32) -> f32`, which is given `len`
+///   little-endian f32 features and returns a score, higher being better.
+///   The features are rms, peak, centroid, rolloff, flatness, zero crossings,
+///   fundamental in Hz or 0 when unpitched, program length and then the mfcc.
+pub struct Plugin {
+    pub name: String,
+    store: RefCell<Store<StoreLimits>>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    mutate: Option<TypedFunc<(i32, i32, i64), i64>>,
+    fitness: Option<TypedFunc<(i32, i32), f32>>,
+}
+
+impl Plugin {
+    pub fn load(name: String, wasm: &[u8]) -> Result<Plugin, String> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| e.to_string())?;
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(MAX_FUEL).map_err(|e| e.to_string())?;
+        let instance = Linker::<StoreLimits>::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|i| i.start(&mut store))
+            .map_err(|e| e.to_string())?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("there is no exported memory")?;
+        let alloc = instance
+            .get_typed_func(&store, "alloc")
+            .map_err(|e| format!("alloc: {}", e))?;
+        let mutate = instance.get_typed_func(&store, "mutate").ok();
+        let fitness = instance.get_typed_func(&store, "fitness").ok();
+        if mutate.is_none() && fitness.is_none() {
+            return Err("it exports neither mutate nor fitness".to_string());
+        }
+        Ok(Plugin {
+            name,
+            store: RefCell::new(store),
+            memory,
+            alloc,
+            mutate,
+            fitness,
+        })
+    }
+
+    pub fn can_mutate(&self) -> bool {
+        self.mutate.is_some()
+    }
+
+    pub fn can_score(&self) -> bool {
+        self.fitness.is_some()
+    }
+
+    /// Copies `data` into the plugin's memory, returning where it went
+    fn write(&self, store: &mut Store<StoreLimits>, data: &[u8]) -> Result<i32, String> {
+        store.set_fuel(MAX_FUEL).map_err(|e| e.to_string())?;
+        let ptr = self
+            .alloc
+            .call(&mut *store, data.len() as i32)
+            .map_err(|e| e.to_string())?;
+        self.memory
+            .write(&mut *store, ptr as u32 as usize, data)
+            .map_err(|e| e.to_string())?;
+        Ok(ptr)
+    }
+
+    /// The mutated program, or None if the plugin left it as it was
+    pub fn mutate(&self, program: &[u8], seed: u64) -> Result<Option<Vec<u8>>, String> {
+        let Some(mutate) = &self.mutate else {
+            return Err(format!("{} can't mutate", self.name));
+        };
+        let mut store = self.store.borrow_mut();
+        let ptr = self.write(&mut store, program)?;
+        store.set_fuel(MAX_FUEL).map_err(|e| e.to_string())?;
+        let result = mutate
+            .call(&mut *store, (ptr, program.len() as i32, seed as i64))
+            .map_err(|e| e.to_string())?;
+        if result < 0 {
+            return Ok(None);
+        }
+        let ptr = (result >> 32) as u32 as usize;
+        let len = (result & 0xffff_ffff) as usize;
+        if len > MAX_PROGRAM_LENGTH {
+            return Err(format!("mutated program is {} bytes long", len));
+        }
+        if ptr + len > self.memory.data_size(&*store) {
+            return Err("mutated program is outside of memory".to_string());
+        }
+        let mut mutated = vec![0; len];
+        self.memory
+            .read(&*store, ptr, &mut mutated)
+            .map_err(|e| e.to_string())?;
+        Ok(Some(mutated))
+    }
+
+    pub fn score(&self, features: &Features) -> Result<f32, String> {
+        let Some(fitness) = &self.fitness else {
+            return Err(format!("{} can't score", self.name));
+        };
+        let mut values = vec![
+            features.rms,
+            features.peak,
+            features.spectral_centroid,
+            features.spectral_rolloff,
+            features.spectral_flatness,
+            features.zero_crossing_rate,
+            features.fundamental.unwrap_or(0.0),
+            features.program_length as f32,
+        ];
+        values.extend_from_slice(&features.mfcc);
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let mut store = self.store.borrow_mut();
+        let ptr = self.write(&mut store, &bytes)?;
+        store.set_fuel(MAX_FUEL).map_err(|e| e.to_string())?;
+        fitness
+            .call(&mut *store, (ptr, values.len() as i32))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// A plugin's `mutate`, as one of the mutation operators to choose from
+pub struct PluginMutation(pub Rc<Plugin>);
+
+impl MutationOperator for PluginMutation {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn apply(&self, program: &mut Program, rng: &mut dyn RngCore) {
+        match self.0.mutate(program.bytes(), rng.next_u64()) {
+            Ok(Some(bytes)) => match Program::new(bytes) {
+                Ok(p) => {
+                    *program = p
+                        .with_features(program.features())
+                        .with_physics(program.physics())
+                }
+                Err(e) => warn!("{} made an invalid program: {}", self.0.name, e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("{} failed to mutate: {}", self.0.name, e),
+        }
+    }
+}
+
+/// The .wasm plugins in a directory, which are loaded again whenever the
+/// files change
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Rc<Plugin>>,
+    // files which failed to load, and why
+    errors: Vec<(String, String)>,
+    // every plugin file and when it was last modified, to notice changes
+    files: Vec<(PathBuf, SystemTime)>,
+}
+
+impl Plugins {
+    pub fn plugins(&self) -> &[Rc<Plugin>] {
+        &self.plugins
+    }
+
+    pub fn errors(&self) -> &[(String, String)] {
+        &self.errors
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Rc<Plugin>> {
+        self.plugins.iter().find(|p| p.name == name)
+    }
+
+    /// Loads every plugin again if any file in `dir` was added, removed or
+    /// modified since last time. Returns whether they were loaded.
+    pub fn reload_if_changed(&mut self, dir: &Path) -> bool {
+        let files = plugin_files(dir).unwrap_or_default();
+        if files == self.files {
+            return false;
+        }
+        self.plugins.clear();
+        self.errors.clear();
+        for (path, _) in &files {
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            match fs::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|wasm| Plugin::load(name.clone(), &wasm))
+            {
+                Ok(plugin) => {
+                    info!("Loaded plugin {}", path.display());
+                    self.plugins.push(Rc::new(plugin));
+                }
+                Err(e) => {
+                    warn!("Failed to load plugin {}: {}", path.display(), e);
+                    self.errors.push((name, e));
+                }
+            }
+        }
+        self.files = files;
+        true
+    }
+}
+
+fn plugin_files(dir: &Path) -> io::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("wasm") {
+            let modified = fs::metadata(&path)?.modified()?;
+            files.push((path, modified));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,106 @@
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::program::{Program, Provenance};
+
+// Population files start with this, then a format version byte, the length
+// of the annotations as a little endian u32, and the annotations as TOML.
+// After that comes each program as its length as a little endian u32 and the
+// program in the container format, which keeps its features, physics and
+// provenance.
+const POPULATION_MAGIC: &[u8; 4] = b"LMRP";
+const POPULATION_VERSION: u8 = 1;
+const POPULATION_HEADER_LENGTH: usize = 9;
+
+/// What was known about an instance when it was exported
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Annotations {
+    pub rating: Option<u8>,
+    pub tags: Vec<String>,
+    pub note: String,
+}
+
+/// One program of an exported population
+pub struct Member {
+    pub program: Program,
+    pub provenance: Provenance,
+    pub annotations: Annotations,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    // one per member, in the same order as the programs
+    #[serde(default)]
+    annotations: Vec<Annotations>,
+}
+
+/// Writes a whole population as one file, for sharing it with someone else
+pub fn write_population<W: Write>(writer: &mut W, members: &[Member]) -> io::Result<()> {
+    let header = toml::to_string(&Header {
+        annotations: members.iter().map(|m| m.annotations.clone()).collect(),
+    })
+    .unwrap();
+    writer.write_all(POPULATION_MAGIC)?;
+    writer.write_all(&[POPULATION_VERSION])?;
+    writer.write_all(&(header.len() as u32).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for member in members {
+        let mut data = Vec::new();
+        member
+            .program
+            .write_container(&mut data, &member.provenance)?;
+        writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        writer.write_all(&data)?;
+    }
+    Ok(())
+}
+
+/// Reads a file written by `write_population`
+pub fn read_population(data: &[u8]) -> Result<Vec<Member>, String> {
+    if !is_population(data) {
+        return Err("not a lemurs population file".to_string());
+    }
+    if data.len() < POPULATION_HEADER_LENGTH {
+        return Err("file is truncated".to_string());
+    }
+    let version = data[4];
+    if version != POPULATION_VERSION {
+        return Err(format!("unsupported format version {}", version));
+    }
+    let header_length = u32::from_le_bytes(data[5..9].try_into().unwrap()) as usize;
+    let header = data
+        .get(POPULATION_HEADER_LENGTH..(POPULATION_HEADER_LENGTH + header_length))
+        .ok_or("file is truncated")?;
+    let header = std::str::from_utf8(header).map_err(|_| "annotations are not UTF-8")?;
+    let header: Header = toml::from_str(header).map_err(|e| e.to_string())?;
+
+    let mut members = Vec::new();
+    let mut rest = &data[(POPULATION_HEADER_LENGTH + header_length)..];
+    while !rest.is_empty() {
+        let length = rest
+            .get(..4)
+            .map(|l| u32::from_le_bytes(l.try_into().unwrap()) as usize)
+            .ok_or("file is truncated")?;
+        let container = rest.get(4..(4 + length)).ok_or("file is truncated")?;
+        let (program, provenance) = Program::read_container(container)
+            .map_err(|e| format!("program {}: {}", members.len() + 1, e))?;
+        members.push(Member {
+            program,
+            provenance,
+            annotations: header
+                .annotations
+                .get(members.len())
+                .cloned()
+                .unwrap_or_default(),
+        });
+        rest = &rest[(4 + length)..];
+    }
+    Ok(members)
+}
+
+/// Whether the data starts like a population file
+pub fn is_population(data: &[u8]) -> bool {
+    data.starts_with(POPULATION_MAGIC)
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::app::ThumbnailMode;
+use crate::evaluate::EvalConfig;
+use crate::instruction::IsaFeatures;
+use crate::storage;
+
+/// Settings for evolving one kind of sound, e.g. "chip", "drone" or
+/// "percussive", kept under `[profiles.<name>]` in lemurs.toml. Settings a
+/// profile leaves out stay as they are.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub mutation_amount: Option<usize>,
+    // use the instruction-aware mutation operators
+    pub smart_mutations: Option<bool>,
+    // weight of each mutation operator by name, e.g. "Flip bit" = 10. Names
+    // of operators which aren't in use are ignored.
+    pub mutation_weights: Option<BTreeMap<String, u32>>,
+    // also mutate the physics programs run with
+    pub evolve_physics: Option<bool>,
+    // length of each instance's output
+    pub preview_secs: Option<f32>,
+    pub time_budget_secs: Option<f32>,
+    // how fast output bytes are played, which can only be chosen at startup
+    pub sample_rate: Option<usize>,
+    // instruction set extensions new programs run with, e.g. ["dsp"]
+    pub isa: Option<IsaFeatures>,
+    pub normalise_loudness: Option<bool>,
+    pub thumbnail_mode: Option<ThumbnailMode>,
+}
+
+impl Profile {
+    /// `base` with the profile's sample rate, preview length and time budget
+    pub fn eval_config(&self, base: &EvalConfig) -> EvalConfig {
+        let sample_rate = self.sample_rate.unwrap_or(base.sample_rate);
+        let mut config = EvalConfig::new(sample_rate, self.preview_secs, base.output_mode);
+        if self.preview_secs.is_none() {
+            config.preview_length = base.preview_length;
+        }
+        config.time_budget = self
+            .time_budget_secs
+            .map_or(base.time_budget, Duration::from_secs_f32);
+        config.max_steps = base.max_steps;
+        config.extra_isa = base.extra_isa;
+        config
+    }
+
+    /// Checks that the profile's lengths of time and sample rate are usable
+    fn validate(&self) -> Result<(), String> {
+        for (name, secs) in [
+            ("preview_secs", self.preview_secs),
+            ("time_budget_secs", self.time_budget_secs),
+        ] {
+            if let Some(secs) = secs {
+                if !secs.is_finite() || secs < 0.0 {
+                    return Err(format!("{} is {}, not a non-negative number", name, secs));
+                }
+            }
+        }
+        if self.sample_rate == Some(0) {
+            return Err("sample_rate is 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// The profiles in lemurs.toml, which is looked for in the working directory
+/// and then the config directory. There are none without the file.
+pub fn load_profiles() -> Result<BTreeMap<String, Profile>, String> {
+    let Some((path, text)) = storage::read_config() else {
+        return Ok(BTreeMap::new());
+    };
+    let profiles = toml::from_str::<ConfigFile>(&text)
+        .map(|c| c.profiles)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    for (name, profile) in &profiles {
+        profile
+            .validate()
+            .map_err(|e| format!("{}: profile \"{}\": {}", path.display(), name, e))?;
+    }
+    Ok(profiles)
+}
@@ -0,0 +1,362 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::instruction::{assemble, required_features, IsaFeatures};
+use crate::physics::Physics;
+
+// Machine addresses are 16 bits, so anything past this could never be reached
+pub const MAX_PROGRAM_LENGTH: usize = 1 << 16;
+
+// Container files start with this, then a format version byte, the length
+// of the metadata as a little endian u32, the metadata as TOML, and finally
+// the program bytes. The metadata is the provenance along with the features
+// the program needs and the physics it runs with.
+const CONTAINER_MAGIC: &[u8; 4] = b"LMRS";
+const CONTAINER_VERSION: u8 = 1;
+const CONTAINER_HEADER_LENGTH: usize = 9;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The initial memory of a machine. Always between 1 and
+/// `MAX_PROGRAM_LENGTH` bytes long. Programs declare the instruction set
+/// extensions they need to sound as intended, which are none unless
+/// `with_features` says otherwise, and the physics they evolved with.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Program {
+    bytes: Vec<u8>,
+    features: IsaFeatures,
+    physics: Physics,
+}
+
+/// A hash of a program's bytes which is the same across runs and platforms,
+/// so it can be stored to identify programs
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ProgramHash(pub u64);
+
+/// Where a program came from
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Provenance {
+    // the program that evolution started from
+    pub seed: Option<ProgramHash>,
+    pub generation: usize,
+    pub parents: Vec<ProgramHash>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    // names of the instruction set extensions the program needs, which older
+    // files don't have
+    #[serde(default)]
+    features: IsaFeatures,
+    #[serde(flatten)]
+    provenance: Provenance,
+    #[serde(default)]
+    physics: Physics,
+}
+
+impl Program {
+    pub fn new(bytes: Vec<u8>) -> Result<Program, String> {
+        if bytes.is_empty() {
+            return Err("program is empty".to_string());
+        }
+        if bytes.len() > MAX_PROGRAM_LENGTH {
+            return Err(format!(
+                "program is {} bytes long, more than the maximum of {}",
+                bytes.len(),
+                MAX_PROGRAM_LENGTH
+            ));
+        }
+        Ok(Program {
+            bytes,
+            features: IsaFeatures::default(),
+            physics: Physics::default(),
+        })
+    }
+
+    /// Assembles a program, which needs the extensions of whichever
+    /// instructions it uses
+    pub fn assemble(text: &str) -> Result<Program, String> {
+        let bytes = assemble(text).map_err(|e| e.to_string())?;
+        Ok(Program::new(bytes)?.with_features(required_features(text)))
+    }
+
+    pub fn with_features(self, features: IsaFeatures) -> Program {
+        Program { features, ..self }
+    }
+
+    pub fn features(&self) -> IsaFeatures {
+        self.features
+    }
+
+    pub fn with_physics(self, physics: Physics) -> Program {
+        Program { physics, ..self }
+    }
+
+    pub fn physics(&self) -> Physics {
+        self.physics
+    }
+
+    pub fn set_physics(&mut self, physics: Physics) {
+        self.physics = physics;
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// For edits which keep the length the same
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Always false, since programs can't be empty
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Inserts a byte before `index`, unless the program is already as long as it can be.
+    /// Returns whether the byte was inserted.
+    pub fn insert(&mut self, index: usize, byte: u8) -> bool {
+        if self.bytes.len() >= MAX_PROGRAM_LENGTH {
+            return false;
+        }
+        self.bytes.insert(index, byte);
+        true
+    }
+
+    /// Removes the byte at `index`, unless it is the only one left
+    pub fn remove(&mut self, index: usize) -> Option<u8> {
+        if self.bytes.len() <= 1 {
+            return None;
+        }
+        Some(self.bytes.remove(index))
+    }
+
+    /// 64-bit FNV-1a of the bytes
+    pub fn content_hash(&self) -> ProgramHash {
+        ProgramHash::of(&self.bytes)
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn from_hex(text: &str) -> Option<Program> {
+        if text.len() % 2 != 0 {
+            return None;
+        }
+        let bytes = (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(text.get(i..(i + 2))?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Program::new(bytes).ok()
+    }
+
+    /// Standard alphabet, padded
+    pub fn to_base64(&self) -> String {
+        let mut text = String::with_capacity(self.bytes.len().div_ceil(3) * 4);
+        for chunk in self.bytes.chunks(3) {
+            let bits = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |bits, (i, b)| bits | (*b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    text.push(BASE64_ALPHABET[(bits >> (18 - 6 * i)) as usize & 63] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    /// Accepts both the standard and the URL-safe alphabet, with or without padding
+    pub fn from_base64(text: &str) -> Option<Program> {
+        let decode_char = |c: u8| -> Option<u32> {
+            match c {
+                b'A'..=b'Z' => Some((c - b'A') as u32),
+                b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+                b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+                b'+' | b'-' => Some(62),
+                b'/' | b'_' => Some(63),
+                _ => None,
+            }
+        };
+        let text = text.trim_end_matches('=').as_bytes();
+        let mut data = Vec::with_capacity(text.len() * 3 / 4);
+        let mut bits: u32 = 0;
+        let mut num_bits = 0;
+        for c in text {
+            bits = (bits << 6) | decode_char(*c)?;
+            num_bits += 6;
+            if num_bits >= 8 {
+                num_bits -= 8;
+                data.push((bits >> num_bits) as u8);
+            }
+        }
+        Program::new(data).ok()
+    }
+
+    /// Parses a program pasted as text, either as hex (optionally prefixed
+    /// with 0x) or as base64. Whitespace is ignored.
+    pub fn from_text(text: &str) -> Option<Program> {
+        let text: String = text.split_whitespace().collect();
+        let hex = text.strip_prefix("0x").unwrap_or(&text);
+        Program::from_hex(hex).or_else(|| Program::from_base64(&text))
+    }
+
+    /// Writes the program, its features, physics and provenance in the container format
+    pub fn write_container<W: Write>(
+        &self,
+        writer: &mut W,
+        provenance: &Provenance,
+    ) -> io::Result<()> {
+        let metadata = toml::to_string(&Metadata {
+            features: self.features,
+            provenance: provenance.clone(),
+            physics: self.physics,
+        })
+        .unwrap();
+        writer.write_all(CONTAINER_MAGIC)?;
+        writer.write_all(&[CONTAINER_VERSION])?;
+        writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+        writer.write_all(metadata.as_bytes())?;
+        writer.write_all(&self.bytes)
+    }
+
+    /// Reads a file written by `write_container`
+    pub fn read_container(data: &[u8]) -> Result<(Program, Provenance), String> {
+        if !Program::is_container(data) {
+            return Err("not a lemurs program file".to_string());
+        }
+        if data.len() < CONTAINER_HEADER_LENGTH {
+            return Err("file is truncated".to_string());
+        }
+        let version = data[4];
+        if version != CONTAINER_VERSION {
+            return Err(format!("unsupported format version {}", version));
+        }
+        let metadata_length = u32::from_le_bytes(data[5..9].try_into().unwrap()) as usize;
+        let metadata = data
+            .get(CONTAINER_HEADER_LENGTH..(CONTAINER_HEADER_LENGTH + metadata_length))
+            .ok_or("file is truncated")?;
+        let metadata = std::str::from_utf8(metadata).map_err(|_| "metadata is not UTF-8")?;
+        let metadata: Metadata = toml::from_str(metadata).map_err(|e| e.to_string())?;
+        let program = Program::new(data[(CONTAINER_HEADER_LENGTH + metadata_length)..].to_vec())?
+            .with_features(metadata.features)
+            .with_physics(metadata.physics);
+        Ok((program, metadata.provenance))
+    }
+
+    /// Whether the data starts like a container file rather than a bare program
+    pub fn is_container(data: &[u8]) -> bool {
+        data.starts_with(CONTAINER_MAGIC)
+    }
+}
+
+impl AsRef<[u8]> for Program {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl ProgramHash {
+    pub fn of(bytes: &[u8]) -> ProgramHash {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for b in bytes {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        ProgramHash(hash)
+    }
+}
+
+impl fmt::Display for ProgramHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+// Programs are stored as a table of their bytes in hex, their features and
+// their physics. Older sessions have just the hex string, which is still read.
+#[derive(Serialize, Deserialize)]
+struct StoredProgram {
+    bytes: String,
+    #[serde(default)]
+    features: IsaFeatures,
+    #[serde(default)]
+    physics: Physics,
+}
+
+fn program_from_hex<E: de::Error>(text: &str) -> Result<Program, E> {
+    Program::from_hex(text).ok_or_else(|| E::custom(format!("invalid program \"{}\"", text)))
+}
+
+impl Serialize for Program {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredProgram {
+            bytes: self.to_hex(),
+            features: self.features,
+            physics: self.physics,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Program {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Program, D::Error> {
+        struct ProgramVisitor;
+
+        impl<'de> Visitor<'de> for ProgramVisitor {
+            type Value = Program;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a program table or a hex string")
+            }
+
+            fn visit_str<E: de::Error>(self, text: &str) -> Result<Program, E> {
+                program_from_hex(text)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Program, A::Error> {
+                let stored =
+                    StoredProgram::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(program_from_hex::<A::Error>(&stored.bytes)?
+                    .with_features(stored.features)
+                    .with_physics(stored.physics))
+            }
+        }
+
+        deserializer.deserialize_any(ProgramVisitor)
+    }
+}
+
+// Hashes are stored as hex strings too, since TOML integers are signed
+impl Serialize for ProgramHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ProgramHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ProgramHash, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        u64::from_str_radix(&text, 16)
+            .map(ProgramHash)
+            .map_err(|_| serde::de::Error::custom(format!("invalid program hash \"{}\"", text)))
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+use crate::machine::Machine;
+
+// Instructions between snapshots, which is the most a rewind has to replay
+const SNAPSHOT_INTERVAL: u64 = 1024;
+
+// Snapshots kept, after which the oldest are forgotten. Each holds a copy of
+// the memory, so this bounds how far back a long run can go.
+const MAX_SNAPSHOTS: usize = 256;
+
+struct Snapshot {
+    steps: u64,
+    output_len: u64,
+    machine: Machine,
+}
+
+/// Snapshots of a machine taken every `SNAPSHOT_INTERVAL` instructions, for
+/// stepping backwards through a program by restoring the last snapshot before
+/// the step wanted and running forward from there
+pub struct Rewind {
+    // oldest first
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl Rewind {
+    /// Starts from a machine which hasn't run yet
+    pub fn new(machine: &Machine) -> Rewind {
+        let mut snapshots = VecDeque::new();
+        snapshots.push_back(Snapshot {
+            steps: 0,
+            output_len: 0,
+            machine: machine.clone(),
+        });
+        Rewind { snapshots }
+    }
+
+    /// To be called after every instruction, with the number of instructions
+    /// run and bytes output so far
+    pub fn record(&mut self, machine: &Machine, steps: u64, output_len: u64) {
+        if steps % SNAPSHOT_INTERVAL != 0 {
+            return;
+        }
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            steps,
+            output_len,
+            machine: machine.clone(),
+        });
+    }
+
+    /// Puts `machine` back to how it was after `target` instructions, returning
+    /// the instructions run and bytes output at that point. If the snapshots
+    /// don't go back that far, it stops at the oldest one.
+    pub fn rewind(&mut self, machine: &mut Machine, target: u64) -> (u64, u64) {
+        let i = self
+            .snapshots
+            .iter()
+            .rposition(|s| s.steps <= target)
+            .unwrap_or(0);
+        self.snapshots.truncate(i + 1);
+        let snapshot = &self.snapshots[i];
+        *machine = snapshot.machine.clone();
+        let mut steps = snapshot.steps;
+        let mut output_len = snapshot.output_len;
+        let mut output = Vec::new();
+        while steps < target {
+            output.clear();
+            machine.run(1, &mut output);
+            steps += 1;
+            output_len += output.len() as u64;
+            self.record(machine, steps, output_len);
+        }
+        (steps, output_len)
+    }
+}
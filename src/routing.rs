@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use crate::evaluate::AUDIO_CHANNELS;
+
+/// How much of each output channel goes to the left and right speakers, for
+/// listening to multi-channel output in stereo
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Routing {
+    // left and right gain of each channel
+    pub gains: [[f32; 2]; AUDIO_CHANNELS],
+}
+
+impl Default for Routing {
+    /// Even channels on the left and odd ones on the right, mixed the way a
+    /// stereo device plays them without routing
+    fn default() -> Routing {
+        let mut gains = [[0.0; 2]; AUDIO_CHANNELS];
+        for (channel, g) in gains.iter_mut().enumerate() {
+            g[channel % 2] = 2.0 / AUDIO_CHANNELS as f32;
+        }
+        Routing { gains }
+    }
+}
+
+impl Routing {
+    /// Mixes interleaved output of `AUDIO_CHANNELS` channels into interleaved
+    /// stereo. Trailing bytes which don't fill a whole frame are dropped.
+    pub fn to_stereo(&self, data: &[u8]) -> Vec<u8> {
+        let mut stereo = Vec::with_capacity(data.len() / AUDIO_CHANNELS * 2);
+        for frame in data.chunks_exact(AUDIO_CHANNELS) {
+            stereo.extend(self.mix_frame(frame));
+        }
+        stereo
+    }
+
+    /// Like `to_stereo`, but keeps the layout of the output so that it can be
+    /// played the same way, with left and right repeated in each pair of
+    /// channels
+    pub fn apply(&self, data: &[u8]) -> Vec<u8> {
+        let mut routed = Vec::with_capacity(data.len());
+        for frame in data.chunks(AUDIO_CHANNELS) {
+            let [left, right] = self.mix_frame(frame);
+            routed.extend((0..frame.len()).map(|c| if c % 2 == 0 { left } else { right }));
+        }
+        routed
+    }
+
+    fn mix_frame(&self, frame: &[u8]) -> [u8; 2] {
+        let mut sums = [0.0f32; 2];
+        for (b, gains) in frame.iter().zip(&self.gains) {
+            let amplitude = *b as f32 - 128.0;
+            sums[0] += amplitude * gains[0];
+            sums[1] += amplitude * gains[1];
+        }
+        sums.map(|s| (s + 128.0).round().clamp(0.0, 255.0) as u8)
+    }
+}
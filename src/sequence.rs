@@ -0,0 +1,154 @@
+use crate::spectrogram::{Spectrogram, NUM_PITCH_CLASSES};
+
+// Output is read as a stream of MIDI bytes arriving at the rate a MIDI cable carries them
+pub const MIDI_BYTES_PER_SECOND: usize = 3125;
+
+// Bytes of output per piano roll column, so it's as wide as a spectrogram of the same output
+const PIANO_ROLL_COLUMN_BYTES: usize = 2048;
+
+const NUM_PITCHES: usize = 128;
+
+/// A channel message decoded from program output
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MidiEvent {
+    // index of the output byte which completed the message
+    pub offset: usize,
+    message: [u8; 3],
+    length: usize,
+}
+
+impl MidiEvent {
+    pub fn bytes(&self) -> &[u8] {
+        &self.message[..self.length]
+    }
+
+    /// Seconds from the start of the output
+    pub fn time(&self) -> f32 {
+        self.offset as f32 / MIDI_BYTES_PER_SECOND as f32
+    }
+}
+
+/// A note from its note on to its note off, in output bytes
+pub struct Note {
+    pub pitch: u8,
+    pub velocity: u8,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Number of data bytes following a channel status byte
+fn data_length(status: u8) -> usize {
+    match status & 0xf0 {
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
+
+/// Reads output as a MIDI byte stream, with running status. Only channel
+/// messages are kept. Other system messages cancel the running status, and
+/// stray data bytes are skipped.
+pub fn decode_midi(output: &[u8]) -> Vec<MidiEvent> {
+    let mut events = Vec::new();
+    let mut status: Option<u8> = None;
+    let mut data = [0; 2];
+    let mut num_data = 0;
+    for (offset, byte) in output.iter().enumerate() {
+        match *byte {
+            0x80..=0xef => {
+                status = Some(*byte);
+                num_data = 0;
+            }
+            // real-time messages may appear anywhere without interrupting anything
+            0xf8..=0xff => (),
+            0xf0..=0xf7 => status = None,
+            _ => {
+                let Some(s) = status else {
+                    continue;
+                };
+                data[num_data] = *byte;
+                num_data += 1;
+                if num_data == data_length(s) {
+                    events.push(MidiEvent {
+                        offset,
+                        message: [s, data[0], data[1]],
+                        length: 1 + num_data,
+                    });
+                    num_data = 0;
+                }
+            }
+        }
+    }
+    events
+}
+
+/// Pairs up note ons and note offs on any channel. Notes which are never
+/// released end with the output.
+pub fn notes(events: &[MidiEvent], output_length: usize) -> Vec<Note> {
+    let mut notes: Vec<Note> = Vec::new();
+    // index into `notes` of the sounding note of each channel and pitch
+    let mut sounding: Vec<Option<usize>> = vec![None; 16 * NUM_PITCHES];
+    for event in events {
+        let [status, pitch, velocity] = event.message;
+        let key = (status & 0x0f) as usize * NUM_PITCHES + pitch as usize;
+        let is_note_on = status & 0xf0 == 0x90 && velocity > 0;
+        let is_note_off = status & 0xf0 == 0x80 || (status & 0xf0 == 0x90 && velocity == 0);
+        if !is_note_on && !is_note_off {
+            continue;
+        }
+        // retriggering a sounding note ends it first
+        if let Some(i) = sounding[key].take() {
+            notes[i].end = event.offset;
+        }
+        if is_note_on {
+            sounding[key] = Some(notes.len());
+            notes.push(Note {
+                pitch,
+                velocity,
+                start: event.offset,
+                end: output_length,
+            });
+        }
+    }
+    notes
+}
+
+/// Shows which notes are sounding over time, highest pitch in the first row,
+/// laid out like a spectrogram so it can be drawn the same way
+pub fn piano_roll(output: &[u8]) -> Spectrogram {
+    let width = output.len().div_ceil(PIANO_ROLL_COLUMN_BYTES).max(1);
+    let mut values = vec![0.0; width * NUM_PITCHES];
+    for note in notes(&decode_midi(output), output.len()) {
+        let row = NUM_PITCHES - 1 - note.pitch as usize;
+        let first_column = note.start / PIANO_ROLL_COLUMN_BYTES;
+        let last_column = (note.end / PIANO_ROLL_COLUMN_BYTES).min(width - 1);
+        for column in first_column..=last_column {
+            let value = &mut values[row * width + column];
+            *value = f32::max(*value, note.velocity as f32 / 127.0);
+        }
+    }
+    Spectrogram {
+        width,
+        height: NUM_PITCHES,
+        values,
+    }
+}
+
+/// The piano roll with its octaves folded together, laid out like `chromagram`
+pub fn chroma_roll(output: &[u8]) -> Spectrogram {
+    let roll = piano_roll(output);
+    let width = roll.width;
+    let mut values = vec![0.0; width * NUM_PITCH_CLASSES];
+    for (row, roll_values) in roll.values.chunks(width).enumerate() {
+        let pitch = NUM_PITCHES - 1 - row;
+        let chroma_row = NUM_PITCH_CLASSES - 1 - pitch % NUM_PITCH_CLASSES;
+        let chroma_values = &mut values[chroma_row * width..(chroma_row + 1) * width];
+        for (c, r) in chroma_values.iter_mut().zip(roll_values) {
+            *c = f32::max(*c, *r);
+        }
+    }
+    Spectrogram {
+        width,
+        height: NUM_PITCH_CLASSES,
+        values,
+    }
+}
@@ -0,0 +1,6 @@
+use clap::Parser;
+use lemurs::cli::serve::{run, Args};
+
+fn main() {
+    run(Args::parse());
+}
@@ -0,0 +1,177 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::program::{Program, Provenance};
+
+// Programs are sent over TCP as a little endian u32 length followed by a
+// program container, any number of times per connection. Anything longer
+// than this is refused.
+const MAX_FRAME_LENGTH: usize = 1 << 20;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long a peer may go without sending anything before it's disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Peers sending at once, beyond which new connections are turned away
+const MAX_CONNECTIONS: usize = 8;
+
+/// Most programs waiting to be looked at, beyond which new ones are dropped
+pub const MAX_INBOX_LENGTH: usize = 64;
+
+/// A program sent by another peer
+pub struct SharedProgram {
+    // address of the peer it came from
+    pub from: String,
+    pub program: Program,
+    pub provenance: Provenance,
+}
+
+/// Sends programs to other running instances and receives theirs
+pub struct Sharing {
+    port: u16,
+    inbox: Receiver<SharedProgram>,
+    outbox: Sender<(String, Vec<u8>)>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+fn read_programs(
+    mut stream: TcpStream,
+    inbox: &SyncSender<SharedProgram>,
+    on_receive: &dyn Fn(),
+) -> io::Result<()> {
+    let from = stream.peer_addr()?.to_string();
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    loop {
+        let mut length = [0; 4];
+        match stream.read_exact(&mut length) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            r => r?,
+        }
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_FRAME_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("refusing a {} byte program", length),
+            ));
+        }
+        let mut data = vec![0; length];
+        stream.read_exact(&mut data)?;
+        let (program, provenance) = Program::read_container(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        info!("Received a {} byte program from {}", program.len(), from);
+        let shared = SharedProgram {
+            from: from.clone(),
+            program,
+            provenance,
+        };
+        match inbox.try_send(shared) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => warn!("Inbox is full, dropping a program from {}", from),
+            Err(TrySendError::Disconnected(_)) => return Ok(()),
+        }
+        on_receive();
+    }
+}
+
+fn send_program(peer: &str, data: &[u8]) -> io::Result<()> {
+    let address = peer
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address found"))?;
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    stream.write_all(&(data.len() as u32).to_le_bytes())?;
+    stream.write_all(data)
+}
+
+impl Sharing {
+    /// Listens for programs on `port` on every interface, from at most
+    /// `MAX_CONNECTIONS` peers at once. `on_receive` is called after each
+    /// program arrives, e.g. to wake up the UI.
+    pub fn start<F: Fn() + Send + Sync + 'static>(port: u16, on_receive: F) -> io::Result<Sharing> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let port = listener.local_addr()?.port();
+        let on_receive = Arc::new(on_receive);
+
+        let (inbox_sender, inbox) = sync_channel(MAX_INBOX_LENGTH);
+        let connections = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("Failed to accept a connection: {}", e);
+                        continue;
+                    }
+                };
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    warn!("Turned away a peer, too many are connected");
+                    continue;
+                }
+                let inbox_sender = inbox_sender.clone();
+                let on_receive = Arc::clone(&on_receive);
+                let connections = Arc::clone(&connections);
+                std::thread::spawn(move || {
+                    if let Err(e) = read_programs(stream, &inbox_sender, &*on_receive) {
+                        warn!("Stopped receiving from a peer: {}", e);
+                    }
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        // Sending happens on a thread of its own, so that slow peers can't block the UI
+        let (outbox, outbox_receiver) = channel::<(String, Vec<u8>)>();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = Arc::clone(&error);
+        std::thread::spawn(move || {
+            for (peer, data) in outbox_receiver {
+                match send_program(&peer, &data) {
+                    Ok(()) => info!("Sent a program to {}", peer),
+                    Err(e) => {
+                        *thread_error.lock().unwrap() =
+                            Some(format!("Failed to send to {}: {}", peer, e))
+                    }
+                }
+            }
+        });
+
+        Ok(Sharing {
+            port,
+            inbox,
+            outbox,
+            error,
+        })
+    }
+
+    /// The port being listened on, which is chosen by the system if 0 was requested
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sends the program to a peer given as host:port, in the background.
+    /// Failures are reported by `take_error`.
+    pub fn send(&self, peer: &str, program: &Program, provenance: &Provenance) {
+        let mut data = Vec::new();
+        program.write_container(&mut data, provenance).unwrap();
+        // fails only if the sending thread has stopped, which can't happen
+        let _ = self.outbox.send((peer.to_string(), data));
+    }
+
+    /// Returns the next program received, if there is one
+    pub fn try_recv(&self) -> Option<SharedProgram> {
+        self.inbox.try_recv().ok()
+    }
+
+    /// Returns a description of the most recent failure to send, if there was one
+    pub fn take_error(&self) -> Option<String> {
+        self.error.lock().unwrap().take()
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+
+use eframe::egui::{self, Key, Modifiers};
+use serde::{Deserialize, Serialize};
+
+/// Something the user can do from the keyboard
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Mutate,
+    SaveSession,
+    // undoes the last change to which instances are selected
+    Undo,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+    ToggleSelected,
+    Audition,
+    // saves the focused instance
+    Save,
+    AppendToTape,
+    ClearRating,
+    Rate1,
+    Rate2,
+    Rate3,
+    Rate4,
+    Rate5,
+    // leaves the detail view
+    Back,
+}
+
+impl Action {
+    pub const ALL: [Action; 18] = [
+        Action::Mutate,
+        Action::SaveSession,
+        Action::Undo,
+        Action::FocusLeft,
+        Action::FocusRight,
+        Action::FocusUp,
+        Action::FocusDown,
+        Action::ToggleSelected,
+        Action::Audition,
+        Action::Save,
+        Action::AppendToTape,
+        Action::ClearRating,
+        Action::Rate1,
+        Action::Rate2,
+        Action::Rate3,
+        Action::Rate4,
+        Action::Rate5,
+        Action::Back,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Mutate => "Mutate",
+            Action::SaveSession => "Save session",
+            Action::Undo => "Undo selection",
+            Action::FocusLeft => "Focus left",
+            Action::FocusRight => "Focus right",
+            Action::FocusUp => "Focus up",
+            Action::FocusDown => "Focus down",
+            Action::ToggleSelected => "Select",
+            Action::Audition => "Audition",
+            Action::Save => "Save instance",
+            Action::AppendToTape => "Append to tape",
+            Action::ClearRating => "Clear rating",
+            Action::Rate1 => "Rate 1",
+            Action::Rate2 => "Rate 2",
+            Action::Rate3 => "Rate 3",
+            Action::Rate4 => "Rate 4",
+            Action::Rate5 => "Rate 5",
+            Action::Back => "Back",
+        }
+    }
+
+    /// The rating the action gives the focused instance, if it's a rating action
+    pub fn rating(&self) -> Option<Option<u8>> {
+        match self {
+            Action::ClearRating => Some(None),
+            Action::Rate1 => Some(Some(1)),
+            Action::Rate2 => Some(Some(2)),
+            Action::Rate3 => Some(Some(3)),
+            Action::Rate4 => Some(Some(4)),
+            Action::Rate5 => Some(Some(5)),
+            _ => None,
+        }
+    }
+}
+
+// Keys which can be bound, looked up by name when reading settings
+const BINDABLE_KEYS: [Key; 62] = [
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+    Key::ArrowUp,
+    Key::Escape,
+    Key::Tab,
+    Key::Backspace,
+    Key::Enter,
+    Key::Space,
+    Key::Insert,
+    Key::Delete,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::Minus,
+    Key::PlusEquals,
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+];
+
+/// A key and the modifiers held with it. Written like "Cmd+Shift+Z", where Cmd
+/// is Ctrl except on a Mac.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Shortcut {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl Shortcut {
+    pub const fn new(modifiers: Modifiers, key: Key) -> Shortcut {
+        Shortcut { modifiers, key }
+    }
+
+    pub fn is_bindable(key: Key) -> bool {
+        BINDABLE_KEYS.contains(&key)
+    }
+}
+
+impl std::fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.command {
+            write!(f, "Cmd+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+impl From<Shortcut> for String {
+    fn from(shortcut: Shortcut) -> String {
+        shortcut.to_string()
+    }
+}
+
+impl TryFrom<String> for Shortcut {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Shortcut, String> {
+        let mut parts: Vec<&str> = text.split('+').collect();
+        let key_name = parts.pop().unwrap();
+        let key = BINDABLE_KEYS
+            .into_iter()
+            .find(|k| k.name() == key_name)
+            .ok_or_else(|| format!("unknown key \"{}\"", key_name))?;
+        let mut modifiers = Modifiers::NONE;
+        for part in parts {
+            modifiers = modifiers
+                | match part {
+                    "Cmd" => Modifiers::COMMAND,
+                    "Alt" => Modifiers::ALT,
+                    "Shift" => Modifiers::SHIFT,
+                    _ => return Err(format!("unknown modifier \"{}\"", part)),
+                };
+        }
+        Ok(Shortcut { modifiers, key })
+    }
+}
+
+/// Which shortcut does each action. Actions can also be left unbound.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    bindings: BTreeMap<Action, Shortcut>,
+}
+
+impl KeyBindings {
+    pub fn shortcut(&self, action: Action) -> Option<Shortcut> {
+        self.bindings.get(&action).copied()
+    }
+
+    /// Binds `shortcut` to `action`, unbinding it from any other action
+    pub fn bind(&mut self, action: Action, shortcut: Option<Shortcut>) {
+        self.bindings.retain(|_, s| Some(*s) != shortcut);
+        match shortcut {
+            Some(s) => self.bindings.insert(action, s),
+            None => self.bindings.remove(&action),
+        };
+    }
+
+    /// Whether the action's shortcut was pressed this frame, unless a text
+    /// field has the keyboard. Consumes the key press.
+    pub fn pressed(&self, ctx: &egui::Context, action: Action) -> bool {
+        let Some(shortcut) = self.shortcut(action) else {
+            return false;
+        };
+        !ctx.wants_keyboard_input()
+            && ctx.input_mut(|i| i.consume_key(shortcut.modifiers, shortcut.key))
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let bindings = [
+            (Action::Mutate, Modifiers::NONE, Key::M),
+            (Action::SaveSession, Modifiers::COMMAND, Key::S),
+            (Action::Undo, Modifiers::COMMAND, Key::Z),
+            (Action::FocusLeft, Modifiers::NONE, Key::ArrowLeft),
+            (Action::FocusRight, Modifiers::NONE, Key::ArrowRight),
+            (Action::FocusUp, Modifiers::NONE, Key::ArrowUp),
+            (Action::FocusDown, Modifiers::NONE, Key::ArrowDown),
+            (Action::ToggleSelected, Modifiers::NONE, Key::Enter),
+            (Action::Audition, Modifiers::NONE, Key::Space),
+            (Action::Save, Modifiers::NONE, Key::S),
+            (Action::AppendToTape, Modifiers::NONE, Key::T),
+            (Action::ClearRating, Modifiers::NONE, Key::Num0),
+            (Action::Rate1, Modifiers::NONE, Key::Num1),
+            (Action::Rate2, Modifiers::NONE, Key::Num2),
+            (Action::Rate3, Modifiers::NONE, Key::Num3),
+            (Action::Rate4, Modifiers::NONE, Key::Num4),
+            (Action::Rate5, Modifiers::NONE, Key::Num5),
+            (Action::Back, Modifiers::NONE, Key::Escape),
+        ];
+        KeyBindings {
+            bindings: bindings
+                .into_iter()
+                .map(|(action, modifiers, key)| (action, Shortcut::new(modifiers, key)))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::sync::Arc;
+
+use log::debug;
+use rustfft::{num_complex::Complex32, Fft, FftPlanner};
+
+#[cfg(feature = "gpu")]
+use crate::gpu_spectrogram::GpuSpectrogram;
+
+pub const FFT_WINDOW_SIZE: usize = 256;
+// const FFT_HOP_SIZE: usize = FFT_WINDOW_SIZE; // / 4;
+// const FFT_HOP_SIZE: usize = 1920 / FFT_WINDOW_SIZE;
+pub const FFT_HOP_SIZE: usize = FFT_WINDOW_SIZE * 8;
+
+// Magnitudes are clamped to this range before log scaling
+const MIN_MAGNITUDE: f32 = 1e0;
+const MAX_MAGNITUDE: f32 = 1e4;
+
+/// Log-scaled spectrogram magnitudes in the range [0, 1], stored row by row
+/// with the highest frequency in the first row.
+pub struct Spectrogram {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<f32>,
+}
+
+impl Spectrogram {
+    /// How much louder each column is than the one before, summed over the
+    /// frequencies which got louder, which peaks at note onsets and beats.
+    /// Normalised so the strongest onset is 1. The first column is 0.
+    pub fn spectral_flux(&self) -> Vec<f32> {
+        let mut flux = vec![0.0; self.width];
+        for x in 1..self.width {
+            flux[x] = (0..self.height)
+                .map(|y| {
+                    let row = &self.values[y * self.width..(y + 1) * self.width];
+                    (row[x] - row[x - 1]).max(0.0)
+                })
+                .sum();
+        }
+        let max = flux.iter().cloned().fold(0.0, f32::max);
+        if max > 0.0 {
+            for f in flux.iter_mut() {
+                *f /= max;
+            }
+        }
+        flux
+    }
+}
+
+// The chromagram analyses longer windows of one channel, so that neighbouring
+// semitones fall into different frequency bins
+const CHROMA_WINDOW: usize = 4096;
+const CHROMA_MIN_FREQUENCY: f32 = 100.0;
+const CHROMA_MAX_FREQUENCY: f32 = 5000.0;
+pub const NUM_PITCH_CLASSES: usize = 12;
+
+/// The energy of each pitch class in the first channel of `output` around each
+/// spectrogram column, laid out like a spectrogram with B in the first row and C
+/// in the last. Each column is normalised so its strongest pitch class is 1.
+pub fn chromagram(output: &[u8], channels: usize, sample_rate: usize, width: usize) -> Spectrogram {
+    let samples: Vec<f32> = output
+        .iter()
+        .step_by(channels)
+        .map(|b| *b as f32 - 128.0)
+        .collect();
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(CHROMA_WINDOW);
+    let bin_frequency = sample_rate as f32 / CHROMA_WINDOW as f32;
+    // pitch class of each bin in the analysed range
+    let classes: Vec<(usize, usize)> = (1..CHROMA_WINDOW / 2)
+        .filter_map(|bin| {
+            let frequency = bin as f32 * bin_frequency;
+            if !(CHROMA_MIN_FREQUENCY..=CHROMA_MAX_FREQUENCY).contains(&frequency) {
+                return None;
+            }
+            let note = (12.0 * (frequency / 440.0).log2()).round() as i32 + 69;
+            Some((bin, note.rem_euclid(NUM_PITCH_CLASSES as i32) as usize))
+        })
+        .collect();
+
+    let mut values = vec![0.0; width * NUM_PITCH_CLASSES];
+    let mut buffer = vec![Complex32::default(); CHROMA_WINDOW];
+    for column in 0..width {
+        let centre = (column * FFT_HOP_SIZE + FFT_WINDOW_SIZE / 2) / channels;
+        let start = centre as isize - (CHROMA_WINDOW / 2) as isize;
+        for (i, v) in buffer.iter_mut().enumerate() {
+            let sample = usize::try_from(start + i as isize)
+                .ok()
+                .and_then(|j| samples.get(j))
+                .copied()
+                .unwrap_or(0.0);
+            let t = i as f32 / CHROMA_WINDOW as f32;
+            let window = 0.5 - 0.5 * (t * std::f32::consts::TAU).cos();
+            *v = Complex32 {
+                re: sample * window,
+                im: 0.0,
+            };
+        }
+        fft.process(&mut buffer);
+
+        let mut energies = [0.0; NUM_PITCH_CLASSES];
+        for (bin, class) in &classes {
+            energies[*class] += buffer[*bin].norm_sqr();
+        }
+        let max = energies.iter().cloned().fold(0.0, f32::max);
+        if max <= 0.0 {
+            continue;
+        }
+        for (class, energy) in energies.iter().enumerate() {
+            let row = NUM_PITCH_CLASSES - 1 - class;
+            values[row * width + column] = energy / max;
+        }
+    }
+    Spectrogram {
+        width,
+        height: NUM_PITCH_CLASSES,
+        values,
+    }
+}
+
+// Spectrogram values are drawn on a gradient through these, from silent to loudest
+pub const SPECTROGRAM_COLOURS: [(f32, f32, f32); 4] = [
+    (0.0, 0.0, 0.0),
+    (0.0, 0.3, 0.8),
+    (1.0, 0.5, 0.0),
+    (1.0, 1.0, 1.0),
+];
+
+/// The colour of a value in [0, 1] on a gradient through `colours`, as 8-bit RGB
+pub fn gradient_colour(t: f32, colours: &[(f32, f32, f32)]) -> [u8; 3] {
+    let i_f = t.clamp(0.0, 1.0) * (colours.len() - 1) as f32;
+    let i_prev = i_f.floor() as usize;
+    let i_next = i_f.ceil() as usize;
+    let d = i_f.fract();
+    let c_prev = colours[i_prev];
+    let c_next = colours[i_next];
+    let (r, g, b) = (
+        c_prev.0 + d * (c_next.0 - c_prev.0),
+        c_prev.1 + d * (c_next.1 - c_prev.1),
+        c_prev.2 + d * (c_next.2 - c_prev.2),
+    );
+    [
+        (r * 255.0).clamp(0.0, 255.0) as u8,
+        (g * 255.0).clamp(0.0, 255.0) as u8,
+        (b * 255.0).clamp(0.0, 255.0) as u8,
+    ]
+}
+
+/// Undoes the log scaling of a spectrogram value
+pub fn linear_magnitude(t: f32) -> f32 {
+    MIN_MAGNITUDE * (MAX_MAGNITUDE / MIN_MAGNITUDE).powf(t)
+}
+
+fn compute_spectrogram(
+    program_output: &[u8],
+    fft: &dyn Fft<f32>,
+    window_coefficients: &[f32],
+    hop_size: usize,
+) -> Spectrogram {
+    let mut buffer: Vec<Complex32> = Vec::new();
+    buffer.resize(FFT_WINDOW_SIZE, Complex32::default());
+    assert!(program_output.len() >= FFT_WINDOW_SIZE);
+    let image_height = FFT_WINDOW_SIZE / 2;
+    let image_width = (program_output.len() - FFT_WINDOW_SIZE + hop_size) / hop_size;
+    debug!("image_width = {}", image_width);
+
+    let mut values: Vec<f32> = Vec::new();
+    values.resize(image_width * image_height, 0.0);
+
+    for h in 0..image_width {
+        let output_offset = h * hop_size;
+        for (i, v) in buffer.iter_mut().enumerate() {
+            *v = Complex32 {
+                re: program_output[output_offset + i] as f32 * window_coefficients[i],
+                im: 0.0,
+            };
+        }
+
+        fft.process(&mut buffer);
+
+        let v_min = MIN_MAGNITUDE;
+        let v_max = MAX_MAGNITUDE;
+        let log_min = v_min.ln();
+        let log_max = v_max.ln();
+        let k = 1.0 / (log_max - log_min);
+        for (i, v) in buffer[0..FFT_WINDOW_SIZE / 2].iter().enumerate() {
+            let abs = v.norm();
+            let log_abs = abs.clamp(v_min, v_max).ln();
+            let t = (log_abs - log_min) * k;
+            let px = h;
+            let py = image_height - 1 - i;
+            values[(py * image_width) + px] = t;
+        }
+    }
+
+    Spectrogram {
+        width: image_width,
+        height: image_height,
+        values,
+    }
+}
+
+/// Computes spectrograms on the GPU when built with the "gpu" feature and a
+/// device is available, and with rustfft otherwise
+pub struct SpectrogramRenderer {
+    fft: Arc<dyn Fft<f32>>,
+    window_coefficients: Vec<f32>,
+    #[cfg(feature = "gpu")]
+    gpu: Option<GpuSpectrogram>,
+}
+
+impl SpectrogramRenderer {
+    pub fn new() -> SpectrogramRenderer {
+        let mut planner = FftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(FFT_WINDOW_SIZE);
+
+        let k_inv_window_size = 1.0 / (FFT_WINDOW_SIZE as f32);
+        let window_coefficients: Vec<f32> = (0..FFT_WINDOW_SIZE)
+            .map(|i| {
+                let t = (i as f32) * k_inv_window_size;
+                0.5 - 0.5 * (t * std::f32::consts::TAU).cos()
+            })
+            .collect();
+
+        #[cfg(feature = "gpu")]
+        let gpu = GpuSpectrogram::new();
+        #[cfg(feature = "gpu")]
+        if gpu.is_none() {
+            log::warn!("No GPU available, computing spectrograms on the CPU");
+        }
+
+        SpectrogramRenderer {
+            fft,
+            window_coefficients,
+            #[cfg(feature = "gpu")]
+            gpu,
+        }
+    }
+
+    /// `program_output` must be at least one FFT window long
+    pub fn compute(&self, program_output: &[u8]) -> Spectrogram {
+        self.compute_with_hop(program_output, FFT_HOP_SIZE)
+    }
+
+    /// One spectrogram per channel of the interleaved `program_output`, stacked
+    /// with the first channel on top. The columns line up with those of `compute`.
+    pub fn compute_channels(&self, program_output: &[u8], channels: usize) -> Spectrogram {
+        let spectrograms: Vec<Spectrogram> = (0..channels)
+            .map(|c| {
+                let mut samples: Vec<u8> = program_output
+                    .iter()
+                    .skip(c)
+                    .step_by(channels)
+                    .copied()
+                    .collect();
+                samples.resize(samples.len().max(FFT_WINDOW_SIZE), 128);
+                self.compute_with_hop(&samples, (FFT_HOP_SIZE / channels).max(1))
+            })
+            .collect();
+        let width = spectrograms.iter().map(|s| s.width).min().unwrap_or(0);
+        let height = FFT_WINDOW_SIZE / 2;
+        let mut values = Vec::with_capacity(width * height * channels);
+        for spectrogram in &spectrograms {
+            for row in spectrogram.values.chunks(spectrogram.width) {
+                values.extend_from_slice(&row[..width]);
+            }
+        }
+        Spectrogram {
+            width,
+            height: height * channels,
+            values,
+        }
+    }
+
+    fn compute_with_hop(&self, program_output: &[u8], hop_size: usize) -> Spectrogram {
+        #[cfg(feature = "gpu")]
+        if let Some(gpu) = &self.gpu {
+            if let Some(values) = gpu.compute(program_output, &self.window_coefficients, hop_size) {
+                return Spectrogram {
+                    width: values.len() / (FFT_WINDOW_SIZE / 2),
+                    height: FFT_WINDOW_SIZE / 2,
+                    values,
+                };
+            }
+        }
+        compute_spectrogram(
+            program_output,
+            &*self.fft,
+            &self.window_coefficients,
+            hop_size,
+        )
+    }
+}
+
+impl Default for SpectrogramRenderer {
+    fn default() -> SpectrogramRenderer {
+        SpectrogramRenderer::new()
+    }
+}
@@ -0,0 +1,49 @@
+// Short-time Fourier transform of unsigned 8-bit samples, one invocation per
+// (frequency bin, column). Magnitudes are log-scaled to [0, 1] exactly like
+// the CPU path, and stored row by row with the highest frequency first.
+
+struct Params {
+    window_size: u32,
+    hop_size: u32,
+    num_columns: u32,
+    num_bins: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+// four samples packed into each word, little endian
+@group(0) @binding(1) var<storage, read> samples: array<u32>;
+@group(0) @binding(2) var<storage, read> window: array<f32>;
+@group(0) @binding(3) var<storage, read_write> values: array<f32>;
+
+fn sample(i: u32) -> f32 {
+    let word = samples[i / 4u];
+    return f32((word >> ((i % 4u) * 8u)) & 255u);
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let bin = id.x;
+    let column = id.y;
+    if (bin >= params.num_bins || column >= params.num_columns) {
+        return;
+    }
+
+    let offset = column * params.hop_size;
+    let k = -6.2831853 / f32(params.window_size);
+    var re = 0.0;
+    var im = 0.0;
+    for (var i = 0u; i < params.window_size; i++) {
+        let x = sample(offset + i) * window[i];
+        // reduce the phase first to keep cos/sin accurate
+        let angle = k * f32((bin * i) % params.window_size);
+        re += x * cos(angle);
+        im += x * sin(angle);
+    }
+
+    let v_min = 1.0;
+    let v_max = 10000.0;
+    let magnitude = clamp(sqrt(re * re + im * im), v_min, v_max);
+    let t = (log(magnitude) - log(v_min)) / (log(v_max) - log(v_min));
+    let row = params.num_bins - 1u - bin;
+    values[row * params.num_columns + column] = t;
+}
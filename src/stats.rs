@@ -0,0 +1,6 @@
+use clap::Parser;
+use lemurs::cli::stats::{run, Args};
+
+fn main() {
+    run(Args::parse());
+}
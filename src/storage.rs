@@ -0,0 +1,146 @@
+use std::io;
+
+/// Saves a file the user asked for. Natively it is written to the working
+/// directory, in the browser it is downloaded.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(name: &str, data: &[u8]) -> io::Result<()> {
+    std::fs::write(name, data)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(name: &str, data: &[u8]) -> io::Result<()> {
+    use wasm_bindgen::JsCast;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+    let anchor: web_sys::HtmlAnchorElement = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or_else(|| io::Error::other("no document"))?
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(|_| io::Error::other("not an anchor element"))?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url).map_err(js_error)
+}
+
+/// Creates a file to keep writing to, in the working directory. Browsers can't
+/// do this.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn create_file(name: &str) -> io::Result<Box<dyn io::Write>> {
+    Ok(Box::new(io::BufWriter::new(std::fs::File::create(name)?)))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn create_file(_name: &str) -> io::Result<Box<dyn io::Write>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "files can't be written to over time in the browser",
+    ))
+}
+
+/// Saves several files together. Natively they are written to a new folder in
+/// the working directory, in the browser each is downloaded with the folder
+/// name as a prefix.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_files(folder: &str, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    let folder = std::path::Path::new(folder);
+    std::fs::create_dir_all(folder)?;
+    for (name, data) in files {
+        std::fs::write(folder.join(name), data)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_files(folder: &str, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    for (name, data) in files {
+        save_file(&format!("{}_{}", folder, name), data)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(value: wasm_bindgen::JsValue) -> io::Error {
+    io::Error::other(format!("{:?}", value))
+}
+
+// Name of the config file with profiles, see `crate::profile`
+#[cfg(not(target_arch = "wasm32"))]
+const CONFIG_FILE_NAME: &str = "lemurs.toml";
+
+/// The path and contents of lemurs.toml, from the working directory or else
+/// the config directory
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_config() -> Option<(std::path::PathBuf, String)> {
+    let mut paths = vec![std::path::PathBuf::from(CONFIG_FILE_NAME)];
+    if let Some(dirs) = directories::ProjectDirs::from("", "", "lemurs") {
+        paths.push(dirs.config_dir().join(CONFIG_FILE_NAME));
+    }
+    paths
+        .into_iter()
+        .find_map(|path| std::fs::read_to_string(&path).ok().map(|text| (path, text)))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_config() -> Option<(std::path::PathBuf, String)> {
+    None
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn settings_path() -> Option<std::path::PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "lemurs")?;
+    Some(dirs.config_dir().join("settings.toml"))
+}
+
+/// Where WebAssembly plugins are loaded from, see `crate::plugin`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn plugins_dir() -> Option<std::path::PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "lemurs")?;
+    Some(dirs.config_dir().join("plugins"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn plugins_dir() -> Option<std::path::PathBuf> {
+    None
+}
+
+/// The saved settings, if there are any
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_settings() -> Option<String> {
+    std::fs::read_to_string(settings_path()?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_settings(text: &str) -> io::Result<()> {
+    let Some(path) = settings_path() else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&path, text)
+}
+
+// Browsers keep the settings in local storage under this key
+#[cfg(target_arch = "wasm32")]
+const SETTINGS_KEY: &str = "lemurs_settings";
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_settings() -> Option<String> {
+    local_storage()?.get_item(SETTINGS_KEY).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_settings(text: &str) -> io::Result<()> {
+    let Some(storage) = local_storage() else {
+        return Ok(());
+    };
+    storage.set_item(SETTINGS_KEY, text).map_err(js_error)
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use crate::evaluate::{AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE};
+use crate::instruction::IsaFeatures;
+use crate::machine::Machine;
+
+/// Number of knobs a host can turn while a voice plays
+pub const NUM_KNOBS: usize = 8;
+
+// The last bytes of memory are overwritten with the playing note, its
+// velocity and then the knobs, so programs can evolve to read them
+const NUM_CONTROL_BYTES: usize = 2 + NUM_KNOBS;
+
+const STEPS_PER_CHUNK: usize = 64;
+
+// A frame is left silent if the program takes longer than this to produce it
+const MAX_STEPS_PER_FRAME: usize = 1 << 14;
+
+/// Plays a program like an instrument, one frame at a time, for hosts which
+/// pull audio in small blocks
+pub struct MachineVoice {
+    program: Vec<u8>,
+    features: IsaFeatures,
+    machine: Machine,
+    note: u8,
+    velocity: u8,
+    knobs: [u8; NUM_KNOBS],
+    pending: VecDeque<u8>,
+    chunk: Vec<u8>,
+    // position between the last two machine frames, in machine frames
+    phase: f64,
+    frame: [f32; AUDIO_CHANNELS],
+    is_playing: bool,
+}
+
+impl MachineVoice {
+    pub fn new(program: Vec<u8>) -> MachineVoice {
+        MachineVoice::new_with_features(program, IsaFeatures::default())
+    }
+
+    pub fn new_with_features(program: Vec<u8>, features: IsaFeatures) -> MachineVoice {
+        MachineVoice {
+            machine: Machine::new_with_features(program.clone(), features),
+            program,
+            features,
+            note: 0,
+            velocity: 0,
+            knobs: [0; NUM_KNOBS],
+            pending: VecDeque::new(),
+            chunk: Vec::new(),
+            phase: 0.0,
+            frame: [0.0; AUDIO_CHANNELS],
+            is_playing: false,
+        }
+    }
+
+    /// Restarts the program from its original memory with the note written in
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        self.machine = Machine::new_with_features(self.program.clone(), self.features);
+        self.note = note;
+        self.velocity = velocity;
+        self.pending.clear();
+        self.phase = 0.0;
+        self.frame = [0.0; AUDIO_CHANNELS];
+        self.is_playing = true;
+        self.write_controls();
+    }
+
+    /// Stops the voice if `note` is the one playing
+    pub fn note_off(&mut self, note: u8) {
+        if note == self.note {
+            self.is_playing = false;
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    /// Sets a knob, which the program sees from the next frame on
+    pub fn set_knob(&mut self, index: usize, value: u8) {
+        self.knobs[index] = value;
+        if self.is_playing {
+            self.write_controls();
+        }
+    }
+
+    fn write_controls(&mut self) {
+        let memory = self.machine.memory_mut();
+        let length = memory.len();
+        let controls = [self.note, self.velocity]
+            .into_iter()
+            .chain(self.knobs.iter().copied());
+        // programs shorter than the control region wrap around, like the machine's addressing
+        for (i, value) in controls.enumerate() {
+            memory[(length - NUM_CONTROL_BYTES % length + i) % length] = value;
+        }
+    }
+
+    /// Runs the machine until it has produced one more frame
+    fn next_machine_frame(&mut self) -> [f32; AUDIO_CHANNELS] {
+        let mut steps = 0;
+        while self.pending.len() < AUDIO_CHANNELS {
+            if steps >= MAX_STEPS_PER_FRAME {
+                return [0.0; AUDIO_CHANNELS];
+            }
+            self.chunk.clear();
+            self.machine.run(STEPS_PER_CHUNK, &mut self.chunk);
+            self.pending.extend(&self.chunk);
+            steps += STEPS_PER_CHUNK;
+        }
+        let mut frame = [0.0; AUDIO_CHANNELS];
+        for sample in frame.iter_mut() {
+            *sample = (self.pending.pop_front().unwrap() as f32 - 128.0) / 128.0;
+        }
+        frame
+    }
+
+    /// The next frame at the host's sample rate, holding each machine frame
+    /// for as long as it would last at the default rate. Silent when no note is playing.
+    pub fn next_frame(&mut self, sample_rate: f32) -> [f32; AUDIO_CHANNELS] {
+        if !self.is_playing {
+            return [0.0; AUDIO_CHANNELS];
+        }
+        self.phase += DEFAULT_SAMPLE_RATE as f64 / sample_rate as f64;
+        while self.phase >= 1.0 {
+            self.frame = self.next_machine_frame();
+            self.phase -= 1.0;
+        }
+        let gain = self.velocity as f32 / 127.0;
+        self.frame.map(|s| s * gain)
+    }
+}
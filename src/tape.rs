@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+/// Part of an instance's output, placed on the tape
+pub struct TapeSegment {
+    // where the output came from, e.g. "#3"
+    pub label: String,
+    pub output: Arc<[u8]>,
+    // the range of `output` which is played, in bytes
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TapeSegment {
+    pub fn bytes(&self) -> &[u8] {
+        &self.output[self.start..self.end]
+    }
+}
+
+/// Segments of output which play one after another without gaps, for
+/// arranging sounds into something longer
+#[derive(Default)]
+pub struct Tape {
+    segments: Vec<TapeSegment>,
+}
+
+impl Tape {
+    pub fn segments(&self) -> &[TapeSegment] {
+        &self.segments
+    }
+
+    pub fn segments_mut(&mut self) -> &mut [TapeSegment] {
+        &mut self.segments
+    }
+
+    /// Adds the whole of `output` to the end
+    pub fn append(&mut self, label: String, output: Arc<[u8]>) {
+        let end = output.len();
+        self.segments.push(TapeSegment {
+            label,
+            output,
+            start: 0,
+            end,
+        });
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.segments.remove(index);
+    }
+
+    /// Swaps a segment with the one after it, if there is one
+    pub fn swap_with_next(&mut self, index: usize) {
+        if index + 1 < self.segments.len() {
+            self.segments.swap(index, index + 1);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Total length in bytes
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.end - s.start).sum()
+    }
+
+    /// Every segment joined together, in order
+    pub fn render(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len());
+        for segment in &self.segments {
+            bytes.extend_from_slice(segment.bytes());
+        }
+        bytes
+    }
+}
@@ -0,0 +1,88 @@
+use crate::instruction::assemble;
+
+/// A small hand-written program to start evolving from, which makes a
+/// recognisable sound where a random program would usually make noise or
+/// silence
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    source: &'static str,
+}
+
+impl Template {
+    pub fn program(&self) -> Vec<u8> {
+        assemble(self.source).unwrap()
+    }
+}
+
+pub const TEMPLATES: [Template; 4] = [
+    Template {
+        name: "counter",
+        description: "A 750 Hz sawtooth from a counter which wraps around",
+        source: "
+start:
+    addmimm r1 r1 3
+    output r1
+    output r1
+    output r1
+    output r1
+    jmp start
+",
+    },
+    Template {
+        name: "noise",
+        description: "White noise from the top bits of a linear congruential generator",
+        source: "
+start:
+    mulmimm r1 r1 1103515245
+    addmimm r1 r1 12345
+    shrimm r2 r1 16
+    output r2
+    output r2
+    output r2
+    output r2
+    jmp start
+",
+    },
+    Template {
+        name: "two-voice",
+        description: "Two sawtooths a fourth apart, halved and added together",
+        source: "
+start:
+    addmimm r1 r1 3
+    addmimm r2 r2 4
+    andimm r3 r1 255
+    shrimm r3 r3 1
+    andimm r4 r2 255
+    shrimm r4 r4 1
+    addm r3 r4
+    output r3
+    output r3
+    output r3
+    output r3
+    jmp start
+",
+    },
+    Template {
+        name: "feedback",
+        description: "A counter whose step depends on its own output, so the pitch wanders",
+        source: "
+start:
+    shrimm r4 r3 5
+    addmimm r4 r4 2
+    addm r1 r4
+    shrimm r3 r1 3
+    xor r3 r1
+    output r3
+    output r3
+    output r3
+    output r3
+    jmp start
+",
+    },
+];
+
+/// Looks up a template by name
+pub fn find_template(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|t| t.name == name)
+}
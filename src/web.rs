@@ -0,0 +1,54 @@
+//! The evolver in a browser, built with `trunk build --release` (see index.html)
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use lemurs::app::{InitialPopulation, LemursApp, Settings};
+    use lemurs::audio::{AudioBackend, NullBackend, WebAudioBackend};
+    use lemurs::evaluate::{EvalConfig, AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE};
+    use lemurs::logging;
+    use log::error;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    logging::init(false);
+
+    let rng = StdRng::from_entropy();
+    // visitors are mostly new, so start them with something to choose between
+    let initial_population = InitialPopulation::Onboarding;
+    let settings = Settings::load();
+    let eval_config = EvalConfig::default();
+    // Carry on without sound rather than not at all
+    let (audio, audio_error): (Box<dyn AudioBackend>, _) =
+        match WebAudioBackend::new(AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE) {
+            Ok(a) => (Box::new(a), None),
+            Err(e) => (
+                Box::new(NullBackend),
+                Some(format!(
+                    "Failed to start WebAudio, audio is disabled: {}",
+                    e
+                )),
+            ),
+        };
+
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = eframe::start_web(
+            "lemurs_canvas",
+            eframe::WebOptions::default(),
+            Box::new(|_| {
+                let mut app = LemursApp::new(initial_population, settings, eval_config, audio, rng);
+                if let Some(e) = audio_error {
+                    app.report_error(e);
+                }
+                Box::new(app)
+            }),
+        )
+        .await;
+        if let Err(e) = result {
+            error!("Failed to start: {:?}", e);
+        }
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    eprintln!("lemurs-web only runs in a browser. Build it with trunk, or use evolve instead.");
+}